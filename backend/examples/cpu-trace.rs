@@ -0,0 +1,56 @@
+//! Records the CPU trace of a ROM, or checks a ROM against a recorded one:
+//!
+//! ```text
+//! $ cargo run --release -p gbemu --example cpu-trace -- record ROM_PATH INSTRUCTIONS OUT.trace
+//! $ cargo run --release -p gbemu --example cpu-trace -- verify ROM_PATH TRACE
+//! ```
+//!
+//! The ROM is run on a DMG without input from the post-boot state, so only
+//! ROMs that don't depend on the host give a reproducible trace.
+
+use std::process::ExitCode;
+
+use gbemu::{serial::StdoutSerialWrite, trace, Emulator, Model};
+
+fn load(rom_path: &str) -> Result<Emulator, String> {
+    let rom =
+        std::fs::read(rom_path).map_err(|err| format!("cannot read '{}': {}", rom_path, err))?;
+    Emulator::with_model(&rom, None, Box::new(StdoutSerialWrite), Model::Dmg)
+        .map_err(|err| format!("invalid cartridge: {}", err))
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    match args {
+        [_, mode, rom_path, count, out_path] if mode == "record" => {
+            let count = count
+                .parse::<usize>()
+                .map_err(|_| format!("invalid instruction count '{}'", count))?;
+            let mut emu = load(rom_path)?;
+            let mut trace = trace::record(&mut emu, count).join("\n");
+            trace.push('\n');
+            std::fs::write(out_path, trace)
+                .map_err(|err| format!("cannot write '{}': {}", out_path, err))
+        }
+        [_, mode, rom_path, trace_path] if mode == "verify" => {
+            let trace = std::fs::read_to_string(trace_path)
+                .map_err(|err| format!("cannot read '{}': {}", trace_path, err))?;
+            let mut emu = load(rom_path)?;
+            trace::verify(&mut emu, &trace).map_err(|divergence| divergence.to_string())
+        }
+        _ => Err(
+            "usage: cpu-trace record ROM_PATH INSTRUCTIONS OUT.trace\n       cpu-trace verify ROM_PATH TRACE"
+                .to_owned(),
+        ),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
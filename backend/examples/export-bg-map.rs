@@ -0,0 +1,66 @@
+//! Runs a ROM headless and exports its background map as a Tiled map with
+//! its tileset:
+//!
+//! ```text
+//! $ cargo run --release -p gbemu --example export-bg-map -- ROM_PATH FRAMES OUT
+//! ```
+//!
+//! writes `OUT.json` and `OUT.png`, the JSON referencing the PNG.
+
+use std::{path::Path, process::ExitCode};
+
+use gbemu::{
+    ppu::bg_map::{TILESET_HEIGHT, TILESET_WIDTH},
+    serial::StdoutSerialWrite,
+    Emulator,
+};
+use image::RgbaImage;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, rom_path, frames, out] = args.as_slice() else {
+        eprintln!("usage: export-bg-map ROM_PATH FRAMES OUT");
+        return ExitCode::FAILURE;
+    };
+
+    let Ok(frames) = frames.parse::<u64>() else {
+        eprintln!("invalid frame count '{}'", frames);
+        return ExitCode::FAILURE;
+    };
+
+    let rom = match std::fs::read(rom_path) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("cannot read '{}': {}", rom_path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut emu = match Emulator::new(&rom, None, Box::new(StdoutSerialWrite)) {
+        Ok(emu) => emu,
+        Err(err) => {
+            eprintln!("invalid cartridge: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    emu.skip_frames(frames);
+    let export = emu.export_bg_map();
+
+    let png_path = format!("{}.png", out);
+    let mut fb = vec![0; TILESET_WIDTH * TILESET_HEIGHT * 4];
+    export.draw_tileset_into_fb(&mut fb);
+    let img = RgbaImage::from_raw(TILESET_WIDTH as u32, TILESET_HEIGHT as u32, fb).unwrap();
+    if let Err(err) = img.save(&png_path) {
+        eprintln!("cannot write '{}': {}", png_path, err);
+        return ExitCode::FAILURE;
+    }
+
+    // the map references the tileset relative to itself
+    let png_name = Path::new(&png_path).file_name().unwrap().to_string_lossy();
+    let json_path = format!("{}.json", out);
+    if let Err(err) = std::fs::write(&json_path, export.to_tiled_json(&png_name)) {
+        eprintln!("cannot write '{}': {}", json_path, err);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
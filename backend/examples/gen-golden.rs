@@ -0,0 +1,56 @@
+//! Runs a ROM headless and saves its last frame, to use as the reference
+//! image of a rendering regression test:
+//!
+//! ```text
+//! $ cargo run --release -p gbemu --example gen-golden -- ROM_PATH FRAMES OUT.png
+//! ```
+//!
+//! The emulator is stepped one frame at a time with no input, so the image
+//! is reproducible as long as the ROM itself is deterministic. ROMs depending
+//! on uninitialized memory or on the host clock will not give a usable
+//! reference.
+
+use std::process::ExitCode;
+
+use gbemu::{ppu::PIXEL_COUNT, serial::StdoutSerialWrite, Emulator, SCREEN_HEIGHT, SCREEN_WIDTH};
+use image::RgbaImage;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, rom_path, frames, out_path] = args.as_slice() else {
+        eprintln!("usage: gen-golden ROM_PATH FRAMES OUT.png");
+        return ExitCode::FAILURE;
+    };
+
+    let Ok(frames) = frames.parse::<u64>() else {
+        eprintln!("invalid frame count '{}'", frames);
+        return ExitCode::FAILURE;
+    };
+
+    let rom = match std::fs::read(rom_path) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("cannot read '{}': {}", rom_path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut emu = match Emulator::new(&rom, None, Box::new(StdoutSerialWrite)) {
+        Ok(emu) => emu,
+        Err(err) => {
+            eprintln!("invalid cartridge: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    emu.skip_frames(frames);
+
+    let mut fb = vec![0; PIXEL_COUNT * 4];
+    emu.display.lock().unwrap().draw_into_fb(&mut fb);
+    let img = RgbaImage::from_raw(SCREEN_WIDTH.into(), SCREEN_HEIGHT.into(), fb).unwrap();
+
+    if let Err(err) = img.save(out_path) {
+        eprintln!("cannot write '{}': {}", out_path, err);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
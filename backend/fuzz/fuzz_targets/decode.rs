@@ -0,0 +1,16 @@
+#![no_main]
+
+use gbemu::{cpu::disassemble::disassemble_range, memory::mock::MockMemory};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let memory = MockMemory::new();
+    let data = &data[..data.len().min(0xFFFF)];
+    for (addr, &byte) in data.iter().enumerate() {
+        memory.set(addr as u16, byte);
+    }
+
+    for line in disassemble_range(&memory, 0..data.len() as u16) {
+        let _ = line.to_string();
+    }
+});
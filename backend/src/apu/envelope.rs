@@ -0,0 +1,53 @@
+/// Volume of a channel, moved up or down by one every `period` frame
+/// sequencer envelope steps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Envelope {
+    /// NRx2 register
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    /// The DAC is off when both the initial volume and the direction bit are
+    /// cleared.
+    pub fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    fn period(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.period();
+    }
+
+    pub fn step(&mut self) {
+        if self.period() == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.period();
+
+        let increase = self.register & 0x08 != 0;
+        if increase && self.volume < 15 {
+            self.volume += 1;
+        } else if !increase && self.volume > 0 {
+            self.volume -= 1;
+        }
+    }
+}
@@ -0,0 +1,45 @@
+/// Silences a channel after a number of frame sequencer length steps, when
+/// enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LengthCounter {
+    max: u16,
+    remaining: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    /// `max` is the count loaded by a trigger while the counter is at 0, 64
+    /// for the square and noise channels.
+    pub fn new(max: u16) -> Self {
+        LengthCounter {
+            max,
+            remaining: 0,
+            enabled: false,
+        }
+    }
+
+    /// `value` is the length field of the NRx1 register.
+    pub fn load(&mut self, value: u8) {
+        self.remaining = self.max - value as u16;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn trigger(&mut self) {
+        if self.remaining == 0 {
+            self.remaining = self.max;
+        }
+    }
+
+    /// Returns true once the channel has to be disabled.
+    pub fn step(&mut self) -> bool {
+        if self.enabled && self.remaining > 0 {
+            self.remaining -= 1;
+            return self.remaining == 0;
+        }
+        false
+    }
+}
@@ -0,0 +1,213 @@
+//! Audio processing unit. The noise channel is not emulated yet, its
+//! registers keep the value written.
+
+mod envelope;
+mod length;
+mod square;
+mod wave;
+
+use crate::model::Model;
+use square::SquareChannel;
+use wave::WaveChannel;
+
+const NR10_ADDR: u16 = 0xFF10;
+const NR14_ADDR: u16 = 0xFF14;
+// NR20 does not exist, channel 2 has no sweep
+const NR20_ADDR: u16 = 0xFF15;
+const NR24_ADDR: u16 = 0xFF19;
+const NR30_ADDR: u16 = 0xFF1A;
+const NR34_ADDR: u16 = 0xFF1E;
+const NR50_ADDR: u16 = 0xFF24;
+const NR51_ADDR: u16 = 0xFF25;
+const NR52_ADDR: u16 = 0xFF26;
+const WAVE_RAM_START_ADDR: u16 = 0xFF30;
+const WAVE_RAM_END_ADDR: u16 = 0xFF3F;
+
+const POWER_BIT: u8 = 1 << 7;
+
+/// Bits reading as 1 in 0xFF10-0xFF2F, whatever was written.
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+/// The frame sequencer runs at 512Hz, every 2048 M-cycles.
+const FRAME_SEQUENCER_PERIOD: u16 = 2048;
+
+/// Scales a channel sample, at most 15 times a master volume of 8, so that
+/// the four channels mixed together fit in an i16.
+const SAMPLE_SCALE: i16 = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct APU {
+    /// 0xFF10-0xFF2F as written
+    registers: [u8; 0x20],
+    powered: bool,
+    channel1: SquareChannel,
+    channel2: SquareChannel,
+    channel3: WaveChannel,
+    frame_sequencer_timer: u16,
+    /// 0-7, length counters are clocked on even steps, the sweep on steps 2
+    /// and 6 and the envelopes on step 7
+    frame_sequencer_step: u8,
+}
+
+impl APU {
+    pub fn new() -> Self {
+        APU {
+            registers: [0; 0x20],
+            powered: false,
+            channel1: SquareChannel::new(true),
+            channel2: SquareChannel::new(false),
+            channel3: WaveChannel::new(),
+            frame_sequencer_timer: FRAME_SEQUENCER_PERIOD,
+            frame_sequencer_step: 0,
+        }
+    }
+
+    /// `addr` is in 0xFF10-0xFF3F, `model` decides how the wave RAM is
+    /// reached while channel 3 plays.
+    pub fn read_register(&self, addr: u16, model: Model) -> u8 {
+        if let WAVE_RAM_START_ADDR..=WAVE_RAM_END_ADDR = addr {
+            return self
+                .channel3
+                .read_wave_ram(addr - WAVE_RAM_START_ADDR, model);
+        }
+
+        let index = (addr - NR10_ADDR) as usize;
+        let value = match addr {
+            NR52_ADDR => {
+                let mut value = if self.powered { POWER_BIT } else { 0 };
+                if self.channel1.is_enabled() {
+                    value |= 1 << 0;
+                }
+                if self.channel2.is_enabled() {
+                    value |= 1 << 1;
+                }
+                if self.channel3.is_enabled() {
+                    value |= 1 << 2;
+                }
+                value
+            }
+            _ => self.registers[index],
+        };
+        value | READ_MASKS[index]
+    }
+
+    /// `addr` is in 0xFF10-0xFF3F. While powered off, only NR52 and the wave
+    /// RAM can be written.
+    pub fn write_register(&mut self, addr: u16, value: u8, model: Model) {
+        match addr {
+            NR52_ADDR => self.set_powered(value & POWER_BIT != 0),
+            WAVE_RAM_START_ADDR..=WAVE_RAM_END_ADDR => {
+                self.channel3
+                    .write_wave_ram(addr - WAVE_RAM_START_ADDR, value, model)
+            }
+            _ if !self.powered => {}
+            NR10_ADDR..=NR51_ADDR => {
+                self.registers[(addr - NR10_ADDR) as usize] = value;
+                match addr {
+                    NR10_ADDR..=NR14_ADDR => self.channel1.write_register(addr - NR10_ADDR, value),
+                    NR20_ADDR..=NR24_ADDR => self.channel2.write_register(addr - NR20_ADDR, value),
+                    NR30_ADDR..=NR34_ADDR => self.channel3.write_register(addr - NR30_ADDR, value),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set_powered(&mut self, powered: bool) {
+        if powered && !self.powered {
+            self.frame_sequencer_timer = FRAME_SEQUENCER_PERIOD;
+            self.frame_sequencer_step = 0;
+        } else if !powered {
+            self.registers.fill(0);
+            self.channel1 = SquareChannel::new(true);
+            self.channel2 = SquareChannel::new(false);
+            self.channel3.power_off();
+        }
+        self.powered = powered;
+    }
+
+    /// Advances the channels and the frame sequencer by one M-cycle.
+    pub fn tick(&mut self) {
+        if !self.powered {
+            return;
+        }
+
+        self.channel1.tick();
+        self.channel2.tick();
+        self.channel3.tick();
+
+        self.frame_sequencer_timer -= 1;
+        if self.frame_sequencer_timer == 0 {
+            self.frame_sequencer_timer = FRAME_SEQUENCER_PERIOD;
+            self.step_frame_sequencer();
+        }
+    }
+
+    fn step_frame_sequencer(&mut self) {
+        let step = self.frame_sequencer_step;
+        if step.is_multiple_of(2) {
+            self.channel1.step_length();
+            self.channel2.step_length();
+            self.channel3.step_length();
+        }
+        if step == 2 || step == 6 {
+            self.channel1.step_sweep();
+        }
+        if step == 7 {
+            self.channel1.step_envelope();
+            self.channel2.step_envelope();
+        }
+        self.frame_sequencer_step = (step + 1) % 8;
+    }
+
+    /// Output levels of channels 1 and 2 before the DACs, as read in the CGB
+    /// PCM12 register.
+    pub fn pcm12(&self) -> u8 {
+        self.channel1.amplitude() | (self.channel2.amplitude() << 4)
+    }
+
+    /// Output levels of channels 3 and 4 before the DACs, as read in the CGB
+    /// PCM34 register.
+    pub fn pcm34(&self) -> u8 {
+        self.channel3.amplitude()
+    }
+
+    /// Left and right outputs, panned by NR51 and scaled by the master volumes
+    /// of NR50.
+    pub fn sample(&self) -> (i16, i16) {
+        let panning = self.registers[(NR51_ADDR - NR10_ADDR) as usize];
+        let volumes = self.registers[(NR50_ADDR - NR10_ADDR) as usize];
+
+        let mut left = 0;
+        let mut right = 0;
+        let samples = [
+            self.channel1.sample(),
+            self.channel2.sample(),
+            self.channel3.sample(),
+        ];
+        for (index, sample) in samples.into_iter().enumerate() {
+            if panning & (0x10 << index) != 0 {
+                left += sample;
+            }
+            if panning & (0x01 << index) != 0 {
+                right += sample;
+            }
+        }
+
+        let left_volume = ((volumes >> 4) & 0x07) as i16 + 1;
+        let right_volume = (volumes & 0x07) as i16 + 1;
+        (
+            left * left_volume * SAMPLE_SCALE,
+            right * right_volume * SAMPLE_SCALE,
+        )
+    }
+}
@@ -0,0 +1,197 @@
+use super::{envelope::Envelope, length::LengthCounter};
+
+/// Waveforms selected by bits 6-7 of NRx1, one bit per duty step, 12.5%,
+/// 25%, 50% and 75% high.
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+const MAX_FREQUENCY: u16 = 2047;
+
+/// Frequency sweep of channel 1, driven by NR10.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Sweep {
+    register: u8,
+    shadow_frequency: u16,
+    timer: u8,
+    enabled: bool,
+}
+
+impl Sweep {
+    fn period(&self) -> u8 {
+        (self.register >> 4) & 0x07
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    // a period of 0 counts as 8
+    fn reload_timer(&mut self) {
+        self.timer = match self.period() {
+            0 => 8,
+            period => period,
+        };
+    }
+
+    /// Next frequency, `None` on an overflow which disables the channel.
+    fn next_frequency(&self) -> Option<u16> {
+        let delta = self.shadow_frequency >> self.shift();
+        let frequency = if self.register & 0x08 != 0 {
+            self.shadow_frequency - delta
+        } else {
+            self.shadow_frequency + delta
+        };
+        (frequency <= MAX_FREQUENCY).then_some(frequency)
+    }
+}
+
+/// Pulse channels, 1 with NR10-NR14 and 2 with NR21-NR24 which has no sweep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SquareChannel {
+    enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    /// M-cycles left before the next duty step
+    timer: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep: Option<Sweep>,
+}
+
+impl SquareChannel {
+    pub fn new(with_sweep: bool) -> Self {
+        SquareChannel {
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+            sweep: with_sweep.then(Sweep::default),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// `register` is 0-4 for NRx0-NRx4.
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                if let Some(sweep) = self.sweep.as_mut() {
+                    sweep.register = value;
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(value & 0x3F);
+            }
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            _ => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0x07) << 8);
+                self.length.set_enabled(value & 0x40 != 0);
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.timer = 2048 - self.frequency;
+        self.length.trigger();
+        self.envelope.trigger();
+
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.shadow_frequency = self.frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period() != 0 || sweep.shift() != 0;
+            if sweep.shift() != 0 && sweep.next_frequency().is_none() {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Advances the waveform by one M-cycle.
+    pub fn tick(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = 2048 - self.frequency;
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    pub fn step_length(&mut self) {
+        if self.length.step() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn step_envelope(&mut self) {
+        self.envelope.step();
+    }
+
+    pub fn step_sweep(&mut self) {
+        let Some(sweep) = self.sweep.as_mut() else {
+            return;
+        };
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0 {
+            return;
+        }
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.period() == 0 {
+            return;
+        }
+
+        match sweep.next_frequency() {
+            Some(frequency) if sweep.shift() != 0 => {
+                self.frequency = frequency;
+                sweep.shadow_frequency = frequency;
+                // checked again with the new frequency, without applying it
+                if sweep.next_frequency().is_none() {
+                    self.enabled = false;
+                }
+            }
+            Some(_) => {}
+            None => self.enabled = false,
+        }
+    }
+
+    /// Output level 0-15 before the DAC, as read in PCM12.
+    pub fn amplitude(&self) -> u8 {
+        if self.enabled && self.high() {
+            self.envelope.volume()
+        } else {
+            0
+        }
+    }
+
+    /// Output of the DAC, the current volume with the sign of the waveform.
+    pub fn sample(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+        let volume = self.envelope.volume() as i16;
+        if self.high() {
+            volume
+        } else {
+            -volume
+        }
+    }
+
+    fn high(&self) -> bool {
+        DUTY_PATTERNS[self.duty as usize] & (0x80 >> self.duty_step) != 0
+    }
+}
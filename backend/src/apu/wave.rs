@@ -0,0 +1,162 @@
+use crate::model::Model;
+
+use super::length::LengthCounter;
+
+const WAVE_RAM_SIZE: usize = 0x10;
+const SAMPLE_COUNT: u8 = 2 * WAVE_RAM_SIZE as u8;
+
+/// Wave channel 3, NR30-NR34, playing the 32 4-bit samples of the wave RAM,
+/// high nibble first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    /// Right shift of the samples from the NR32 volume code, 4 mutes them
+    volume_shift: u8,
+    frequency: u16,
+    /// Units of 2 T-cycles left before the next sample
+    timer: u16,
+    position: u8,
+    /// Last sample read, played until the next one
+    sample_buffer: u8,
+    /// A byte of the wave RAM was read during the last M-cycle
+    wave_ram_read: bool,
+    length: LengthCounter,
+    wave_ram: [u8; WAVE_RAM_SIZE],
+}
+
+impl WaveChannel {
+    pub fn new() -> Self {
+        WaveChannel {
+            enabled: false,
+            dac_enabled: false,
+            volume_shift: 4,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            wave_ram_read: false,
+            length: LengthCounter::new(256),
+            wave_ram: [0; WAVE_RAM_SIZE],
+        }
+    }
+
+    /// Back to the power-on state, the wave RAM is kept.
+    pub fn power_off(&mut self) {
+        *self = WaveChannel {
+            wave_ram: self.wave_ram,
+            ..WaveChannel::new()
+        };
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// `register` is 0-4 for NR30-NR34.
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(value),
+            2 => {
+                self.volume_shift = match (value >> 5) & 0x03 {
+                    0 => 4,
+                    code => code - 1,
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            _ => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0x07) << 8);
+                self.length.set_enabled(value & 0x40 != 0);
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    // the buffer is not reloaded, the first sample read is the second one
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.timer = 2048 - self.frequency;
+        self.position = 0;
+        self.length.trigger();
+    }
+
+    /// While the channel plays, the CPU only reaches the byte being read by
+    /// the channel, whatever the address. The DMG only allows it on the
+    /// M-cycle the channel reads that byte, `None` otherwise.
+    fn wave_ram_index(&self, offset: u16, model: Model) -> Option<usize> {
+        if !self.enabled {
+            Some(offset as usize)
+        } else if model.is_cgb() || self.wave_ram_read {
+            Some(self.position as usize / 2)
+        } else {
+            None
+        }
+    }
+
+    /// `offset` is the address minus 0xFF30.
+    pub fn read_wave_ram(&self, offset: u16, model: Model) -> u8 {
+        self.wave_ram_index(offset, model)
+            .map_or(0xFF, |index| self.wave_ram[index])
+    }
+
+    pub fn write_wave_ram(&mut self, offset: u16, value: u8, model: Model) {
+        if let Some(index) = self.wave_ram_index(offset, model) {
+            self.wave_ram[index] = value;
+        }
+    }
+
+    /// Advances the position by one M-cycle, 2 units of the frequency timer.
+    pub fn tick(&mut self) {
+        self.wave_ram_read = false;
+        if !self.enabled {
+            return;
+        }
+        for _ in 0..2 {
+            self.timer = self.timer.saturating_sub(1);
+            if self.timer == 0 {
+                self.timer = 2048 - self.frequency;
+                self.position = (self.position + 1) % SAMPLE_COUNT;
+                let byte = self.wave_ram[self.position as usize / 2];
+                self.wave_ram_read = true;
+                self.sample_buffer = if self.position.is_multiple_of(2) {
+                    byte >> 4
+                } else {
+                    byte & 0x0F
+                };
+            }
+        }
+    }
+
+    pub fn step_length(&mut self) {
+        if self.length.step() {
+            self.enabled = false;
+        }
+    }
+
+    /// Output level 0-15 before the DAC, as read in PCM34.
+    pub fn amplitude(&self) -> u8 {
+        if self.enabled {
+            self.sample_buffer >> self.volume_shift
+        } else {
+            0
+        }
+    }
+
+    /// Output of the DAC, centered on 0 like the square channels. The division
+    /// keeps it symmetric, and silent with the 4 bits shift.
+    pub fn sample(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+        (self.sample_buffer as i16 * 2 - 15) / (1 << self.volume_shift)
+    }
+}
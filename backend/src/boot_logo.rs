@@ -0,0 +1,61 @@
+//! Scrolling logo shown in place of the bootstrap ROM, drawn from the logo
+//! bitmap of the cartridge header.
+
+use crate::ppu::{PIXEL_COUNT, SCREEN_WIDTH};
+
+pub const LOGO_WIDTH: usize = 48;
+pub const LOGO_HEIGHT: usize = 8;
+/// Length of the animation, about one second
+pub const ANIMATION_FRAMES: usize = 60;
+
+/// The logo is drawn with each bitmap pixel doubled, at the place of the
+/// bootstrap ROM one.
+const SCALE: usize = 2;
+const LOGO_X: usize = (SCREEN_WIDTH as usize - LOGO_WIDTH * SCALE) / 2;
+const LOGO_END_Y: isize = 64;
+
+/// Decodes the 48 bytes logo of the header: 12x2 tiles of 4x4 pixels, each
+/// byte holding two rows of 4 pixels.
+pub fn decode_logo(logo: &[u8]) -> [[bool; LOGO_WIDTH]; LOGO_HEIGHT] {
+    assert_eq!(logo.len(), LOGO_WIDTH * LOGO_HEIGHT / 8);
+
+    let mut bitmap = [[false; LOGO_WIDTH]; LOGO_HEIGHT];
+    for (index, byte) in logo.iter().enumerate() {
+        let half = index / 24;
+        let tile = (index % 24) / 2;
+        let row = half * 4 + (index % 2) * 2;
+        for (row_offset, nibble) in [byte >> 4, byte & 0xF].into_iter().enumerate() {
+            for bit in 0..4 {
+                bitmap[row + row_offset][tile * 4 + bit] = nibble & (0x8 >> bit) != 0;
+            }
+        }
+    }
+    bitmap
+}
+
+/// Color indices of frame `frame` of the animation, the logo scrolling down
+/// from the top of the screen.
+pub fn animation_frame(logo: &[u8], frame: usize) -> [u8; PIXEL_COUNT] {
+    let bitmap = decode_logo(logo);
+    let start_y = -((LOGO_HEIGHT * SCALE) as isize);
+    let progress = frame.min(ANIMATION_FRAMES - 1) as isize;
+    let top = start_y + (LOGO_END_Y - start_y) * progress / (ANIMATION_FRAMES as isize - 1);
+
+    let mut pixels = [0; PIXEL_COUNT];
+    for (y, row) in bitmap.iter().enumerate() {
+        for (x, &set) in row.iter().enumerate() {
+            if !set {
+                continue;
+            }
+            for dy in 0..SCALE {
+                let screen_y = top + (y * SCALE + dy) as isize;
+                if screen_y < 0 {
+                    continue;
+                }
+                let offset = screen_y as usize * SCREEN_WIDTH as usize + LOGO_X + x * SCALE;
+                pixels[offset..(offset + SCALE)].fill(3);
+            }
+        }
+    }
+    pixels
+}
@@ -0,0 +1,16 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusAccessKind {
+    Read,
+    Write,
+}
+
+/// Memory access done by the CPU, see `CPU::bus_trace`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusAccess {
+    /// M-cycle of the instruction the access happened on, the opcode fetch
+    /// being 0. The decoder reads the operands along with the opcode.
+    pub cycle: u8,
+    pub addr: u16,
+    pub value: u8,
+    pub kind: BusAccessKind,
+}
@@ -0,0 +1,76 @@
+use std::{fmt, sync::Arc};
+
+use super::Instruction;
+
+type InstructionMatcher = Arc<dyn Fn(&Instruction) -> bool + Send + Sync>;
+
+/// Stops the CPU before the execution of matching instructions. A stopped
+/// CPU does nothing on `step` until `resume` is called.
+#[derive(Clone, Default)]
+pub struct Debugger {
+    opcodes: Vec<u8>,
+    matchers: Vec<InstructionMatcher>,
+    stopped_at: Option<u16>,
+    resuming: bool,
+}
+
+impl fmt::Debug for Debugger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Debugger")
+            .field("opcodes", &self.opcodes)
+            .field("matchers", &self.matchers.len())
+            .field("stopped_at", &self.stopped_at)
+            .finish()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Breaks on the instructions starting with `opcode`, 0xCB for all the
+    /// prefixed ones.
+    pub fn break_on_opcode(&mut self, opcode: u8) {
+        self.opcodes.push(opcode);
+    }
+
+    pub fn break_on_instruction(
+        &mut self,
+        matcher: impl Fn(&Instruction) -> bool + Send + Sync + 'static,
+    ) {
+        self.matchers.push(Arc::new(matcher));
+    }
+
+    /// Address of the instruction the CPU is stopped before.
+    pub fn stopped_at(&self) -> Option<u16> {
+        self.stopped_at
+    }
+
+    /// Restarts the CPU, executing the instruction it is stopped before.
+    pub fn resume(&mut self) {
+        if self.stopped_at.take().is_some() {
+            self.resuming = true;
+        }
+    }
+
+    /// Forgets the instruction the CPU is stopped before, the breakpoints are
+    /// kept.
+    pub(super) fn clear_stop(&mut self) {
+        self.stopped_at = None;
+        self.resuming = false;
+    }
+
+    pub(super) fn should_break(&mut self, pc: u16, opcode: u8, instruction: &Instruction) -> bool {
+        if std::mem::take(&mut self.resuming) {
+            return false;
+        }
+
+        let hit = self.opcodes.contains(&opcode)
+            || self.matchers.iter().any(|matcher| matcher(instruction));
+        if hit {
+            self.stopped_at = Some(pc);
+        }
+        hit
+    }
+}
@@ -2,19 +2,27 @@ use super::{
     instruction::{Instruction, JumpCondition, PrePostOperation},
     micro_op::Reg8OrIndirect,
     register::{Register16, Register8},
-    CPU,
 };
-use crate::memory::Memory;
 
-pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
-    let pc = cpu.pc;
-    let opcode = cpu.fetch_and_advance();
+/// Source of the instruction bytes, the CPU or a disassembler.
+pub trait ByteStream {
+    fn fetch_and_advance(&mut self) -> u8;
+
+    fn fetch_and_advance_u16(&mut self) -> u16 {
+        let low = self.fetch_and_advance();
+        let high = self.fetch_and_advance();
+        u16::from_le_bytes([low, high])
+    }
+}
+
+pub fn decode_instruction<S: ByteStream>(stream: &mut S) -> Instruction {
+    let opcode = stream.fetch_and_advance();
 
     match opcode {
         0x00 => Instruction::Nop,
         0x01 => Instruction::LoadLiteralIntoReg16 {
             reg: Register16::BC,
-            literal: cpu.fetch_and_advance_u16(),
+            literal: stream.fetch_and_advance_u16(),
         },
         0x02 => Instruction::WriteReg8ValueAtIndirect {
             reg: Register8::A,
@@ -28,11 +36,11 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         0x05 => Instruction::DecReg8 { reg: Register8::B },
         0x06 => Instruction::LoadLiteralIntoReg8 {
             reg: Register8::B,
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0x07 => Instruction::RotateLeftA,
         0x08 => Instruction::WriteReg16ValueAtAddress {
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
             reg: Register16::SP,
         },
         0x09 => Instruction::AddHLWithReg {
@@ -50,13 +58,13 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         0x0D => Instruction::DecReg8 { reg: Register8::C },
         0x0E => Instruction::LoadLiteralIntoReg8 {
             reg: Register8::C,
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0x0F => Instruction::RotateRightA,
         0x10 => Instruction::Stop,
         0x11 => Instruction::LoadLiteralIntoReg16 {
             reg: Register16::DE,
-            literal: cpu.fetch_and_advance_u16(),
+            literal: stream.fetch_and_advance_u16(),
         },
         0x12 => Instruction::WriteReg8ValueAtIndirect {
             addr: Register16::DE,
@@ -70,12 +78,12 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         0x15 => Instruction::DecReg8 { reg: Register8::D },
         0x16 => Instruction::LoadLiteralIntoReg8 {
             reg: Register8::D,
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0x17 => Instruction::RotateLeftThroughCarryA,
         0x18 => Instruction::JumpRelative {
             condition: None,
-            offset: cpu.fetch_and_advance() as i8,
+            offset: stream.fetch_and_advance() as i8,
         },
         0x19 => Instruction::AddHLWithReg {
             reg: Register16::DE,
@@ -92,16 +100,16 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         0x1D => Instruction::DecReg8 { reg: Register8::E },
         0x1E => Instruction::LoadLiteralIntoReg8 {
             reg: Register8::E,
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0x1F => Instruction::RotateRightThroughCarryA,
         0x20 => Instruction::JumpRelative {
             condition: Some(JumpCondition::NonZero),
-            offset: cpu.fetch_and_advance() as i8,
+            offset: stream.fetch_and_advance() as i8,
         },
         0x21 => Instruction::LoadLiteralIntoReg16 {
             reg: Register16::HL,
-            literal: cpu.fetch_and_advance_u16(),
+            literal: stream.fetch_and_advance_u16(),
         },
         0x22 => Instruction::WriteReg8ValueAtIndirect {
             addr: Register16::HL,
@@ -115,12 +123,12 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         0x25 => Instruction::DecReg8 { reg: Register8::H },
         0x26 => Instruction::LoadLiteralIntoReg8 {
             reg: Register8::H,
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0x27 => Instruction::Daa,
         0x28 => Instruction::JumpRelative {
             condition: Some(JumpCondition::Zero),
-            offset: cpu.fetch_and_advance() as i8,
+            offset: stream.fetch_and_advance() as i8,
         },
         0x29 => Instruction::AddHLWithReg {
             reg: Register16::HL,
@@ -137,16 +145,16 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         0x2D => Instruction::DecReg8 { reg: Register8::L },
         0x2E => Instruction::LoadLiteralIntoReg8 {
             reg: Register8::L,
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0x2F => Instruction::ComplementA,
         0x30 => Instruction::JumpRelative {
             condition: Some(JumpCondition::NonCarry),
-            offset: cpu.fetch_and_advance() as i8,
+            offset: stream.fetch_and_advance() as i8,
         },
         0x31 => Instruction::LoadLiteralIntoReg16 {
             reg: Register16::SP,
-            literal: cpu.fetch_and_advance_u16(),
+            literal: stream.fetch_and_advance_u16(),
         },
         0x32 => Instruction::WriteReg8ValueAtIndirect {
             addr: Register16::HL,
@@ -164,12 +172,12 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         },
         0x36 => Instruction::WriteLiteralAtIndirect {
             addr: Register16::HL,
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0x37 => Instruction::SetCarryFlag,
         0x38 => Instruction::JumpRelative {
             condition: Some(JumpCondition::Carry),
-            offset: cpu.fetch_and_advance() as i8,
+            offset: stream.fetch_and_advance() as i8,
         },
         0x39 => Instruction::AddHLWithReg {
             reg: Register16::SP,
@@ -186,7 +194,7 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         0x3D => Instruction::DecReg8 { reg: Register8::A },
         0x3E => Instruction::LoadLiteralIntoReg8 {
             reg: Register8::A,
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0x3F => Instruction::ComplementCarryFlag,
         0x40 => Instruction::Move {
@@ -546,21 +554,21 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         },
         0xC2 => Instruction::JumpAbsolute {
             condition: Some(JumpCondition::NonZero),
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
         },
         0xC3 => Instruction::JumpAbsolute {
             condition: None,
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
         },
         0xC4 => Instruction::CallAddr {
             condition: Some(JumpCondition::NonZero),
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
         },
         0xC5 => Instruction::PushReg16 {
             reg: Register16::BC,
         },
         0xC6 => Instruction::AddAWithLiteral {
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0xC7 => Instruction::Reset { offset: 0x00 },
         0xC8 => Instruction::Return {
@@ -569,11 +577,11 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         0xC9 => Instruction::Return { condition: None },
         0xCA => Instruction::JumpAbsolute {
             condition: Some(JumpCondition::Zero),
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
         },
         0xCB => {
             // prefix 0xCB:
-            match cpu.fetch_and_advance() {
+            match stream.fetch_and_advance() {
                 opcode @ 0x00..=0x3F => decode_rotate(opcode),
                 opcode @ 0x40..=0x7F => decode_bit_test(opcode),
                 opcode @ 0x80..=0xBF => decode_reset_bit(opcode),
@@ -582,14 +590,14 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         }
         0xCC => Instruction::CallAddr {
             condition: Some(JumpCondition::Zero),
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
         },
         0xCD => Instruction::CallAddr {
             condition: None,
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
         },
         0xCE => Instruction::AdcAWithLiteral {
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0xCF => Instruction::Reset { offset: 0x08 },
         0xD0 => Instruction::Return {
@@ -600,18 +608,18 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         },
         0xD2 => Instruction::JumpAbsolute {
             condition: Some(JumpCondition::NonCarry),
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
         },
         // 0xD3 => nothing
         0xD4 => Instruction::CallAddr {
             condition: Some(JumpCondition::NonCarry),
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
         },
         0xD5 => Instruction::PushReg16 {
             reg: Register16::DE,
         },
         0xD6 => Instruction::SubAWithLiteral {
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0xD7 => Instruction::Reset { offset: 0x10 },
         0xD8 => Instruction::Return {
@@ -620,20 +628,20 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
         0xD9 => Instruction::ReturnInterrupt,
         0xDA => Instruction::JumpAbsolute {
             condition: Some(JumpCondition::Carry),
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
         },
         // 0xDB => nothing
         0xDC => Instruction::CallAddr {
             condition: Some(JumpCondition::Carry),
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
         },
         // 0xDD => nothing
         0xDE => Instruction::SbcAWithLiteral {
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0xDF => Instruction::Reset { offset: 0x18 },
         0xE0 => Instruction::WriteReg8ValueAtZeroPageOffsetLiteral {
-            lit_offset: cpu.fetch_and_advance(),
+            lit_offset: stream.fetch_and_advance(),
             reg: Register8::A,
         },
         0xE1 => Instruction::PopReg16 {
@@ -649,30 +657,30 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
             reg: Register16::HL,
         },
         0xE6 => Instruction::AndAWithLiteral {
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0xE7 => Instruction::Reset { offset: 0x20 },
         0xE8 => Instruction::AddOffsetToReg16 {
             reg: Register16::SP,
-            offset: cpu.fetch_and_advance() as i8,
+            offset: stream.fetch_and_advance() as i8,
         },
         0xE9 => Instruction::JumpRegister16 {
             reg: Register16::HL,
         },
         0xEA => Instruction::WriteReg8ValueAtAddress {
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
             reg: Register8::A,
         },
         // 0xEB => nothing
         // 0xEC => nothing
         // 0xED => nothing
         0xEE => Instruction::XorAWithLiteral {
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0xEF => Instruction::Reset { offset: 0x28 },
         0xF0 => Instruction::ReadZeroPageOffsetLiteralToReg8 {
             reg: Register8::A,
-            lit_offset: cpu.fetch_and_advance(),
+            lit_offset: stream.fetch_and_advance(),
         },
         0xF1 => Instruction::PopReg16 {
             reg: Register16::AF,
@@ -687,30 +695,30 @@ pub fn decode_instruction<M: Memory>(cpu: &mut CPU<M>) -> Instruction {
             reg: Register16::AF,
         },
         0xF6 => Instruction::OrAWithLiteral {
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0xF7 => Instruction::Reset { offset: 0x30 },
         0xF8 => Instruction::LoadAddressOffsetIntoReg16 {
             dest: Register16::HL,
             base: Register16::SP,
-            offset: cpu.fetch_and_advance() as i8,
+            offset: stream.fetch_and_advance() as i8,
         },
         0xF9 => Instruction::Move16Bits {
             dest: Register16::SP,
             src: Register16::HL,
         },
         0xFA => Instruction::ReadAtAddressToReg8 {
-            addr: cpu.fetch_and_advance_u16(),
+            addr: stream.fetch_and_advance_u16(),
             reg: Register8::A,
         },
         0xFB => Instruction::EnableInterrupts,
         // 0xFC => nothing
         // 0xFD => nothing
         0xFE => Instruction::CompareAWithLiteral {
-            literal: cpu.fetch_and_advance(),
+            literal: stream.fetch_and_advance(),
         },
         0xFF => Instruction::Reset { offset: 0x38 },
-        _ => panic!("Unknown opcode {:#x} at {:#x}", opcode, pc),
+        _ => Instruction::IllegalOpcode(opcode),
     }
 }
 
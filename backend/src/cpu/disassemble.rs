@@ -0,0 +1,100 @@
+use std::{fmt, ops::Range};
+
+use super::{
+    decode::{self, ByteStream},
+    Instruction,
+};
+use crate::memory::{Memory, MMU};
+
+pub struct DisassembledInstruction {
+    pub addr: u16,
+    /// ROM bank the instruction is read from, when known.
+    pub bank: Option<usize>,
+    pub bytes: Vec<u8>,
+    pub instruction: Instruction,
+    /// Bank and address a `CALL` or `JP` to the switchable ROM region lands on.
+    pub target: Option<(usize, u16)>,
+}
+
+impl fmt::Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02x}:{:04x}  ", bank, self.addr)?,
+            None => write!(f, "{:04x}  ", self.addr)?,
+        }
+        write!(f, "{}", self.instruction.disassemble_with_pc(self.addr))?;
+        if let Some((bank, addr)) = self.target {
+            write!(f, "  ; -> {:02x}:{:04x}", bank, addr)?;
+        }
+        Ok(())
+    }
+}
+
+struct MemoryStream<'a, M> {
+    memory: &'a M,
+    addr: u16,
+}
+
+impl<'a, M: Memory> ByteStream for MemoryStream<'a, M> {
+    fn fetch_and_advance(&mut self) -> u8 {
+        let byte = self.memory.read_memory(self.addr);
+        self.addr = self.addr.wrapping_add(1);
+        byte
+    }
+}
+
+pub fn disassemble_range<M: Memory>(memory: &M, range: Range<u16>) -> Vec<DisassembledInstruction> {
+    disassemble(memory, range, |_| None)
+}
+
+/// Same as `disassemble_range`, with every address in ROM labeled with the
+/// bank currently mapped there.
+pub fn disassemble_range_banked(mmu: &MMU, range: Range<u16>) -> Vec<DisassembledInstruction> {
+    disassemble(mmu, range, |addr| match addr {
+        0x0000..=0x3FFF => Some(0),
+        0x4000..=0x7FFF => Some(mmu.current_rom_bank()),
+        _ => None,
+    })
+}
+
+fn disassemble<M: Memory>(
+    memory: &M,
+    range: Range<u16>,
+    bank_of: impl Fn(u16) -> Option<usize>,
+) -> Vec<DisassembledInstruction> {
+    let mut lines = Vec::new();
+    let mut addr = range.start as u32;
+
+    while addr < range.end as u32 {
+        let mut stream = MemoryStream {
+            memory,
+            addr: addr as u16,
+        };
+        let instruction = decode::decode_instruction(&mut stream);
+        let len = stream.addr.wrapping_sub(addr as u16) as u32;
+        let bytes = (0..len)
+            .map(|offset| memory.read_memory((addr + offset) as u16))
+            .collect();
+
+        let target = match instruction {
+            Instruction::CallAddr { addr: target, .. }
+            | Instruction::JumpAbsolute { addr: target, .. }
+                if (0x4000..=0x7FFF).contains(&target) =>
+            {
+                bank_of(target).map(|bank| (bank, target))
+            }
+            _ => None,
+        };
+
+        lines.push(DisassembledInstruction {
+            addr: addr as u16,
+            bank: bank_of(addr as u16),
+            bytes,
+            instruction,
+            target,
+        });
+        addr += len;
+    }
+
+    lines
+}
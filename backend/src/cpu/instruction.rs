@@ -1,6 +1,6 @@
 use std::fmt;
 
-use super::micro_op::simpl;
+use super::micro_op::{micro_ops, simpl, MicroOps};
 use super::{Destination8Bits, MicroOp, Register16, Register8, Source8bits};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -282,6 +282,8 @@ pub enum Instruction {
     DisableInterrupts,
     Halt,
     Stop,
+    /// One of the opcodes not used by the CPU, it locks up until reset.
+    IllegalOpcode(u8),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -317,6 +319,24 @@ pub enum PrePostOperation {
     Inc,
 }
 
+impl Instruction {
+    /// Same as the `Display` format, with the target of relative jumps
+    /// resolved for an instruction at `pc`.
+    pub fn disassemble_with_pc(&self, pc: u16) -> String {
+        match self {
+            Instruction::JumpRelative { condition, offset } => {
+                // the offset is relative to the end of the 2 bytes instruction
+                let target = pc.wrapping_add(2).wrapping_add(*offset as u16);
+                match condition {
+                    Some(cond) => format!("JR {}, ${:04x}", cond, target),
+                    None => format!("JR ${:04x}", target),
+                }
+            }
+            _ => self.to_string(),
+        }
+    }
+}
+
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -336,7 +356,7 @@ impl fmt::Display for Instruction {
                 write!(f, "LD {}, ${:04x}", reg, literal)
             }
             Instruction::LoadAddressOffsetIntoReg16 { dest, base, offset } => {
-                write!(f, "LD {}, {}+{}", dest, base, offset)
+                write!(f, "LD {}, {}{:+}", dest, base, offset)
             }
             Instruction::AndAWithReg8 { reg } => {
                 write!(f, "AND A, {}", reg)
@@ -378,7 +398,7 @@ impl fmt::Display for Instruction {
                 write!(f, "ADD HL, {}", reg)
             }
             Instruction::AddOffsetToReg16 { reg, offset } => {
-                write!(f, "ADD {}, {}", reg, offset) // check format for offset
+                write!(f, "ADD {}, {:+}", reg, offset)
             }
             Instruction::AdcAWithReg8 { reg } => {
                 write!(f, "ADC A, {}", reg)
@@ -486,9 +506,9 @@ impl fmt::Display for Instruction {
             }
             Instruction::JumpRelative { condition, offset } => {
                 if let Some(cond) = condition {
-                    write!(f, "JR {}, {}", cond, offset) // TODO: change the offset format
+                    write!(f, "JR {}, {:+}", cond, offset)
                 } else {
-                    write!(f, "JR {}", offset) // TODO: change the offset format
+                    write!(f, "JR {:+}", offset)
                 }
             }
             Instruction::JumpAbsolute { condition, addr } => {
@@ -598,17 +618,18 @@ impl fmt::Display for Instruction {
             Instruction::DisableInterrupts => write!(f, "DI"),
             Instruction::Halt => write!(f, "HALT"),
             Instruction::Stop => write!(f, "STOP"),
+            Instruction::IllegalOpcode(opcode) => write!(f, "DB ${:02x}", opcode),
         }
     }
 }
 
 impl Instruction {
-    pub fn to_micro_ops(self) -> Vec<MicroOp> {
+    pub fn to_micro_ops(self) -> MicroOps {
         match self {
-            Instruction::Nop => vec![MicroOp::Nop],
-            Instruction::Move { dest, src } => vec![simpl::move_micro_op(dest, src)],
+            Instruction::Nop => micro_ops![MicroOp::Nop],
+            Instruction::Move { dest, src } => micro_ops![simpl::move_micro_op(dest, src)],
             Instruction::Move16Bits { dest, src } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Move16Bits {
                         destination: dest,
@@ -617,17 +638,17 @@ impl Instruction {
                 ]
             }
             Instruction::LoadLiteralIntoReg8 { reg, literal } => {
-                vec![MicroOp::Nop, simpl::load_literal_into_reg8(literal, reg)]
+                micro_ops![MicroOp::Nop, simpl::load_literal_into_reg8(literal, reg)]
             }
             Instruction::LoadLiteralIntoReg16 { reg, literal } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     simpl::load_literal_into_reg8(literal as u8, reg.lower_half()),
                     simpl::load_literal_into_reg8((literal >> 8) as u8, reg.higher_half()),
                 ]
             }
             Instruction::LoadAddressOffsetIntoReg16 { dest, base, offset } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::AddOffsetToReg16IntoReg16 {
@@ -638,9 +659,9 @@ impl Instruction {
                     },
                 ]
             }
-            Instruction::AndAWithReg8 { reg } => vec![MicroOp::AndA { rhs: reg.into() }],
+            Instruction::AndAWithReg8 { reg } => micro_ops![MicroOp::AndA { rhs: reg.into() }],
             Instruction::AndAWithLiteral { literal } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::AndA {
                         rhs: literal.into(),
@@ -648,38 +669,38 @@ impl Instruction {
                 ]
             }
             Instruction::AndAWithIndirect { addr } => {
-                vec![MicroOp::Nop, MicroOp::AndA { rhs: addr.into() }]
+                micro_ops![MicroOp::Nop, MicroOp::AndA { rhs: addr.into() }]
             }
-            Instruction::OrAWithReg8 { reg } => vec![MicroOp::OrA { rhs: reg.into() }],
+            Instruction::OrAWithReg8 { reg } => micro_ops![MicroOp::OrA { rhs: reg.into() }],
             Instruction::OrAWithIndirect { addr } => {
-                vec![MicroOp::Nop, MicroOp::OrA { rhs: addr.into() }]
+                micro_ops![MicroOp::Nop, MicroOp::OrA { rhs: addr.into() }]
             }
-            Instruction::OrAWithLiteral { literal } => vec![
+            Instruction::OrAWithLiteral { literal } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::OrA {
                     rhs: literal.into(),
                 },
             ],
             Instruction::XorAWithReg8 { reg } => {
-                vec![MicroOp::XorA { rhs: reg.into() }]
+                micro_ops![MicroOp::XorA { rhs: reg.into() }]
             }
             Instruction::XorAWithIndirect { addr } => {
-                vec![MicroOp::Nop, MicroOp::XorA { rhs: addr.into() }]
+                micro_ops![MicroOp::Nop, MicroOp::XorA { rhs: addr.into() }]
             }
             Instruction::XorAWithLiteral { literal } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::XorA {
                         rhs: literal.into(),
                     },
                 ]
             }
-            Instruction::AddAWithReg8 { reg } => vec![MicroOp::AddA { rhs: reg.into() }],
+            Instruction::AddAWithReg8 { reg } => micro_ops![MicroOp::AddA { rhs: reg.into() }],
             Instruction::AddAWithIndirect { addr } => {
-                vec![MicroOp::Nop, MicroOp::AddA { rhs: addr.into() }]
+                micro_ops![MicroOp::Nop, MicroOp::AddA { rhs: addr.into() }]
             }
             Instruction::AddAWithLiteral { literal } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::AddA {
                         rhs: literal.into(),
@@ -687,10 +708,10 @@ impl Instruction {
                 ]
             }
             Instruction::AddHLWithReg { reg } => {
-                vec![MicroOp::Nop, MicroOp::AddHL { rhs: reg }]
+                micro_ops![MicroOp::Nop, MicroOp::AddHL { rhs: reg }]
             }
             Instruction::AddOffsetToReg16 { reg, offset } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Nop,
@@ -703,10 +724,10 @@ impl Instruction {
                 ]
             }
             Instruction::AdcAWithReg8 { reg } => {
-                vec![MicroOp::AdcA { rhs: reg.into() }]
+                micro_ops![MicroOp::AdcA { rhs: reg.into() }]
             }
             Instruction::AdcAWithLiteral { literal } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::AdcA {
                         rhs: literal.into(),
@@ -714,13 +735,13 @@ impl Instruction {
                 ]
             }
             Instruction::AdcAWithIndirect { addr } => {
-                vec![MicroOp::Nop, MicroOp::AdcA { rhs: addr.into() }]
+                micro_ops![MicroOp::Nop, MicroOp::AdcA { rhs: addr.into() }]
             }
             Instruction::SubAWithReg8 { reg } => {
-                vec![MicroOp::SubA { rhs: reg.into() }]
+                micro_ops![MicroOp::SubA { rhs: reg.into() }]
             }
             Instruction::SubAWithLiteral { literal } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::SubA {
                         rhs: literal.into(),
@@ -728,13 +749,13 @@ impl Instruction {
                 ]
             }
             Instruction::SubAWithIndirect { addr } => {
-                vec![MicroOp::Nop, MicroOp::SubA { rhs: addr.into() }]
+                micro_ops![MicroOp::Nop, MicroOp::SubA { rhs: addr.into() }]
             }
             Instruction::SbcAWithReg8 { reg } => {
-                vec![MicroOp::SbcA { rhs: reg.into() }]
+                micro_ops![MicroOp::SbcA { rhs: reg.into() }]
             }
             Instruction::SbcAWithLiteral { literal } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::SbcA {
                         rhs: literal.into(),
@@ -742,12 +763,12 @@ impl Instruction {
                 ]
             }
             Instruction::SbcAWithIndirect { addr } => {
-                vec![MicroOp::Nop, MicroOp::SbcA { rhs: addr.into() }]
+                micro_ops![MicroOp::Nop, MicroOp::SbcA { rhs: addr.into() }]
             }
-            Instruction::Daa => vec![MicroOp::Daa],
-            Instruction::ComplementA => vec![MicroOp::ComplementA],
+            Instruction::Daa => micro_ops![MicroOp::Daa],
+            Instruction::ComplementA => micro_ops![MicroOp::ComplementA],
             Instruction::WriteReg8ValueAtAddress { addr, reg } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Nop,
@@ -758,7 +779,7 @@ impl Instruction {
                 ]
             }
             Instruction::WriteReg16ValueAtAddress { addr, reg } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Nop,
@@ -767,13 +788,13 @@ impl Instruction {
                         source: reg.lower_half().into(),
                     },
                     MicroOp::Move8Bits {
-                        destination: Destination8Bits::Address(addr + 1),
+                        destination: Destination8Bits::Address(addr.wrapping_add(1)),
                         source: reg.higher_half().into(),
                     },
                 ]
             }
             Instruction::WriteLiteralAtIndirect { addr, literal } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Move8Bits {
@@ -783,7 +804,7 @@ impl Instruction {
                 ]
             }
             Instruction::WriteReg8ValueAtIndirect { addr, reg, post_op } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::WriteMem {
                         addr,
@@ -794,11 +815,11 @@ impl Instruction {
                 ]
             }
             Instruction::WriteReg8ValueAtZeroPageOffsetReg8 { reg_offset, reg } => {
-                vec![MicroOp::Nop, MicroOp::WriteMemZeroPage { reg_offset, reg }]
+                micro_ops![MicroOp::Nop, MicroOp::WriteMemZeroPage { reg_offset, reg }]
             }
 
             Instruction::WriteReg8ValueAtZeroPageOffsetLiteral { lit_offset, reg } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Move8Bits {
@@ -807,7 +828,7 @@ impl Instruction {
                     },
                 ]
             }
-            Instruction::ReadZeroPageOffsetLiteralToReg8 { lit_offset, reg } => vec![
+            Instruction::ReadZeroPageOffsetLiteralToReg8 { lit_offset, reg } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::Nop,
                 MicroOp::Move8Bits {
@@ -815,14 +836,14 @@ impl Instruction {
                     source: Source8bits::Address(0xFF00 + lit_offset as u16),
                 },
             ],
-            Instruction::ReadZeroPageOffsetReg8ToReg8 { offset, reg } => vec![
+            Instruction::ReadZeroPageOffsetReg8ToReg8 { offset, reg } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::Move8Bits {
                     destination: Destination8Bits::Register(reg),
                     source: Source8bits::ZeroPageOffsetReg8(offset),
                 },
             ],
-            Instruction::ReadAtAddressToReg8 { reg, addr } => vec![
+            Instruction::ReadAtAddressToReg8 { reg, addr } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::Nop,
                 MicroOp::Nop,
@@ -833,9 +854,9 @@ impl Instruction {
             ],
 
             Instruction::ReadIndirectToReg8 { reg, addr, post_op } => {
-                vec![MicroOp::Nop, MicroOp::ReadMem { reg, addr, post_op }]
+                micro_ops![MicroOp::Nop, MicroOp::ReadMem { reg, addr, post_op }]
             }
-            Instruction::PushReg16 { reg } => vec![
+            Instruction::PushReg16 { reg } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::Nop,
                 MicroOp::WriteMem {
@@ -851,7 +872,7 @@ impl Instruction {
                     post_op: None,
                 },
             ],
-            Instruction::PopReg16 { reg } => vec![
+            Instruction::PopReg16 { reg } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::ReadMem {
                     reg: reg.lower_half(),
@@ -864,28 +885,28 @@ impl Instruction {
                     post_op: Some(PrePostOperation::Inc),
                 },
             ],
-            Instruction::BitTest { reg, bit } => vec![
+            Instruction::BitTest { reg, bit } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::BitTest {
                     reg: reg.into(),
                     bit,
                 },
             ],
-            Instruction::ResetBit { reg, bit } => vec![
+            Instruction::ResetBit { reg, bit } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::ResetBit {
                     reg: reg.into(),
                     bit,
                 },
             ],
-            Instruction::SetBit { reg, bit } => vec![
+            Instruction::SetBit { reg, bit } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::SetBit {
                     reg: reg.into(),
                     bit,
                 },
             ],
-            Instruction::BitTestIndirect { addr, bit } => vec![
+            Instruction::BitTestIndirect { addr, bit } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::Nop,
                 MicroOp::BitTest {
@@ -893,7 +914,7 @@ impl Instruction {
                     bit,
                 },
             ],
-            Instruction::ResetBitIndirect { addr, bit } => vec![
+            Instruction::ResetBitIndirect { addr, bit } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::Nop,
                 MicroOp::Nop,
@@ -902,7 +923,7 @@ impl Instruction {
                     bit,
                 },
             ],
-            Instruction::SetBitIndirect { addr, bit } => vec![
+            Instruction::SetBitIndirect { addr, bit } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::Nop,
                 MicroOp::Nop,
@@ -914,35 +935,34 @@ impl Instruction {
             Instruction::CallAddr { condition, addr } => {
                 if let Some(cond) = condition {
                     //
-                    vec![
+                    micro_ops![
                         // TODO: check if this is really the correct order
                         MicroOp::Nop,
                         MicroOp::Nop,
                         MicroOp::CheckFlags {
                             condition: cond,
-                            true_ops: vec![
-                                MicroOp::WriteMem {
-                                    addr: Register16::SP,
-                                    reg: Register8::PCHigh,
-                                    pre_op: Some(PrePostOperation::Dec),
-                                    post_op: None,
-                                },
-                                MicroOp::WriteMem {
-                                    addr: Register16::SP,
-                                    reg: Register8::PCLow,
-                                    pre_op: Some(PrePostOperation::Dec),
-                                    post_op: None,
-                                },
-                                MicroOp::LoadReg16Lit {
-                                    reg: Register16::PC,
-                                    literal: addr,
-                                },
-                            ],
-                            false_ops: vec![],
+                            true_count: 3,
+                            false_count: 0,
+                        },
+                        MicroOp::WriteMem {
+                            addr: Register16::SP,
+                            reg: Register8::PCHigh,
+                            pre_op: Some(PrePostOperation::Dec),
+                            post_op: None,
+                        },
+                        MicroOp::WriteMem {
+                            addr: Register16::SP,
+                            reg: Register8::PCLow,
+                            pre_op: Some(PrePostOperation::Dec),
+                            post_op: None,
+                        },
+                        MicroOp::LoadReg16Lit {
+                            reg: Register16::PC,
+                            literal: addr,
                         },
                     ]
                 } else {
-                    vec![
+                    micro_ops![
                         // TODO: check if this is really the correct order
                         MicroOp::Nop,
                         MicroOp::Nop,
@@ -968,28 +988,27 @@ impl Instruction {
             }
             Instruction::Return { condition } => {
                 if let Some(cond) = condition {
-                    vec![
+                    micro_ops![
                         MicroOp::Nop,
                         MicroOp::CheckFlags {
                             condition: cond,
-                            true_ops: vec![
-                                MicroOp::Nop,
-                                MicroOp::ReadMem {
-                                    reg: Register8::PCLow,
-                                    addr: Register16::SP,
-                                    post_op: Some(PrePostOperation::Inc),
-                                },
-                                MicroOp::ReadMem {
-                                    reg: Register8::PCHigh,
-                                    addr: Register16::SP,
-                                    post_op: Some(PrePostOperation::Inc),
-                                },
-                            ],
-                            false_ops: vec![],
+                            true_count: 3,
+                            false_count: 0,
+                        },
+                        MicroOp::Nop,
+                        MicroOp::ReadMem {
+                            reg: Register8::PCLow,
+                            addr: Register16::SP,
+                            post_op: Some(PrePostOperation::Inc),
+                        },
+                        MicroOp::ReadMem {
+                            reg: Register8::PCHigh,
+                            addr: Register16::SP,
+                            post_op: Some(PrePostOperation::Inc),
                         },
                     ]
                 } else {
-                    vec![
+                    micro_ops![
                         MicroOp::Nop,
                         MicroOp::Nop,
                         MicroOp::ReadMem {
@@ -1006,7 +1025,7 @@ impl Instruction {
                 }
             }
             Instruction::ReturnInterrupt => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::ReadMem {
                         reg: Register8::PCLow,
@@ -1023,33 +1042,34 @@ impl Instruction {
             }
             Instruction::JumpRelative { condition, offset } => {
                 if let Some(cond) = condition {
-                    vec![
+                    micro_ops![
                         MicroOp::Nop,
                         MicroOp::CheckFlags {
                             condition: cond,
-                            true_ops: vec![simpl::jump_relative(offset)],
-                            false_ops: vec![],
+                            true_count: 1,
+                            false_count: 0,
                         },
+                        simpl::jump_relative(offset),
                     ]
                 } else {
-                    vec![MicroOp::Nop, MicroOp::Nop, simpl::jump_relative(offset)]
+                    micro_ops![MicroOp::Nop, MicroOp::Nop, simpl::jump_relative(offset)]
                 }
             }
             Instruction::JumpAbsolute { condition, addr } => {
                 if let Some(cond) = condition {
-                    vec![
+                    micro_ops![
                         MicroOp::Nop,
                         MicroOp::CheckFlags {
                             condition: cond,
-                            true_ops: vec![
-                                simpl::load_literal_into_reg8(addr as u8, Register8::PCLow),
-                                simpl::load_literal_into_reg8((addr >> 8) as u8, Register8::PCHigh),
-                            ],
-                            false_ops: vec![MicroOp::Nop],
+                            true_count: 2,
+                            false_count: 1,
                         },
+                        simpl::load_literal_into_reg8(addr as u8, Register8::PCLow),
+                        simpl::load_literal_into_reg8((addr >> 8) as u8, Register8::PCHigh),
+                        MicroOp::Nop,
                     ]
                 } else {
-                    vec![
+                    micro_ops![
                         MicroOp::Nop,
                         MicroOp::Nop,
                         simpl::load_literal_into_reg8(addr as u8, Register8::PCLow),
@@ -1057,11 +1077,11 @@ impl Instruction {
                     ]
                 }
             }
-            Instruction::JumpRegister16 { reg } => vec![MicroOp::Move16Bits {
+            Instruction::JumpRegister16 { reg } => micro_ops![MicroOp::Move16Bits {
                 destination: Register16::PC,
                 source: reg,
             }],
-            Instruction::Reset { offset } => vec![
+            Instruction::Reset { offset } => micro_ops![
                 MicroOp::Nop,
                 MicroOp::WriteMem {
                     addr: Register16::SP,
@@ -1080,47 +1100,49 @@ impl Instruction {
                     literal: offset,
                 },
             ],
-            Instruction::IncReg16 { reg } => vec![MicroOp::Nop, MicroOp::IncReg16 { reg }],
-            Instruction::IncReg8 { reg } => vec![MicroOp::Inc { reg: reg.into() }],
+            Instruction::IncReg16 { reg } => micro_ops![MicroOp::Nop, MicroOp::IncReg16 { reg }],
+            Instruction::IncReg8 { reg } => micro_ops![MicroOp::Inc { reg: reg.into() }],
             Instruction::IncIndirect { addr } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Inc { reg: addr.into() },
                 ]
             }
-            Instruction::DecReg16 { reg } => vec![MicroOp::Nop, MicroOp::DecReg16 { reg }],
-            Instruction::DecReg8 { reg } => vec![MicroOp::Dec { reg: reg.into() }],
+            Instruction::DecReg16 { reg } => micro_ops![MicroOp::Nop, MicroOp::DecReg16 { reg }],
+            Instruction::DecReg8 { reg } => micro_ops![MicroOp::Dec { reg: reg.into() }],
             Instruction::DecIndirect { addr } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Dec { reg: addr.into() },
                 ]
             }
             Instruction::CompareAWithLiteral { literal } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::CompareA {
                         rhs: literal.into(),
                     },
                 ]
             }
-            Instruction::CompareAWithReg { reg } => vec![MicroOp::CompareA { rhs: reg.into() }],
+            Instruction::CompareAWithReg { reg } => {
+                micro_ops![MicroOp::CompareA { rhs: reg.into() }]
+            }
             Instruction::CompareAWithIndirect { addr } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::CompareA {
                         rhs: Source8bits::Indirect(addr),
                     },
                 ]
             }
-            Instruction::RotateLeftThroughCarryA => vec![MicroOp::RotateLeftThroughCarry {
+            Instruction::RotateLeftThroughCarryA => micro_ops![MicroOp::RotateLeftThroughCarry {
                 reg: Register8::A.into(),
                 set_zero: false,
             }],
             Instruction::RotateLeftThroughCarry { reg } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::RotateLeftThroughCarry {
                         reg: reg.into(),
@@ -1129,7 +1151,7 @@ impl Instruction {
                 ]
             }
             Instruction::RotateLeftThroughCarryWithIndirect { addr } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Nop,
@@ -1139,12 +1161,12 @@ impl Instruction {
                     },
                 ]
             }
-            Instruction::RotateRightThroughCarryA => vec![MicroOp::RotateRightThroughCarry {
+            Instruction::RotateRightThroughCarryA => micro_ops![MicroOp::RotateRightThroughCarry {
                 reg: Register8::A.into(),
                 set_zero: false,
             }],
             Instruction::RotateRightThroughCarry { reg } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::RotateRightThroughCarry {
                         reg: reg.into(),
@@ -1153,7 +1175,7 @@ impl Instruction {
                 ]
             }
             Instruction::RotateRightThroughCarryWithIndirect { addr } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Nop,
@@ -1163,12 +1185,12 @@ impl Instruction {
                     },
                 ]
             }
-            Instruction::RotateLeftA => vec![MicroOp::RotateLeft {
+            Instruction::RotateLeftA => micro_ops![MicroOp::RotateLeft {
                 reg: Register8::A.into(),
                 set_zero: false,
             }],
             Instruction::RotateLeft { reg } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::RotateLeft {
                         reg: reg.into(),
@@ -1177,7 +1199,7 @@ impl Instruction {
                 ]
             }
             Instruction::RotateLeftWithIndirect { addr } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Nop,
@@ -1187,12 +1209,12 @@ impl Instruction {
                     },
                 ]
             }
-            Instruction::RotateRightA => vec![MicroOp::RotateRight {
+            Instruction::RotateRightA => micro_ops![MicroOp::RotateRight {
                 reg: Register8::A.into(),
                 set_zero: false,
             }],
             Instruction::RotateRight { reg } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::RotateRight {
                         reg: reg.into(),
@@ -1201,7 +1223,7 @@ impl Instruction {
                 ]
             }
             Instruction::RotateRightWithIndirect { addr } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Nop,
@@ -1212,13 +1234,13 @@ impl Instruction {
                 ]
             }
             Instruction::ShiftLeftIntoCarry { reg } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::ShiftLeftIntoCarry { reg: reg.into() },
                 ]
             }
             Instruction::ShiftLeftIntoCarryWithIndirect { addr } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Nop,
@@ -1226,13 +1248,13 @@ impl Instruction {
                 ]
             }
             Instruction::ShiftRightWithZeroIntoCarry { reg } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::ShiftRightWithZeroIntoCarry { reg: reg.into() },
                 ]
             }
             Instruction::ShiftRightWithZeroIntoCarryWithIndirect { addr } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Nop,
@@ -1240,13 +1262,13 @@ impl Instruction {
                 ]
             }
             Instruction::ShiftRightWithSignIntoCarry { reg } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::ShiftRightWithSignIntoCarry { reg: reg.into() },
                 ]
             }
             Instruction::ShiftRightWithSignIntoCarryWithIndirect { addr } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Nop,
@@ -1254,22 +1276,23 @@ impl Instruction {
                 ]
             }
             Instruction::SwapReg8 { reg } => {
-                vec![MicroOp::Nop, MicroOp::SwapReg8 { reg: reg.into() }]
+                micro_ops![MicroOp::Nop, MicroOp::SwapReg8 { reg: reg.into() }]
             }
             Instruction::SwapIndirect { addr } => {
-                vec![
+                micro_ops![
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::Nop,
                     MicroOp::SwapReg8 { reg: addr.into() },
                 ]
             }
-            Instruction::SetCarryFlag => vec![MicroOp::SetCarryFlag],
-            Instruction::ComplementCarryFlag => vec![MicroOp::ComplementCarryFlag],
-            Instruction::EnableInterrupts => vec![MicroOp::EnableInterrupts],
-            Instruction::DisableInterrupts => vec![MicroOp::DisableInterrupts],
-            Instruction::Halt => vec![MicroOp::Halt],
-            Instruction::Stop => vec![MicroOp::Stop],
+            Instruction::SetCarryFlag => micro_ops![MicroOp::SetCarryFlag],
+            Instruction::ComplementCarryFlag => micro_ops![MicroOp::ComplementCarryFlag],
+            Instruction::EnableInterrupts => micro_ops![MicroOp::EnableInterruptsDelayed],
+            Instruction::DisableInterrupts => micro_ops![MicroOp::DisableInterrupts],
+            Instruction::Halt => micro_ops![MicroOp::Halt],
+            Instruction::Stop => micro_ops![MicroOp::Stop],
+            Instruction::IllegalOpcode(opcode) => micro_ops![MicroOp::Lock { opcode }],
         }
     }
 }
@@ -52,7 +52,7 @@ impl From<Register16> for Reg8OrIndirect {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MicroOp {
     Nop,
     Move8Bits {
@@ -169,18 +169,61 @@ pub enum MicroOp {
     SwapReg8 {
         reg: Reg8OrIndirect,
     },
+    /// Followed in the pipeline by `true_count` ops run if the condition holds
+    /// then `false_count` ops run otherwise, the other ones are dropped.
     CheckFlags {
         condition: JumpCondition,
-        true_ops: Vec<MicroOp>,
-        false_ops: Vec<MicroOp>,
+        true_count: u8,
+        false_count: u8,
     },
     SetCarryFlag,
     ComplementCarryFlag,
     EnableInterrupts,
+    /// EI, interrupts are enabled once the next instruction is decoded
+    EnableInterruptsDelayed,
     DisableInterrupts,
     Halt,
     Stop,
+    Lock {
+        opcode: u8,
+    },
+}
+
+const MAX_MICRO_OPS: usize = 8;
+
+/// The micro ops of one instruction, stored inline to avoid an allocation on
+/// each decode.
+#[derive(Debug, Clone, Copy)]
+pub struct MicroOps {
+    ops: [MicroOp; MAX_MICRO_OPS],
+    len: usize,
+}
+
+impl MicroOps {
+    pub fn from_slice(slice: &[MicroOp]) -> Self {
+        let mut ops = [MicroOp::Nop; MAX_MICRO_OPS];
+        ops[..slice.len()].copy_from_slice(slice);
+        MicroOps {
+            ops,
+            len: slice.len(),
+        }
+    }
+}
+
+impl std::ops::Deref for MicroOps {
+    type Target = [MicroOp];
+
+    fn deref(&self) -> &[MicroOp] {
+        &self.ops[..self.len]
+    }
+}
+
+macro_rules! micro_ops {
+    ($($op:expr),* $(,)?) => {
+        $crate::cpu::micro_op::MicroOps::from_slice(&[$($op),*])
+    };
 }
+pub(crate) use micro_ops;
 
 pub mod simpl {
     use super::*;
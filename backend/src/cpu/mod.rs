@@ -1,23 +1,86 @@
 use crate::{
     interrupt::{IntKind, InterruptControllerPtr},
-    memory::Memory,
+    memory::{Memory, OamBugAccess},
+    model::Model,
     utils::combine,
 };
 use bitflags::bitflags;
 use std::collections::VecDeque;
 
+mod bus_trace;
+pub mod debugger;
 mod decode;
+pub mod disassemble;
 mod instruction;
 mod micro_op;
 mod register;
 
-use instruction::{Instruction, JumpCondition};
-use log::{debug, warn};
+pub use bus_trace::{BusAccess, BusAccessKind};
+use debugger::Debugger;
+pub use instruction::Instruction;
+use instruction::JumpCondition;
+use log::{debug, error, warn};
 use micro_op::{Destination8Bits, MicroOp, Reg8OrIndirect, Source8bits};
-use register::{Register16, Register8};
+pub use register::{Register16, Register8};
 
 use self::instruction::PrePostOperation;
 
+const INSTRUCTION_HISTORY_SIZE: usize = 16;
+/// The base opcodes followed by the 0xCB prefixed ones.
+pub const OPCODE_COUNT: usize = 512;
+
+// IO registers after the boot ROM, see the "Power Up Sequence" of the Pan Docs
+const POST_BOOT_IO_REGS: &[(u16, u8)] = &[
+    (0xFF00, 0xCF), // P1
+    (0xFF01, 0x00), // SB
+    (0xFF02, 0x7E), // SC
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    // the other sound registers ignore writes while the APU is off
+    (0xFF26, 0xF1), // $F1-GB, $F0-SGB - NR52
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF14, 0xBF), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF17, 0x00), // NR22
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF21, 0x00), // NR42
+    (0xFF22, 0x00), // NR43
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF40, 0x91), // LCDC
+    (0xFF41, 0x85), // STAT
+    (0xFF42, 0x00), // SCY
+    (0xFF43, 0x00), // SCX
+    (0xFF45, 0x00), // LYC
+    (0xFF47, 0xFC), // BGP
+    (0xFF48, 0xFF), // OBP0
+    (0xFF49, 0xFF), // OBP1
+    (0xFF4A, 0x00), // WY
+    (0xFF4B, 0x00), // WX
+    (0xFFFF, 0x00), // IE
+];
+
+// CGB differences applied on top of POST_BOOT_IO_REGS
+const CGB_POST_BOOT_IO_REGS: &[(u16, u8)] = &[
+    (0xFF02, 0x7F), // SC
+    (0xFF4D, 0x7E), // KEY1
+    (0xFF4F, 0xFE), // VBK
+    (0xFF70, 0xF8), // SVBK
+];
+
 bitflags! {
     #[derive(Debug, Clone, Copy)]
     struct Flags: u8 {
@@ -28,6 +91,35 @@ bitflags! {
     }
 }
 
+/// Snapshot of the CPU registers, see `CPU::registers`. The low nibble of
+/// `f` is always 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Registers {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+/// Registers of a CPU between two instructions, see `CPU::save_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuState {
+    pub registers: Registers,
+    pub halted: bool,
+    pub stopped: bool,
+    pub locked: bool,
+    pub halt_bug: bool,
+    pub ime_pending: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct CPU<M: Memory> {
     memory: M,
@@ -46,9 +138,23 @@ pub struct CPU<M: Memory> {
     pub pc: u16,
 
     pipeline: VecDeque<MicroOp>,
+    history: VecDeque<(u16, Instruction)>,
+    opcode_coverage: Option<Box<[bool; OPCODE_COUNT]>>,
+    debugger: Option<Debugger>,
+    bus_trace: Option<Vec<BusAccess>>,
+    // M-cycles run by the instruction in the pipeline
+    instruction_cycles: u8,
+    last_instruction_cycles: u8,
     interrupt_controller: InterruptControllerPtr,
     halted: bool,
     stoped: bool,
+    /// HALT run with IME cleared and an interrupt pending: the CPU does not
+    /// halt and the next opcode byte is read twice
+    halt_bug: bool,
+    /// Set by EI, IME is only set after the following instruction
+    ime_pending: bool,
+    /// Set by an illegal opcode, nothing but a reset gets the CPU going again
+    locked: bool,
 }
 
 impl<M: Memory> CPU<M> {
@@ -66,12 +172,44 @@ impl<M: Memory> CPU<M> {
             pc: 0,
             flags: Flags::empty(),
             pipeline: VecDeque::new(),
+            history: VecDeque::with_capacity(INSTRUCTION_HISTORY_SIZE),
+            opcode_coverage: None,
+            debugger: None,
+            bus_trace: None,
+            instruction_cycles: 0,
+            last_instruction_cycles: 0,
             interrupt_controller,
             halted: false,
             stoped: false,
+            halt_bug: false,
+            ime_pending: false,
+            locked: false,
         }
     }
 
+    /// Back to the power-on state. The opcode coverage, the debugger and the
+    /// bus trace stay enabled, the breakpoints and the opcodes covered so far
+    /// are kept.
+    pub fn reset(&mut self)
+    where
+        M: Clone,
+    {
+        let mut debugger = self.debugger.take();
+        if let Some(debugger) = debugger.as_mut() {
+            debugger.clear_stop();
+        }
+        let mut bus_trace = self.bus_trace.take();
+        if let Some(trace) = bus_trace.as_mut() {
+            trace.clear();
+        }
+        *self = CPU {
+            opcode_coverage: self.opcode_coverage.take(),
+            debugger,
+            bus_trace,
+            ..CPU::new(self.memory.clone(), self.interrupt_controller.clone())
+        };
+    }
+
     pub fn load_reg8(&self, reg: Register8) -> u8 {
         match reg {
             Register8::A => self.reg_a,
@@ -148,68 +286,240 @@ impl<M: Memory> CPU<M> {
         }
     }
 
+    pub fn dump_registers(&self) -> String {
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X}",
+            self.reg_a,
+            self.flags.bits(),
+            self.reg_b,
+            self.reg_c,
+            self.reg_d,
+            self.reg_e,
+            self.reg_h,
+            self.reg_l,
+            self.sp,
+            self.pc
+        )
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            a: self.reg_a,
+            f: self.flags.bits(),
+            b: self.reg_b,
+            c: self.reg_c,
+            d: self.reg_d,
+            e: self.reg_e,
+            h: self.reg_h,
+            l: self.reg_l,
+            sp: self.sp,
+            pc: self.pc,
+        }
+    }
+
+    /// Overwrites every register, the low nibble of `f` is dropped. An
+    /// instruction in progress keeps running.
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.reg_a = registers.a;
+        self.flags = Flags::from_bits_truncate(registers.f);
+        self.reg_b = registers.b;
+        self.reg_c = registers.c;
+        self.reg_d = registers.d;
+        self.reg_e = registers.e;
+        self.reg_h = registers.h;
+        self.reg_l = registers.l;
+        self.sp = registers.sp;
+        self.pc = registers.pc;
+    }
+
+    /// Panics if an instruction is in progress, the state is only taken
+    /// between two instructions.
+    pub fn save_state(&self) -> CpuState {
+        assert!(self.is_pipeline_empty(), "CPU state saved mid-instruction");
+        CpuState {
+            registers: self.registers(),
+            halted: self.halted,
+            stopped: self.stoped,
+            locked: self.locked,
+            halt_bug: self.halt_bug,
+            ime_pending: self.ime_pending,
+        }
+    }
+
+    pub fn load_state(&mut self, state: &CpuState) {
+        self.pipeline.clear();
+        self.set_registers(state.registers);
+        self.halted = state.halted;
+        self.stoped = state.stopped;
+        self.locked = state.locked;
+        self.halt_bug = state.halt_bug;
+        self.ime_pending = state.ime_pending;
+    }
+
+    /// Last decoded instructions with their address, oldest first.
+    pub fn recent_instructions(&self) -> impl Iterator<Item = &(u16, Instruction)> {
+        self.history.iter()
+    }
+
+    /// Starts recording which opcodes are executed, see `opcode_coverage`.
+    pub fn set_opcode_coverage(&mut self, enabled: bool) {
+        self.opcode_coverage = enabled.then(|| Box::new([false; OPCODE_COUNT]));
+    }
+
+    /// Executed opcodes, indexed by opcode for the base ones and by 0x100 +
+    /// opcode for the 0xCB prefixed ones. All false if the recording is off.
+    pub fn opcode_coverage(&self) -> [bool; OPCODE_COUNT] {
+        self.opcode_coverage
+            .as_deref()
+            .copied()
+            .unwrap_or([false; OPCODE_COUNT])
+    }
+
+    fn record_opcode(&mut self, pc: u16) {
+        if let Some(coverage) = self.opcode_coverage.as_mut() {
+            let opcode = self.memory.read_memory(pc);
+            let index = if opcode == 0xCB {
+                0x100 + self.memory.read_memory(pc.wrapping_add(1)) as usize
+            } else {
+                opcode as usize
+            };
+            coverage[index] = true;
+        }
+    }
+
+    /// Starts recording the memory accesses of each instruction, see
+    /// `bus_trace`.
+    pub fn set_bus_trace(&mut self, enabled: bool) {
+        self.bus_trace = enabled.then(Vec::new);
+    }
+
+    /// Memory accesses of the current or last instruction or interrupt
+    /// dispatch, in order. Empty if the recording is off.
+    pub fn bus_trace(&self) -> &[BusAccess] {
+        self.bus_trace.as_deref().unwrap_or_default()
+    }
+
+    fn record_bus_access(&mut self, addr: u16, value: u8, kind: BusAccessKind) {
+        if let Some(trace) = self.bus_trace.as_mut() {
+            trace.push(BusAccess {
+                cycle: self.instruction_cycles,
+                addr,
+                value,
+                kind,
+            });
+        }
+    }
+
+    fn bus_read(&mut self, addr: u16) -> u8 {
+        let value = self.memory.read_memory(addr);
+        self.record_bus_access(addr, value, BusAccessKind::Read);
+        value
+    }
+
+    fn bus_write(&mut self, addr: u16, value: u8) {
+        self.memory.write_memory(addr, value);
+        self.record_bus_access(addr, value, BusAccessKind::Write);
+    }
+
+    fn clear_bus_trace(&mut self) {
+        if let Some(trace) = self.bus_trace.as_mut() {
+            trace.clear();
+        }
+    }
+
+    pub fn set_debugger(&mut self, debugger: Option<Debugger>) {
+        self.debugger = debugger;
+    }
+
+    pub fn debugger(&self) -> Option<&Debugger> {
+        self.debugger.as_ref()
+    }
+
+    pub fn debugger_mut(&mut self) -> Option<&mut Debugger> {
+        self.debugger.as_mut()
+    }
+
+    /// True while the debugger keeps the CPU stopped before an instruction.
+    pub fn is_at_breakpoint(&self) -> bool {
+        self.debugger
+            .as_ref()
+            .is_some_and(|debugger| debugger.stopped_at().is_some())
+    }
+
+    /// M-cycles taken by the last completed instruction or interrupt
+    /// dispatch.
+    pub fn last_instruction_cycles(&self) -> u8 {
+        self.last_instruction_cycles
+    }
+
     pub fn is_pipeline_empty(&self) -> bool {
         self.pipeline.is_empty()
     }
 
-    pub fn manual_bootstrap(&mut self) {
-        self.store_reg16(Register16::AF, 0x01B0);
-        self.store_reg16(Register16::BC, 0x0013);
-        self.store_reg16(Register16::DE, 0x00D8);
-        self.store_reg16(Register16::HL, 0x014D);
+    /// True once an illegal opcode has been executed.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Puts the CPU and the IO registers in the state the boot ROM of `model`
+    /// leaves them in.
+    pub fn manual_bootstrap(&mut self, model: Model) {
+        let [af, bc, de, hl] = model.post_boot_registers();
+        self.store_reg16(Register16::AF, af);
+        self.store_reg16(Register16::BC, bc);
+        self.store_reg16(Register16::DE, de);
+        self.store_reg16(Register16::HL, hl);
         self.pc = 0x100;
         self.sp = 0xFFFE;
 
-        self.memory.write_memory(0xFF05, 0x00); // TIMA
-        self.memory.write_memory(0xFF06, 0x00); // TMA
-        self.memory.write_memory(0xFF07, 0x00); // TAC
-        self.memory.write_memory(0xFF10, 0x80); // NR10
-        self.memory.write_memory(0xFF11, 0xBF); // NR11
-        self.memory.write_memory(0xFF12, 0xF3); // NR12
-        self.memory.write_memory(0xFF14, 0xBF); // NR14
-        self.memory.write_memory(0xFF16, 0x3F); // NR21
-        self.memory.write_memory(0xFF17, 0x00); // NR22
-        self.memory.write_memory(0xFF19, 0xBF); // NR24
-        self.memory.write_memory(0xFF1A, 0x7F); // NR30
-        self.memory.write_memory(0xFF1B, 0xFF); // NR31
-        self.memory.write_memory(0xFF1C, 0x9F); // NR32
-        self.memory.write_memory(0xFF1E, 0xBF); // NR34
-        self.memory.write_memory(0xFF20, 0xFF); // NR41
-        self.memory.write_memory(0xFF21, 0x00); // NR42
-        self.memory.write_memory(0xFF22, 0x00); // NR43
-        self.memory.write_memory(0xFF23, 0xBF); // NR44
-        self.memory.write_memory(0xFF24, 0x77); // NR50
-        self.memory.write_memory(0xFF25, 0xF3); // NR51
-        self.memory.write_memory(0xFF26, 0xF1); // $F1-GB, $F0-SGB - NR52
-        self.memory.write_memory(0xFF40, 0x91); // LCDC
-        self.memory.write_memory(0xFF42, 0x00); // SCY
-        self.memory.write_memory(0xFF43, 0x00); // SCX
-        self.memory.write_memory(0xFF45, 0x00); // LYC
-        self.memory.write_memory(0xFF47, 0xFC); // BGP
-        self.memory.write_memory(0xFF48, 0xFF); // OBP0
-        self.memory.write_memory(0xFF49, 0xFF); // OBP1
-        self.memory.write_memory(0xFF4A, 0x00); // WY
-        self.memory.write_memory(0xFF4B, 0x00); // WX
-        self.memory.write_memory(0xFFFF, 0x00); // IE
+        for &(addr, value) in POST_BOOT_IO_REGS {
+            self.memory.write_memory(addr, value);
+        }
+        if model.is_cgb() {
+            for &(addr, value) in CGB_POST_BOOT_IO_REGS {
+                self.memory.write_memory(addr, value);
+            }
+        } else {
+            // writing to DIV resets it
+            self.interrupt_controller.lock().unwrap().divider_register = 0xAB;
+        }
     }
 
     pub fn fetch_and_advance(&mut self) -> u8 {
-        let byte = self.memory.read_memory(self.pc);
-        self.pc += 1;
+        let byte = self.bus_read(self.pc);
+        if self.halt_bug {
+            self.halt_bug = false;
+        } else {
+            self.pc += 1;
+        }
         byte
     }
 
     pub fn fetch_and_advance_u16(&mut self) -> u16 {
         let low = self.fetch_and_advance();
         let high = self.fetch_and_advance();
-        ((high as u16) << 8) | (low as u16)
+        u16::from_le_bytes([low, high])
     }
 
     pub fn fetch_and_decode(&mut self) -> Instruction {
         decode::decode_instruction(self)
     }
 
+    fn check_oam_bug(&mut self, addr: u16, access: OamBugAccess) {
+        if (0xFE00..=0xFEFF).contains(&addr) {
+            self.memory.trigger_oam_bug(addr, access);
+        }
+    }
+
     fn run_pre_post_op(&mut self, reg16: Register16, op: Option<PrePostOperation>) {
+        if op.is_some() {
+            self.check_oam_bug(self.load_reg16(reg16), OamBugAccess::Write);
+        }
+        self.apply_pre_post_op(reg16, op);
+    }
+
+    /// Increments or decrements `reg16` without triggering the OAM bug.
+    fn apply_pre_post_op(&mut self, reg16: Register16, op: Option<PrePostOperation>) {
         match op {
             Some(PrePostOperation::Dec) => {
                 self.store_reg16(reg16, self.load_reg16(reg16).wrapping_sub(1))
@@ -221,44 +531,45 @@ impl<M: Memory> CPU<M> {
         }
     }
 
-    fn source_8bits_to_value(&self, src: Source8bits) -> u8 {
+    fn source_8bits_to_value(&mut self, src: Source8bits) -> u8 {
         match src {
             Source8bits::Register(reg) => self.load_reg8(reg),
             Source8bits::Literal(lit) => lit,
-            Source8bits::Indirect(addr) => self.memory.read_memory(self.load_reg16(addr)),
-            Source8bits::Address(addr) => self.memory.read_memory(addr),
-            Source8bits::ZeroPageOffsetReg8(offset) => self
-                .memory
-                .read_memory(0xFF00 + self.load_reg8(offset) as u16),
+            Source8bits::Indirect(addr) => self.bus_read(self.load_reg16(addr)),
+            Source8bits::Address(addr) => self.bus_read(addr),
+            Source8bits::ZeroPageOffsetReg8(offset) => {
+                self.bus_read(0xFF00 + self.load_reg8(offset) as u16)
+            }
         }
     }
 
-    fn load_reg8_or_indirect(&self, op: Reg8OrIndirect) -> u8 {
+    fn load_reg8_or_indirect(&mut self, op: Reg8OrIndirect) -> u8 {
         match op {
             Reg8OrIndirect::Reg8(reg) => self.load_reg8(reg),
-            Reg8OrIndirect::Indirect(addr) => self.memory.read_memory(self.load_reg16(addr)),
+            Reg8OrIndirect::Indirect(addr) => self.bus_read(self.load_reg16(addr)),
         }
     }
 
     fn store_reg8_or_indirect(&mut self, op: Reg8OrIndirect, value: u8) {
         match op {
             Reg8OrIndirect::Reg8(reg) => self.store_reg8(reg, value),
-            Reg8OrIndirect::Indirect(addr) => {
-                self.memory.write_memory(self.load_reg16(addr), value)
-            }
+            Reg8OrIndirect::Indirect(addr) => self.bus_write(self.load_reg16(addr), value),
         }
     }
 
     fn handle_interrupts(&mut self) {
         let mut controller = self.interrupt_controller.lock().unwrap();
         if controller.handle_new_interrupt() {
-            self.halted = false;
             self.stoped = false;
         }
+        if controller.should_wake_from_halt() {
+            self.halted = false;
+        }
 
         if let Some(kind) = controller.is_interrupt_waiting() {
             controller.interrupt_flag.remove(kind);
             controller.master_enable = false;
+            controller.log_interrupt(kind);
 
             let addr = match kind {
                 IntKind::VBLANK => 0x40,
@@ -269,7 +580,7 @@ impl<M: Memory> CPU<M> {
                 _ => panic!("Failed to get interrupt handler address"),
             };
 
-            let micro_ops = vec![
+            let micro_ops = [
                 MicroOp::Nop,
                 MicroOp::Nop,
                 MicroOp::WriteMem {
@@ -289,28 +600,89 @@ impl<M: Memory> CPU<M> {
                     literal: addr,
                 },
             ];
+            drop(controller);
             self.pipeline.extend(micro_ops);
+            self.clear_bus_trace();
         }
     }
 
     fn decode_next_instruction(&mut self) {
+        self.clear_bus_trace();
+        let pc = self.pc;
+        let halt_bug = self.halt_bug;
         let instruction = self.fetch_and_decode();
-        debug!("{:#06x}: {}", self.pc, instruction);
-        self.pipeline.extend(instruction.to_micro_ops());
+        if let Some(debugger) = self.debugger.as_mut() {
+            let opcode = self.memory.read_memory(pc);
+            if debugger.should_break(pc, opcode, &instruction) {
+                debug!("{:#06x}: breakpoint on {}", pc, instruction);
+                // decoded again once resumed
+                self.pc = pc;
+                self.halt_bug = halt_bug;
+                return;
+            }
+        }
+        self.record_opcode(pc);
+        debug!("{:#06x}: {}", pc, instruction.disassemble_with_pc(pc));
+
+        if self.history.len() == INSTRUCTION_HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        self.history.push_back((pc, instruction));
+
+        // interrupts were checked before this instruction, the earliest one
+        // serviced after EI comes once it completes
+        if std::mem::take(&mut self.ime_pending) {
+            self.interrupt_controller.lock().unwrap().master_enable = true;
+        }
+
+        self.pipeline.extend(instruction.to_micro_ops().iter());
+    }
+
+    /// Runs the CPU alone up to the end of the current instruction, returns
+    /// the M-cycles taken. A halted or stopped CPU runs one M-cycle, a CPU at
+    /// a breakpoint none.
+    pub fn step_instruction(&mut self) -> u8 {
+        if self.is_at_breakpoint() {
+            return 0;
+        }
+
+        let mut cycles = 0;
+        loop {
+            self.step();
+            cycles += 1;
+            if self.is_pipeline_empty() {
+                return cycles;
+            }
+        }
     }
 
     pub fn step(&mut self) {
+        self.step_with_hook(|_| {});
+    }
+
+    /// Same as `step`, `on_decode` is called before an instruction is decoded,
+    /// with `pc` on it.
+    pub fn step_with_hook(&mut self, mut on_decode: impl FnMut(&Self)) {
+        if self.is_at_breakpoint() {
+            return;
+        }
+
         self.memory.tick();
 
         if !self.stoped {
             self.interrupt_controller.lock().unwrap().timer_step(4);
         }
 
+        if self.locked {
+            return;
+        }
+
         if self.pipeline.is_empty() {
             self.handle_interrupts();
         }
 
         if self.pipeline.is_empty() && !self.halted && !self.stoped {
+            on_decode(self);
             self.decode_next_instruction();
         }
 
@@ -328,10 +700,10 @@ impl<M: Memory> CPU<M> {
                             self.store_reg8(reg, value);
                         }
                         Destination8Bits::Indirect(addr) => {
-                            self.memory.write_memory(self.load_reg16(addr), value);
+                            self.bus_write(self.load_reg16(addr), value);
                         }
                         Destination8Bits::Address(addr) => {
-                            self.memory.write_memory(addr, value);
+                            self.bus_write(addr, value);
                         }
                     }
                 }
@@ -414,10 +786,12 @@ impl<M: Memory> CPU<M> {
                     self.update_flags_arith(res, false, carry, half_carry);
                 }
                 MicroOp::SubA { rhs } => {
-                    self.sub_a(self.source_8bits_to_value(rhs), false, true);
+                    let rhs = self.source_8bits_to_value(rhs);
+                    self.sub_a(rhs, false, true);
                 }
                 MicroOp::SbcA { rhs } => {
-                    self.sub_a(self.source_8bits_to_value(rhs), true, true);
+                    let rhs = self.source_8bits_to_value(rhs);
+                    self.sub_a(rhs, true, true);
                 }
                 MicroOp::Daa => {
                     let mut a = self.reg_a as u32;
@@ -460,18 +834,22 @@ impl<M: Memory> CPU<M> {
                     pre_op,
                     post_op,
                 } => {
-                    self.run_pre_post_op(addr, pre_op);
+                    // the increment/decrement and the write happen on the
+                    // same M-cycle, they corrupt the OAM like a single write
+                    self.apply_pre_post_op(addr, pre_op);
                     let addr_value = self.load_reg16(addr);
-                    self.memory.write_memory(addr_value, self.load_reg8(reg));
-                    self.run_pre_post_op(addr, post_op);
+                    self.check_oam_bug(addr_value, OamBugAccess::Write);
+                    self.bus_write(addr_value, self.load_reg8(reg));
+                    self.apply_pre_post_op(addr, post_op);
                 }
                 MicroOp::WriteMemZeroPage { reg_offset, reg } => {
                     let addr_value = 0xFF00 + self.load_reg8(reg_offset) as u16;
-                    self.memory.write_memory(addr_value, self.load_reg8(reg));
+                    self.bus_write(addr_value, self.load_reg8(reg));
                 }
                 MicroOp::ReadMem { reg, addr, post_op } => {
                     let addr_value = self.load_reg16(addr);
-                    let mem_value = self.memory.read_memory(addr_value);
+                    self.check_oam_bug(addr_value, OamBugAccess::Read);
+                    let mem_value = self.bus_read(addr_value);
                     self.store_reg8(reg, mem_value);
                     self.run_pre_post_op(addr, post_op);
                 }
@@ -492,8 +870,8 @@ impl<M: Memory> CPU<M> {
                 }
                 MicroOp::CheckFlags {
                     condition,
-                    true_ops,
-                    false_ops,
+                    true_count,
+                    false_count,
                 } => {
                     let cond_true = match condition {
                         instruction::JumpCondition::NonZero => !self.flags.contains(Flags::ZERO),
@@ -502,9 +880,11 @@ impl<M: Memory> CPU<M> {
                         instruction::JumpCondition::Carry => self.flags.contains(Flags::CARRY),
                     };
 
-                    let to_prepend_ops = if cond_true { true_ops } else { false_ops };
-                    for op in to_prepend_ops.into_iter().rev() {
-                        self.pipeline.push_front(op);
+                    let (true_count, false_count) = (true_count as usize, false_count as usize);
+                    if cond_true {
+                        self.pipeline.drain(true_count..true_count + false_count);
+                    } else {
+                        self.pipeline.drain(..true_count);
                     }
                 }
                 MicroOp::AddOffsetToReg16IntoReg16 {
@@ -545,6 +925,7 @@ impl<M: Memory> CPU<M> {
                 }
                 MicroOp::IncReg16 { reg } => {
                     // No flags change for this micro op
+                    self.check_oam_bug(self.load_reg16(reg), OamBugAccess::Write);
                     self.store_reg16(reg, self.load_reg16(reg).wrapping_add(1));
                 }
                 MicroOp::Inc { reg } => {
@@ -564,6 +945,7 @@ impl<M: Memory> CPU<M> {
                 }
                 MicroOp::DecReg16 { reg } => {
                     // No flags change for this micro op
+                    self.check_oam_bug(self.load_reg16(reg), OamBugAccess::Write);
                     self.store_reg16(reg, self.load_reg16(reg).wrapping_sub(1));
                 }
                 MicroOp::Dec { reg } => {
@@ -702,17 +1084,43 @@ impl<M: Memory> CPU<M> {
                 MicroOp::EnableInterrupts => {
                     self.interrupt_controller.lock().unwrap().master_enable = true;
                 }
+                MicroOp::EnableInterruptsDelayed => {
+                    self.ime_pending = true;
+                }
                 MicroOp::DisableInterrupts => {
                     self.interrupt_controller.lock().unwrap().master_enable = false;
                 }
                 MicroOp::Halt => {
-                    self.halted = true;
+                    let controller = self.interrupt_controller.lock().unwrap();
+                    if !controller.master_enable && !controller.pending_interrupts().is_empty() {
+                        debug!("HALT bug at pc={:#x}", self.pc);
+                        self.halt_bug = true;
+                    } else {
+                        self.halted = true;
+                    }
                 }
                 MicroOp::Stop => {
-                    self.stoped = true;
-                    warn!("CPU stopped pc={:#x}", self.pc);
+                    if self.memory.switch_speed() {
+                        debug!("CPU speed switched pc={:#x}", self.pc);
+                    } else {
+                        self.stoped = true;
+                        warn!("CPU stopped pc={:#x}", self.pc);
+                    }
+                }
+                MicroOp::Lock { opcode } => {
+                    self.locked = true;
+                    error!(
+                        "Illegal opcode {:#04x} at {:#06x}, CPU locked",
+                        opcode,
+                        self.pc.wrapping_sub(1)
+                    );
                 }
             }
+
+            self.instruction_cycles += 1;
+            if self.pipeline.is_empty() {
+                self.last_instruction_cycles = std::mem::take(&mut self.instruction_cycles);
+            }
         }
     }
 
@@ -760,6 +1168,16 @@ impl<M: Memory> CPU<M> {
     }
 }
 
+impl<M: Memory> decode::ByteStream for CPU<M> {
+    fn fetch_and_advance(&mut self) -> u8 {
+        CPU::fetch_and_advance(self)
+    }
+
+    fn fetch_and_advance_u16(&mut self) -> u16 {
+        CPU::fetch_and_advance_u16(self)
+    }
+}
+
 fn check_half_carry(a: u8, b: u8) -> bool {
     (((a & 0xf) + (b & 0xf)) & 0x10) == 0x10
 }
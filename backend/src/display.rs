@@ -1,45 +1,269 @@
-use crate::{memory::Memory, ppu::pixel::PixelSource};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use crate::{interrupt::Keys, memory::Memory, ppu::pixel::PixelSource};
 
 use super::ppu::pixel::byte_pair_to_pixels;
-use super::ppu::PIXEL_COUNT;
+use super::ppu::{PIXEL_COUNT, SCAN_LINE_COUNT, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub type DisplayPtr = Arc<Mutex<Display>>;
+
+const LCD_GRID_SCALE: usize = 4;
+pub const MAX_FRAME_BLEND: usize = 8;
+
+const STAT_STRIP_WIDTH: usize = 8;
+/// Size of the framebuffer drawn by `draw_stat_lines_into_fb`.
+pub const STAT_LINES_WIDTH: usize = SCREEN_WIDTH as usize + STAT_STRIP_WIDTH;
+pub const STAT_LINES_HEIGHT: usize = SCAN_LINE_COUNT as usize;
+
+const INPUT_OVERLAY_MARGIN: usize = 2;
+const INPUT_OVERLAY_HEIGHT: usize = 9;
+/// Position and size of each button in the input overlay, in screen pixels
+/// from its top left corner: the d-pad, then Select, Start, B and A.
+const INPUT_OVERLAY_BUTTONS: [(Keys, usize, usize, usize, usize); Keys::KeysMax as usize] = [
+    (Keys::Up, 3, 0, 3, 3),
+    (Keys::Left, 0, 3, 3, 3),
+    (Keys::Right, 6, 3, 3, 3),
+    (Keys::Down, 3, 6, 3, 3),
+    (Keys::Select, 11, 6, 4, 2),
+    (Keys::Start, 16, 6, 4, 2),
+    (Keys::B, 22, 4, 3, 3),
+    (Keys::A, 26, 2, 3, 3),
+];
+
+/// Length of a framebuffer that is not the screen at any scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferSizeError(pub usize);
+
+impl fmt::Display for FramebufferSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes is not a scaled screen framebuffer", self.0)
+    }
+}
+
+impl std::error::Error for FramebufferSizeError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScreenEffect {
+    #[default]
+    None,
+    /// Darkens the right and bottom edges of each pixel to mimic the grid of
+    /// the LCD. The framebuffer is 4 times larger in each dimension.
+    LcdGrid,
+    /// Blends the current frame with the previous one to mimic the slow
+    /// response time of the LCD.
+    Ghosting,
+}
+
+impl ScreenEffect {
+    /// Returns how many framebuffer pixels are used for each screen pixel, in
+    /// each dimension.
+    pub fn scale(self) -> usize {
+        match self {
+            ScreenEffect::LcdGrid => LCD_GRID_SCALE,
+            ScreenEffect::None | ScreenEffect::Ghosting => 1,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Display {
-    frame: [u8; PIXEL_COUNT],
+    // most recent frame first
+    frames: VecDeque<[u8; PIXEL_COUNT]>,
+    frame_blend: usize,
+    stat_lines: [bool; SCAN_LINE_COUNT as usize],
 }
 
 impl Default for Display {
     fn default() -> Self {
         Display {
-            frame: [0; PIXEL_COUNT],
+            frames: VecDeque::from(vec![[0; PIXEL_COUNT]; 2]),
+            frame_blend: 1,
+            stat_lines: [false; SCAN_LINE_COUNT as usize],
         }
     }
 }
 
 impl Display {
     pub fn push_frame(&mut self, frame: &[u8]) {
-        assert_eq!(frame.len(), self.frame.len());
-        self.frame.copy_from_slice(frame);
+        assert_eq!(frame.len(), PIXEL_COUNT);
+
+        let mut new_frame = if self.frames.len() >= self.retained_frames() {
+            self.frames.pop_back().unwrap()
+        } else {
+            [0; PIXEL_COUNT]
+        };
+        new_frame.copy_from_slice(frame);
+        self.frames.push_front(new_frame);
+    }
+
+    /// Replaces every retained frame with `indices`, as if the PPU had output
+    /// the same frame since the start, so that effects blending frames show
+    /// it as is. Used to test the display without running a ROM.
+    pub fn load_frame_from_indices(&mut self, indices: &[u8; PIXEL_COUNT]) {
+        for frame in &mut self.frames {
+            frame.copy_from_slice(indices);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for frame in &mut self.frames {
+            frame.fill(0);
+        }
+        self.stat_lines.fill(false);
+    }
+
+    pub fn set_stat_lines(&mut self, lines: &[bool; SCAN_LINE_COUNT as usize]) {
+        self.stat_lines = *lines;
+    }
+
+    /// Averages the color indices of the last `n` frames before the palette
+    /// lookup, to mimic how the LCD smooths sprites flickering every other
+    /// frame. `n` is clamped between 1 (no blending) and `MAX_FRAME_BLEND`.
+    pub fn set_frame_blend(&mut self, n: usize) {
+        self.frame_blend = n.clamp(1, MAX_FRAME_BLEND);
+        let retained = self.retained_frames();
+        self.frames.truncate(retained);
+    }
+
+    fn retained_frames(&self) -> usize {
+        // the ghosting effect needs the previous frame
+        self.frame_blend.max(2)
+    }
+
+    fn frame(&self) -> &[u8; PIXEL_COUNT] {
+        &self.frames[0]
+    }
+
+    fn blended_color(&self, index: usize) -> u8 {
+        if self.frame_blend == 1 {
+            return self.frame()[index];
+        }
+
+        let frames = self.frames.iter().take(self.frame_blend);
+        let count = frames.len();
+        let sum: usize = frames.map(|frame| frame[index] as usize).sum();
+        ((sum + count / 2) / count) as u8
     }
 
     pub fn draw_into_fb(&self, fb: &mut [u8]) {
         assert_eq!(PIXEL_COUNT * 4, fb.len());
 
         for (i, pixel) in fb.chunks_exact_mut(4).enumerate() {
-            let color = self.frame[i];
+            let color = self.blended_color(i);
             pixel.copy_from_slice(&pixel_color_to_screen_color(color));
         }
     }
 
-    pub fn draw_tiles_into_fb(memory: &dyn Memory, fb: &mut [u8]) {
+    pub fn draw_into_fb_with_effect(&self, fb: &mut [u8], effect: ScreenEffect) {
+        match effect {
+            ScreenEffect::None => self.draw_into_fb(fb),
+            ScreenEffect::LcdGrid => self.draw_lcd_grid_into_fb(fb),
+            ScreenEffect::Ghosting => self.draw_ghosting_into_fb(fb),
+        }
+    }
+
+    fn draw_lcd_grid_into_fb(&self, fb: &mut [u8]) {
+        assert_eq!(PIXEL_COUNT * LCD_GRID_SCALE * LCD_GRID_SCALE * 4, fb.len());
+
+        let fb_width = SCREEN_WIDTH as usize * LCD_GRID_SCALE;
+        for (i, pixel) in fb.chunks_exact_mut(4).enumerate() {
+            let (fb_x, fb_y) = (i % fb_width, i / fb_width);
+            let (x, y) = (fb_x / LCD_GRID_SCALE, fb_y / LCD_GRID_SCALE);
+
+            let mut color =
+                pixel_color_to_screen_color(self.blended_color(y * SCREEN_WIDTH as usize + x));
+            let is_edge = fb_x % LCD_GRID_SCALE == LCD_GRID_SCALE - 1
+                || fb_y % LCD_GRID_SCALE == LCD_GRID_SCALE - 1;
+            if is_edge {
+                for channel in &mut color[..3] {
+                    *channel = (*channel as u16 * 3 / 4) as u8;
+                }
+            }
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    fn draw_ghosting_into_fb(&self, fb: &mut [u8]) {
+        assert_eq!(PIXEL_COUNT * 4, fb.len());
+
+        for (i, pixel) in fb.chunks_exact_mut(4).enumerate() {
+            let current = pixel_color_to_screen_color(self.frames[0][i]);
+            let previous = pixel_color_to_screen_color(self.frames[1][i]);
+
+            let mut color = current;
+            for (channel, previous) in color[..3].iter_mut().zip(previous) {
+                *channel = ((*channel as u16 + previous as u16) / 2) as u8;
+            }
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    /// Draws the last frame, including the VBlank lines, with a strip on its
+    /// right marking the lines on which a STAT interrupt was requested.
+    pub fn draw_stat_lines_into_fb(&self, fb: &mut [u8]) {
+        assert_eq!(STAT_LINES_WIDTH * STAT_LINES_HEIGHT * 4, fb.len());
+
+        for (y, row) in fb.chunks_exact_mut(STAT_LINES_WIDTH * 4).enumerate() {
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let color = if x >= SCREEN_WIDTH as usize {
+                    if self.stat_lines[y] {
+                        [255, 0, 0, 255]
+                    } else {
+                        [40, 40, 40, 255]
+                    }
+                } else if y < SCREEN_HEIGHT as usize {
+                    pixel_color_to_screen_color(self.frame()[y * SCREEN_WIDTH as usize + x])
+                } else {
+                    [0, 0, 0, 255]
+                };
+                pixel.copy_from_slice(&color);
+            }
+        }
+    }
+
+    /// Draws the joypad in the bottom left corner of a frame drawn by
+    /// `draw_into_fb_with_effect`, with the pressed buttons in red.
+    pub fn draw_input_overlay(
+        fb: &mut [u8],
+        keys_state: [bool; Keys::KeysMax as usize],
+    ) -> Result<(), FramebufferSizeError> {
+        let scale = (1..=LCD_GRID_SCALE)
+            .find(|scale| PIXEL_COUNT * scale * scale * 4 == fb.len())
+            .ok_or(FramebufferSizeError(fb.len()))?;
+        let fb_width = SCREEN_WIDTH as usize * scale;
+        let top = SCREEN_HEIGHT as usize - INPUT_OVERLAY_MARGIN - INPUT_OVERLAY_HEIGHT;
+
+        for (key, x, y, width, height) in INPUT_OVERLAY_BUTTONS {
+            let color = if keys_state[key as usize] {
+                [255, 0, 0, 255]
+            } else {
+                [40, 40, 40, 255]
+            };
+
+            let fb_x = (INPUT_OVERLAY_MARGIN + x) * scale;
+            for fb_y in ((top + y) * scale)..((top + y + height) * scale) {
+                let offset = (fb_y * fb_width + fb_x) * 4;
+                for pixel in fb[offset..(offset + width * scale * 4)].chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&color);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn draw_tiles_into_fb(memory: &dyn Memory, fb: &mut [u8], bank: usize) {
         let addresses: Vec<u16> = (0x8000..0x9800).collect();
         for (tile_id, tile) in addresses.chunks_exact(16).enumerate() {
             let tile_y = tile_id / 20;
             let tile_x = tile_id % 20;
 
             for (y, byte_addresses) in tile.chunks_exact(2).enumerate() {
-                let low = memory.read_memory(byte_addresses[0]);
-                let high = memory.read_memory(byte_addresses[1]);
+                let low = memory.read_vram(byte_addresses[0], bank);
+                let high = memory.read_vram(byte_addresses[1], bank);
                 let pixels = byte_pair_to_pixels(low, high, PixelSource::BackgroundWindow);
 
                 for (x, pixel) in pixels.iter().enumerate() {
@@ -56,7 +280,7 @@ impl Display {
     }
 }
 
-fn pixel_color_to_screen_color(color: u8) -> [u8; 4] {
+pub(crate) fn pixel_color_to_screen_color(color: u8) -> [u8; 4] {
     /*
     // green
     match color {
@@ -0,0 +1,439 @@
+use std::{
+    io,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use log::error;
+
+use crate::{
+    display::{Display, DisplayPtr},
+    interrupt::{InterruptController, InterruptControllerPtr},
+    memory::{self, CartridgeError, MMUBuilder, MMUPtr, RamSnapshot},
+    model::Model,
+    ppu::{bg_map::BgMapExport, Mode},
+    serial::SerialPtr,
+    state::{EmuState, StateError},
+    CPU, PPU,
+};
+
+#[cfg(feature = "serde")]
+use crate::state::StateDiff;
+#[cfg(feature = "scripting")]
+use crate::{ppu::SCREEN_HEIGHT, scripting::Script};
+
+const T_CYCLES_PER_STEP: u64 = 4;
+pub const T_CYCLES_PER_FRAME: u64 = 70224;
+
+pub struct Emulator {
+    pub interrupt_controller: InterruptControllerPtr,
+    pub memory: MMUPtr,
+    pub cpu: CPU<MMUPtr>,
+    pub ppu: PPU<MMUPtr>,
+    pub display: DisplayPtr,
+    has_bootstrap: bool,
+    /// Model given to `with_model`, if any
+    requested_model: Option<Model>,
+    model: Model,
+    cgb_cartridge: bool,
+    save_path: Option<PathBuf>,
+    cycle_budget: u64,
+    total_cycles: u64,
+    /// APU output of each M-cycle since the last `take_audio_samples`
+    audio_samples: Option<Vec<(i16, i16)>>,
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
+}
+
+impl Emulator {
+    /// Emulates a CGB for CGB cartridges and a DMG for the others.
+    pub fn new(
+        rom: &[u8],
+        bootstrap: Option<&[u8]>,
+        serial: SerialPtr,
+    ) -> Result<Self, CartridgeError> {
+        Self::build(rom, bootstrap, serial, None)
+    }
+
+    /// Emulates `model` whatever the cartridge: the boot state and the
+    /// hardware quirks follow it.
+    pub fn with_model(
+        rom: &[u8],
+        bootstrap: Option<&[u8]>,
+        serial: SerialPtr,
+        model: Model,
+    ) -> Result<Self, CartridgeError> {
+        Self::build(rom, bootstrap, serial, Some(model))
+    }
+
+    fn build(
+        rom: &[u8],
+        bootstrap: Option<&[u8]>,
+        serial: SerialPtr,
+        requested_model: Option<Model>,
+    ) -> Result<Self, CartridgeError> {
+        let interrupt_controller = Arc::new(Mutex::new(InterruptController::new()));
+        let cgb_cartridge = supports_cgb(rom);
+        let model = requested_model.unwrap_or_else(|| default_model(cgb_cartridge));
+
+        let mbc = memory::build_mbc(rom)?;
+        let mut mmu = MMUBuilder::new(mbc)
+            .interrupt_controller(interrupt_controller.clone())
+            .serial(serial)
+            .model(model);
+        if let Some(bootstrap) = bootstrap {
+            mmu = mmu.bootstrap_rom(bootstrap);
+        }
+        let mmu = mmu.build();
+
+        let memory = Arc::new(RwLock::new(mmu));
+        let display = Arc::new(Mutex::new(Display::default()));
+
+        let cpu = CPU::new(memory.clone(), interrupt_controller.clone());
+        let ppu = PPU::new(
+            memory.clone(),
+            interrupt_controller.clone(),
+            display.clone(),
+        );
+
+        let mut emulator = Emulator {
+            interrupt_controller,
+            memory,
+            cpu,
+            ppu,
+            display,
+            has_bootstrap: bootstrap.is_some(),
+            requested_model,
+            model,
+            cgb_cartridge,
+            save_path: None,
+            cycle_budget: 0,
+            total_cycles: 0,
+            audio_samples: None,
+            #[cfg(feature = "scripting")]
+            script: None,
+        };
+        emulator.power_on();
+        Ok(emulator)
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Swaps the cartridge for `rom` and resets the whole machine, keeping the
+    /// existing interrupt controller, memory and display allocations. The RAM
+    /// of the previous cartridge is flushed to its save file first, if any.
+    /// The save file is kept and loaded in the new cartridge, e.g. for a
+    /// rebuilt ROM, call `set_save_path` for another game.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), CartridgeError> {
+        let mbc = memory::build_mbc(rom)?;
+
+        if let Err(err) = self.flush_ram() {
+            error!("Failed to flush cartridge RAM: {}", err);
+        }
+
+        #[cfg(feature = "cgb")]
+        let was_cgb_mode = self.cgb_mode();
+        self.cgb_cartridge = supports_cgb(rom);
+        self.model = self
+            .requested_model
+            .unwrap_or_else(|| default_model(self.cgb_cartridge));
+        {
+            let mut memory = self.memory.write().unwrap();
+            memory.reset(mbc);
+            memory.set_model(self.model);
+        }
+        self.reset_components();
+        // the CGB object priority set at power on does not apply anymore
+        #[cfg(feature = "cgb")]
+        if was_cgb_mode && !self.cgb_mode() {
+            self.ppu
+                .set_object_priority(crate::ppu::ObjectPriority::Coordinate);
+        }
+
+        if let Some(path) = self.save_path.clone() {
+            if let Err(err) = self.set_save_path(path) {
+                error!("Failed to load cartridge RAM: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Power cycles the machine with the same cartridge. The cartridge RAM is
+    /// cleared unless it is backed by a battery.
+    pub fn reset(&mut self) {
+        self.memory.write().unwrap().power_cycle();
+        self.reset_components();
+    }
+
+    /// The debugging and rendering options of the components are kept.
+    fn reset_components(&mut self) {
+        self.interrupt_controller.lock().unwrap().reset();
+        self.display.lock().unwrap().clear();
+
+        self.cpu.reset();
+        self.ppu.reset();
+        self.cycle_budget = 0;
+        self.total_cycles = 0;
+
+        self.power_on();
+    }
+
+    /// CGB mode is on for CGB cartridges, unless another model was requested.
+    #[cfg_attr(not(feature = "cgb"), allow(dead_code))]
+    fn cgb_mode(&self) -> bool {
+        self.cgb_cartridge && self.requested_model.is_none_or(Model::is_cgb)
+    }
+
+    fn power_on(&mut self) {
+        {
+            let mut mmu = self.memory.write().unwrap();
+            #[cfg(feature = "cgb")]
+            mmu.set_cgb_mode(self.cgb_mode());
+            if !self.has_bootstrap {
+                mmu.unmount_bootstrap_rom();
+            }
+        }
+        #[cfg(feature = "cgb")]
+        if self.cgb_mode() {
+            self.ppu
+                .set_object_priority(crate::ppu::ObjectPriority::OamIndex);
+        }
+        if !self.has_bootstrap {
+            self.cpu.manual_bootstrap(self.model);
+        }
+        self.ppu.sync_registers();
+    }
+
+    /// Backs the cartridge RAM with the file at `path`, loading its content if
+    /// it already exists.
+    pub fn set_save_path<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        if path.exists() {
+            let data = std::fs::read(path)?;
+            self.memory.write().unwrap().load_cartridge_ram(&data);
+        }
+        self.save_path = Some(path.to_owned());
+        Ok(())
+    }
+
+    /// Writes the cartridge RAM to the save file, if one was set.
+    pub fn flush_ram(&self) -> io::Result<()> {
+        if let Some(path) = &self.save_path {
+            if let Some(data) = self.memory.read().unwrap().dump_cartridge_ram() {
+                std::fs::write(path, data)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs up to the next point where the CPU is between two instructions and
+    /// the PPU is not transferring pixels, then takes a snapshot of the
+    /// machine. The cartridge, the model and the debugging options are not
+    /// part of it. Fails if a breakpoint stops the CPU before that point.
+    pub fn save_state(&mut self) -> Result<EmuState, StateError> {
+        while !self.cpu.is_pipeline_empty() || self.ppu.mode() == Mode::LCDTransfer {
+            if let Some(pc) = self
+                .cpu
+                .debugger()
+                .and_then(|debugger| debugger.stopped_at())
+            {
+                return Err(StateError::Breakpoint { pc });
+            }
+            self.step();
+        }
+
+        Ok(EmuState {
+            cpu: self.cpu.save_state(),
+            interrupt_controller: self.interrupt_controller.lock().unwrap().clone(),
+            memory: self.memory.read().unwrap().save_state(),
+            ppu: self.ppu.save_state()?,
+            total_cycles: self.total_cycles,
+        })
+    }
+
+    /// Restores a snapshot taken with `save_state` on the same cartridge. A
+    /// snapshot that does not fit the machine is rejected before anything is
+    /// changed.
+    pub fn load_state(&mut self, state: &EmuState) -> Result<(), StateError> {
+        let mut memory = self.memory.write().unwrap();
+        memory.check_state(&state.memory)?;
+        self.ppu.check_state(&state.ppu)?;
+
+        memory.load_state(&state.memory)?;
+        drop(memory);
+        self.ppu.load_state(&state.ppu)?;
+        self.cpu.load_state(&state.cpu);
+        self.interrupt_controller
+            .lock()
+            .unwrap()
+            .load_state(&state.interrupt_controller);
+        self.total_cycles = state.total_cycles;
+        self.cycle_budget = 0;
+        Ok(())
+    }
+
+    /// Deserializes two save states and compares them, e.g. to find where two
+    /// runs that should match diverge.
+    #[cfg(feature = "serde")]
+    pub fn diff_state(a: &[u8], b: &[u8]) -> bincode::Result<StateDiff> {
+        Ok(EmuState::load(a)?.diff(&EmuState::load(b)?))
+    }
+
+    /// Advances the whole machine by one M-cycle (4 T-cycles). Nothing
+    /// happens while the CPU is stopped at a breakpoint.
+    pub fn step(&mut self) {
+        if self.cpu.is_at_breakpoint() {
+            return;
+        }
+
+        #[cfg(feature = "scripting")]
+        let scan_line = self.ppu.scan_line();
+
+        #[cfg(feature = "scripting")]
+        match self.script.as_mut() {
+            Some(script) if script.has_pc_hooks() => {
+                self.cpu.step_with_hook(|cpu| script.run_pc_hooks(cpu))
+            }
+            _ => self.cpu.step(),
+        }
+        #[cfg(not(feature = "scripting"))]
+        self.cpu.step();
+        self.ppu.step();
+        self.total_cycles += T_CYCLES_PER_STEP;
+        if let Some(samples) = self.audio_samples.as_mut() {
+            samples.push(self.memory.read().unwrap().apu().sample());
+        }
+
+        #[cfg(feature = "scripting")]
+        self.run_script_hooks(scan_line);
+    }
+
+    /// Advances the whole machine up to the end of the current CPU
+    /// instruction, returns the M-cycles taken. One M-cycle when the CPU is
+    /// halted or stopped, none at a breakpoint.
+    pub fn step_instruction(&mut self) -> u8 {
+        if self.cpu.is_at_breakpoint() {
+            return 0;
+        }
+
+        let mut cycles = 0;
+        loop {
+            self.step();
+            cycles += 1;
+            if self.cpu.is_pipeline_empty() {
+                return cycles;
+            }
+        }
+    }
+
+    /// Keeps the APU output of every M-cycle, at `wav::APU_SAMPLE_RATE`,
+    /// until `take_audio_samples` is called.
+    pub fn set_audio_capture(&mut self, enabled: bool) {
+        self.audio_samples = enabled.then(Vec::new);
+    }
+
+    pub fn take_audio_samples(&mut self) -> Vec<(i16, i16)> {
+        self.audio_samples
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    #[cfg(feature = "scripting")]
+    pub fn set_script(&mut self, script: Option<Script>) {
+        self.script = script;
+    }
+
+    #[cfg(feature = "scripting")]
+    fn run_script_hooks(&mut self, previous_scan_line: u8) {
+        let Some(script) = self.script.as_mut() else {
+            return;
+        };
+
+        if previous_scan_line != SCREEN_HEIGHT && self.ppu.scan_line() == SCREEN_HEIGHT {
+            script.run_frame_hooks(&self.cpu);
+        }
+    }
+
+    /// Advances the whole machine by `t_cycles` T-cycles. The CPU runs at
+    /// M-cycle granularity, so leftover T-cycles are kept for the next call.
+    pub fn step_cycles(&mut self, t_cycles: u64) {
+        self.cycle_budget += t_cycles;
+        while self.cycle_budget >= T_CYCLES_PER_STEP {
+            self.step();
+            self.cycle_budget -= T_CYCLES_PER_STEP;
+        }
+    }
+
+    /// Advances the whole machine by the duration of one frame.
+    pub fn step_frame(&mut self) {
+        self.step_cycles(T_CYCLES_PER_FRAME);
+    }
+
+    /// Runs `n` frames without looking at their output, e.g. to get past the
+    /// boot logo and intro of a game.
+    pub fn skip_frames(&mut self, n: u64) {
+        for _ in 0..n {
+            self.step_frame();
+        }
+    }
+
+    pub fn is_bootstrap_rom_mounted(&self) -> bool {
+        self.memory.read().unwrap().is_bootstrap_rom_mounted()
+    }
+
+    /// Addresses of WRAM and HRAM whose value matches `predicate`, to look
+    /// for cheat codes.
+    pub fn search_memory(&self, predicate: impl Fn(u16, u8) -> bool) -> Vec<u16> {
+        self.memory.read().unwrap().search_ram(predicate)
+    }
+
+    pub fn snapshot_ram(&self) -> RamSnapshot {
+        self.memory.read().unwrap().snapshot_ram()
+    }
+
+    /// Addresses for which `cmp(old, new)` holds between `snapshot` and now.
+    pub fn changed_addresses(
+        &self,
+        snapshot: &RamSnapshot,
+        cmp: impl Fn(u8, u8) -> bool,
+    ) -> Vec<u16> {
+        self.memory.read().unwrap().changed_addresses(snapshot, cmp)
+    }
+
+    /// Writes `value` over `range`, through the MMU like the CPU would.
+    pub fn fill_memory(&mut self, range: RangeInclusive<u16>, value: u8) {
+        self.memory.write().unwrap().fill_memory(range, value);
+    }
+
+    /// Exports the active background map, e.g. to edit it in Tiled.
+    pub fn export_bg_map(&self) -> BgMapExport {
+        BgMapExport::read_from_memory(&*self.memory.read().unwrap())
+    }
+
+    /// Number of frames completed since the cartridge was loaded.
+    pub fn total_frames(&self) -> u64 {
+        self.ppu.frame_count()
+    }
+
+    /// Number of T-cycles run since the cartridge was loaded.
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+}
+
+/// Model emulated when none was requested: CGB cartridges run on a CGB.
+fn default_model(cgb_cartridge: bool) -> Model {
+    if cfg!(feature = "cgb") && cgb_cartridge {
+        Model::Cgb
+    } else {
+        Model::default()
+    }
+}
+
+fn supports_cgb(rom: &[u8]) -> bool {
+    memory::CartridgeHeader::read(rom).is_some_and(|h| h.supports_cgb())
+}
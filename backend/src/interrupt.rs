@@ -1,11 +1,17 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
 
 use bitflags::bitflags;
 
 pub type InterruptControllerPtr = Arc<Mutex<InterruptController>>;
 
+pub const INTERRUPT_LOG_SIZE: usize = 256;
+
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct IntKind: u8 {
         const VBLANK   = 1 << 0;
         const LCD_STAT = 1 << 1;
@@ -18,6 +24,7 @@ bitflags! {
 }
 
 bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct JoypadBits: u8 {
         const P15_SELECT_BUTTON_KEYS    = 1 << 5;
         const P14_SELECT_DIRECTION_KEYS = 1 << 4;
@@ -42,7 +49,27 @@ pub enum Keys {
     KeysMax,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptEvent {
+    pub kind: IntKind,
+    /// T-cycles elapsed since power on when the interrupt was serviced
+    pub cycle: u64,
+}
+
+/// TIMA is reloaded from TMA one M-cycle after it overflows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimerState {
+    Running,
+    /// TIMA overflowed and reads 0, writing it cancels the reload.
+    Overflowing,
+    /// TIMA was just reloaded, writing it is ignored and writing TMA also
+    /// writes TIMA.
+    Reloading,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InterruptController {
     pub master_enable: bool,
     pub interrupt_enable: IntKind,
@@ -54,6 +81,7 @@ pub struct InterruptController {
     pub timer_counter: u8,
     pub timer_modulo: u8,
     timer_sub_counter: u32,
+    timer_state: TimerState,
 
     pub timer_control: u8,
 
@@ -63,9 +91,34 @@ pub struct InterruptController {
     keys_state: [bool; Keys::KeysMax as usize],
     select_buttons: bool,
     select_directions: bool,
+
+    cycles: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    interrupt_log: Option<VecDeque<InterruptEvent>>,
 }
 
 impl InterruptController {
+    /// Compares DIV, TIMA, TMA, TAC and their internal counters.
+    pub(crate) fn same_timer(&self, other: &InterruptController) -> bool {
+        (
+            self.divider_register,
+            self.divider_counter,
+            self.timer_counter,
+            self.timer_modulo,
+            self.timer_sub_counter,
+            self.timer_state,
+            self.timer_control,
+        ) == (
+            other.divider_register,
+            other.divider_counter,
+            other.timer_counter,
+            other.timer_modulo,
+            other.timer_sub_counter,
+            other.timer_state,
+            other.timer_control,
+        )
+    }
+
     pub fn new() -> Self {
         InterruptController {
             master_enable: false, // should implement delay, 1 instruction
@@ -78,6 +131,7 @@ impl InterruptController {
             timer_counter: 0,
             timer_modulo: 0,
             timer_sub_counter: 0,
+            timer_state: TimerState::Running,
 
             timer_control: 0,
 
@@ -87,10 +141,50 @@ impl InterruptController {
             keys_state: [false; Keys::KeysMax as usize],
             select_buttons: false,
             select_directions: false,
+
+            cycles: 0,
+            interrupt_log: None,
+        }
+    }
+
+    /// Back to the power-on state, the interrupt log is kept.
+    pub fn reset(&mut self) {
+        self.load_state(&InterruptController::new());
+    }
+
+    /// Takes every register and timer counter from `state`, the interrupt
+    /// log is kept.
+    pub fn load_state(&mut self, state: &InterruptController) {
+        let interrupt_log = self.interrupt_log.take();
+        *self = state.clone();
+        self.interrupt_log = interrupt_log;
+    }
+
+    /// Enables the log of serviced interrupts, keeping the last
+    /// `INTERRUPT_LOG_SIZE` events.
+    pub fn set_interrupt_log(&mut self, enabled: bool) {
+        self.interrupt_log = enabled.then(|| VecDeque::with_capacity(INTERRUPT_LOG_SIZE));
+    }
+
+    pub fn interrupt_log(&self) -> impl Iterator<Item = &InterruptEvent> {
+        self.interrupt_log.iter().flatten()
+    }
+
+    pub fn log_interrupt(&mut self, kind: IntKind) {
+        if let Some(log) = self.interrupt_log.as_mut() {
+            if log.len() == INTERRUPT_LOG_SIZE {
+                log.pop_front();
+            }
+            log.push_back(InterruptEvent {
+                kind,
+                cycle: self.cycles,
+            });
         }
     }
 
     pub fn timer_step(&mut self, ticks: u32) {
+        self.cycles += ticks as u64;
+
         // divider (increase at 1/256 the frequency of the CPU)
         self.divider_counter = self.divider_counter.wrapping_add(ticks);
         while self.divider_counter >= 256 {
@@ -99,18 +193,25 @@ impl InterruptController {
         }
 
         // timer
+        self.timer_state = match self.timer_state {
+            TimerState::Overflowing => {
+                self.timer_counter = self.timer_modulo;
+                self.trigger_timer_int();
+                TimerState::Reloading
+            }
+            TimerState::Running | TimerState::Reloading => TimerState::Running,
+        };
+
         if self.is_timer_enabled() {
             let divider = self.timer_divider();
 
             self.timer_sub_counter += ticks;
             while self.timer_sub_counter >= divider {
                 let (new_timer, carry) = self.timer_counter.overflowing_add(1);
-                self.timer_counter = if carry {
-                    self.trigger_timer_int();
-                    self.timer_modulo
-                } else {
-                    new_timer
-                };
+                self.timer_counter = new_timer;
+                if carry {
+                    self.timer_state = TimerState::Overflowing;
+                }
 
                 self.timer_sub_counter -= divider;
             }
@@ -120,6 +221,30 @@ impl InterruptController {
         }
     }
 
+    pub fn timer_state(&self) -> TimerState {
+        self.timer_state
+    }
+
+    /// CPU write to TIMA.
+    pub fn write_timer_counter(&mut self, value: u8) {
+        match self.timer_state {
+            TimerState::Running => self.timer_counter = value,
+            TimerState::Overflowing => {
+                self.timer_counter = value;
+                self.timer_state = TimerState::Running;
+            }
+            TimerState::Reloading => {}
+        }
+    }
+
+    /// CPU write to TMA.
+    pub fn write_timer_modulo(&mut self, value: u8) {
+        self.timer_modulo = value;
+        if self.timer_state == TimerState::Reloading {
+            self.timer_counter = value;
+        }
+    }
+
     fn timer_divider(&self) -> u32 {
         let control = self.timer_control & 0b11;
         match control {
@@ -135,25 +260,31 @@ impl InterruptController {
         self.timer_control & 0b100 == 0b100
     }
 
-    pub fn trigger_vblank_int(&mut self) {
-        self.interrupt_flag |= IntKind::VBLANK;
+    /// Sets the IF bits of `kind`, as the hardware raising them would.
+    pub fn request_interrupt(&mut self, kind: IntKind) {
+        self.interrupt_flag |= kind & !IntKind::DUMMY;
         self.new_int_waiting = true;
+    }
+
+    pub fn trigger_vblank_int(&mut self) {
+        self.request_interrupt(IntKind::VBLANK);
         self.should_redraw = true;
     }
 
     pub fn trigger_timer_int(&mut self) {
-        self.interrupt_flag |= IntKind::TIMER;
-        self.new_int_waiting = true;
+        self.request_interrupt(IntKind::TIMER);
     }
 
     pub fn trigger_lcd_stat_int(&mut self) {
-        self.interrupt_flag |= IntKind::LCD_STAT;
-        self.new_int_waiting = true;
+        self.request_interrupt(IntKind::LCD_STAT);
+    }
+
+    pub fn trigger_serial_int(&mut self) {
+        self.request_interrupt(IntKind::SERIAL);
     }
 
     pub fn trigger_joypad_int(&mut self) {
-        self.interrupt_flag |= IntKind::JOYPAD;
-        self.new_int_waiting = true;
+        self.request_interrupt(IntKind::JOYPAD);
     }
 
     pub fn handle_new_interrupt(&mut self) -> bool {
@@ -162,12 +293,23 @@ impl InterruptController {
         res
     }
 
+    /// Interrupts both requested in IF and enabled in IE.
+    pub fn pending_interrupts(&self) -> IntKind {
+        self.interrupt_flag & self.interrupt_enable & !IntKind::DUMMY
+    }
+
+    /// HALT ends as soon as an enabled interrupt is requested, even with IME
+    /// cleared.
+    pub fn should_wake_from_halt(&self) -> bool {
+        !self.pending_interrupts().is_empty()
+    }
+
     pub fn is_interrupt_waiting(&self) -> Option<IntKind> {
         if !self.master_enable {
             return None;
         }
 
-        let requested = self.interrupt_flag & self.interrupt_enable & !IntKind::DUMMY;
+        let requested = self.pending_interrupts();
 
         [
             IntKind::VBLANK,
@@ -181,56 +323,76 @@ impl InterruptController {
         .copied()
     }
 
-    pub fn change_key_state(&mut self, key: Keys, pressed: bool) {
-        let old_key_state = std::mem::replace(&mut self.keys_state[key as usize], pressed);
-        if pressed && !old_key_state {
+    pub fn keys_state(&self) -> [bool; Keys::KeysMax as usize] {
+        self.keys_state
+    }
+
+    /// Input lines pulled low by a pressed key of a selected group. A line
+    /// going low requests the joypad interrupt.
+    fn pressed_lines(&self) -> JoypadBits {
+        let groups = [
+            (
+                self.select_directions,
+                [Keys::Down, Keys::Up, Keys::Left, Keys::Right],
+            ),
+            (
+                self.select_buttons,
+                [Keys::Start, Keys::Select, Keys::B, Keys::A],
+            ),
+        ];
+        let lines = [
+            JoypadBits::P13_INPUT_DOWN_OR_START,
+            JoypadBits::P12_INPUT_UP_OR_SELECT,
+            JoypadBits::P11_INPUT_LEFT_OR_B,
+            JoypadBits::P10_INPUT_RIGHT_OR_A,
+        ];
+
+        let mut flags = JoypadBits::empty();
+        for (selected, keys) in groups {
+            if !selected {
+                continue;
+            }
+            for (key, line) in keys.into_iter().zip(lines) {
+                if self.keys_state[key as usize] {
+                    flags |= line;
+                }
+            }
+        }
+        flags
+    }
+
+    fn update_joypad(&mut self, change: impl FnOnce(&mut Self)) {
+        let pressed_lines = self.pressed_lines();
+        change(self);
+        if !self.pressed_lines().difference(pressed_lines).is_empty() {
             self.trigger_joypad_int();
         }
     }
 
+    pub fn change_key_state(&mut self, key: Keys, pressed: bool) {
+        self.update_joypad(|ic| ic.keys_state[key as usize] = pressed);
+    }
+
     pub fn write_joypad_reg(&mut self, reg_value: u8) {
         // 0 is selected
         let flags = JoypadBits::from_bits_truncate(!reg_value);
 
-        self.select_directions = flags.contains(JoypadBits::P14_SELECT_DIRECTION_KEYS);
-        self.select_buttons = flags.contains(JoypadBits::P15_SELECT_BUTTON_KEYS);
+        self.update_joypad(|ic| {
+            ic.select_directions = flags.contains(JoypadBits::P14_SELECT_DIRECTION_KEYS);
+            ic.select_buttons = flags.contains(JoypadBits::P15_SELECT_BUTTON_KEYS);
+        });
     }
 
     pub fn read_joypad_reg(&mut self) -> u8 {
-        let mut flags = JoypadBits::empty();
-
+        let mut flags = self.pressed_lines();
         if self.select_directions {
             flags |= JoypadBits::P14_SELECT_DIRECTION_KEYS;
-            if self.keys_state[Keys::Down as usize] {
-                flags |= JoypadBits::P13_INPUT_DOWN_OR_START;
-            }
-            if self.keys_state[Keys::Up as usize] {
-                flags |= JoypadBits::P12_INPUT_UP_OR_SELECT;
-            }
-            if self.keys_state[Keys::Left as usize] {
-                flags |= JoypadBits::P11_INPUT_LEFT_OR_B;
-            }
-            if self.keys_state[Keys::Right as usize] {
-                flags |= JoypadBits::P10_INPUT_RIGHT_OR_A;
-            }
         }
-
         if self.select_buttons {
             flags |= JoypadBits::P15_SELECT_BUTTON_KEYS;
-            if self.keys_state[Keys::Start as usize] {
-                flags |= JoypadBits::P13_INPUT_DOWN_OR_START;
-            }
-            if self.keys_state[Keys::Select as usize] {
-                flags |= JoypadBits::P12_INPUT_UP_OR_SELECT;
-            }
-            if self.keys_state[Keys::B as usize] {
-                flags |= JoypadBits::P11_INPUT_LEFT_OR_B;
-            }
-            if self.keys_state[Keys::A as usize] {
-                flags |= JoypadBits::P10_INPUT_RIGHT_OR_A;
-            }
         }
 
+        // selected lines and pressed keys read as 0, the unused bits 6-7 as 1
         !flags.bits()
     }
 }
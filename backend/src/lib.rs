@@ -1,13 +1,25 @@
 #![allow(clippy::new_without_default)]
 
+pub mod apu;
+pub mod boot_logo;
 pub mod cpu;
 pub mod display;
+pub mod emulator;
 pub mod interrupt;
 pub mod memory;
+pub mod model;
 pub mod ppu;
+#[cfg(feature = "scripting")]
+pub mod scripting;
 pub mod serial;
+pub mod state;
+pub mod trace;
 pub mod utils;
+pub mod wav;
+pub mod y4m;
 
 pub use cpu::CPU;
+pub use emulator::Emulator;
 pub use memory::Memory;
+pub use model::Model;
 pub use ppu::{PPU, SCREEN_HEIGHT, SCREEN_WIDTH};
@@ -0,0 +1,109 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    interrupt::{InterruptController, InterruptControllerPtr},
+    model::Model,
+    serial::{SerialPtr, StdoutSerialWrite},
+};
+
+use super::{BoxMBC, MMU};
+
+/// Builds an `MMU` in a given state, e.g. to run the PPU on prepared VRAM
+/// content. By default the bootstrap ROM is unmounted, a new interrupt
+/// controller is created and the serial output goes to stdout.
+pub struct MMUBuilder {
+    mbc: BoxMBC,
+    interrupt_controller: Option<InterruptControllerPtr>,
+    serial: Option<SerialPtr>,
+    model: Model,
+    #[cfg(feature = "cgb")]
+    cgb_mode: bool,
+    bootstrap_rom: Option<Vec<u8>>,
+    vram: Vec<(usize, Vec<u8>)>,
+    wram: Option<Vec<u8>>,
+}
+
+impl MMUBuilder {
+    pub fn new(mbc: BoxMBC) -> Self {
+        MMUBuilder {
+            mbc,
+            interrupt_controller: None,
+            serial: None,
+            model: Model::default(),
+            #[cfg(feature = "cgb")]
+            cgb_mode: false,
+            bootstrap_rom: None,
+            vram: Vec::new(),
+            wram: None,
+        }
+    }
+
+    pub fn interrupt_controller(mut self, interrupt_controller: InterruptControllerPtr) -> Self {
+        self.interrupt_controller = Some(interrupt_controller);
+        self
+    }
+
+    pub fn serial(mut self, serial: SerialPtr) -> Self {
+        self.serial = Some(serial);
+        self
+    }
+
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Enables the second CGB VRAM bank, needed to preload it.
+    #[cfg(feature = "cgb")]
+    pub fn cgb_mode(mut self, cgb_mode: bool) -> Self {
+        self.cgb_mode = cgb_mode;
+        self
+    }
+
+    /// Mounts `bootstrap_rom` at 0x0000, the CPU is expected to start there.
+    pub fn bootstrap_rom(mut self, bootstrap_rom: &[u8]) -> Self {
+        self.bootstrap_rom = Some(bootstrap_rom.to_vec());
+        self
+    }
+
+    /// Copies `data` at the start of VRAM bank `bank`.
+    pub fn vram(mut self, bank: usize, data: &[u8]) -> Self {
+        self.vram.push((bank, data.to_vec()));
+        self
+    }
+
+    /// Copies `data` at the start of WRAM, 0xC000.
+    pub fn wram(mut self, data: &[u8]) -> Self {
+        self.wram = Some(data.to_vec());
+        self
+    }
+
+    /// Panics if a preloaded VRAM bank does not exist or some preloaded data
+    /// does not fit.
+    pub fn build(self) -> MMU {
+        let interrupt_controller = self
+            .interrupt_controller
+            .unwrap_or_else(|| Arc::new(Mutex::new(InterruptController::new())));
+        let serial = self.serial.unwrap_or_else(|| Box::new(StdoutSerialWrite));
+
+        let mut mmu = MMU::new(self.mbc, interrupt_controller, serial);
+        mmu.set_model(self.model);
+        #[cfg(feature = "cgb")]
+        mmu.set_cgb_mode(self.cgb_mode);
+
+        match self.bootstrap_rom {
+            Some(bootstrap_rom) => mmu.write_bootstrap_rom(&bootstrap_rom),
+            None => mmu.unmount_bootstrap_rom(),
+        }
+
+        for (bank, data) in self.vram {
+            let vram = mmu.vram.get_mut(bank).expect("VRAM bank out of range");
+            vram[..data.len()].copy_from_slice(&data);
+        }
+        if let Some(data) = self.wram {
+            mmu.wram[..data.len()].copy_from_slice(&data);
+        }
+
+        mmu
+    }
+}
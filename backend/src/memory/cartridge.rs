@@ -0,0 +1,90 @@
+use std::fmt;
+
+const LOGO_ADDR: usize = 0x0104;
+const LOGO_END_ADDR: usize = 0x0134;
+const TITLE_ADDR: usize = 0x0134;
+const TITLE_END_ADDR: usize = 0x0143;
+const CGB_FLAG_ADDR: usize = 0x0143;
+const CARTRIDGE_TYPE_ADDR: usize = 0x0147;
+const HEADER_END_ADDR: usize = 0x0150;
+
+const NINTENDO_LOGO: [u8; LOGO_END_ADDR - LOGO_ADDR] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub cgb_flag: u8,
+    pub cartridge_type: u8,
+}
+
+impl CartridgeHeader {
+    pub fn read(content: &[u8]) -> Option<Self> {
+        if content.len() < HEADER_END_ADDR {
+            return None;
+        }
+
+        let title = content[TITLE_ADDR..TITLE_END_ADDR]
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as char)
+            .collect();
+
+        Some(CartridgeHeader {
+            title,
+            cgb_flag: content[CGB_FLAG_ADDR],
+            cartridge_type: content[CARTRIDGE_TYPE_ADDR],
+        })
+    }
+
+    pub fn supports_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 != 0
+    }
+
+    /// Logo bitmap shown by the bootstrap ROM.
+    pub fn logo(content: &[u8]) -> Option<&[u8]> {
+        content.get(LOGO_ADDR..LOGO_END_ADDR)
+    }
+
+    /// Checks the Nintendo logo the boot ROM compares before starting the
+    /// cartridge.
+    pub fn logo_valid(content: &[u8]) -> bool {
+        content.get(LOGO_ADDR..LOGO_END_ADDR) == Some(&NINTENDO_LOGO[..])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CartridgeError {
+    TooShort(usize),
+    UnsupportedRomSize(u8),
+    SizeMismatch { declared: usize, actual: usize },
+    UnknownRamSize(u8),
+    UnsupportedCartridgeType(u8),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::TooShort(len) => {
+                write!(f, "ROM is too short to contain a header ({} bytes)", len)
+            }
+            CartridgeError::UnsupportedRomSize(tag) => {
+                write!(f, "unsupported ROM size tag {:#04x}", tag)
+            }
+            CartridgeError::SizeMismatch { declared, actual } => write!(
+                f,
+                "ROM header declares {} bytes but the file is only {} bytes",
+                declared, actual
+            ),
+            CartridgeError::UnknownRamSize(tag) => write!(f, "unknown RAM size tag {:#04x}", tag),
+            CartridgeError::UnsupportedCartridgeType(kind) => {
+                write!(f, "unsupported cartridge type {:#04x}", kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
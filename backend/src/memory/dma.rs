@@ -1,3 +1,5 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DMAInfo {
     pub high_byte_addr: u8,
     timer: u8,
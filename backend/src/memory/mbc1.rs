@@ -1,20 +1,33 @@
-use log::error;
+use log::{debug, error};
 
-use super::MBC;
+use super::{CartridgeHeader, MbcState, MBC, MBC_LOG_TARGET};
 
 const BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+const MULTICART_BANK_COUNT: usize = 64;
+const MULTICART_GAME_BANKS: usize = 16;
 
 pub struct MBC1 {
     bank_count: usize,
-    bank_index: usize,
-    ram_index: usize,
+    /// 5-bit register written at 0x2000-0x3FFF
+    rom_bank_low: u8,
+    /// 2-bit register written at 0x4000-0x5FFF, selecting the RAM bank and
+    /// the upper ROM bank bits
+    bank_high: u8,
+    /// Mode written at 0x6000-0x7FFF, maps `bank_high` on 0x0000-0x3FFF too
+    advanced_banking: bool,
+    /// Multicart boards wire `bank_high` to ROM bank bits 4-5 instead of 5-6,
+    /// and only the lower 4 bits of `rom_bank_low`
+    multicart: bool,
     ram_enabled: bool,
     rom: Vec<u8>,
     ram: Vec<u8>,
+    /// RAM kept across power cycles and saved
+    battery: bool,
 }
 
 impl MBC1 {
-    pub fn new(content: &[u8], rom_size: usize, ram_size: usize) -> Self {
+    pub fn new(content: &[u8], rom_size: usize, ram_size: usize, battery: bool) -> Self {
         assert_eq!(content.len() % BANK_SIZE, 0);
 
         let mut rom: Vec<u8> = content.to_vec();
@@ -24,23 +37,97 @@ impl MBC1 {
 
         MBC1 {
             bank_count: content.len() / BANK_SIZE,
-            bank_index: 1,
-            ram_index: 0,
+            rom_bank_low: 1,
+            bank_high: 0,
+            advanced_banking: false,
+            multicart: is_multicart(content),
             ram_enabled: false,
             rom,
             ram,
+            battery,
         }
     }
+
+    fn bank_high_shift(&self) -> usize {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    fn rom_bank(&self) -> usize {
+        // 0 is turned into 1 before the multicart wiring drops bit 4, so 0x10
+        // still selects the first bank of a game
+        let mut low = self.rom_bank_low.max(1) as usize;
+        if self.multicart {
+            low &= 0xF;
+        }
+        ((self.bank_high as usize) << self.bank_high_shift() | low) % self.bank_count
+    }
+
+    fn rom_bank_zero(&self) -> usize {
+        if self.advanced_banking {
+            ((self.bank_high as usize) << self.bank_high_shift()) % self.bank_count
+        } else {
+            0
+        }
+    }
+
+    /// Banks mapped at 0x0000, 0x4000 and 0xA000.
+    fn banks(&self) -> (usize, usize, usize) {
+        (self.rom_bank_zero(), self.rom_bank(), self.ram_bank())
+    }
+
+    fn log_bank_switches(&self, (rom_zero, rom, ram): (usize, usize, usize)) {
+        let (new_rom_zero, new_rom, new_ram) = self.banks();
+        if new_rom_zero != rom_zero {
+            debug!(target: MBC_LOG_TARGET, "ROM bank {:#04x} mapped at 0x0000", new_rom_zero);
+        }
+        if new_rom != rom {
+            debug!(target: MBC_LOG_TARGET, "ROM bank {:#04x} mapped at 0x4000", new_rom);
+        }
+        if new_ram != ram {
+            debug!(target: MBC_LOG_TARGET, "RAM bank {} mapped at 0xA000", new_ram);
+        }
+    }
+
+    /// `bank_high` only selects the RAM bank in the advanced banking mode,
+    /// bank 0 is mapped otherwise.
+    fn ram_bank(&self) -> usize {
+        if self.advanced_banking {
+            self.bank_high as usize
+        } else {
+            0
+        }
+    }
+
+    /// Carts with less than 4 banks of RAM ignore the upper bank bits.
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        Some((self.ram_bank() * RAM_BANK_SIZE + (addr as usize - 0xA000)) % self.ram.len())
+    }
+}
+
+/// Multicarts have no dedicated cartridge type, they are detected as 1MB ROMs
+/// with a game header, and so the Nintendo logo, at the start of the second
+/// game.
+fn is_multicart(content: &[u8]) -> bool {
+    content.len() == MULTICART_BANK_COUNT * BANK_SIZE
+        && CartridgeHeader::logo_valid(&content[MULTICART_GAME_BANKS * BANK_SIZE..])
 }
 
 impl MBC for MBC1 {
     fn read_memory(&self, addr: u16) -> u8 {
         match addr {
-            0x0000..=0x3FFF => self.rom[addr as usize],
-            0x4000..=0x7FFF => self.rom[self.bank_index * BANK_SIZE + (addr as usize - 0x4000)],
+            0x0000..=0x3FFF => self.rom[self.rom_bank_zero() * BANK_SIZE + addr as usize],
+            0x4000..=0x7FFF => self.rom[self.rom_bank() * BANK_SIZE + (addr as usize - 0x4000)],
             0xA000..=0xBFFF => {
                 if self.ram_enabled {
-                    self.ram[self.ram_index * BANK_SIZE + (addr as usize - 0xA000)]
+                    self.ram_offset(addr)
+                        .map_or(0xFF, |offset| self.ram[offset])
                 } else {
                     error!("Read from ram with ram disabled");
                     0xFF
@@ -53,28 +140,26 @@ impl MBC for MBC1 {
     fn write_memory(&mut self, addr: u16, value: u8) {
         match addr {
             0x0000..=0x1FFF => {
-                self.ram_enabled = value == 0x0A;
-            }
-            0x2000..=0x3FFF => {
-                let value = value & 0b11111;
-                let mut bank_index = value as usize % self.bank_count;
-                if bank_index & 0xF == 0 {
-                    bank_index += 1;
+                let ram_enabled = value == 0x0A;
+                if ram_enabled != self.ram_enabled {
+                    debug!(target: MBC_LOG_TARGET, "RAM enabled: {}", ram_enabled);
                 }
-                self.bank_index = bank_index;
+                self.ram_enabled = ram_enabled;
             }
-            0x4000..=0x5FFF => {
-                let value = value & 0b11;
-                self.ram_index = value as usize;
-            }
-            0x6000..=0x7FFF => {
-                if value != 0 {
-                    unimplemented!()
+            0x2000..=0x7FFF => {
+                let banks = self.banks();
+                match addr {
+                    0x2000..=0x3FFF => self.rom_bank_low = value & 0b11111,
+                    0x4000..=0x5FFF => self.bank_high = value & 0b11,
+                    _ => self.advanced_banking = value & 1 != 0,
                 }
+                self.log_bank_switches(banks);
             }
             0xA000..=0xBFFF => {
                 if self.ram_enabled {
-                    self.ram[self.ram_index * BANK_SIZE + (addr as usize - 0xA000)] = value;
+                    if let Some(offset) = self.ram_offset(addr) {
+                        self.ram[offset] = value;
+                    }
                 } else {
                     error!("Write to ram with ram disabled");
                 }
@@ -82,4 +167,58 @@ impl MBC for MBC1 {
             _ => panic!("Access MBC in non managed space"),
         }
     }
+
+    fn dump_ram(&self) -> Option<Vec<u8>> {
+        if self.ram.is_empty() || !self.battery {
+            None
+        } else {
+            Some(self.ram.clone())
+        }
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn power_cycle(&mut self) {
+        self.rom_bank_low = 1;
+        self.bank_high = 0;
+        self.advanced_banking = false;
+        self.ram_enabled = false;
+        if !self.battery {
+            self.ram.fill(0);
+        }
+    }
+
+    fn current_rom_bank(&self) -> usize {
+        self.rom_bank()
+    }
+
+    fn current_ram_bank(&self) -> usize {
+        self.ram_bank()
+    }
+
+    fn save_state(&self) -> MbcState {
+        MbcState {
+            registers: vec![
+                self.rom_bank_low,
+                self.bank_high,
+                self.advanced_banking as u8,
+                self.ram_enabled as u8,
+            ],
+            ram: self.ram.clone(),
+            rtc: None,
+        }
+    }
+
+    fn load_state(&mut self, state: &MbcState) {
+        if let [rom_bank_low, bank_high, advanced_banking, ram_enabled] = state.registers[..] {
+            self.rom_bank_low = rom_bank_low;
+            self.bank_high = bank_high;
+            self.advanced_banking = advanced_banking != 0;
+            self.ram_enabled = ram_enabled != 0;
+        }
+        state.restore_ram(&mut self.ram);
+    }
 }
@@ -0,0 +1,135 @@
+use log::{debug, error};
+
+use super::{MbcState, MBC, MBC_LOG_TARGET};
+
+const ROM_BANK_SIZE: usize = 0x4000;
+/// 512 half-bytes, mirrored over 0xA000-0xBFFF
+const RAM_SIZE: usize = 0x200;
+// bit 8 of the address picks between RAM enable and ROM bank writes
+const ROM_BANK_SELECT_BIT: u16 = 1 << 8;
+
+pub struct MBC2 {
+    bank_count: usize,
+    /// 4-bit register written at 0x0000-0x3FFF with address bit 8 set
+    rom_bank: u8,
+    ram_enabled: bool,
+    rom: Vec<u8>,
+    /// Only the low nibble of each byte is stored
+    ram: Box<[u8; RAM_SIZE]>,
+    /// RAM kept across power cycles and saved
+    battery: bool,
+}
+
+impl MBC2 {
+    pub fn new(content: &[u8], rom_size: usize, battery: bool) -> Self {
+        assert_eq!(content.len() % ROM_BANK_SIZE, 0);
+
+        let mut rom: Vec<u8> = content.to_vec();
+        rom.resize(rom_size, 0);
+
+        MBC2 {
+            bank_count: content.len() / ROM_BANK_SIZE,
+            rom_bank: 1,
+            ram_enabled: false,
+            rom,
+            ram: Box::new([0; RAM_SIZE]),
+            battery,
+        }
+    }
+
+    fn mapped_rom_bank(&self) -> usize {
+        self.rom_bank.max(1) as usize % self.bank_count
+    }
+}
+
+impl MBC for MBC2 {
+    fn read_memory(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => self.rom[addr as usize],
+            0x4000..=0x7FFF => {
+                self.rom[self.mapped_rom_bank() * ROM_BANK_SIZE + (addr as usize - 0x4000)]
+            }
+            0xA000..=0xBFFF => {
+                if self.ram_enabled {
+                    // the upper nibble is not connected and reads as 1s
+                    0xF0 | self.ram[(addr as usize - 0xA000) % RAM_SIZE]
+                } else {
+                    error!("Read from ram with ram disabled");
+                    0xFF
+                }
+            }
+            _ => panic!("Access MBC in non managed space"),
+        }
+    }
+
+    fn write_memory(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x3FFF if addr & ROM_BANK_SELECT_BIT != 0 => {
+                let rom_bank = self.mapped_rom_bank();
+                self.rom_bank = value & 0x0F;
+                if self.mapped_rom_bank() != rom_bank {
+                    debug!(
+                        target: MBC_LOG_TARGET,
+                        "ROM bank {:#04x} mapped at 0x4000",
+                        self.mapped_rom_bank()
+                    );
+                }
+            }
+            0x0000..=0x3FFF => {
+                let ram_enabled = value & 0x0F == 0x0A;
+                if ram_enabled != self.ram_enabled {
+                    debug!(target: MBC_LOG_TARGET, "RAM enabled: {}", ram_enabled);
+                }
+                self.ram_enabled = ram_enabled;
+            }
+            0x4000..=0x7FFF => {}
+            0xA000..=0xBFFF => {
+                if self.ram_enabled {
+                    self.ram[(addr as usize - 0xA000) % RAM_SIZE] = value & 0x0F;
+                } else {
+                    error!("Write to ram with ram disabled");
+                }
+            }
+            _ => panic!("Access MBC in non managed space"),
+        }
+    }
+
+    /// One byte per half-byte cell, as most emulators save it.
+    fn dump_ram(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.to_vec())
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        for (cell, value) in self.ram.iter_mut().zip(data) {
+            *cell = value & 0x0F;
+        }
+    }
+
+    fn power_cycle(&mut self) {
+        self.rom_bank = 1;
+        self.ram_enabled = false;
+        if !self.battery {
+            self.ram.fill(0);
+        }
+    }
+
+    fn current_rom_bank(&self) -> usize {
+        self.mapped_rom_bank()
+    }
+
+    fn save_state(&self) -> MbcState {
+        MbcState {
+            registers: vec![self.rom_bank, self.ram_enabled as u8],
+            ram: self.ram.to_vec(),
+            rtc: None,
+        }
+    }
+
+    fn load_state(&mut self, state: &MbcState) {
+        if let [rom_bank, ram_enabled] = state.registers[..] {
+            self.rom_bank = rom_bank;
+            self.ram_enabled = ram_enabled != 0;
+        }
+        state.restore_ram(&mut self.ram[..]);
+    }
+}
@@ -0,0 +1,318 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{debug, error};
+
+use super::{
+    rtc::{self, RtcRegisters, RtcSave},
+    MbcState, MBC, MBC_LOG_TARGET,
+};
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+
+const RTC_SECONDS: u8 = 0x08;
+const RTC_MINUTES: u8 = 0x09;
+const RTC_HOURS: u8 = 0x0A;
+const RTC_DAYS_LOW: u8 = 0x0B;
+const RTC_DAYS_HIGH: u8 = 0x0C;
+
+// bits of the upper day counter register
+const DAY_HIGH_BIT: u8 = 1 << 0;
+const HALT_BIT: u8 = 1 << 6;
+const DAY_CARRY_BIT: u8 = 1 << 7;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const DAY_COUNTER_LIMIT: u64 = 512;
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Clock running on the wall clock time: `registers` is the value at
+/// `base_time`, the elapsed time is added when they are read.
+#[derive(Debug, Clone, Copy)]
+struct Clock {
+    registers: RtcRegisters,
+    base_time: u64,
+}
+
+impl Clock {
+    fn new() -> Self {
+        Clock {
+            registers: RtcRegisters::default(),
+            base_time: unix_time(),
+        }
+    }
+
+    fn now(&self) -> RtcRegisters {
+        let registers = self.registers;
+        let elapsed = unix_time().saturating_sub(self.base_time);
+        if registers.days_high & HALT_BIT != 0 || elapsed == 0 {
+            return registers;
+        }
+
+        let days = ((registers.days_high & DAY_HIGH_BIT) as u64) << 8 | registers.days_low as u64;
+        let total = days * SECONDS_PER_DAY
+            + registers.hours as u64 * 3600
+            + registers.minutes as u64 * 60
+            + registers.seconds as u64
+            + elapsed;
+
+        let days = total / SECONDS_PER_DAY;
+        let mut days_high = registers.days_high & !DAY_HIGH_BIT;
+        if days >= DAY_COUNTER_LIMIT {
+            days_high |= DAY_CARRY_BIT;
+        }
+        let days = days % DAY_COUNTER_LIMIT;
+
+        RtcRegisters {
+            seconds: (total % 60) as u8,
+            minutes: (total / 60 % 60) as u8,
+            hours: (total / 3600 % 24) as u8,
+            days_low: days as u8,
+            days_high: days_high | (days >> 8) as u8,
+        }
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        let mut registers = self.now();
+        match register {
+            RTC_SECONDS => registers.seconds = value & 0x3F,
+            RTC_MINUTES => registers.minutes = value & 0x3F,
+            RTC_HOURS => registers.hours = value & 0x1F,
+            RTC_DAYS_LOW => registers.days_low = value,
+            _ => registers.days_high = value & (DAY_HIGH_BIT | HALT_BIT | DAY_CARRY_BIT),
+        }
+        self.registers = registers;
+        self.base_time = unix_time();
+    }
+}
+
+pub struct MBC3 {
+    bank_count: usize,
+    /// 7-bit register written at 0x2000-0x3FFF
+    rom_bank: u8,
+    /// RAM bank 0-3 or RTC register 0x08-0x0C, written at 0x4000-0x5FFF
+    ram_bank: u8,
+    ram_enabled: bool,
+    /// Last value written at 0x6000-0x7FFF, the clock is latched when 1
+    /// follows 0
+    latch_value: u8,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    /// RAM and clock kept across power cycles and saved
+    battery: bool,
+    clock: Option<Clock>,
+    latched: RtcRegisters,
+}
+
+impl MBC3 {
+    pub fn new(
+        content: &[u8],
+        rom_size: usize,
+        ram_size: usize,
+        battery: bool,
+        timer: bool,
+    ) -> Self {
+        assert_eq!(content.len() % ROM_BANK_SIZE, 0);
+
+        let mut rom: Vec<u8> = content.to_vec();
+        rom.resize(rom_size, 0);
+
+        MBC3 {
+            bank_count: content.len() / ROM_BANK_SIZE,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            latch_value: 0xFF,
+            rom,
+            ram: vec![0; ram_size],
+            battery,
+            clock: timer.then(Clock::new),
+            latched: RtcRegisters::default(),
+        }
+    }
+
+    fn mapped_rom_bank(&self) -> usize {
+        self.rom_bank.max(1) as usize % self.bank_count
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        let offset = self.ram_bank as usize * RAM_BANK_SIZE + (addr as usize - 0xA000);
+        (offset < self.ram.len()).then_some(offset)
+    }
+
+    fn read_latched(&self, register: u8) -> u8 {
+        let latched = &self.latched;
+        match register {
+            RTC_SECONDS => latched.seconds,
+            RTC_MINUTES => latched.minutes,
+            RTC_HOURS => latched.hours,
+            RTC_DAYS_LOW => latched.days_low,
+            _ => latched.days_high,
+        }
+    }
+}
+
+impl MBC for MBC3 {
+    fn read_memory(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => self.rom[addr as usize],
+            0x4000..=0x7FFF => {
+                self.rom[self.mapped_rom_bank() * ROM_BANK_SIZE + (addr as usize - 0x4000)]
+            }
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    error!("Read from ram with ram disabled");
+                    return 0xFF;
+                }
+                match self.ram_bank {
+                    RTC_SECONDS..=RTC_DAYS_HIGH if self.clock.is_some() => {
+                        self.read_latched(self.ram_bank)
+                    }
+                    _ => self
+                        .ram_offset(addr)
+                        .map_or(0xFF, |offset| self.ram[offset]),
+                }
+            }
+            _ => panic!("Access MBC in non managed space"),
+        }
+    }
+
+    fn write_memory(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => {
+                let ram_enabled = value & 0x0F == 0x0A;
+                if ram_enabled != self.ram_enabled {
+                    debug!(target: MBC_LOG_TARGET, "RAM enabled: {}", ram_enabled);
+                }
+                self.ram_enabled = ram_enabled;
+            }
+            0x2000..=0x3FFF => {
+                let rom_bank = self.mapped_rom_bank();
+                self.rom_bank = value & 0x7F;
+                if self.mapped_rom_bank() != rom_bank {
+                    debug!(
+                        target: MBC_LOG_TARGET,
+                        "ROM bank {:#04x} mapped at 0x4000",
+                        self.mapped_rom_bank()
+                    );
+                }
+            }
+            0x4000..=0x5FFF => {
+                if value != self.ram_bank {
+                    debug!(target: MBC_LOG_TARGET, "RAM bank {:#04x} mapped at 0xA000", value);
+                }
+                self.ram_bank = value;
+            }
+            0x6000..=0x7FFF => {
+                if let Some(clock) = &self.clock {
+                    if self.latch_value == 0 && value == 1 {
+                        self.latched = clock.now();
+                    }
+                }
+                self.latch_value = value;
+            }
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    error!("Write to ram with ram disabled");
+                    return;
+                }
+                match (self.ram_bank, self.clock.as_mut()) {
+                    (RTC_SECONDS..=RTC_DAYS_HIGH, Some(clock)) => {
+                        clock.write_register(self.ram_bank, value)
+                    }
+                    _ => {
+                        if let Some(offset) = self.ram_offset(addr) {
+                            self.ram[offset] = value;
+                        }
+                    }
+                }
+            }
+            _ => panic!("Access MBC in non managed space"),
+        }
+    }
+
+    /// The clock state follows the RAM content, see `rtc`.
+    fn dump_ram(&self) -> Option<Vec<u8>> {
+        if !self.battery {
+            return None;
+        }
+        match self.rtc_state() {
+            Some(state) => Some(rtc::join_save(&self.ram, &state)),
+            None if self.ram.is_empty() => None,
+            None => Some(self.ram.clone()),
+        }
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        let (ram, state) = rtc::split_save(data, self.ram.len());
+        let len = ram.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&ram[..len]);
+        if let Some(state) = state {
+            self.set_rtc_state(state);
+        }
+    }
+
+    fn power_cycle(&mut self) {
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.ram_enabled = false;
+        self.latch_value = 0xFF;
+        if !self.battery {
+            self.ram.fill(0);
+        }
+    }
+
+    fn current_rom_bank(&self) -> usize {
+        self.mapped_rom_bank()
+    }
+
+    fn current_ram_bank(&self) -> usize {
+        self.ram_bank as usize
+    }
+
+    fn rtc_state(&self) -> Option<RtcSave> {
+        self.clock.map(|clock| RtcSave {
+            current: clock.registers,
+            latched: self.latched,
+            timestamp: clock.base_time,
+        })
+    }
+
+    fn set_rtc_state(&mut self, state: RtcSave) {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.registers = state.current;
+            clock.base_time = state.timestamp;
+            self.latched = state.latched;
+        }
+    }
+
+    fn save_state(&self) -> MbcState {
+        MbcState {
+            registers: vec![
+                self.rom_bank,
+                self.ram_bank,
+                self.ram_enabled as u8,
+                self.latch_value,
+            ],
+            ram: self.ram.clone(),
+            rtc: self.rtc_state(),
+        }
+    }
+
+    fn load_state(&mut self, state: &MbcState) {
+        if let [rom_bank, ram_bank, ram_enabled, latch_value] = state.registers[..] {
+            self.rom_bank = rom_bank;
+            self.ram_bank = ram_bank;
+            self.ram_enabled = ram_enabled != 0;
+            self.latch_value = latch_value;
+        }
+        state.restore_ram(&mut self.ram);
+        if let Some(rtc) = state.rtc {
+            self.set_rtc_state(rtc);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use log::{debug, error};
+
+use super::{MbcState, MBC, MBC_LOG_TARGET};
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+
+pub struct MBC5 {
+    bank_count: usize,
+    /// 9-bit register, the low byte is written at 0x2000-0x2FFF and bit 8 at
+    /// 0x3000-0x3FFF. Unlike MBC1, bank 0 can be mapped at 0x4000
+    rom_bank: u16,
+    /// 4-bit register written at 0x4000-0x5FFF, bit 3 drives the rumble
+    /// motor on rumble cartridges
+    ram_bank: u8,
+    ram_enabled: bool,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    /// RAM kept across power cycles and saved
+    battery: bool,
+    rumble: bool,
+}
+
+impl MBC5 {
+    pub fn new(
+        content: &[u8],
+        rom_size: usize,
+        ram_size: usize,
+        battery: bool,
+        rumble: bool,
+    ) -> Self {
+        assert_eq!(content.len() % ROM_BANK_SIZE, 0);
+
+        let mut rom: Vec<u8> = content.to_vec();
+        rom.resize(rom_size, 0);
+
+        MBC5 {
+            bank_count: content.len() / ROM_BANK_SIZE,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            rom,
+            ram: vec![0; ram_size],
+            battery,
+            rumble,
+        }
+    }
+
+    fn mapped_rom_bank(&self) -> usize {
+        self.rom_bank as usize % self.bank_count
+    }
+
+    fn mapped_ram_bank(&self) -> usize {
+        if self.rumble {
+            (self.ram_bank & 0x07) as usize
+        } else {
+            self.ram_bank as usize
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        let offset = self.mapped_ram_bank() * RAM_BANK_SIZE + (addr as usize - 0xA000);
+        Some(offset % self.ram.len())
+    }
+
+    fn set_rom_bank(&mut self, rom_bank: u16) {
+        let previous = self.mapped_rom_bank();
+        self.rom_bank = rom_bank;
+        if self.mapped_rom_bank() != previous {
+            debug!(
+                target: MBC_LOG_TARGET,
+                "ROM bank {:#05x} mapped at 0x4000",
+                self.mapped_rom_bank()
+            );
+        }
+    }
+}
+
+impl MBC for MBC5 {
+    fn read_memory(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => self.rom[addr as usize],
+            0x4000..=0x7FFF => {
+                self.rom[self.mapped_rom_bank() * ROM_BANK_SIZE + (addr as usize - 0x4000)]
+            }
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    error!("Read from ram with ram disabled");
+                    return 0xFF;
+                }
+                self.ram_offset(addr)
+                    .map_or(0xFF, |offset| self.ram[offset])
+            }
+            _ => panic!("Access MBC in non managed space"),
+        }
+    }
+
+    fn write_memory(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => {
+                let ram_enabled = value == 0x0A;
+                if ram_enabled != self.ram_enabled {
+                    debug!(target: MBC_LOG_TARGET, "RAM enabled: {}", ram_enabled);
+                }
+                self.ram_enabled = ram_enabled;
+            }
+            0x2000..=0x2FFF => self.set_rom_bank((self.rom_bank & 0x100) | value as u16),
+            0x3000..=0x3FFF => {
+                self.set_rom_bank((self.rom_bank & 0xFF) | ((value as u16 & 1) << 8))
+            }
+            0x4000..=0x5FFF => {
+                let ram_bank = self.mapped_ram_bank();
+                self.ram_bank = value & 0x0F;
+                if self.mapped_ram_bank() != ram_bank {
+                    debug!(
+                        target: MBC_LOG_TARGET,
+                        "RAM bank {} mapped at 0xA000",
+                        self.mapped_ram_bank()
+                    );
+                }
+            }
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    error!("Write to ram with ram disabled");
+                    return;
+                }
+                if let Some(offset) = self.ram_offset(addr) {
+                    self.ram[offset] = value;
+                }
+            }
+            _ => panic!("Access MBC in non managed space"),
+        }
+    }
+
+    fn dump_ram(&self) -> Option<Vec<u8>> {
+        if self.ram.is_empty() || !self.battery {
+            None
+        } else {
+            Some(self.ram.clone())
+        }
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn power_cycle(&mut self) {
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.ram_enabled = false;
+        if !self.battery {
+            self.ram.fill(0);
+        }
+    }
+
+    fn current_rom_bank(&self) -> usize {
+        self.mapped_rom_bank()
+    }
+
+    fn current_ram_bank(&self) -> usize {
+        self.mapped_ram_bank()
+    }
+
+    fn save_state(&self) -> MbcState {
+        let [rom_bank_low, rom_bank_high] = self.rom_bank.to_le_bytes();
+        MbcState {
+            registers: vec![
+                rom_bank_low,
+                rom_bank_high,
+                self.ram_bank,
+                self.ram_enabled as u8,
+            ],
+            ram: self.ram.clone(),
+            rtc: None,
+        }
+    }
+
+    fn load_state(&mut self, state: &MbcState) {
+        if let [rom_bank_low, rom_bank_high, ram_bank, ram_enabled] = state.registers[..] {
+            self.rom_bank = u16::from_le_bytes([rom_bank_low, rom_bank_high]);
+            self.ram_bank = ram_bank;
+            self.ram_enabled = ram_enabled != 0;
+        }
+        state.restore_ram(&mut self.ram);
+    }
+}
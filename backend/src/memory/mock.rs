@@ -0,0 +1,132 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use super::Memory;
+
+type ReadHook = Box<dyn FnMut() -> u8 + Send>;
+type WriteHook = Box<dyn FnMut(u8) + Send>;
+
+struct MockState {
+    data: Box<[u8; 0x10000]>,
+    reads: Vec<u16>,
+    writes: Vec<(u16, u8)>,
+    read_hooks: HashMap<u16, ReadHook>,
+    write_hooks: HashMap<u16, WriteHook>,
+    ticks: u64,
+}
+
+/// Flat 64KB memory for testing the CPU and PPU parts in isolation. Every
+/// access is logged, and reads or writes of specific addresses can be
+/// scripted. Clones share the same state, like an `MMUPtr`.
+#[derive(Clone)]
+pub struct MockMemory {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl Default for MockMemory {
+    fn default() -> Self {
+        MockMemory {
+            state: Arc::new(Mutex::new(MockState {
+                data: Box::new([0; 0x10000]),
+                reads: Vec::new(),
+                writes: Vec::new(),
+                read_hooks: HashMap::new(),
+                write_hooks: HashMap::new(),
+                ticks: 0,
+            })),
+        }
+    }
+}
+
+impl MockMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the content at `addr` without logging a write.
+    pub fn set(&self, addr: u16, value: u8) {
+        self.state.lock().unwrap().data[addr as usize] = value;
+    }
+
+    /// Returns the content at `addr` without logging a read.
+    pub fn get(&self, addr: u16) -> u8 {
+        self.state.lock().unwrap().data[addr as usize]
+    }
+
+    /// Reads of `addr` return the value of `hook` instead of the content.
+    pub fn on_read(&self, addr: u16, hook: impl FnMut() -> u8 + Send + 'static) {
+        self.state
+            .lock()
+            .unwrap()
+            .read_hooks
+            .insert(addr, Box::new(hook));
+    }
+
+    /// Writes to `addr` are passed to `hook`, after updating the content.
+    pub fn on_write(&self, addr: u16, hook: impl FnMut(u8) + Send + 'static) {
+        self.state
+            .lock()
+            .unwrap()
+            .write_hooks
+            .insert(addr, Box::new(hook));
+    }
+
+    /// Addresses read so far, in order.
+    pub fn reads(&self) -> Vec<u16> {
+        self.state.lock().unwrap().reads.clone()
+    }
+
+    /// Writes so far, in order.
+    pub fn writes(&self) -> Vec<(u16, u8)> {
+        self.state.lock().unwrap().writes.clone()
+    }
+
+    pub fn write_count(&self, addr: u16) -> usize {
+        let state = self.state.lock().unwrap();
+        state.writes.iter().filter(|(a, _)| *a == addr).count()
+    }
+
+    pub fn clear_log(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.reads.clear();
+        state.writes.clear();
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.state.lock().unwrap().ticks
+    }
+}
+
+impl Memory for MockMemory {
+    fn read_memory(&self, addr: u16) -> u8 {
+        let mut state = self.state.lock().unwrap();
+        state.reads.push(addr);
+        match state.read_hooks.get_mut(&addr) {
+            Some(hook) => hook(),
+            None => state.data[addr as usize],
+        }
+    }
+
+    fn write_memory(&mut self, addr: u16, value: u8) {
+        let mut state = self.state.lock().unwrap();
+        state.writes.push((addr, value));
+        state.data[addr as usize] = value;
+        if let Some(hook) = state.write_hooks.get_mut(&addr) {
+            hook(value);
+        }
+    }
+
+    fn read_vram(&self, addr: u16, bank: usize) -> u8 {
+        if bank == 0 {
+            self.read_memory(addr)
+        } else {
+            0xFF
+        }
+    }
+
+    fn tick(&mut self) {
+        self.state.lock().unwrap().ticks += 1;
+    }
+}
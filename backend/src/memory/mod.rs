@@ -1,43 +1,116 @@
 use std::sync::{Arc, RwLock};
 
-use log::{debug, warn};
+use log::{debug, error, warn};
 
+mod builder;
+mod cartridge;
 mod dma;
 mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+#[cfg(feature = "test-utils")]
+pub mod mock;
+pub mod rtc;
+mod search;
 mod simple;
+mod state;
+pub use builder::MMUBuilder;
+pub use cartridge::{CartridgeError, CartridgeHeader};
 use dma::DMAInfo;
 use mbc1::MBC1;
+use mbc2::MBC2;
+use mbc3::MBC3;
+use mbc5::MBC5;
+pub use search::RamSnapshot;
 use simple::Simple as SimpleMBC;
+pub use state::{MbcState, MmuState};
 
 use crate::{
+    apu::APU,
     interrupt::{IntKind, InterruptControllerPtr},
+    model::Model,
     serial::SerialPtr,
 };
 
 pub type BoxMBC = Box<dyn MBC + Send + Sync>;
 
+/// Log target of the bank switches, e.g. `RUST_LOG=gbemu::mbc=debug`
+pub const MBC_LOG_TARGET: &str = "gbemu::mbc";
+pub type MMUPtr = Arc<RwLock<MMU>>;
+
 pub struct MMU {
     bootstrap_rom: Box<[u8; 0x100]>,
     mbc: BoxMBC,
-    vram: Box<[u8; 0x2000]>,
+    model: Model,
+    cgb_mode: bool,
+    vram: Vec<Box<[u8; VRAM_BANK_SIZE]>>,
+    vram_bank_index: usize,
     wram: Box<[u8; 0x2000]>,
+    wram_bank_index: usize,
     oam: Box<[u8; 0xA0]>,
     io_regs: Box<[u8; 0x80]>,
     hram: Box<[u8; 0x7F]>,
+    apu: APU,
     serial: SerialPtr,
     interrupt_controller: InterruptControllerPtr,
     waiting_dma: Option<DMAInfo>,
+    serial_transfer_cycles: u32,
+    // M-cycles left in the transfer clocked by this side
+    waiting_serial: Option<u32>,
+    oam_bug: bool,
+    oam_scan_row: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryRegion {
+    Vram,
+    Wram,
+    Oam,
+    Hram,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OamBugAccess {
+    Read,
+    Write,
 }
 
+const VRAM_BANK_SIZE: usize = 0x2000;
+const CGB_WRAM_BANK_COUNT: usize = 8;
+
+const OAM_ROW_SIZE: usize = 8;
+const OAM_ROW_COUNT: usize = 0xA0 / OAM_ROW_SIZE;
+
+// 0xE000-0xFDFF mirrors 0xC000-0xDDFF
+const ECHO_RAM_OFFSET: u16 = 0x2000;
+
 const JOYPAD_STATUS_ADDR: u16 = 0xFF00;
 
 const SERIAL_TRANSFER_DATA_ADDR: u16 = 0xFF01;
 const SERIAL_TRANSFER_CONTROL_ADDR: u16 = 0xFF02;
 
+/// M-cycles to transfer a byte with the internal clock, at 8192 bits per
+/// second.
+pub const SERIAL_TRANSFER_CYCLES: u32 = 1024;
+/// The CGB fast clock, bit 1 of SC, runs the transfers 32 times faster. The
+/// serial clock follows the CPU in double speed mode, so the count of
+/// M-cycles stays the same.
+pub const SERIAL_FAST_CLOCK_FACTOR: u32 = 32;
+
+const LCD_CONTROL_REG_ADDR: u16 = 0xFF40;
 const LCD_OAM_DMA_ADDR: u16 = 0xFF46;
 
+const SPEED_SWITCH_ADDR: u16 = 0xFF4D;
+const VRAM_BANK_CONTROL_ADDR: u16 = 0xFF4F;
+const WRAM_BANK_CONTROL_ADDR: u16 = 0xFF70;
+
 const BOOTSTRAP_ROM_MOUNT_CONTROL_ADDR: u16 = 0xFF50;
 
+const UNDOCUMENTED_FF75_ADDR: u16 = 0xFF75;
+const PCM12_ADDR: u16 = 0xFF76;
+const PCM34_ADDR: u16 = 0xFF77;
+
 const DIVIDER_REGISTER_ADDR: u16 = 0xFF04;
 const TIMER_COUNTER_ADDR: u16 = 0xFF05;
 const TIMER_MODULO_ADDR: u16 = 0xFF06;
@@ -45,26 +118,247 @@ const TIMER_CONTROL_ADDR: u16 = 0xFF07;
 
 const INTERRUPT_FLAG_ADDR: u16 = 0xFF0F;
 
+const SOUND_REGS_START_ADDR: u16 = 0xFF10;
+// including the wave RAM
+const SOUND_REGS_END_ADDR: u16 = 0xFF3F;
+
 impl MMU {
     pub fn new(mbc: BoxMBC, int_controller: InterruptControllerPtr, serial: SerialPtr) -> Self {
-        let mut mmu = MMU {
+        MMU {
             bootstrap_rom: Box::new([0; 0x100]),
             mbc,
-            vram: Box::new([0; 0x2000]),
+            model: Model::default(),
+            cgb_mode: false,
+            vram: vec![Box::new([0; VRAM_BANK_SIZE])],
+            vram_bank_index: 0,
             wram: Box::new([0; 0x2000]),
+            wram_bank_index: 0,
             oam: Box::new([0; 0xA0]),
             io_regs: Box::new([0; 0x80]),
             hram: Box::new([0; 0x7F]),
+            apu: APU::new(),
             serial,
             interrupt_controller: int_controller,
             waiting_dma: None,
+            serial_transfer_cycles: SERIAL_TRANSFER_CYCLES,
+            waiting_serial: None,
+            oam_bug: false,
+            oam_scan_row: None,
+        }
+    }
+
+    /// Swaps in a new cartridge and puts every other part of the memory map
+    /// back to its power-on state. The bootstrap ROM content is kept and
+    /// mounted again.
+    pub fn reset(&mut self, mbc: BoxMBC) {
+        self.mbc = mbc;
+        self.power_cycle();
+    }
+
+    /// Same as `reset`, keeping the cartridge. Its RAM is only kept if backed
+    /// by a battery.
+    pub fn power_cycle(&mut self) {
+        self.mbc.power_cycle();
+        self.cgb_mode = false;
+        self.vram = vec![Box::new([0; VRAM_BANK_SIZE])];
+        self.vram_bank_index = 0;
+        self.wram.fill(0);
+        self.wram_bank_index = 0;
+        self.oam.fill(0);
+        self.io_regs.fill(0);
+        self.hram.fill(0);
+        self.apu = APU::new();
+        self.waiting_dma = None;
+        self.waiting_serial = None;
+        self.oam_scan_row = None;
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn apu(&self) -> &APU {
+        &self.apu
+    }
+
+    /// Selects the hardware whose quirks are emulated. This is kept across
+    /// resets.
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+    }
+
+    /// Enables the DMG OAM corruption bug, triggered by CPU accesses and 16-bit
+    /// increments/decrements in 0xFE00-0xFEFF while the PPU scans the OAM.
+    pub fn set_oam_bug(&mut self, enabled: bool) {
+        self.oam_bug = enabled;
+    }
+
+    fn corrupt_oam(&mut self, access: OamBugAccess) {
+        // the first row is never corrupted
+        let row = match self.oam_scan_row {
+            Some(row) if row > 0 && (row as usize) < OAM_ROW_COUNT => row as usize,
+            _ => return,
+        };
+
+        let current = row * OAM_ROW_SIZE;
+        let previous = current - OAM_ROW_SIZE;
+        let word = |offset: usize| u16::from_le_bytes([self.oam[offset], self.oam[offset + 1]]);
+
+        let a = word(current);
+        let b = word(previous);
+        let c = word(previous + 4);
+        let corrupted = match access {
+            OamBugAccess::Read => b | (a & c),
+            OamBugAccess::Write => ((a ^ c) & (b ^ c)) ^ c,
         };
-        mmu.init_default_values();
-        mmu
+
+        self.oam[current..(current + 2)].copy_from_slice(&corrupted.to_le_bytes());
+        self.oam.copy_within((previous + 2)..current, current + 2);
     }
 
-    fn init_default_values(&mut self) {
-        self.write_memory(0xFF4D, 0xFF);
+    /// The CPU can't access OAM while the PPU scans it in mode 2.
+    fn oam_blocked(&self) -> bool {
+        let lcd_enabled = self.io_regs[LCD_CONTROL_REG_ADDR as usize - 0xFF00] & 0x80 != 0;
+        lcd_enabled && self.oam_scan_row.is_some()
+    }
+
+    fn read_unusable(&self, addr: u16) -> u8 {
+        // the high nibble of the low address byte, twice
+        let nibble = ((addr >> 4) & 0xF) as u8;
+        match self.model {
+            Model::Dmg | Model::Mgb | Model::Sgb if self.oam_blocked() => 0xFF,
+            Model::Dmg | Model::Mgb | Model::Sgb => 0x00,
+            Model::Cgb if addr < 0xFEC0 => 0x00,
+            Model::Cgb | Model::Agb => (nibble << 4) | nibble,
+        }
+    }
+
+    /// Changes how many M-cycles a serial transfer clocked by this side takes,
+    /// e.g. to speed up link tests. With 0, transfers complete on the next
+    /// M-cycle.
+    pub fn set_serial_transfer_cycles(&mut self, cycles: u32) {
+        self.serial_transfer_cycles = cycles;
+    }
+
+    fn start_serial_transfer(&mut self, control: u8) {
+        if control & 0x80 == 0 {
+            self.waiting_serial = None;
+        } else if control & 0x01 != 0 {
+            let fast_clock = self.cgb_mode() && control & 0x02 != 0;
+            self.waiting_serial = Some(if fast_clock {
+                self.serial_transfer_cycles / SERIAL_FAST_CLOCK_FACTOR
+            } else {
+                self.serial_transfer_cycles
+            });
+        } else {
+            // the other side clocks the transfer
+            self.waiting_serial = None;
+            let byte = self.io_regs[SERIAL_TRANSFER_DATA_ADDR as usize - 0xFF00];
+            self.serial.listen(byte);
+        }
+    }
+
+    fn serial_tick(&mut self) {
+        let control = self.io_regs[SERIAL_TRANSFER_CONTROL_ADDR as usize - 0xFF00];
+        if control & 0x80 == 0 {
+            return;
+        }
+
+        let received = match self.waiting_serial.as_mut() {
+            Some(cycles) => {
+                *cycles = cycles.saturating_sub(1);
+                if *cycles > 0 {
+                    return;
+                }
+                self.waiting_serial = None;
+                let byte = self.io_regs[SERIAL_TRANSFER_DATA_ADDR as usize - 0xFF00];
+                self.serial.exchange_byte(byte)
+            }
+            None => match self.serial.poll_received() {
+                Some(byte) => byte,
+                None => return,
+            },
+        };
+
+        self.io_regs[SERIAL_TRANSFER_DATA_ADDR as usize - 0xFF00] = received;
+        self.io_regs[SERIAL_TRANSFER_CONTROL_ADDR as usize - 0xFF00] = control & !0x80;
+        self.interrupt_controller
+            .lock()
+            .unwrap()
+            .trigger_serial_int();
+    }
+
+    pub fn dump_cartridge_ram(&self) -> Option<Vec<u8>> {
+        self.mbc.dump_ram()
+    }
+
+    pub fn load_cartridge_ram(&mut self, data: &[u8]) {
+        self.mbc.load_ram(data);
+    }
+
+    pub fn current_rom_bank(&self) -> usize {
+        self.mbc.current_rom_bank()
+    }
+
+    pub fn current_ram_bank(&self) -> usize {
+        self.mbc.current_ram_bank()
+    }
+
+    pub fn rtc_state(&self) -> Option<rtc::RtcSave> {
+        self.mbc.rtc_state()
+    }
+
+    pub fn set_rtc_state(&mut self, state: rtc::RtcSave) {
+        self.mbc.set_rtc_state(state);
+    }
+
+    /// Raw content of `region`, with every bank in order for the banked ones.
+    pub fn dump_region(&self, region: MemoryRegion) -> Vec<u8> {
+        match region {
+            MemoryRegion::Vram => self
+                .vram
+                .iter()
+                .flat_map(|bank| bank.iter().copied())
+                .collect(),
+            MemoryRegion::Wram => self.wram.to_vec(),
+            MemoryRegion::Oam => self.oam.to_vec(),
+            MemoryRegion::Hram => self.hram.to_vec(),
+        }
+    }
+
+    /// Always false when built without the `cgb` feature, so the CGB only
+    /// paths are compiled out.
+    fn cgb_mode(&self) -> bool {
+        cfg!(feature = "cgb") && self.cgb_mode
+    }
+
+    #[cfg(feature = "cgb")]
+    pub fn set_cgb_mode(&mut self, cgb_mode: bool) {
+        self.cgb_mode = cgb_mode;
+
+        let bank_count = if cgb_mode { 2 } else { 1 };
+        self.vram
+            .resize_with(bank_count, || Box::new([0; VRAM_BANK_SIZE]));
+        self.vram_bank_index = 0;
+        self.wram_bank_index = 0;
+    }
+
+    fn switch_vram_bank(&mut self, value: u8) {
+        if self.cgb_mode() {
+            self.vram_bank_index = (value & 0b1) as usize;
+        } else {
+            error!("VRAM bank switch outside of CGB mode");
+        }
+    }
+
+    // only the selection is kept, 0xD000-0xDFFF is not banked yet
+    fn switch_wram_bank(&mut self, value: u8) {
+        if self.cgb_mode() {
+            // only the low bits are wired, any value selects a valid bank
+            self.wram_bank_index = value as usize % CGB_WRAM_BANK_COUNT;
+        } else {
+            error!("WRAM bank switch outside of CGB mode");
+        }
     }
 
     pub fn write_bootstrap_rom(&mut self, slice: &[u8]) {
@@ -91,6 +385,11 @@ impl MMU {
         self.write_memory(BOOTSTRAP_ROM_MOUNT_CONTROL_ADDR, 1);
     }
 
+    /// True until the bootstrap ROM unmounts itself by writing to 0xFF50.
+    pub fn is_bootstrap_rom_mounted(&self) -> bool {
+        self.read_memory(BOOTSTRAP_ROM_MOUNT_CONTROL_ADDR) == 0
+    }
+
     pub fn read_io_reg(&self, addr: u16) -> u8 {
         match addr {
             JOYPAD_STATUS_ADDR => self.interrupt_controller.lock().unwrap().read_joypad_reg(),
@@ -98,12 +397,22 @@ impl MMU {
             TIMER_COUNTER_ADDR => self.interrupt_controller.lock().unwrap().timer_counter,
             TIMER_MODULO_ADDR => self.interrupt_controller.lock().unwrap().timer_modulo,
             TIMER_CONTROL_ADDR => self.interrupt_controller.lock().unwrap().timer_control,
-            INTERRUPT_FLAG_ADDR => self
-                .interrupt_controller
-                .lock()
-                .unwrap()
-                .interrupt_flag
-                .bits(),
+            // the unused upper bits always read as 1
+            INTERRUPT_FLAG_ADDR => {
+                (self.interrupt_controller.lock().unwrap().interrupt_flag | IntKind::DUMMY).bits()
+            }
+            // the current speed in bit 7 and the armed switch in bit 0
+            SPEED_SWITCH_ADDR if self.cgb_mode() => 0x7E | self.io_regs[addr as usize - 0xFF00],
+            SPEED_SWITCH_ADDR => 0xFF,
+            VRAM_BANK_CONTROL_ADDR if self.cgb_mode() => (!0b1) | self.vram_bank_index as u8,
+            VRAM_BANK_CONTROL_ADDR => 0xFF,
+            WRAM_BANK_CONTROL_ADDR if self.cgb_mode() => (!0b111) | self.wram_bank_index as u8,
+            WRAM_BANK_CONTROL_ADDR => 0xFF,
+            0xFF72..=0xFF77 if !self.cgb_mode() => 0xFF,
+            UNDOCUMENTED_FF75_ADDR => self.io_regs[addr as usize - 0xFF00] | 0b10001111,
+            PCM12_ADDR => self.apu.pcm12(),
+            PCM34_ADDR => self.apu.pcm34(),
+            SOUND_REGS_START_ADDR..=SOUND_REGS_END_ADDR => self.apu.read_register(addr, self.model),
             _ => self.io_regs[addr as usize - 0xFF00],
         }
     }
@@ -116,20 +425,45 @@ impl MMU {
                 .unwrap()
                 .write_joypad_reg(value),
             DIVIDER_REGISTER_ADDR => self.interrupt_controller.lock().unwrap().divider_register = 0,
-            TIMER_COUNTER_ADDR => self.interrupt_controller.lock().unwrap().timer_counter = value,
-            TIMER_MODULO_ADDR => self.interrupt_controller.lock().unwrap().timer_modulo = value,
+            TIMER_COUNTER_ADDR => self
+                .interrupt_controller
+                .lock()
+                .unwrap()
+                .write_timer_counter(value),
+            TIMER_MODULO_ADDR => self
+                .interrupt_controller
+                .lock()
+                .unwrap()
+                .write_timer_modulo(value),
             TIMER_CONTROL_ADDR => self.interrupt_controller.lock().unwrap().timer_control = value,
             INTERRUPT_FLAG_ADDR => {
                 self.interrupt_controller.lock().unwrap().interrupt_flag =
                     IntKind::from_bits_truncate(value)
             }
+            SPEED_SWITCH_ADDR if self.cgb_mode() => {
+                let speed = self.io_regs[addr as usize - 0xFF00] & 0x80;
+                self.io_regs[addr as usize - 0xFF00] = speed | (value & 0x01);
+            }
+            SPEED_SWITCH_ADDR => {}
+            VRAM_BANK_CONTROL_ADDR => self.switch_vram_bank(value),
+            WRAM_BANK_CONTROL_ADDR => self.switch_wram_bank(value),
+            0xFF72..=0xFF77 if !self.cgb_mode() => {}
+            UNDOCUMENTED_FF75_ADDR => self.io_regs[addr as usize - 0xFF00] = value & 0b01110000,
+            PCM12_ADDR | PCM34_ADDR => {}
+            SOUND_REGS_START_ADDR..=SOUND_REGS_END_ADDR => {
+                self.apu.write_register(addr, value, self.model)
+            }
+            SERIAL_TRANSFER_CONTROL_ADDR => {
+                self.io_regs[addr as usize - 0xFF00] = value;
+                self.start_serial_transfer(value);
+            }
             _ => {
                 if addr == LCD_OAM_DMA_ADDR {
+                    // a new DMA restarts the transfer from the new source
                     if self.waiting_dma.is_some() {
-                        warn!("New DMA while another one was running");
-                    } else {
-                        self.waiting_dma = Some(DMAInfo::new(value));
+                        debug!("DMA restarted with source {:#04x}00", value);
                     }
+                    self.waiting_dma = Some(DMAInfo::new(value));
                 }
                 self.io_regs[addr as usize - 0xFF00] = value;
             }
@@ -142,14 +476,15 @@ impl Memory for MMU {
         match addr {
             0x0000..=0x00FF => self.read_mounted_rom(addr),
             0x0100..=0x7FFF => self.mbc.read_memory(addr),
-            0x8000..=0x9FFF => self.vram[addr as usize - 0x8000],
+            0x8000..=0x9FFF => self.read_vram(addr, self.vram_bank_index),
             0xA000..=0xBFFF => self.mbc.read_memory(addr),
-            0xC000..=0xDFFF => self.wram[addr as usize - 0xC000],
-            0xE000..=0xFDFF => self.wram[addr as usize - 0xE000],
+            0xC000..=0xDFFF => self.wram[wram_offset(addr)],
+            0xE000..=0xFDFF => self.wram[wram_offset(addr - ECHO_RAM_OFFSET)],
+            0xFE00..=0xFE9F if self.oam_blocked() => 0xFF,
             0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00],
             0xFEA0..=0xFEFF => {
                 debug!("Unusable space {:#x}", addr);
-                0xFF
+                self.read_unusable(addr)
             }
             0xFF00..=0xFF7F => self.read_io_reg(addr),
             0xFF80..=0xFFFE => self.hram[addr as usize - 0xFF80],
@@ -172,10 +507,13 @@ impl Memory for MMU {
         match addr {
             0x0000..=0x00FF => self.write_mounted_rom(addr, value),
             0x0100..=0x7FFF => self.mbc.write_memory(addr, value),
-            0x8000..=0x9FFF => self.vram[addr as usize - 0x8000] = value,
+            0x8000..=0x9FFF => self.vram[self.vram_bank_index][addr as usize - 0x8000] = value,
             0xA000..=0xBFFF => self.mbc.write_memory(addr, value),
-            0xC000..=0xDFFF => self.wram[addr as usize - 0xC000] = value,
-            0xE000..=0xFDFF => self.wram[addr as usize - 0xE000] = value,
+            0xC000..=0xDFFF => self.wram[wram_offset(addr)] = value,
+            0xE000..=0xFDFF => self.wram[wram_offset(addr - ECHO_RAM_OFFSET)] = value,
+            0xFE00..=0xFE9F if self.oam_blocked() => {
+                debug!("Write to OAM during OAM scan {:#x}", addr)
+            }
             0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00] = value,
             0xFEA0..=0xFEFF => {
                 debug!("Write to unusable space {:#x}", addr)
@@ -189,25 +527,108 @@ impl Memory for MMU {
         }
     }
 
+    fn read_vram(&self, addr: u16, bank: usize) -> u8 {
+        match self.vram.get(bank) {
+            Some(vram) => vram[addr as usize - 0x8000],
+            None => 0xFF,
+        }
+    }
+
+    fn read_oam(&self, addr: u16) -> u8 {
+        self.oam[addr as usize - 0xFE00]
+    }
+
+    fn read_word(&self, addr: u16) -> u16 {
+        // both bytes come from the same array only inside a region
+        let bytes = match addr {
+            0xC000..=0xDFFE => {
+                let offset = addr as usize - 0xC000;
+                [self.wram[offset], self.wram[offset + 1]]
+            }
+            0xFE00..=0xFE9E if !self.oam_blocked() => {
+                let offset = addr as usize - 0xFE00;
+                [self.oam[offset], self.oam[offset + 1]]
+            }
+            0xFF80..=0xFFFD => {
+                let offset = addr as usize - 0xFF80;
+                [self.hram[offset], self.hram[offset + 1]]
+            }
+            _ => [
+                self.read_memory(addr),
+                self.read_memory(addr.wrapping_add(1)),
+            ],
+        };
+        u16::from_le_bytes(bytes)
+    }
+
     fn tick(&mut self) {
         if let Some(dma_info) = self.waiting_dma.as_mut() {
             if dma_info.tick() {
                 let start_addr = (dma_info.high_byte_addr as u16) << 8;
+                // the DMA writes to OAM even while the PPU scans it
                 for offset in 0x00..0xA0 {
                     let src_addr = start_addr + offset;
-                    let dest_addr = 0xFE00 + offset;
-                    self.write_memory(dest_addr, self.read_memory(src_addr));
+                    self.oam[offset as usize] = self.read_memory(src_addr);
                 }
                 self.waiting_dma = None;
             }
         }
+        self.serial_tick();
+        self.apu.tick();
+    }
+
+    fn set_oam_scan_row(&mut self, row: Option<u8>) {
+        self.oam_scan_row = row;
+    }
+
+    fn trigger_oam_bug(&mut self, addr: u16, access: OamBugAccess) {
+        // the bug was fixed in the CGB
+        if self.oam_bug && !self.model.is_cgb() && (0xFE00..=0xFEFF).contains(&addr) {
+            self.corrupt_oam(access);
+        }
+    }
+
+    fn switch_speed(&mut self) -> bool {
+        let index = SPEED_SWITCH_ADDR as usize - 0xFF00;
+        if !self.cgb_mode() || self.io_regs[index] & 0x01 == 0 {
+            return false;
+        }
+        self.io_regs[index] = (self.io_regs[index] ^ 0x80) & 0x80;
+        true
     }
 }
 
 pub trait Memory {
     fn read_memory(&self, addr: u16) -> u8;
     fn write_memory(&mut self, addr: u16, value: u8);
+    fn read_vram(&self, addr: u16, bank: usize) -> u8;
     fn tick(&mut self);
+
+    /// Reads OAM for the PPU, bypassing the CPU access restrictions.
+    fn read_oam(&self, addr: u16) -> u8 {
+        self.read_memory(addr)
+    }
+
+    /// Reads the little-endian word at `addr`, low byte first.
+    fn read_word(&self, addr: u16) -> u16 {
+        let low = self.read_memory(addr);
+        let high = self.read_memory(addr.wrapping_add(1));
+        u16::from_le_bytes([low, high])
+    }
+
+    /// Called by the PPU with the OAM row it is reading during mode 2.
+    fn set_oam_scan_row(&mut self, _row: Option<u8>) {}
+
+    /// Called by the CPU when it accesses, increments or decrements an address
+    /// in 0xFE00-0xFEFF.
+    fn trigger_oam_bug(&mut self, _addr: u16, _access: OamBugAccess) {}
+
+    /// Called by STOP, switches the CPU speed if a switch was armed through
+    /// KEY1 and returns true instead of stopping. Only KEY1 reports the new
+    /// speed, double speed is not emulated.
+    fn switch_speed(&mut self) -> bool {
+        false
+    }
 }
 
 impl<M: Memory> Memory for Arc<RwLock<M>> {
@@ -219,28 +640,109 @@ impl<M: Memory> Memory for Arc<RwLock<M>> {
         self.write().unwrap().write_memory(addr, value);
     }
 
+    fn read_vram(&self, addr: u16, bank: usize) -> u8 {
+        self.read().unwrap().read_vram(addr, bank)
+    }
+
+    fn read_oam(&self, addr: u16) -> u8 {
+        self.read().unwrap().read_oam(addr)
+    }
+
     fn tick(&mut self) {
         self.write().unwrap().tick();
     }
+
+    fn read_word(&self, addr: u16) -> u16 {
+        self.read().unwrap().read_word(addr)
+    }
+
+    fn set_oam_scan_row(&mut self, row: Option<u8>) {
+        self.write().unwrap().set_oam_scan_row(row);
+    }
+
+    fn trigger_oam_bug(&mut self, addr: u16, access: OamBugAccess) {
+        self.write().unwrap().trigger_oam_bug(addr, access);
+    }
+
+    fn switch_speed(&mut self) -> bool {
+        self.write().unwrap().switch_speed()
+    }
+}
+
+fn wram_offset(addr: u16) -> usize {
+    addr as usize - 0xC000
 }
 
 pub trait MBC {
     fn read_memory(&self, addr: u16) -> u8;
     fn write_memory(&mut self, addr: u16, value: u8);
+
+    fn dump_ram(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn load_ram(&mut self, _data: &[u8]) {}
+
+    /// Puts the registers back to their power-on state, clearing the RAM not
+    /// backed by a battery.
+    fn power_cycle(&mut self) {}
+
+    /// Bank mapped at 0x4000-0x7FFF.
+    fn current_rom_bank(&self) -> usize {
+        0
+    }
+
+    /// Bank mapped at 0xA000-0xBFFF.
+    fn current_ram_bank(&self) -> usize {
+        0
+    }
+
+    /// State of the real-time clock, for the cartridges having one.
+    fn rtc_state(&self) -> Option<rtc::RtcSave> {
+        None
+    }
+
+    /// Restores the clock, it then runs from `state.timestamp`.
+    fn set_rtc_state(&mut self, _state: rtc::RtcSave) {}
+
+    fn save_state(&self) -> MbcState {
+        MbcState::default()
+    }
+
+    fn load_state(&mut self, _state: &MbcState) {}
 }
 
-pub fn build_mbc(content: &[u8]) -> BoxMBC {
+pub fn build_mbc(content: &[u8]) -> Result<BoxMBC, CartridgeError> {
     const CARTRIDGE_TYPE_ADDR: usize = 0x0147;
     const CARTRIDGE_ROM_SIZE_ADDR: usize = 0x0148;
     const CARTRIDGE_RAM_SIZE_ADDR: usize = 0x0149;
+    const HEADER_END_ADDR: usize = 0x0150;
+
+    if content.len() < HEADER_END_ADDR {
+        return Err(CartridgeError::TooShort(content.len()));
+    }
 
     let rom_size_tag = content[CARTRIDGE_ROM_SIZE_ADDR];
     if rom_size_tag > 0x08 {
-        unimplemented!()
+        return Err(CartridgeError::UnsupportedRomSize(rom_size_tag));
     }
 
     let rom_size = (1 << 15) << rom_size_tag;
-    assert_eq!(rom_size, content.len());
+    if content.len() < rom_size {
+        return Err(CartridgeError::SizeMismatch {
+            declared: rom_size,
+            actual: content.len(),
+        });
+    }
+    if content.len() > rom_size {
+        // overdumps and padded files carry garbage after the declared ROM
+        warn!(
+            "ROM file is {} bytes but the header declares {} bytes, ignoring the trailing data",
+            content.len(),
+            rom_size
+        );
+    }
+    let content = &content[..rom_size];
 
     let ram_size = match content[CARTRIDGE_RAM_SIZE_ADDR] {
         0x00 => 0,
@@ -249,13 +751,39 @@ pub fn build_mbc(content: &[u8]) -> BoxMBC {
         0x03 => 1 << 15,
         0x04 => 1 << 17,
         0x05 => 1 << 16,
-        _ => panic!("Unknown RAM Size"),
+        tag => return Err(CartridgeError::UnknownRamSize(tag)),
     };
 
     match content[CARTRIDGE_TYPE_ADDR] {
-        0x00 => Box::new(SimpleMBC::new(content)),
-        0x01 => Box::new(MBC1::new(content, rom_size, 0)),
-        0x02 | 0x03 => Box::new(MBC1::new(content, rom_size, ram_size)),
-        _ => unimplemented!(),
+        // without a controller only 32KB can be mapped
+        0x00 if rom_size > 0x8000 => Err(CartridgeError::UnsupportedRomSize(rom_size_tag)),
+        0x00 => Ok(Box::new(SimpleMBC::new(content))),
+        0x01 => Ok(Box::new(MBC1::new(content, rom_size, 0, false))),
+        0x02 => Ok(Box::new(MBC1::new(content, rom_size, ram_size, false))),
+        0x03 => Ok(Box::new(MBC1::new(content, rom_size, ram_size, true))),
+        0x05 => Ok(Box::new(MBC2::new(content, rom_size, false))),
+        0x06 => Ok(Box::new(MBC2::new(content, rom_size, true))),
+        0x0F => Ok(Box::new(MBC3::new(content, rom_size, 0, true, true))),
+        0x10 => Ok(Box::new(MBC3::new(content, rom_size, ram_size, true, true))),
+        0x11 => Ok(Box::new(MBC3::new(content, rom_size, 0, false, false))),
+        0x12 => Ok(Box::new(MBC3::new(
+            content, rom_size, ram_size, false, false,
+        ))),
+        0x13 => Ok(Box::new(MBC3::new(
+            content, rom_size, ram_size, true, false,
+        ))),
+        0x19 => Ok(Box::new(MBC5::new(content, rom_size, 0, false, false))),
+        0x1A => Ok(Box::new(MBC5::new(
+            content, rom_size, ram_size, false, false,
+        ))),
+        0x1B => Ok(Box::new(MBC5::new(
+            content, rom_size, ram_size, true, false,
+        ))),
+        0x1C => Ok(Box::new(MBC5::new(content, rom_size, 0, false, true))),
+        0x1D => Ok(Box::new(MBC5::new(
+            content, rom_size, ram_size, false, true,
+        ))),
+        0x1E => Ok(Box::new(MBC5::new(content, rom_size, ram_size, true, true))),
+        kind => Err(CartridgeError::UnsupportedCartridgeType(kind)),
     }
 }
@@ -0,0 +1,99 @@
+//! Real-time clock state appended to the cartridge RAM in `.sav` files, in
+//! the layout used by VBA and BGB: the 5 clock registers, the 5 latched
+//! registers, each stored as a little-endian u32, followed by the UNIX
+//! timestamp of the save as a little-endian u64. Some older saves use a u32
+//! timestamp.
+
+pub const RTC_SAVE_SIZE: usize = 48;
+const RTC_SAVE_SIZE_32BIT_TIMESTAMP: usize = 44;
+const REGISTERS_SIZE: usize = 5 * 4;
+
+/// Clock registers, in the order of their register numbers 0x08-0x0C.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub days_low: u8,
+    pub days_high: u8,
+}
+
+impl RtcRegisters {
+    fn write_to(&self, out: &mut Vec<u8>) {
+        for value in [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days_low,
+            self.days_high,
+        ] {
+            out.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+    }
+
+    fn read_from(data: &[u8]) -> Self {
+        let value = |index: usize| data[index * 4];
+        RtcRegisters {
+            seconds: value(0),
+            minutes: value(1),
+            hours: value(2),
+            days_low: value(3),
+            days_high: value(4),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RtcSave {
+    pub current: RtcRegisters,
+    pub latched: RtcRegisters,
+    /// UNIX time at which the registers were saved
+    pub timestamp: u64,
+}
+
+impl RtcSave {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(RTC_SAVE_SIZE);
+        self.current.write_to(&mut out);
+        self.latched.write_to(&mut out);
+        out.extend_from_slice(&self.timestamp.to_le_bytes());
+        out
+    }
+
+    /// Reads the 48 bytes layout, or the 44 bytes one with a 32-bit
+    /// timestamp.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let timestamp = match data.len() {
+            RTC_SAVE_SIZE => u64::from_le_bytes(data[40..48].try_into().unwrap()),
+            RTC_SAVE_SIZE_32BIT_TIMESTAMP => {
+                u32::from_le_bytes(data[40..44].try_into().unwrap()) as u64
+            }
+            _ => return None,
+        };
+
+        Some(RtcSave {
+            current: RtcRegisters::read_from(&data[..REGISTERS_SIZE]),
+            latched: RtcRegisters::read_from(&data[REGISTERS_SIZE..2 * REGISTERS_SIZE]),
+            timestamp,
+        })
+    }
+}
+
+/// Splits a save file of a cartridge with `ram_size` bytes of RAM into the
+/// RAM content and the clock state appended after it, if any.
+pub fn split_save(data: &[u8], ram_size: usize) -> (&[u8], Option<RtcSave>) {
+    if data.len() <= ram_size {
+        return (data, None);
+    }
+    let (ram, rtc) = data.split_at(ram_size);
+    (ram, RtcSave::from_bytes(rtc))
+}
+
+/// Appends the clock state to the RAM content, to write a save file.
+pub fn join_save(ram: &[u8], rtc: &RtcSave) -> Vec<u8> {
+    let mut data = ram.to_vec();
+    data.extend_from_slice(&rtc.to_bytes());
+    data
+}
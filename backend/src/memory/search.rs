@@ -0,0 +1,71 @@
+use std::ops::RangeInclusive;
+
+use super::{Memory, MMU};
+
+/// RAM the game state lives in, as seen by the CPU: WRAM with the current
+/// bank and HRAM. Cartridge RAM is left out since reading it while disabled
+/// is an error.
+const SEARCHED_RANGES: [RangeInclusive<u16>; 2] = [0xC000..=0xDFFF, 0xFF80..=0xFFFE];
+
+/// Content of the searched RAM at some point, to find the addresses that
+/// changed since.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RamSnapshot {
+    values: Vec<(u16, u8)>,
+}
+
+impl RamSnapshot {
+    pub fn get(&self, addr: u16) -> Option<u8> {
+        self.values
+            .iter()
+            .find(|(snapshot_addr, _)| *snapshot_addr == addr)
+            .map(|&(_, value)| value)
+    }
+}
+
+impl MMU {
+    fn searched_values(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        SEARCHED_RANGES
+            .into_iter()
+            .flatten()
+            .map(|addr| (addr, self.read_memory(addr)))
+    }
+
+    /// Addresses of WRAM and HRAM whose value matches `predicate`.
+    pub fn search_ram(&self, predicate: impl Fn(u16, u8) -> bool) -> Vec<u16> {
+        self.searched_values()
+            .filter(|&(addr, value)| predicate(addr, value))
+            .map(|(addr, _)| addr)
+            .collect()
+    }
+
+    pub fn snapshot_ram(&self) -> RamSnapshot {
+        RamSnapshot {
+            values: self.searched_values().collect(),
+        }
+    }
+
+    /// Addresses for which `cmp(old, new)` holds between the `snapshot` value
+    /// and the current one, e.g. `|old, new| new < old` for a health bar
+    /// that went down.
+    pub fn changed_addresses(
+        &self,
+        snapshot: &RamSnapshot,
+        cmp: impl Fn(u8, u8) -> bool,
+    ) -> Vec<u16> {
+        snapshot
+            .values
+            .iter()
+            .filter(|&&(addr, old)| cmp(old, self.read_memory(addr)))
+            .map(|&(addr, _)| addr)
+            .collect()
+    }
+
+    /// Writes `value` at every address of `range` as the CPU would, e.g. to
+    /// clear a buffer before watching which bytes the game touches.
+    pub fn fill_memory(&mut self, range: RangeInclusive<u16>, value: u8) {
+        for addr in range {
+            self.write_memory(addr, value);
+        }
+    }
+}
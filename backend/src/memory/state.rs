@@ -0,0 +1,174 @@
+use crate::{apu::APU, state::StateError};
+
+use super::{dma::DMAInfo, rtc::RtcSave, CGB_WRAM_BANK_COUNT, MMU, VRAM_BANK_SIZE};
+
+/// Registers and RAM of a cartridge controller. The layout of `registers`
+/// is private to each controller.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MbcState {
+    pub(super) registers: Vec<u8>,
+    pub(super) ram: Vec<u8>,
+    pub(super) rtc: Option<RtcSave>,
+}
+
+impl MbcState {
+    /// Copies the saved RAM into `ram`, a state of a cartridge with another
+    /// RAM size only fills the common part.
+    pub(super) fn restore_ram(&self, ram: &mut [u8]) {
+        let len = self.ram.len().min(ram.len());
+        ram[..len].copy_from_slice(&self.ram[..len]);
+    }
+}
+
+/// Whole memory map apart from the ROMs, see `MMU::save_state`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MmuState {
+    cgb_mode: bool,
+    /// Every bank in order
+    vram: Vec<u8>,
+    vram_bank_index: usize,
+    wram: Vec<u8>,
+    wram_bank_index: usize,
+    oam: Vec<u8>,
+    io_regs: Vec<u8>,
+    hram: Vec<u8>,
+    apu: APU,
+    waiting_dma: Option<DMAInfo>,
+    waiting_serial: Option<u32>,
+    oam_scan_row: Option<u8>,
+    mbc: MbcState,
+}
+
+impl MmuState {
+    pub(crate) fn regions(&self) -> [(&'static str, &[u8]); 6] {
+        [
+            ("VRAM", &self.vram),
+            ("WRAM", &self.wram),
+            ("OAM", &self.oam),
+            ("IO registers", &self.io_regs),
+            ("HRAM", &self.hram),
+            ("cartridge RAM", &self.mbc.ram),
+        ]
+    }
+
+    /// Components of the memory map apart from its regions that differ in
+    /// `other`.
+    pub(crate) fn differing_components(&self, other: &MmuState) -> Vec<&'static str> {
+        let banking = |state: &MmuState| {
+            (
+                state.cgb_mode,
+                state.vram_bank_index,
+                state.wram_bank_index,
+                state.waiting_dma,
+                state.waiting_serial,
+                state.oam_scan_row,
+            )
+        };
+        let components = [
+            ("memory map", banking(self) != banking(other)),
+            ("APU", self.apu != other.apu),
+            (
+                "cartridge controller",
+                (&self.mbc.registers, &self.mbc.rtc) != (&other.mbc.registers, &other.mbc.rtc),
+            ),
+        ];
+        components
+            .into_iter()
+            .filter(|&(_, differs)| differs)
+            .map(|(component, _)| component)
+            .collect()
+    }
+}
+
+impl MMU {
+    /// The model, the bootstrap ROM content and the debugging options are not
+    /// part of the state, they stay as configured.
+    pub fn save_state(&self) -> MmuState {
+        MmuState {
+            cgb_mode: self.cgb_mode,
+            vram: self
+                .vram
+                .iter()
+                .flat_map(|bank| bank.iter().copied())
+                .collect(),
+            vram_bank_index: self.vram_bank_index,
+            wram: self.wram.to_vec(),
+            wram_bank_index: self.wram_bank_index,
+            oam: self.oam.to_vec(),
+            io_regs: self.io_regs.to_vec(),
+            hram: self.hram.to_vec(),
+            apu: self.apu.clone(),
+            waiting_dma: self.waiting_dma,
+            waiting_serial: self.waiting_serial,
+            oam_scan_row: self.oam_scan_row,
+            mbc: self.mbc.save_state(),
+        }
+    }
+
+    /// Checks that the regions and the bank selections of `state` fit this
+    /// memory map, without loading it.
+    pub fn check_state(&self, state: &MmuState) -> Result<(), StateError> {
+        let (vram_bank_count, wram_bank_count) = if state.cgb_mode {
+            (2, CGB_WRAM_BANK_COUNT)
+        } else {
+            (1, 1)
+        };
+        let regions = [
+            ("VRAM", vram_bank_count * VRAM_BANK_SIZE, state.vram.len()),
+            ("WRAM", self.wram.len(), state.wram.len()),
+            ("OAM", self.oam.len(), state.oam.len()),
+            ("IO registers", self.io_regs.len(), state.io_regs.len()),
+            ("HRAM", self.hram.len(), state.hram.len()),
+        ];
+        for (region, expected, actual) in regions {
+            if actual != expected {
+                return Err(StateError::RegionSize {
+                    region,
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        let banks = [
+            ("VRAM", state.vram_bank_index, vram_bank_count),
+            ("WRAM", state.wram_bank_index, wram_bank_count),
+        ];
+        for (region, index, bank_count) in banks {
+            if index >= bank_count {
+                return Err(StateError::BankIndex {
+                    region,
+                    index,
+                    bank_count,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Leaves the memory map untouched if the state does not fit it.
+    pub fn load_state(&mut self, state: &MmuState) -> Result<(), StateError> {
+        self.check_state(state)?;
+
+        self.cgb_mode = state.cgb_mode;
+        self.vram = state
+            .vram
+            .chunks_exact(VRAM_BANK_SIZE)
+            .map(|bank| Box::new(bank.try_into().unwrap()))
+            .collect();
+        self.vram_bank_index = state.vram_bank_index;
+        self.wram.copy_from_slice(&state.wram);
+        self.wram_bank_index = state.wram_bank_index;
+        self.oam.copy_from_slice(&state.oam);
+        self.io_regs.copy_from_slice(&state.io_regs);
+        self.hram.copy_from_slice(&state.hram);
+        self.apu = state.apu.clone();
+        self.waiting_dma = state.waiting_dma;
+        self.waiting_serial = state.waiting_serial;
+        self.oam_scan_row = state.oam_scan_row;
+        self.mbc.load_state(&state.mbc);
+        Ok(())
+    }
+}
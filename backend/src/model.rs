@@ -0,0 +1,27 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Model {
+    #[default]
+    Dmg,
+    Mgb,
+    Sgb,
+    Cgb,
+    Agb,
+}
+
+impl Model {
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    /// AF, BC, DE and HL as left by the boot ROM, games read A to detect the
+    /// model they run on.
+    pub fn post_boot_registers(self) -> [u16; 4] {
+        match self {
+            Model::Dmg => [0x01B0, 0x0013, 0x00D8, 0x014D],
+            Model::Mgb => [0xFFB0, 0x0013, 0x00D8, 0x014D],
+            Model::Sgb => [0x0100, 0x0014, 0x0000, 0xC060],
+            Model::Cgb => [0x1180, 0x0000, 0xFF56, 0x000D],
+            Model::Agb => [0x1100, 0x0100, 0xFF56, 0x000D],
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt::Write;
+
+use crate::{display::pixel_color_to_screen_color, memory::Memory};
+
+use super::{
+    pixel::{byte_pair_to_pixels, PixelSource},
+    ControlReg, BG_PALETTE_DATA_ADDR, LCD_CONTROL_REG_ADDR,
+};
+
+pub const MAP_SIZE: usize = 32;
+pub const TILE_COUNT: usize = 384;
+const TILE_SIZE: usize = 16;
+
+const TILESET_COLUMNS: usize = 16;
+pub const TILESET_WIDTH: usize = TILESET_COLUMNS * 8;
+pub const TILESET_HEIGHT: usize = TILE_COUNT / TILESET_COLUMNS * 8;
+
+/// Editable content of the active background map: the tile grid, the tiles
+/// of VRAM bank 0 and the background palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BgMapExport {
+    /// Index in `tiles` of each cell, row by row. The tile data addressing
+    /// mode is already applied, so 0 is the tile at 0x8000.
+    pub tile_map: Vec<u16>,
+    pub tiles: Vec<[u8; TILE_SIZE]>,
+    pub palette: u8,
+}
+
+impl BgMapExport {
+    pub fn read_from_memory(memory: &dyn Memory) -> Self {
+        let lcdc = ControlReg::from_bits_truncate(memory.read_memory(LCD_CONTROL_REG_ADDR));
+        let map_addr = lcdc.background_tile_map_addr();
+        let addressing_mode = lcdc.addressing_mode();
+
+        let tile_map = (0..(MAP_SIZE * MAP_SIZE) as u16)
+            .map(|offset| addressing_mode.real_tile_id(memory.read_vram(map_addr + offset, 0)))
+            .collect();
+
+        let tiles = (0..TILE_COUNT)
+            .map(|tile_id| {
+                let tile_addr = 0x8000 + (tile_id * TILE_SIZE) as u16;
+                let mut tile = [0; TILE_SIZE];
+                for (offset, byte) in tile.iter_mut().enumerate() {
+                    *byte = memory.read_vram(tile_addr + offset as u16, 0);
+                }
+                tile
+            })
+            .collect();
+
+        BgMapExport {
+            tile_map,
+            tiles,
+            palette: memory.read_memory(BG_PALETTE_DATA_ADDR),
+        }
+    }
+
+    /// Draws the tiles through the palette, 16 tiles per row.
+    pub fn draw_tileset_into_fb(&self, fb: &mut [u8]) {
+        assert_eq!(TILESET_WIDTH * TILESET_HEIGHT * 4, fb.len());
+
+        for (tile_id, tile) in self.tiles.iter().enumerate() {
+            let tile_x = (tile_id % TILESET_COLUMNS) * 8;
+            let tile_y = (tile_id / TILESET_COLUMNS) * 8;
+
+            for (y, line) in tile.chunks_exact(2).enumerate() {
+                let pixels = byte_pair_to_pixels(line[0], line[1], PixelSource::BackgroundWindow);
+                for (x, pixel) in pixels.iter().enumerate() {
+                    let shade = (self.palette >> (pixel.color * 2)) & 0b11;
+                    let offset = ((tile_y + y) * TILESET_WIDTH + tile_x + x) * 4;
+                    fb[offset..(offset + 4)].copy_from_slice(&pixel_color_to_screen_color(shade));
+                }
+            }
+        }
+    }
+
+    /// Map in the JSON format of the Tiled editor, using the tileset drawn by
+    /// `draw_tileset_into_fb` saved at `tileset_path`.
+    pub fn to_tiled_json(&self, tileset_path: &str) -> String {
+        // Tiled uses 0 for empty cells
+        let mut data = String::new();
+        for (index, tile_id) in self.tile_map.iter().enumerate() {
+            if index != 0 {
+                data.push(',');
+            }
+            write!(data, "{}", tile_id + 1).unwrap();
+        }
+
+        format!(
+            concat!(
+                "{{\"type\":\"map\",\"orientation\":\"orthogonal\",\"renderorder\":\"right-down\",",
+                "\"infinite\":false,\"width\":{size},\"height\":{size},",
+                "\"tilewidth\":8,\"tileheight\":8,\"nextlayerid\":2,\"nextobjectid\":1,",
+                "\"layers\":[{{\"id\":1,\"name\":\"background\",\"type\":\"tilelayer\",",
+                "\"x\":0,\"y\":0,\"width\":{size},\"height\":{size},\"opacity\":1,\"visible\":true,",
+                "\"data\":[{data}]}}],",
+                "\"tilesets\":[{{\"firstgid\":1,\"name\":\"vram\",\"image\":\"{image}\",",
+                "\"imagewidth\":{width},\"imageheight\":{height},\"tilewidth\":8,\"tileheight\":8,",
+                "\"tilecount\":{count},\"columns\":{columns},\"margin\":0,\"spacing\":0}}],",
+                "\"properties\":[{{\"name\":\"bgp\",\"type\":\"int\",\"value\":{palette}}}]}}"
+            ),
+            size = MAP_SIZE,
+            data = data,
+            image = tileset_path.replace('\\', "\\\\").replace('"', "\\\""),
+            width = TILESET_WIDTH,
+            height = TILESET_HEIGHT,
+            count = TILE_COUNT,
+            columns = TILESET_COLUMNS,
+            palette = self.palette,
+        )
+    }
+}
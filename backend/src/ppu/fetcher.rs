@@ -70,18 +70,10 @@ impl<M: Memory> Fetcher<M> {
 
     pub fn fetch_pixels(&mut self, memory: &M) -> [Pixel; 8] {
         let offset = (self.tile_y as u16) * 32 + (self.tile_x as u16);
-        let tile_id = memory.read_memory(self.map_addr + offset);
+        // the tile map is in bank 0 whatever bank the CPU selected
+        let tile_id = memory.read_vram(self.map_addr + offset, 0);
 
-        let real_tile_id = match self.addressing_mode {
-            AddressingMode::From8000 => tile_id as u16,
-            AddressingMode::From8800 => {
-                if tile_id < 128 {
-                    tile_id as u16 + 256
-                } else {
-                    tile_id as u16
-                }
-            }
-        };
+        let real_tile_id = self.addressing_mode.real_tile_id(tile_id);
 
         self.tile_x = (self.tile_x + 1) % 32;
 
@@ -99,3 +91,19 @@ pub enum AddressingMode {
     From8000,
     From8800,
 }
+
+impl AddressingMode {
+    /// Index of the tile from 0x8000 for the `tile_id` of a tile map.
+    pub fn real_tile_id(self, tile_id: u8) -> u16 {
+        match self {
+            AddressingMode::From8000 => tile_id as u16,
+            AddressingMode::From8800 => {
+                if tile_id < 128 {
+                    tile_id as u16 + 256
+                } else {
+                    tile_id as u16
+                }
+            }
+        }
+    }
+}
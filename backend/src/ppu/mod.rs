@@ -1,8 +1,10 @@
-use std::sync::{Arc, Mutex};
-
-use crate::{display::Display, interrupt::InterruptControllerPtr, memory::Memory};
+use crate::{
+    display::DisplayPtr, interrupt::InterruptControllerPtr, memory::Memory, state::StateError,
+};
 use bitflags::bitflags;
+use log::trace;
 
+pub mod bg_map;
 mod fetcher;
 mod oam;
 pub mod pixel;
@@ -23,6 +25,16 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Rendering layers, for debugging.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Layers: u8 {
+        const BACKGROUND = 1 << 0;
+        const WINDOW = 1 << 1;
+        const SPRITES = 1 << 2;
+    }
+}
+
 impl ControlReg {
     pub fn background_tile_map_addr(&self) -> u16 {
         if self.contains(ControlReg::BG_TILE_MAP_DISPLAY_SELECT) {
@@ -53,7 +65,13 @@ pub const SCREEN_WIDTH: u8 = 160;
 pub const SCREEN_HEIGHT: u8 = 144;
 pub const PIXEL_COUNT: usize = (SCREEN_WIDTH as usize) * (SCREEN_HEIGHT as usize);
 
-const SCAN_LINE_COUNT: u8 = SCREEN_HEIGHT + 10;
+pub const SCAN_LINE_COUNT: u8 = SCREEN_HEIGHT + 10;
+/// LY when the game code starts without a bootstrap ROM. The PPU starts at
+/// the first dot of this line, in mode 2, as if the LCD was just turned on.
+/// The real bootstrap ROM hands over mid-frame, its STAT value of 0x85 is
+/// VBlank, but games can't rely on its exact timing and this gives the same
+/// first frame on every run.
+pub const POST_BOOT_SCAN_LINE: u8 = 0;
 const DOT_PER_LINE_COUNT: u32 = 80 + 172 + 204;
 
 const LCD_CONTROL_REG_ADDR: u16 = 0xFF40;
@@ -79,32 +97,230 @@ pub struct PPU<M: Memory> {
     dot_in_line: u32,
     state: PPUState,
     int_cond_met: bool,
-
-    display: Arc<Mutex<Display>>,
+    oam_scan_row: Option<u8>,
+    frame_count: u64,
+    stat_lines: [bool; SCAN_LINE_COUNT as usize],
+    last_stat_lines: [bool; SCAN_LINE_COUNT as usize],
+    lcd_enabled: bool,
+    /// The first line after turning the LCD on reports mode 0 instead of
+    /// mode 2, and does not block OAM
+    first_line_after_enable: bool,
+    /// Frames pushed blank to the display, the LCD shows nothing while off
+    /// and during the first frame after being turned on
+    blank_frames: u8,
+    /// Blanks the frames around turning the LCD on, see `set_enable_blanking`
+    enable_blanking: bool,
+
+    display: DisplayPtr,
     pub frame: [u8; PIXEL_COUNT],
 
     pixel_fifo: PixelFIFO<M>,
+    renderer: RendererKind,
+    /// Renderer of the current line, `renderer` changes apply at the next
+    /// transfer
+    line_renderer: RendererKind,
 }
 
 impl<M: Memory + Clone> PPU<M> {
     pub fn new(
         memory: M,
         interrupt_controller: InterruptControllerPtr,
-        display: Arc<Mutex<Display>>,
+        display: DisplayPtr,
     ) -> Self {
         PPU {
             memory: memory.clone(),
             interrupt_controller,
 
-            scan_line: 0,
+            scan_line: POST_BOOT_SCAN_LINE,
             dot_in_line: 0,
             state: PPUState::OAMSearchBegin,
             int_cond_met: false,
+            oam_scan_row: None,
+            frame_count: 0,
+            stat_lines: [false; SCAN_LINE_COUNT as usize],
+            last_stat_lines: [false; SCAN_LINE_COUNT as usize],
+            lcd_enabled: false,
+            first_line_after_enable: false,
+            blank_frames: 0,
+            enable_blanking: true,
 
             display,
             frame: [0; PIXEL_COUNT],
 
             pixel_fifo: PixelFIFO::new(memory),
+            renderer: RendererKind::default(),
+            line_renderer: RendererKind::default(),
+        }
+    }
+
+    /// Back to the power-on state, the renderer and the rendering options are
+    /// kept.
+    pub fn reset(&mut self) {
+        self.scan_line = POST_BOOT_SCAN_LINE;
+        self.dot_in_line = 0;
+        self.state = PPUState::OAMSearchBegin;
+        self.int_cond_met = false;
+        self.oam_scan_row = None;
+        self.frame_count = 0;
+        self.stat_lines = [false; SCAN_LINE_COUNT as usize];
+        self.last_stat_lines = [false; SCAN_LINE_COUNT as usize];
+        self.lcd_enabled = false;
+        self.first_line_after_enable = false;
+        self.blank_frames = 0;
+        self.frame.fill(0);
+        self.pixel_fifo.reset();
+        self.line_renderer = self.renderer;
+    }
+
+    /// The LCD shows nothing during the first frame after being turned on.
+    /// Without the blanking, that frame and the one interrupted by turning
+    /// the LCD off are displayed as drawn.
+    pub fn set_enable_blanking(&mut self, enabled: bool) {
+        self.enable_blanking = enabled;
+    }
+
+    /// Without the limit, every object on a scan line is drawn instead of the
+    /// first 10, which removes the flickering of some games.
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.pixel_fifo.set_sprite_limit(enabled);
+    }
+
+    /// Hides rendering layers regardless of LCDC, the games still read the
+    /// actual register.
+    pub fn set_hidden_layers(&mut self, layers: Layers) {
+        self.pixel_fifo.set_hidden_layers(layers);
+    }
+
+    pub fn set_object_priority(&mut self, priority: ObjectPriority) {
+        self.pixel_fifo.set_object_priority(priority);
+    }
+
+    /// Can be switched at any time, the change applies from the next scan
+    /// line.
+    pub fn set_renderer(&mut self, renderer: RendererKind) {
+        self.renderer = renderer;
+    }
+
+    pub fn renderer(&self) -> RendererKind {
+        self.renderer
+    }
+
+    /// Fails during the pixel transfer, the FIFOs are not part of the state.
+    pub fn save_state(&self) -> Result<PpuState, StateError> {
+        if self.mode() == Mode::LCDTransfer {
+            return Err(StateError::PpuPosition {
+                scan_line: self.scan_line,
+                dot_in_line: self.dot_in_line,
+            });
+        }
+        Ok(PpuState {
+            scan_line: self.scan_line,
+            dot_in_line: self.dot_in_line,
+            int_cond_met: self.int_cond_met,
+            oam_scan_row: self.oam_scan_row,
+            frame_count: self.frame_count,
+            lcd_enabled: self.lcd_enabled,
+            first_line_after_enable: self.first_line_after_enable,
+            blank_frames: self.blank_frames,
+            window_scan_line: self.pixel_fifo.window_scan_line(),
+            frame: self.frame.to_vec(),
+        })
+    }
+
+    /// Checks that the frame and the position of `state` fit this PPU,
+    /// without loading it.
+    pub fn check_state(&self, state: &PpuState) -> Result<(), StateError> {
+        if state.frame.len() != self.frame.len() {
+            return Err(StateError::RegionSize {
+                region: "frame",
+                expected: self.frame.len(),
+                actual: state.frame.len(),
+            });
+        }
+        if state.scan_line >= SCAN_LINE_COUNT || state.dot_in_line >= DOT_PER_LINE_COUNT {
+            return Err(StateError::PpuPosition {
+                scan_line: state.scan_line,
+                dot_in_line: state.dot_in_line,
+            });
+        }
+        Ok(())
+    }
+
+    /// Leaves the PPU untouched if the state does not fit it.
+    pub fn load_state(&mut self, state: &PpuState) -> Result<(), StateError> {
+        self.check_state(state)?;
+
+        self.scan_line = state.scan_line;
+        self.dot_in_line = state.dot_in_line;
+        self.state = PPUState::current_state(state.dot_in_line, state.scan_line);
+        self.int_cond_met = state.int_cond_met;
+        self.oam_scan_row = state.oam_scan_row;
+        self.frame_count = state.frame_count;
+        self.lcd_enabled = state.lcd_enabled;
+        self.first_line_after_enable = state.first_line_after_enable;
+        self.blank_frames = state.blank_frames;
+        self.frame.copy_from_slice(&state.frame);
+
+        // outside of the transfer, the FIFOs only know the current line
+        self.pixel_fifo.end_of_line();
+        self.pixel_fifo.set_window_scan_line(state.window_scan_line);
+        if self.scan_line < SCREEN_HEIGHT && self.dot_in_line > 0 {
+            self.pixel_fifo.begin_of_line(self.scan_line);
+        }
+        Ok(())
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.state.mode()
+    }
+
+    pub fn scan_line(&self) -> u8 {
+        self.scan_line
+    }
+
+    /// Scan lines on which a STAT interrupt was requested during the last
+    /// complete frame.
+    pub fn stat_lines(&self) -> &[bool; SCAN_LINE_COUNT as usize] {
+        &self.last_stat_lines
+    }
+
+    /// Number of frames completed since power on, counted at VBlank.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Writes LY and the STAT mode of the current state, so the CPU reads
+    /// them right even before the first step. The LCD state is taken as is,
+    /// an LCD already on at power on is not treated as just turned on.
+    pub fn sync_registers(&mut self) {
+        self.lcd_enabled = self.is_lcd_enabled();
+        self.update_registers();
+    }
+
+    fn is_lcd_enabled(&self) -> bool {
+        ControlReg::from_bits_truncate(self.memory.read_memory(LCD_CONTROL_REG_ADDR))
+            .contains(ControlReg::DISPLAY_ENABLE)
+    }
+
+    /// Mode seen by the CPU through STAT and OAM blocking.
+    fn visible_mode(&self) -> Mode {
+        match self.state.mode() {
+            Mode::OAMSearch if self.first_line_after_enable => Mode::HBlank,
+            mode => mode,
+        }
+    }
+
+    /// Restarts at the beginning of a frame when the LCD is turned on, this
+    /// frame is not displayed.
+    fn restart_after_enable(&mut self) {
+        self.scan_line = 0;
+        self.dot_in_line = 0;
+        self.state = PPUState::current_state(0, 0);
+        self.pixel_fifo.end_of_frame();
+        self.first_line_after_enable = true;
+        if self.enable_blanking {
+            // the frame interrupted by turning the LCD off, then the first one
+            self.blank_frames = 2;
         }
     }
 
@@ -112,7 +328,7 @@ impl<M: Memory + Clone> PPU<M> {
         // status reg
         let coincidence = self.scan_line == self.memory.read_memory(LCD_LYC_ADDR);
 
-        let updated_part = ((coincidence as u8) << 2) | (self.state.mode() as u8);
+        let updated_part = ((coincidence as u8) << 2) | (self.visible_mode() as u8);
         let old_reg = self.memory.read_memory(LCD_STATUS_REG_ADDR);
         self.memory
             .write_memory(LCD_STATUS_REG_ADDR, (old_reg & 0b11111000) | updated_part);
@@ -147,15 +363,26 @@ impl<M: Memory + Clone> PPU<M> {
                 .lock()
                 .unwrap()
                 .trigger_lcd_stat_int();
+            self.stat_lines[self.scan_line as usize] = true;
         }
         self.int_cond_met = new_int_cond_met
     }
 
+    fn update_oam_scan_row(&mut self) {
+        // the PPU reads one 8 bytes row of OAM per M-cycle during mode 2
+        let row = (self.visible_mode() == Mode::OAMSearch).then_some((self.dot_in_line / 4) as u8);
+        if row != self.oam_scan_row {
+            self.oam_scan_row = row;
+            self.memory.set_oam_scan_row(row);
+        }
+    }
+
     fn next_dot(&mut self) {
         self.dot_in_line += 1;
 
         if self.dot_in_line == DOT_PER_LINE_COUNT {
             self.dot_in_line = 0;
+            self.first_line_after_enable = false;
             self.scan_line += 1;
             if self.scan_line == SCAN_LINE_COUNT {
                 self.scan_line = 0;
@@ -163,11 +390,32 @@ impl<M: Memory + Clone> PPU<M> {
             self.memory.write_memory(LCD_LY_ADDR, self.scan_line);
         }
 
-        self.state = PPUState::current_state(self.dot_in_line, self.scan_line);
+        let state = PPUState::current_state(self.dot_in_line, self.scan_line);
+        if state.mode() != self.state.mode() {
+            trace!(
+                "{:?} -> {:?} at dot {} of line {}",
+                self.state.mode(),
+                state.mode(),
+                self.dot_in_line,
+                self.scan_line
+            );
+        }
+        self.state = state;
     }
 
     fn clear_frame(&mut self) {
-        self.display.lock().unwrap().push_frame(&self.frame);
+        self.last_stat_lines = self.stat_lines;
+        self.stat_lines = [false; SCAN_LINE_COUNT as usize];
+        {
+            let mut display = self.display.lock().unwrap();
+            if self.blank_frames > 0 {
+                self.blank_frames -= 1;
+                display.push_frame(&[0; PIXEL_COUNT]);
+            } else {
+                display.push_frame(&self.frame);
+            }
+            display.set_stat_lines(&self.last_stat_lines);
+        }
 
         for pixel in self.frame.iter_mut() {
             *pixel = 0;
@@ -175,8 +423,17 @@ impl<M: Memory + Clone> PPU<M> {
     }
 
     fn cycle(&mut self) {
+        let lcd_enabled = self.is_lcd_enabled();
+        if lcd_enabled && !self.lcd_enabled {
+            self.restart_after_enable();
+        }
+        self.lcd_enabled = lcd_enabled;
+
         self.update_registers();
         self.maybe_trigger_stat_int();
+        if self.dot_in_line.is_multiple_of(4) {
+            self.update_oam_scan_row();
+        }
 
         if self.dot_in_line == 0 && self.scan_line == 0 {
             self.clear_frame();
@@ -191,8 +448,13 @@ impl<M: Memory + Clone> PPU<M> {
                 self.pixel_fifo.end_of_oam_search();
             }
             PPUState::TransferInit => {
-                self.pixel_fifo.begin_lcd_transfer();
+                self.line_renderer = self.renderer;
+                match self.line_renderer {
+                    RendererKind::PixelFifo => self.pixel_fifo.begin_lcd_transfer(),
+                    RendererKind::Line => self.render_line(),
+                }
             }
+            PPUState::Transfer { .. } if self.line_renderer == RendererKind::Line => {}
             PPUState::Transfer { x } => {
                 assert!(x < 160);
 
@@ -210,6 +472,7 @@ impl<M: Memory + Clone> PPU<M> {
             }
             PPUState::HBlank => {}
             PPUState::VBlankInit => {
+                self.frame_count += 1;
                 self.interrupt_controller
                     .lock()
                     .unwrap()
@@ -223,6 +486,14 @@ impl<M: Memory + Clone> PPU<M> {
         self.next_dot();
     }
 
+    fn render_line(&mut self) {
+        let line = self.pixel_fifo.render_line();
+        let offset = (self.scan_line as usize) * (SCREEN_WIDTH as usize);
+        for (x, pixel) in line.iter().enumerate() {
+            self.frame[offset + x] = pixel.through_palette(&self.memory);
+        }
+    }
+
     pub fn step(&mut self) {
         for _ in 0..4 {
             self.cycle();
@@ -230,6 +501,62 @@ impl<M: Memory + Clone> PPU<M> {
     }
 }
 
+/// Which object is drawn on top where objects overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObjectPriority {
+    /// The leftmost object, then the first in OAM, like the DMG
+    #[default]
+    Coordinate,
+    /// The first object in OAM, whatever their positions, like the CGB
+    OamIndex,
+}
+
+/// Position and frame in progress of a PPU outside of the pixel transfer, see
+/// `PPU::save_state`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PpuState {
+    pub scan_line: u8,
+    pub dot_in_line: u32,
+    int_cond_met: bool,
+    oam_scan_row: Option<u8>,
+    frame_count: u64,
+    lcd_enabled: bool,
+    first_line_after_enable: bool,
+    blank_frames: u8,
+    window_scan_line: Option<u8>,
+    frame: Vec<u8>,
+}
+
+impl PpuState {
+    pub(crate) fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Compares everything apart from the frame in progress.
+    pub(crate) fn same_position(&self, other: &PpuState) -> bool {
+        PpuState {
+            frame: Vec::new(),
+            ..self.clone()
+        } == PpuState {
+            frame: Vec::new(),
+            ..other.clone()
+        }
+    }
+}
+
+/// How the scan lines are drawn. The STAT modes and their timing are the
+/// same with both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RendererKind {
+    /// Pixel by pixel through the background and object FIFOs, mid-line
+    /// changes are visible
+    #[default]
+    PixelFifo,
+    /// The whole line at the start of the transfer, faster
+    Line,
+}
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
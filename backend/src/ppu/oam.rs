@@ -32,6 +32,8 @@ impl OAMSize {
 
 #[derive(Debug, Clone)]
 pub struct Oam {
+    /// Position in OAM, 0 to 39
+    pub index: u8,
     pub y_pos: u8,
     pub x_pos: u8,
     pub tile_id: u8,
@@ -40,12 +42,13 @@ pub struct Oam {
 
 impl Oam {
     pub fn read_from_memory(memory: &dyn Memory, addr: u16) -> Self {
-        let y_pos = memory.read_memory(addr);
-        let x_pos = memory.read_memory(addr + 1);
-        let tile_id = memory.read_memory(addr + 2);
-        let flags = OAMFlags::from_bits_truncate(memory.read_memory(addr + 3));
+        let y_pos = memory.read_oam(addr);
+        let x_pos = memory.read_oam(addr + 1);
+        let tile_id = memory.read_oam(addr + 2);
+        let flags = OAMFlags::from_bits_truncate(memory.read_oam(addr + 3));
 
         Oam {
+            index: ((addr - 0xFE00) / 4) as u8,
             y_pos,
             x_pos,
             tile_id,
@@ -52,8 +52,9 @@ pub fn read_tile_pixels(
     let tile_addr = 0x8000 + real_tile_id * 16;
     let row_addr = tile_addr + (in_tile_y as u16) * 2;
 
-    let byte1 = memory.read_memory(row_addr);
-    let byte2 = memory.read_memory(row_addr + 1);
+    // tiles from bank 1 are selected by the CGB attributes, not supported yet
+    let byte1 = memory.read_vram(row_addr, 0);
+    let byte2 = memory.read_vram(row_addr + 1, 0);
 
     byte_pair_to_pixels(byte1, byte2, source)
 }
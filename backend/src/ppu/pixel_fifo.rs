@@ -7,17 +7,31 @@ use super::{
     oam::{OAMSize, Oam},
     pixel::{Pixel, PixelSource},
     LCD_SCROLL_X_ADDR, LCD_SCROLL_Y_ADDR, LCD_WINDOW_X_POSITION_ADDR, LCD_WINDOW_Y_POSITION_ADDR,
+    SCREEN_WIDTH,
 };
-use super::{fetcher::FetcherKind, ControlReg, LCD_CONTROL_REG_ADDR};
+use super::{fetcher::FetcherKind, ControlReg, Layers, ObjectPriority, LCD_CONTROL_REG_ADDR};
+
+// hardware limit of objects drawn on a scan line
+const MAX_OBJECTS_PER_LINE: usize = 10;
+
+#[derive(Debug, Clone)]
+struct ObjectPixel {
+    pixel: Pixel,
+    /// OAM index of the object the pixel comes from
+    oam_index: u8,
+}
 
 #[derive(Debug, Clone)]
 pub struct PixelFIFO<M: Memory> {
     background_window_fetcher: Option<Fetcher<M>>,
     objects: Vec<Oam>,
     oam_size: OAMSize,
+    sprite_limit: bool,
+    hidden_layers: Layers,
+    object_priority: ObjectPriority,
 
     background_fifo: VecDeque<Pixel>,
-    oam_fifo: VecDeque<Pixel>,
+    oam_fifo: VecDeque<ObjectPixel>,
 
     memory: M,
     window_scan_line: Option<u8>,
@@ -31,6 +45,9 @@ impl<M: Memory> PixelFIFO<M> {
             background_window_fetcher: None,
             objects: Vec::new(),
             oam_size: OAMSize::_8x8,
+            sprite_limit: true,
+            hidden_layers: Layers::empty(),
+            object_priority: ObjectPriority::default(),
 
             background_fifo: VecDeque::new(),
             oam_fifo: VecDeque::new(),
@@ -42,28 +59,69 @@ impl<M: Memory> PixelFIFO<M> {
         }
     }
 
+    /// Back to the power-on state, the rendering options are kept.
+    pub fn reset(&mut self)
+    where
+        M: Clone,
+    {
+        *self = PixelFIFO {
+            sprite_limit: self.sprite_limit,
+            hidden_layers: self.hidden_layers,
+            object_priority: self.object_priority,
+            ..PixelFIFO::new(self.memory.clone())
+        };
+    }
+
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.sprite_limit = enabled;
+    }
+
+    pub fn set_hidden_layers(&mut self, layers: Layers) {
+        self.hidden_layers = layers;
+    }
+
+    pub fn set_object_priority(&mut self, priority: ObjectPriority) {
+        self.object_priority = priority;
+    }
+
     fn control_reg(&self) -> ControlReg {
-        ControlReg::from_bits_truncate(self.memory.read_memory(LCD_CONTROL_REG_ADDR))
+        let mut lcdc =
+            ControlReg::from_bits_truncate(self.memory.read_memory(LCD_CONTROL_REG_ADDR));
+        if self.hidden_layers.contains(Layers::WINDOW) {
+            lcdc.remove(ControlReg::WINDOW_DISPLAY_ENABLE);
+        }
+        if self.hidden_layers.contains(Layers::SPRITES) {
+            lcdc.remove(ControlReg::OBJ_DISPLAY_ENABLE);
+        }
+        lcdc
+    }
+
+    fn background_mode(&self) -> Option<FetcherKind> {
+        (!self.hidden_layers.contains(Layers::BACKGROUND)).then_some(FetcherKind::Background)
+    }
+
+    /// First x of the window on the current scan line, if it is enabled and
+    /// already started vertically.
+    fn window_start(&self, lcdc: &ControlReg) -> Option<u8> {
+        if !lcdc.contains(ControlReg::WINDOW_DISPLAY_ENABLE) {
+            return None;
+        }
+
+        let window_y_pos = self.memory.read_memory(LCD_WINDOW_Y_POSITION_ADDR);
+        let window_x_pos = self.memory.read_memory(LCD_WINDOW_X_POSITION_ADDR);
+        if window_x_pos < 7 || window_x_pos == 166 {
+            unimplemented!("Unimplemented window x pos {}", window_x_pos)
+        }
+
+        (self.current_scan_line >= window_y_pos).then_some(window_x_pos - 7)
     }
 
     fn current_requested_mode(&self) -> Option<FetcherKind> {
         let lcdc = self.control_reg();
         if lcdc.contains(ControlReg::BG_WINDOW_DISPLAY_PRIORITY) {
-            if lcdc.contains(ControlReg::WINDOW_DISPLAY_ENABLE) {
-                let window_y_pos = self.memory.read_memory(LCD_WINDOW_Y_POSITION_ADDR);
-                let window_x_pos = self.memory.read_memory(LCD_WINDOW_X_POSITION_ADDR);
-                if window_x_pos < 7 || window_x_pos == 166 {
-                    unimplemented!("Unimplemented window x pos {}", window_x_pos)
-                }
-                let window_x_pos = window_x_pos - 7;
-
-                if self.current_scan_line >= window_y_pos && self.current_x >= window_x_pos {
-                    Some(FetcherKind::Window)
-                } else {
-                    Some(FetcherKind::Background)
-                }
-            } else {
-                Some(FetcherKind::Background)
+            match self.window_start(&lcdc) {
+                Some(window_x_pos) if self.current_x >= window_x_pos => Some(FetcherKind::Window),
+                _ => self.background_mode(),
             }
         } else {
             None
@@ -114,7 +172,7 @@ impl<M: Memory> PixelFIFO<M> {
                 self.objects.push(oam);
             }
 
-            if self.objects.len() >= 10 {
+            if self.sprite_limit && self.objects.len() >= MAX_OBJECTS_PER_LINE {
                 break;
             }
         }
@@ -163,7 +221,7 @@ impl<M: Memory> PixelFIFO<M> {
 
         let oam_pixel = {
             self.fill_oam_fifo_if_needed();
-            self.oam_fifo.pop_front().unwrap()
+            self.oam_fifo.pop_front().unwrap().pixel
         };
 
         self.current_x += 1;
@@ -181,10 +239,100 @@ impl<M: Memory> PixelFIFO<M> {
         self.oam_fifo.clear();
     }
 
+    /// Lines of the window drawn in the current frame.
+    pub fn window_scan_line(&self) -> Option<u8> {
+        self.window_scan_line
+    }
+
+    pub fn set_window_scan_line(&mut self, window_scan_line: Option<u8>) {
+        self.window_scan_line = window_scan_line;
+    }
+
     pub fn end_of_frame(&mut self) {
         self.window_scan_line = None;
     }
 
+    /// Draws the whole current scan line at once from the state at the start
+    /// of the transfer, instead of going through the FIFOs pixel by pixel.
+    /// Mid-line register and VRAM changes are not visible.
+    pub fn render_line(&mut self) -> [Pixel; SCREEN_WIDTH as usize] {
+        let lcdc = self.control_reg();
+        let mut line = [Pixel {
+            color: 0,
+            source: PixelSource::BackgroundWindow,
+        }; SCREEN_WIDTH as usize];
+
+        if lcdc.contains(ControlReg::BG_WINDOW_DISPLAY_PRIORITY) {
+            let window_start = self
+                .window_start(&lcdc)
+                .filter(|&start| start < SCREEN_WIDTH);
+            let background_end = window_start.unwrap_or(SCREEN_WIDTH) as usize;
+
+            if self.background_mode().is_some() {
+                let scroll_x = self.memory.read_memory(LCD_SCROLL_X_ADDR);
+                let mut fetcher = Fetcher::<M>::new_background(
+                    lcdc.background_tile_map_addr(),
+                    lcdc.addressing_mode(),
+                    scroll_x,
+                    self.memory.read_memory(LCD_SCROLL_Y_ADDR),
+                    self.current_scan_line,
+                );
+                let pixels = std::iter::from_fn(|| Some(fetcher.fetch_pixels(&self.memory)))
+                    .flatten()
+                    .skip((scroll_x % 8) as usize);
+                for (slot, pixel) in line[..background_end].iter_mut().zip(pixels) {
+                    *slot = pixel;
+                }
+            }
+
+            if let Some(window_start) = window_start {
+                let scan_line = self.window_scan_line.unwrap_or(0);
+                self.window_scan_line = Some(scan_line + 1);
+
+                let mut fetcher = Fetcher::<M>::new_window(
+                    lcdc.window_tile_map_addr(),
+                    lcdc.addressing_mode(),
+                    scan_line,
+                );
+                let pixels =
+                    std::iter::from_fn(|| Some(fetcher.fetch_pixels(&self.memory))).flatten();
+                for (slot, pixel) in line[window_start as usize..].iter_mut().zip(pixels) {
+                    *slot = pixel;
+                }
+            }
+        }
+
+        if lcdc.contains(ControlReg::OBJ_DISPLAY_ENABLE) {
+            let mut objects: Vec<&Oam> = self.objects.iter().collect();
+            match self.object_priority {
+                ObjectPriority::Coordinate => objects.sort_by_key(|oam| (oam.x_pos, oam.index)),
+                ObjectPriority::OamIndex => objects.sort_by_key(|oam| oam.index),
+            }
+
+            let mut object_line: [Option<Pixel>; SCREEN_WIDTH as usize] =
+                [None; SCREEN_WIDTH as usize];
+            for oam in objects {
+                let in_oam_y = self.current_scan_line + 16 - oam.y_pos;
+                let pixels = oam.get_pixels(&self.memory, in_oam_y, self.oam_size);
+                for (i, pixel) in pixels.into_iter().enumerate() {
+                    let x = oam.x_pos as usize + i;
+                    if x < 8 || x >= SCREEN_WIDTH as usize + 8 || pixel.color == 0 {
+                        continue;
+                    }
+                    object_line[x - 8].get_or_insert(pixel);
+                }
+            }
+
+            for (slot, oam_pixel) in line.iter_mut().zip(object_line) {
+                if let Some(oam_pixel) = oam_pixel {
+                    *slot = choose_pixel(*slot, oam_pixel, true);
+                }
+            }
+        }
+
+        line
+    }
+
     fn fill_background_fifo_if_needed(&mut self) {
         if let Some(fetcher) = self.background_window_fetcher.as_mut() {
             if self.background_fifo.len() < 8 {
@@ -196,23 +344,36 @@ impl<M: Memory> PixelFIFO<M> {
 
     fn fill_oam_fifo_if_needed(&mut self) {
         if self.oam_fifo.len() < 8 {
-            self.oam_fifo.resize_with(8, || Pixel {
-                color: 0,
-                source: PixelSource::OAM {
-                    palette: 0,
-                    bg_priority: true,
+            self.oam_fifo.resize_with(8, || ObjectPixel {
+                pixel: Pixel {
+                    color: 0,
+                    source: PixelSource::OAM {
+                        palette: 0,
+                        bg_priority: true,
+                    },
                 },
+                oam_index: u8::MAX,
             });
         }
 
+        // objects are injected from left to right, so with the coordinate
+        // priority the pixels already in the FIFO win
         for oam in &self.objects {
             if self.current_x + 8 == oam.x_pos {
                 let in_oam_y = self.current_scan_line + 16 - oam.y_pos;
                 let pixels = oam.get_pixels(&self.memory, in_oam_y, self.oam_size);
 
                 for (i, pixel) in pixels.into_iter().enumerate() {
-                    if self.oam_fifo[i].color == 0 {
-                        self.oam_fifo[i] = pixel;
+                    let current = &self.oam_fifo[i];
+                    let replace = current.pixel.color == 0
+                        || (self.object_priority == ObjectPriority::OamIndex
+                            && pixel.color != 0
+                            && oam.index < current.oam_index);
+                    if replace {
+                        self.oam_fifo[i] = ObjectPixel {
+                            pixel,
+                            oam_index: oam.index,
+                        };
                     }
                 }
             }
@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use log::error;
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, AST, INT};
+
+use crate::{
+    cpu::{Register16, CPU},
+    memory::{MMUPtr, Memory},
+};
+
+pub type ScriptError = Box<EvalAltResult>;
+
+type RegisterGetter = fn(&Registers) -> u16;
+
+#[derive(Default)]
+struct Hooks {
+    frame: Vec<FnPtr>,
+    pc: HashMap<u16, Vec<FnPtr>>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Registers {
+    af: u16,
+    bc: u16,
+    de: u16,
+    hl: u16,
+    sp: u16,
+    pc: u16,
+}
+
+/// A rhai script driving the emulator. The script is run once when loaded and
+/// can use the following API:
+/// - `read(addr)` and `write(addr, value)` to access the memory
+/// - `on_frame(fn)` to call `fn` at the start of each VBlank
+/// - `on_pc(addr, fn)` to call `fn` before the instruction at `addr` runs
+/// - `reg_a()`, `reg_f()`, ..., `reg_af()`, ..., `reg_sp()`, `reg_pc()` to
+///   read the registers
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    hooks: Arc<Mutex<Hooks>>,
+    registers: Arc<Mutex<Registers>>,
+}
+
+impl Script {
+    pub fn new(source: &str, memory: MMUPtr) -> Result<Self, ScriptError> {
+        let mut engine = Engine::new();
+        let hooks = Arc::new(Mutex::new(Hooks::default()));
+        let registers = Arc::new(Mutex::new(Registers::default()));
+
+        let read_memory = memory.clone();
+        engine.register_fn("read", move |addr: INT| {
+            read_memory.read_memory(addr as u16) as INT
+        });
+        engine.register_fn("write", move |addr: INT, value: INT| {
+            memory
+                .write()
+                .unwrap()
+                .write_memory(addr as u16, value as u8);
+        });
+
+        let frame_hooks = hooks.clone();
+        engine.register_fn("on_frame", move |callback: FnPtr| {
+            frame_hooks.lock().unwrap().frame.push(callback);
+        });
+        let pc_hooks = hooks.clone();
+        engine.register_fn("on_pc", move |addr: INT, callback: FnPtr| {
+            pc_hooks
+                .lock()
+                .unwrap()
+                .pc
+                .entry(addr as u16)
+                .or_default()
+                .push(callback);
+        });
+
+        let getters: [(&str, RegisterGetter); 14] = [
+            ("reg_a", |r| r.af >> 8),
+            ("reg_f", |r| r.af & 0xFF),
+            ("reg_b", |r| r.bc >> 8),
+            ("reg_c", |r| r.bc & 0xFF),
+            ("reg_d", |r| r.de >> 8),
+            ("reg_e", |r| r.de & 0xFF),
+            ("reg_h", |r| r.hl >> 8),
+            ("reg_l", |r| r.hl & 0xFF),
+            ("reg_af", |r| r.af),
+            ("reg_bc", |r| r.bc),
+            ("reg_de", |r| r.de),
+            ("reg_hl", |r| r.hl),
+            ("reg_sp", |r| r.sp),
+            ("reg_pc", |r| r.pc),
+        ];
+        for (name, getter) in getters {
+            let registers = registers.clone();
+            engine.register_fn(name, move || getter(&registers.lock().unwrap()) as INT);
+        }
+
+        let ast = engine.compile(source)?;
+        engine.run_ast(&ast)?;
+
+        Ok(Script {
+            engine,
+            ast,
+            hooks,
+            registers,
+        })
+    }
+
+    pub fn has_pc_hooks(&self) -> bool {
+        !self.hooks.lock().unwrap().pc.is_empty()
+    }
+
+    pub fn run_frame_hooks<M: Memory>(&mut self, cpu: &CPU<M>) {
+        let callbacks = self.hooks.lock().unwrap().frame.clone();
+        self.run_callbacks(cpu, &callbacks);
+    }
+
+    pub fn run_pc_hooks<M: Memory>(&mut self, cpu: &CPU<M>) {
+        let callbacks = match self.hooks.lock().unwrap().pc.get(&cpu.pc) {
+            Some(callbacks) => callbacks.clone(),
+            None => return,
+        };
+        self.run_callbacks(cpu, &callbacks);
+    }
+
+    fn run_callbacks<M: Memory>(&mut self, cpu: &CPU<M>, callbacks: &[FnPtr]) {
+        if callbacks.is_empty() {
+            return;
+        }
+
+        *self.registers.lock().unwrap() = Registers {
+            af: cpu.load_reg16(Register16::AF),
+            bc: cpu.load_reg16(Register16::BC),
+            de: cpu.load_reg16(Register16::DE),
+            hl: cpu.load_reg16(Register16::HL),
+            sp: cpu.load_reg16(Register16::SP),
+            pc: cpu.pc,
+        };
+
+        for callback in callbacks {
+            if let Err(err) = callback.call::<Dynamic>(&self.engine, &self.ast, ()) {
+                error!("Script error: {}", err);
+            }
+        }
+    }
+}
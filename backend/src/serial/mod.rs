@@ -0,0 +1,168 @@
+use std::{
+    fs::File,
+    io::{self, stderr, stdout, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use log::error;
+
+#[cfg(feature = "printer")]
+mod printer;
+#[cfg(feature = "printer")]
+pub use printer::{PrintedImage, PrinterSerial};
+
+pub type SerialPtr = Box<dyn SerialWrite + Send + Sync>;
+
+pub trait SerialWrite {
+    /// Called with the byte sent when a transfer is started with the internal
+    /// clock, used for the output of test roms.
+    fn write_byte(&mut self, byte: u8);
+
+    /// Ends a transfer clocked by this side, returns the byte received from
+    /// the other side, 0xFF when nothing is connected.
+    fn exchange_byte(&mut self, _byte: u8) -> u8 {
+        0xFF
+    }
+
+    /// Offers `byte` to the other side, for a transfer clocked by it.
+    fn listen(&mut self, _byte: u8) {}
+
+    /// Byte received once the other side clocked a transfer.
+    fn poll_received(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+pub struct StdoutSerialWrite;
+
+impl SerialWrite for StdoutSerialWrite {
+    fn write_byte(&mut self, byte: u8) {
+        print!("{}", byte as char);
+        let _ = stdout().flush();
+    }
+}
+
+/// Same as `StdoutSerialWrite` for when stdout carries something else.
+pub struct StderrSerialWrite;
+
+impl SerialWrite for StderrSerialWrite {
+    fn write_byte(&mut self, byte: u8) {
+        eprint!("{}", byte as char);
+        let _ = stderr().flush();
+    }
+}
+
+/// Writes the serial output to a file, e.g. to keep the log of a test ROM.
+pub struct FileSerialWrite {
+    file: File,
+}
+
+impl FileSerialWrite {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(FileSerialWrite {
+            file: File::create(path)?,
+        })
+    }
+}
+
+impl SerialWrite for FileSerialWrite {
+    fn write_byte(&mut self, byte: u8) {
+        if let Err(err) = self.file.write_all(&[byte]) {
+            error!("Failed to write serial output: {}", err);
+        }
+    }
+}
+
+/// Sends every byte to all of the inner serial writers. The byte received
+/// comes from the first one connected to something, a writer answering 0xFF
+/// is taken as unconnected.
+pub struct TeeSerialWrite {
+    writers: Vec<SerialPtr>,
+}
+
+impl TeeSerialWrite {
+    pub fn new(writers: Vec<SerialPtr>) -> Self {
+        TeeSerialWrite { writers }
+    }
+}
+
+impl SerialWrite for TeeSerialWrite {
+    fn write_byte(&mut self, byte: u8) {
+        for writer in &mut self.writers {
+            writer.write_byte(byte);
+        }
+    }
+
+    fn exchange_byte(&mut self, byte: u8) -> u8 {
+        let mut received = 0xFF;
+        for writer in &mut self.writers {
+            let response = writer.exchange_byte(byte);
+            if received == 0xFF {
+                received = response;
+            }
+        }
+        received
+    }
+
+    fn listen(&mut self, byte: u8) {
+        for writer in &mut self.writers {
+            writer.listen(byte);
+        }
+    }
+
+    fn poll_received(&mut self) -> Option<u8> {
+        self.writers
+            .iter_mut()
+            .find_map(|writer| writer.poll_received())
+    }
+}
+
+#[derive(Debug, Default)]
+struct LinkState {
+    listening: [Option<u8>; 2],
+    received: [Option<u8>; 2],
+}
+
+/// One end of a link cable between two emulators of the same process.
+pub struct LinkPort {
+    side: usize,
+    state: Arc<Mutex<LinkState>>,
+}
+
+impl LinkPort {
+    pub fn pair() -> (LinkPort, LinkPort) {
+        let state = Arc::new(Mutex::new(LinkState::default()));
+        (
+            LinkPort {
+                side: 0,
+                state: state.clone(),
+            },
+            LinkPort { side: 1, state },
+        )
+    }
+}
+
+impl SerialWrite for LinkPort {
+    fn write_byte(&mut self, _byte: u8) {}
+
+    fn exchange_byte(&mut self, byte: u8) -> u8 {
+        let mut state = self.state.lock().unwrap();
+        let other = 1 - self.side;
+        match state.listening[other].take() {
+            Some(received) => {
+                state.received[other] = Some(byte);
+                received
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn listen(&mut self, byte: u8) {
+        self.state.lock().unwrap().listening[self.side] = Some(byte);
+    }
+
+    fn poll_received(&mut self) -> Option<u8> {
+        self.state.lock().unwrap().received[self.side].take()
+    }
+}
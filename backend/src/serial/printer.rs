@@ -0,0 +1,284 @@
+use std::path::PathBuf;
+
+use image::GrayImage;
+use log::{error, info};
+
+use super::SerialWrite;
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const ALIVE: u8 = 0x81;
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_PRINTING: u8 = 0x02;
+const STATUS_IMAGE_DATA_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED_DATA: u8 = 0x08;
+
+/// The printer keeps up to 9 data packets, 2 rows of 20 tiles each.
+const BUFFER_SIZE: usize = 0x1680;
+const TILES_PER_ROW: usize = 20;
+const TILE_SIZE: usize = 16;
+
+const PRINTER_WIDTH: usize = TILES_PER_ROW * 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+#[derive(Debug, Default)]
+struct Packet {
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+}
+
+/// A printed page, one shade (0 for white to 3 for black) per pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintedImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl PrintedImage {
+    fn from_tiles(tiles: &[u8], palette: u8) -> Self {
+        let rows = tiles.len() / (TILES_PER_ROW * TILE_SIZE);
+        let height = rows * 8;
+        let mut pixels = vec![0; PRINTER_WIDTH * height];
+
+        for (index, tile) in tiles
+            .chunks_exact(TILE_SIZE)
+            .take(rows * TILES_PER_ROW)
+            .enumerate()
+        {
+            let tile_x = (index % TILES_PER_ROW) * 8;
+            let tile_y = (index / TILES_PER_ROW) * 8;
+            for (y, line) in tile.chunks_exact(2).enumerate() {
+                for x in 0..8 {
+                    let bit = 7 - x;
+                    let color = (((line[1] >> bit) & 1) << 1) | ((line[0] >> bit) & 1);
+                    let shade = (palette >> (color * 2)) & 0b11;
+                    pixels[(tile_y + y) * PRINTER_WIDTH + tile_x + x] = shade;
+                }
+            }
+        }
+
+        PrintedImage {
+            width: PRINTER_WIDTH,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn to_gray_image(&self) -> GrayImage {
+        let luma = self
+            .pixels
+            .iter()
+            .map(|shade| 0xFF - shade * 0x55)
+            .collect();
+        GrayImage::from_raw(self.width as u32, self.height as u32, luma).unwrap()
+    }
+}
+
+/// Emulates a Game Boy Printer plugged in the serial port, every page printed
+/// is written as a PNG to `out_dir`.
+pub struct PrinterSerial {
+    out_dir: PathBuf,
+    state: PacketState,
+    packet: Packet,
+    buffer: Vec<u8>,
+    status: u8,
+    page_count: usize,
+    last_image: Option<PrintedImage>,
+}
+
+impl PrinterSerial {
+    pub fn new<P: Into<PathBuf>>(out_dir: P) -> Self {
+        PrinterSerial {
+            out_dir: out_dir.into(),
+            state: PacketState::Magic(0),
+            packet: Packet::default(),
+            buffer: Vec::new(),
+            status: 0,
+            page_count: 0,
+            last_image: None,
+        }
+    }
+
+    pub fn last_image(&self) -> Option<&PrintedImage> {
+        self.last_image.as_ref()
+    }
+
+    fn receive(&mut self, byte: u8) -> u8 {
+        let packet = &mut self.packet;
+        match self.state {
+            PacketState::Magic(index) => {
+                self.state = if byte != MAGIC[index] {
+                    PacketState::Magic(0)
+                } else if index + 1 < MAGIC.len() {
+                    PacketState::Magic(index + 1)
+                } else {
+                    PacketState::Command
+                };
+            }
+            PacketState::Command => {
+                *packet = Packet {
+                    command: byte,
+                    checksum: byte as u16,
+                    ..Default::default()
+                };
+                self.state = PacketState::Compression;
+            }
+            PacketState::Compression => {
+                packet.compressed = byte & 1 != 0;
+                packet.checksum = packet.checksum.wrapping_add(byte as u16);
+                self.state = PacketState::LengthLow;
+            }
+            PacketState::LengthLow => {
+                packet.length = byte as u16;
+                packet.checksum = packet.checksum.wrapping_add(byte as u16);
+                self.state = PacketState::LengthHigh;
+            }
+            PacketState::LengthHigh => {
+                packet.length |= (byte as u16) << 8;
+                packet.checksum = packet.checksum.wrapping_add(byte as u16);
+                self.state = if packet.length == 0 {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                };
+            }
+            PacketState::Data => {
+                packet.data.push(byte);
+                packet.checksum = packet.checksum.wrapping_add(byte as u16);
+                if packet.data.len() == packet.length as usize {
+                    self.state = PacketState::ChecksumLow;
+                }
+            }
+            PacketState::ChecksumLow => {
+                packet.received_checksum = byte as u16;
+                self.state = PacketState::ChecksumHigh;
+            }
+            PacketState::ChecksumHigh => {
+                packet.received_checksum |= (byte as u16) << 8;
+                self.process_packet();
+                self.state = PacketState::Alive;
+            }
+            PacketState::Alive => {
+                self.state = PacketState::Status;
+                return ALIVE;
+            }
+            PacketState::Status => {
+                self.state = PacketState::Magic(0);
+                let status = self.status;
+                // printing is done instantly, but games wait to see the busy
+                // flag before the printer is ready again
+                if self.packet.command == COMMAND_STATUS {
+                    self.status &= !STATUS_PRINTING;
+                }
+                return status;
+            }
+        }
+        0x00
+    }
+
+    fn process_packet(&mut self) {
+        if self.packet.checksum != self.packet.received_checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.packet.command {
+            COMMAND_INIT => {
+                self.buffer.clear();
+                self.status = 0;
+            }
+            COMMAND_PRINT => {
+                // palette 0 is handled as the default one
+                let palette = match self.packet.data.get(2) {
+                    Some(0) | None => 0xE4,
+                    Some(&palette) => palette,
+                };
+                self.print(palette);
+                self.buffer.clear();
+                self.status &= !(STATUS_UNPROCESSED_DATA | STATUS_IMAGE_DATA_FULL);
+                self.status |= STATUS_PRINTING;
+            }
+            COMMAND_DATA => {
+                let data = std::mem::take(&mut self.packet.data);
+                if self.packet.compressed {
+                    decompress_into(&data, &mut self.buffer);
+                } else {
+                    self.buffer.extend_from_slice(&data);
+                }
+                self.buffer.truncate(BUFFER_SIZE);
+                if !self.buffer.is_empty() {
+                    self.status |= STATUS_UNPROCESSED_DATA;
+                }
+                if self.buffer.len() == BUFFER_SIZE {
+                    self.status |= STATUS_IMAGE_DATA_FULL;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn print(&mut self, palette: u8) {
+        let image = PrintedImage::from_tiles(&self.buffer, palette);
+        if image.height != 0 {
+            let path = self
+                .out_dir
+                .join(format!("print-{:03}.png", self.page_count));
+            match image.to_gray_image().save(&path) {
+                Ok(()) => info!("Printed page to {}", path.display()),
+                Err(err) => error!("Failed to write '{}': {}", path.display(), err),
+            }
+            self.page_count += 1;
+        }
+        self.last_image = Some(image);
+    }
+}
+
+/// Expands the run-length encoding of the data packets: a control byte with
+/// bit 7 set repeats the next byte `(control & 0x7F) + 2` times, otherwise the
+/// next `control + 1` bytes are copied as is.
+fn decompress_into(data: &[u8], out: &mut Vec<u8>) {
+    let mut bytes = data.iter().copied();
+    while let Some(control) = bytes.next() {
+        if control & 0x80 != 0 {
+            let Some(value) = bytes.next() else {
+                break;
+            };
+            let count = (control & 0x7F) as usize + 2;
+            out.extend(std::iter::repeat_n(value, count));
+        } else {
+            out.extend(bytes.by_ref().take(control as usize + 1));
+        }
+    }
+}
+
+impl SerialWrite for PrinterSerial {
+    fn write_byte(&mut self, _byte: u8) {}
+
+    fn exchange_byte(&mut self, byte: u8) -> u8 {
+        self.receive(byte)
+    }
+}
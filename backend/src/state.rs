@@ -0,0 +1,251 @@
+//! Save states: a snapshot of the whole machine, restored with
+//! `Emulator::load_state`. The CPU and the PPU share the memory map through
+//! the `MMUPtr` lock, so the snapshot is taken by the thread running the
+//! emulator, between two steps. A frontend running the emulator on its own
+//! thread pauses it and asks that thread for the snapshot.
+
+use std::fmt;
+
+use crate::{cpu::CpuState, interrupt::InterruptController, memory::MmuState, ppu::PpuState};
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmuState {
+    pub cpu: CpuState,
+    pub interrupt_controller: InterruptController,
+    pub memory: MmuState,
+    pub ppu: PpuState,
+    /// T-cycles elapsed since power on
+    pub total_cycles: u64,
+}
+
+/// A snapshot that does not fit the machine, corrupted or saved by another
+/// version of the emulator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    RegionSize {
+        region: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    BankIndex {
+        region: &'static str,
+        index: usize,
+        bank_count: usize,
+    },
+    PpuPosition {
+        scan_line: u8,
+        dot_in_line: u32,
+    },
+    /// The CPU stopped at a breakpoint before the PPU finished its pixel
+    /// transfer, resume it to take the snapshot
+    Breakpoint {
+        pc: u16,
+    },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::RegionSize {
+                region,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} of the state is {} bytes instead of {}",
+                region, actual, expected
+            ),
+            StateError::BankIndex {
+                region,
+                index,
+                bank_count,
+            } => write!(
+                f,
+                "{} bank {} selected out of {} banks",
+                region, index, bank_count
+            ),
+            StateError::PpuPosition {
+                scan_line,
+                dot_in_line,
+            } => write!(f, "PPU at dot {} of line {}", dot_in_line, scan_line),
+            StateError::Breakpoint { pc } => write!(
+                f,
+                "CPU stopped at a breakpoint at {:#06x} during the pixel transfer",
+                pc
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// A component that differs between two states, see `EmuState::diff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    Register {
+        name: &'static str,
+        a: u16,
+        b: u16,
+    },
+    /// Number of bytes of a memory region that differ
+    Region {
+        name: &'static str,
+        byte_count: usize,
+    },
+    TotalCycles {
+        a: u64,
+        b: u64,
+    },
+    /// Internal state of a component, e.g. the timer counters
+    Component(&'static str),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Register { name, a, b } => {
+                write!(f, "{} differs: {:#06x} vs {:#06x}", name, a, b)
+            }
+            Difference::Region { name, byte_count } => {
+                write!(f, "{} differs at {} bytes", name, byte_count)
+            }
+            Difference::TotalCycles { a, b } => {
+                write!(f, "total cycles differ: {} vs {}", a, b)
+            }
+            Difference::Component(name) => write!(f, "{} differs", name),
+        }
+    }
+}
+
+/// Everything that differs between two states, to find where two runs that
+/// should match diverge.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub differences: Vec<Difference>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "states are identical");
+        }
+        for (index, difference) in self.differences.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+impl EmuState {
+    pub fn diff(&self, other: &EmuState) -> StateDiff {
+        let mut differences = Vec::new();
+
+        let (a, b) = (&self.cpu.registers, &other.cpu.registers);
+        let registers = [
+            ("A", a.a, b.a),
+            ("F", a.f, b.f),
+            ("B", a.b, b.b),
+            ("C", a.c, b.c),
+            ("D", a.d, b.d),
+            ("E", a.e, b.e),
+            ("H", a.h, b.h),
+            ("L", a.l, b.l),
+        ]
+        .map(|(name, a, b)| (name, a as u16, b as u16))
+        .into_iter()
+        .chain([("SP", a.sp, b.sp), ("PC", a.pc, b.pc)]);
+        differences.extend(
+            registers
+                .filter(|&(_, a, b)| a != b)
+                .map(|(name, a, b)| Difference::Register { name, a, b }),
+        );
+
+        let cpu_flags = |cpu: &CpuState| {
+            (
+                cpu.halted,
+                cpu.stopped,
+                cpu.locked,
+                cpu.halt_bug,
+                cpu.ime_pending,
+            )
+        };
+        if cpu_flags(&self.cpu) != cpu_flags(&other.cpu) {
+            differences.push(Difference::Component("CPU"));
+        }
+
+        let interrupts = |controller: &InterruptController| {
+            (
+                controller.master_enable,
+                controller.interrupt_enable,
+                controller.interrupt_flag,
+            )
+        };
+        if interrupts(&self.interrupt_controller) != interrupts(&other.interrupt_controller) {
+            differences.push(Difference::Component("interrupts"));
+        }
+        if !self
+            .interrupt_controller
+            .same_timer(&other.interrupt_controller)
+        {
+            differences.push(Difference::Component("timer"));
+        }
+
+        let regions = self
+            .memory
+            .regions()
+            .into_iter()
+            .chain([("frame", self.ppu.frame())])
+            .zip(
+                other
+                    .memory
+                    .regions()
+                    .into_iter()
+                    .chain([("frame", other.ppu.frame())]),
+            );
+        for ((name, a), (_, b)) in regions {
+            let byte_count =
+                a.iter().zip(b).filter(|(a, b)| a != b).count() + a.len().abs_diff(b.len());
+            if byte_count > 0 {
+                differences.push(Difference::Region { name, byte_count });
+            }
+        }
+        differences.extend(
+            self.memory
+                .differing_components(&other.memory)
+                .into_iter()
+                .map(Difference::Component),
+        );
+        if !self.ppu.same_position(&other.ppu) {
+            differences.push(Difference::Component("PPU"));
+        }
+
+        if self.total_cycles != other.total_cycles {
+            differences.push(Difference::TotalCycles {
+                a: self.total_cycles,
+                b: other.total_cycles,
+            });
+        }
+        StateDiff { differences }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl EmuState {
+    pub fn save(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("save state serialization")
+    }
+
+    pub fn load(bytes: &[u8]) -> bincode::Result<Self> {
+        bincode::deserialize(bytes)
+    }
+}
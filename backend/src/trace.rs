@@ -0,0 +1,71 @@
+//! CPU traces, one register dump per executed instruction, to catch the CPU
+//! behavior changes in the middle of a run that an end result check misses.
+
+use std::fmt;
+
+use crate::Emulator;
+
+/// Runs `instruction_count` instructions, returns the registers before each
+/// of them. An interrupt dispatch and each M-cycle spent halted count as one
+/// instruction.
+pub fn record(emu: &mut Emulator, instruction_count: usize) -> Vec<String> {
+    let mut trace = Vec::with_capacity(instruction_count);
+    for _ in 0..instruction_count {
+        trace.push(emu.cpu.dump_registers());
+        emu.step_instruction();
+    }
+    trace
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDivergence {
+    /// Index of the first instruction whose registers differ
+    pub index: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl TraceDivergence {
+    /// Names and values of the registers that differ.
+    pub fn differing_fields(&self) -> Vec<(&str, &str, &str)> {
+        self.expected
+            .split_whitespace()
+            .zip(self.actual.split_whitespace())
+            .filter(|(expected, actual)| expected != actual)
+            .map(|(expected, actual)| {
+                let (name, expected) = expected.split_once(':').unwrap_or(("?", expected));
+                let actual = actual.split_once(':').map_or(actual, |(_, value)| value);
+                (name, expected, actual)
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for TraceDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "trace diverges at instruction {}", self.index)?;
+        writeln!(f, "  expected: {}", self.expected)?;
+        writeln!(f, "  actual:   {}", self.actual)?;
+        for (name, expected, actual) in self.differing_fields() {
+            writeln!(f, "  {}: expected {}, got {}", name, expected, actual)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replays the recorded `trace`, one line per instruction, and stops at the
+/// first instruction whose registers differ.
+pub fn verify(emu: &mut Emulator, trace: &str) -> Result<(), TraceDivergence> {
+    for (index, expected) in trace.lines().enumerate() {
+        let actual = emu.cpu.dump_registers();
+        if actual != expected {
+            return Err(TraceDivergence {
+                index,
+                expected: expected.to_owned(),
+                actual,
+            });
+        }
+        emu.step_instruction();
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//! WAV recording of the mixed APU output, e.g. to rip the music of a game.
+
+use std::io::{self, Seek, Write};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+/// The APU output changes at most once per M-cycle.
+pub const APU_SAMPLE_RATE: u32 = 1 << 20;
+
+/// Lowers APU samples given at `APU_SAMPLE_RATE` to another rate, each
+/// output sample is the average of the APU samples it covers.
+#[derive(Debug, Clone)]
+pub struct Downsampler {
+    sample_rate: u32,
+    /// Sum and number of the APU samples of the next output sample, up to
+    /// `APU_SAMPLE_RATE` of them at 1Hz
+    sum: (i64, i64),
+    count: i64,
+    /// Advances by `sample_rate` per APU sample, a sample is output every
+    /// `APU_SAMPLE_RATE`
+    phase: u32,
+}
+
+impl Downsampler {
+    /// `sample_rate` is at most `APU_SAMPLE_RATE`.
+    pub fn new(sample_rate: u32) -> Self {
+        assert!(sample_rate > 0 && sample_rate <= APU_SAMPLE_RATE);
+        Downsampler {
+            sample_rate,
+            sum: (0, 0),
+            count: 0,
+            phase: 0,
+        }
+    }
+
+    /// `sample` is the left and right output of `APU::sample`, returns the
+    /// next output sample once all the APU samples it covers are pushed.
+    pub fn push(&mut self, (left, right): (i16, i16)) -> Option<(i16, i16)> {
+        self.sum.0 += left as i64;
+        self.sum.1 += right as i64;
+        self.count += 1;
+
+        self.phase += self.sample_rate;
+        if self.phase < APU_SAMPLE_RATE {
+            return None;
+        }
+        self.phase -= APU_SAMPLE_RATE;
+
+        let sample = (
+            (self.sum.0 / self.count) as i16,
+            (self.sum.1 / self.count) as i16,
+        );
+        self.sum = (0, 0);
+        self.count = 0;
+        Some(sample)
+    }
+}
+
+/// Writes 16 bits stereo samples given at `APU_SAMPLE_RATE`, see
+/// `Downsampler` for a lower rate.
+pub struct WavRecorder<W: Write + Seek> {
+    writer: WavWriter<W>,
+    downsampler: Downsampler,
+}
+
+impl<W: Write + Seek> WavRecorder<W> {
+    /// Writes the header, `sample_rate` is at most `APU_SAMPLE_RATE`.
+    pub fn new(out: W, sample_rate: u32) -> io::Result<Self> {
+        let downsampler = Downsampler::new(sample_rate);
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        Ok(WavRecorder {
+            writer: WavWriter::new(out, spec).map_err(into_io_error)?,
+            downsampler,
+        })
+    }
+
+    /// `sample` is the left and right output of `APU::sample`.
+    pub fn push(&mut self, sample: (i16, i16)) -> io::Result<()> {
+        let Some((left, right)) = self.downsampler.push(sample) else {
+            return Ok(());
+        };
+        self.writer
+            .write_sample(left)
+            .and_then(|_| self.writer.write_sample(right))
+            .map_err(into_io_error)
+    }
+
+    /// Updates the sizes in the header, the file is incomplete without it.
+    pub fn finalize(self) -> io::Result<()> {
+        self.writer.finalize().map_err(into_io_error)
+    }
+}
+
+fn into_io_error(err: hound::Error) -> io::Error {
+    match err {
+        hound::Error::IoError(err) => err,
+        err => io::Error::other(err),
+    }
+}
@@ -0,0 +1,59 @@
+//! YUV4MPEG2 video stream of the frames, which ffmpeg reads directly, e.g.
+//! `ffmpeg -i capture.y4m capture.mp4`.
+
+use std::io::{self, Write};
+
+/// The LCD refresh rate, 4194304 Hz / 70224 cycles per frame, ~59.73 fps.
+const FRAME_RATE: &str = "4194304:70224";
+
+/// Writes RGBA frames as full resolution 4:4:4 BT.601 YUV.
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    width: usize,
+    height: usize,
+    planes: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Writes the stream header.
+    pub fn new(mut out: W, width: usize, height: usize) -> io::Result<Self> {
+        writeln!(
+            out,
+            "YUV4MPEG2 W{} H{} F{} Ip A1:1 C444",
+            width, height, FRAME_RATE
+        )?;
+        Ok(Y4mWriter {
+            out,
+            width,
+            height,
+            planes: vec![0; width * height * 3],
+        })
+    }
+
+    pub fn write_frame(&mut self, rgba: &[u8]) -> io::Result<()> {
+        let pixel_count = self.width * self.height;
+        assert_eq!(pixel_count * 4, rgba.len());
+
+        let (y_plane, chroma) = self.planes.split_at_mut(pixel_count);
+        let (u_plane, v_plane) = chroma.split_at_mut(pixel_count);
+        for (i, pixel) in rgba.chunks_exact(4).enumerate() {
+            let [y, u, v] = rgb_to_yuv(pixel[0], pixel[1], pixel[2]);
+            y_plane[i] = y;
+            u_plane[i] = u;
+            v_plane[i] = v;
+        }
+
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&self.planes)?;
+        self.out.flush()
+    }
+}
+
+/// BT.601 limited range conversion.
+fn rgb_to_yuv(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    [y as u8, u as u8, v as u8]
+}
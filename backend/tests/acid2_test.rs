@@ -5,8 +5,7 @@ mod common;
 
 fn read_img_file(path: &str) -> image::RgbaImage {
     let img = image::open(path).unwrap();
-    let img = img.to_rgba8();
-    img
+    img.to_rgba8()
 }
 
 #[test]
@@ -0,0 +1,337 @@
+use gbemu::{memory::MMU, Memory, Model};
+
+mod common;
+
+const NR10: u16 = 0xFF10;
+const NR11: u16 = 0xFF11;
+const NR12: u16 = 0xFF12;
+const NR13: u16 = 0xFF13;
+const NR14: u16 = 0xFF14;
+const NR20: u16 = 0xFF15;
+const NR21: u16 = 0xFF16;
+const NR22: u16 = 0xFF17;
+const NR23: u16 = 0xFF18;
+const NR24: u16 = 0xFF19;
+const NR30: u16 = 0xFF1A;
+const NR31: u16 = 0xFF1B;
+const NR32: u16 = 0xFF1C;
+const NR33: u16 = 0xFF1D;
+const NR34: u16 = 0xFF1E;
+const NR50: u16 = 0xFF24;
+const NR51: u16 = 0xFF25;
+const NR52: u16 = 0xFF26;
+const WAVE_RAM: u16 = 0xFF30;
+
+const FRAME_SEQUENCER_PERIOD: usize = 2048;
+
+fn setup_apu() -> MMU {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    mmu.write_memory(NR52, 0x80);
+    mmu.write_memory(NR50, 0x77);
+    mmu.write_memory(NR51, 0xFF);
+    mmu
+}
+
+fn tick(mmu: &mut MMU, count: usize) {
+    for _ in 0..count {
+        mmu.tick();
+    }
+}
+
+fn channel1_enabled(mmu: &MMU) -> bool {
+    mmu.read_memory(NR52) & 0x01 != 0
+}
+
+#[test]
+fn test_square_channel_waveform() {
+    let mut mmu = setup_apu();
+    // 50% duty, volume 15, frequency 0x700: 256 M-cycles per duty step
+    mmu.write_memory(NR11, 0x80);
+    mmu.write_memory(NR12, 0xF0);
+    mmu.write_memory(NR13, 0x00);
+    mmu.write_memory(NR14, 0x87);
+    assert!(channel1_enabled(&mmu));
+
+    let mut samples = Vec::new();
+    for _ in 0..(256 * 16) {
+        mmu.tick();
+        samples.push(mmu.apu().sample());
+    }
+
+    let level = 15 * 8 * 64;
+    assert!(samples
+        .iter()
+        .all(|&(left, right)| left == right && left.abs() == level));
+    let transitions = samples
+        .windows(2)
+        .filter(|pair| pair[0].0 != pair[1].0)
+        .count();
+    // 1 high step then 4 low ones then 3 high ones, twice
+    assert_eq!(transitions, 4);
+}
+
+#[test]
+fn test_square_channel_panning() {
+    let mut mmu = setup_apu();
+    mmu.write_memory(NR51, 0x01);
+    mmu.write_memory(NR50, 0x70);
+    mmu.write_memory(NR11, 0xC0);
+    mmu.write_memory(NR12, 0xF0);
+    mmu.write_memory(NR14, 0x80);
+    tick(&mut mmu, 4096);
+
+    let (left, right) = mmu.apu().sample();
+    assert_eq!(left, 0);
+    assert_eq!(right.abs(), 15 * 64);
+}
+
+#[test]
+fn test_square_channel_length() {
+    let mut mmu = setup_apu();
+    // length of 1, enabled
+    mmu.write_memory(NR11, 0x3F);
+    mmu.write_memory(NR12, 0xF0);
+    mmu.write_memory(NR14, 0xC0);
+    assert!(channel1_enabled(&mmu));
+
+    tick(&mut mmu, FRAME_SEQUENCER_PERIOD);
+    assert!(!channel1_enabled(&mmu));
+    assert_eq!(mmu.apu().sample(), (0, 0));
+}
+
+#[test]
+fn test_square_channel_envelope() {
+    let mut mmu = setup_apu();
+    // volume 2, decreasing every envelope step
+    mmu.write_memory(NR11, 0x80);
+    mmu.write_memory(NR12, 0x21);
+    mmu.write_memory(NR14, 0x80);
+
+    tick(&mut mmu, FRAME_SEQUENCER_PERIOD * 8);
+    assert_eq!(mmu.apu().sample().0.abs(), 8 * 64);
+    tick(&mut mmu, FRAME_SEQUENCER_PERIOD * 8);
+    assert_eq!(mmu.apu().sample(), (0, 0));
+    // the DAC stays on, the channel is silent but still enabled
+    assert!(channel1_enabled(&mmu));
+}
+
+#[test]
+fn test_square_channel_sweep() {
+    let mut mmu = setup_apu();
+    mmu.write_memory(NR12, 0xF0);
+
+    // increasing by half the frequency overflows right at the trigger
+    mmu.write_memory(NR10, 0x11);
+    mmu.write_memory(NR13, 0xFF);
+    mmu.write_memory(NR14, 0x87);
+    assert!(!channel1_enabled(&mmu));
+
+    // 0x400 becomes 0x500 then 0x640 then 0x7D0, whose next value overflows
+    mmu.write_memory(NR10, 0x12);
+    mmu.write_memory(NR13, 0x00);
+    mmu.write_memory(NR14, 0x84);
+    assert!(channel1_enabled(&mmu));
+    tick(&mut mmu, FRAME_SEQUENCER_PERIOD * 7);
+    assert!(channel1_enabled(&mmu));
+    tick(&mut mmu, FRAME_SEQUENCER_PERIOD * 4);
+    assert!(!channel1_enabled(&mmu));
+}
+
+fn channel2_enabled(mmu: &MMU) -> bool {
+    mmu.read_memory(NR52) & 0x02 != 0
+}
+
+#[test]
+fn test_square_channel2_length() {
+    let mut mmu = setup_apu();
+    // 12.5% duty, volume 15, length of 4, enabled
+    mmu.write_memory(NR21, 0x3C);
+    mmu.write_memory(NR22, 0xF0);
+    mmu.write_memory(NR23, 0x00);
+    mmu.write_memory(NR24, 0xC7);
+    assert!(channel2_enabled(&mmu));
+    assert!(!channel1_enabled(&mmu));
+
+    let mut samples = Vec::new();
+    for _ in 0..(FRAME_SEQUENCER_PERIOD * 8) {
+        mmu.tick();
+        samples.push(mmu.apu().sample().0);
+    }
+    // the length is clocked on frame sequencer steps 0, 2, 4 and 6, the
+    // last one ending after 7 periods
+    let (playing, silent) = samples.split_at(FRAME_SEQUENCER_PERIOD * 7 - 1);
+    assert!(playing.contains(&(15 * 8 * 64)));
+    assert!(playing.contains(&(-15 * 8 * 64)));
+    assert!(silent.iter().all(|&sample| sample == 0));
+    assert!(!channel2_enabled(&mmu));
+}
+
+#[test]
+fn test_square_channel2_without_sweep() {
+    let mut mmu = setup_apu();
+    // would overflow on channel 1
+    mmu.write_memory(NR20, 0x11);
+    mmu.write_memory(NR22, 0xF0);
+    mmu.write_memory(NR23, 0xFF);
+    mmu.write_memory(NR24, 0x87);
+    assert!(channel2_enabled(&mmu));
+    tick(&mut mmu, FRAME_SEQUENCER_PERIOD * 8);
+    assert!(channel2_enabled(&mmu));
+    assert_eq!(mmu.read_memory(NR20), 0xFF);
+}
+
+#[test]
+fn test_square_channels_mixed() {
+    let mut mmu = setup_apu();
+    // channel 1 on both sides, channel 2 on the left only
+    mmu.write_memory(NR51, 0x31);
+    mmu.write_memory(NR50, 0x00);
+    // 75% duty at the same frequency, both start high
+    for (nrx1, nrx2, nrx4) in [(NR11, NR12, NR14), (NR21, NR22, NR24)] {
+        mmu.write_memory(nrx1, 0xC0);
+        mmu.write_memory(nrx2, 0xA0);
+        mmu.write_memory(nrx4, 0x87);
+    }
+    tick(&mut mmu, 300);
+
+    assert_eq!(mmu.apu().sample(), (20 * 64, 10 * 64));
+}
+
+fn channel3_enabled(mmu: &MMU) -> bool {
+    mmu.read_memory(NR52) & 0x04 != 0
+}
+
+/// Loads the nibbles 0 to 15 twice in the wave RAM and plays them at 16
+/// M-cycles per sample, with the NR32 volume `volume`.
+fn setup_wave_ramp(volume: u8) -> MMU {
+    let mut mmu = setup_apu();
+    for offset in 0..16 {
+        let high = (offset * 2) % 16;
+        mmu.write_memory(WAVE_RAM + offset, ((high << 4) | (high + 1)) as u8);
+    }
+    mmu.write_memory(NR30, 0x80);
+    mmu.write_memory(NR32, volume);
+    mmu.write_memory(NR33, 0xE0);
+    mmu.write_memory(NR34, 0x87);
+    mmu
+}
+
+/// Sample of channel 3 after each of the next 32 positions.
+fn wave_samples(mmu: &mut MMU) -> Vec<i16> {
+    (0..32)
+        .map(|_| {
+            tick(mmu, 16);
+            mmu.apu().sample().0 / (8 * 64)
+        })
+        .collect()
+}
+
+#[test]
+fn test_wave_channel_ramp() {
+    let mut mmu = setup_wave_ramp(0x20);
+    assert!(channel3_enabled(&mmu));
+
+    // the first sample played is the second one of the RAM
+    let expected: Vec<i16> = (1..=32).map(|n| (n % 16) * 2 - 15).collect();
+    assert_eq!(wave_samples(&mut mmu), expected);
+}
+
+#[test]
+fn test_wave_channel_volume() {
+    let mut mmu = setup_wave_ramp(0x40);
+    let expected: Vec<i16> = (1..=32).map(|n| ((n % 16) * 2 - 15) / 2).collect();
+    assert_eq!(wave_samples(&mut mmu), expected);
+
+    // muted
+    mmu.write_memory(NR32, 0x00);
+    assert!(wave_samples(&mut mmu).iter().all(|&sample| sample == 0));
+    assert!(channel3_enabled(&mmu));
+}
+
+#[test]
+fn test_wave_channel_length() {
+    let mut mmu = setup_wave_ramp(0x20);
+    // length of 1, enabled
+    mmu.write_memory(NR31, 0xFF);
+    mmu.write_memory(NR34, 0xC7);
+    assert!(channel3_enabled(&mmu));
+    tick(&mut mmu, FRAME_SEQUENCER_PERIOD);
+    assert!(!channel3_enabled(&mmu));
+    assert_eq!(mmu.apu().sample(), (0, 0));
+}
+
+#[test]
+fn test_wave_ram_access_while_playing() {
+    let mut mmu = setup_wave_ramp(0x20);
+    mmu.set_model(Model::Cgb);
+    // at position 5, in the third byte
+    tick(&mut mmu, 16 * 5 + 1);
+    assert_eq!(mmu.read_memory(WAVE_RAM), 0x45);
+    assert_eq!(mmu.read_memory(WAVE_RAM + 0x0F), 0x45);
+    mmu.write_memory(WAVE_RAM + 0x0A, 0x99);
+
+    // turning the DAC off stops the channel, the RAM is then accessed freely
+    mmu.write_memory(NR30, 0x00);
+    assert!(!channel3_enabled(&mmu));
+    assert_eq!(mmu.read_memory(WAVE_RAM + 0x02), 0x99);
+    assert_eq!(mmu.read_memory(WAVE_RAM + 0x0A), 0x45);
+}
+
+#[test]
+fn test_wave_ram_access_while_playing_dmg() {
+    let mut mmu = setup_wave_ramp(0x20);
+    // position 5 is read on this M-cycle, the CPU reaches its byte
+    tick(&mut mmu, 16 * 5);
+    assert_eq!(mmu.read_memory(WAVE_RAM + 0x0F), 0x45);
+    mmu.write_memory(WAVE_RAM + 0x0F, 0x99);
+
+    // on the next one, reads give 0xFF and writes are dropped
+    tick(&mut mmu, 1);
+    assert_eq!(mmu.read_memory(WAVE_RAM + 0x02), 0xFF);
+    mmu.write_memory(WAVE_RAM + 0x02, 0x77);
+
+    mmu.write_memory(NR30, 0x00);
+    assert_eq!(mmu.read_memory(WAVE_RAM + 0x02), 0x99);
+}
+
+#[test]
+fn test_wave_ram_kept_on_power_off() {
+    let mut mmu = setup_wave_ramp(0x20);
+    mmu.write_memory(NR30, 0x00);
+    mmu.write_memory(NR52, 0x00);
+    assert_eq!(mmu.read_memory(WAVE_RAM + 1), 0x23);
+    mmu.write_memory(WAVE_RAM + 1, 0x77);
+    assert_eq!(mmu.read_memory(WAVE_RAM + 1), 0x77);
+}
+
+#[test]
+fn test_dac_off_disables_channel() {
+    let mut mmu = setup_apu();
+    mmu.write_memory(NR12, 0xF0);
+    mmu.write_memory(NR14, 0x80);
+    assert!(channel1_enabled(&mmu));
+
+    mmu.write_memory(NR12, 0x00);
+    assert!(!channel1_enabled(&mmu));
+    mmu.write_memory(NR14, 0x80);
+    assert!(!channel1_enabled(&mmu));
+}
+
+#[test]
+fn test_apu_power_off() {
+    let mut mmu = setup_apu();
+    mmu.write_memory(NR11, 0x80);
+    mmu.write_memory(NR12, 0xF3);
+    assert_eq!(mmu.read_memory(NR11), 0xBF);
+    assert_eq!(mmu.read_memory(NR12), 0xF3);
+    assert_eq!(mmu.read_memory(NR52), 0xF0);
+
+    mmu.write_memory(NR52, 0x00);
+    assert_eq!(mmu.read_memory(NR52), 0x70);
+    assert_eq!(mmu.read_memory(NR11), 0x3F);
+    assert_eq!(mmu.read_memory(NR12), 0x00);
+
+    // ignored until powered on again
+    mmu.write_memory(NR12, 0xF3);
+    assert_eq!(mmu.read_memory(NR12), 0x00);
+}
@@ -0,0 +1,58 @@
+use gbemu::{
+    boot_logo::{self, ANIMATION_FRAMES, LOGO_HEIGHT, LOGO_WIDTH},
+    memory::CartridgeHeader,
+    SCREEN_WIDTH,
+};
+
+const ROM_PATH: &str = "./test_roms/acid2/dmg-acid2.gb";
+
+fn nintendo_logo() -> Vec<u8> {
+    let rom = std::fs::read(ROM_PATH).unwrap();
+    CartridgeHeader::logo(&rom).unwrap().to_vec()
+}
+
+#[test]
+fn test_decode_logo() {
+    let bitmap = boot_logo::decode_logo(&nintendo_logo());
+    // 0xCE 0xED: the top left tile rows are 1100, 1110, 1110, 1101
+    assert_eq!(bitmap[0][..4], [true, true, false, false]);
+    assert_eq!(bitmap[1][..4], [true, true, true, false]);
+    assert_eq!(bitmap[2][..4], [true, true, true, false]);
+    assert_eq!(bitmap[3][..4], [true, true, false, true]);
+    assert!(bitmap.iter().flatten().any(|&set| set));
+
+    let mut single = [0; LOGO_WIDTH * LOGO_HEIGHT / 8];
+    // bottom half, second tile, last row
+    single[24 + 3] = 0x01;
+    let bitmap = boot_logo::decode_logo(&single);
+    for (y, row) in bitmap.iter().enumerate() {
+        for (x, &set) in row.iter().enumerate() {
+            assert_eq!(set, (x, y) == (7, 7));
+        }
+    }
+}
+
+#[test]
+fn test_last_animation_frame() {
+    let logo = nintendo_logo();
+    let bitmap = boot_logo::decode_logo(&logo);
+    let frame = boot_logo::animation_frame(&logo, ANIMATION_FRAMES - 1);
+
+    // the logo ends doubled at (32, 64), where the bootstrap ROM draws it
+    for (y, row) in bitmap.iter().enumerate() {
+        for (x, &set) in row.iter().enumerate() {
+            let screen = (64 + y * 2) * SCREEN_WIDTH as usize + 32 + x * 2;
+            let expected = if set { 3 } else { 0 };
+            assert_eq!(frame[screen], expected);
+            assert_eq!(frame[screen + 1], expected);
+        }
+    }
+    let lit = frame.iter().filter(|&&color| color == 3).count();
+    assert_eq!(lit, bitmap.iter().flatten().filter(|&&set| set).count() * 4);
+}
+
+#[test]
+fn test_first_animation_frame_is_above_screen() {
+    let frame = boot_logo::animation_frame(&nintendo_logo(), 0);
+    assert!(frame.iter().all(|&color| color == 0));
+}
@@ -0,0 +1,61 @@
+use gbemu::{
+    cpu::{BusAccess, BusAccessKind},
+    Emulator, Memory,
+};
+
+mod common;
+
+fn run_instruction_at(emu: &mut Emulator, addr: u16) {
+    for _ in 0..100 {
+        let start_pc = emu.cpu.pc;
+        emu.step_instruction();
+        if start_pc == addr {
+            return;
+        }
+    }
+    panic!("instruction at {:#06x} never ran", addr);
+}
+
+fn read(cycle: u8, addr: u16, value: u8) -> BusAccess {
+    BusAccess {
+        cycle,
+        addr,
+        value,
+        kind: BusAccessKind::Read,
+    }
+}
+
+#[test]
+fn test_bus_trace() {
+    // LD HL, $C000; LD A, (HL); INC A; LD (HL), A; JR -2
+    let mut emu = common::setup_program(&[0x21, 0x00, 0xC0, 0x7E, 0x3C, 0x77, 0x18, 0xFE]);
+    emu.memory.write_memory(0xC000, 0x5A);
+    emu.cpu.set_bus_trace(true);
+
+    run_instruction_at(&mut emu, 0x103);
+    assert_eq!(
+        emu.cpu.bus_trace(),
+        [read(0, 0x103, 0x7E), read(1, 0xC000, 0x5A)]
+    );
+
+    run_instruction_at(&mut emu, 0x105);
+    assert_eq!(
+        emu.cpu.bus_trace(),
+        [
+            read(0, 0x105, 0x77),
+            BusAccess {
+                cycle: 1,
+                addr: 0xC000,
+                value: 0x5B,
+                kind: BusAccessKind::Write,
+            }
+        ]
+    );
+}
+
+#[test]
+fn test_bus_trace_disabled() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    run_instruction_at(&mut emu, 0x100);
+    assert!(emu.cpu.bus_trace().is_empty());
+}
@@ -0,0 +1,79 @@
+use gbemu::memory::{self, CartridgeError, CartridgeHeader};
+
+const ROM_PATH: &str = "./test_roms/acid2/dmg-acid2.gb";
+
+#[test]
+fn test_logo_valid() {
+    let mut rom = std::fs::read(ROM_PATH).unwrap();
+    assert!(CartridgeHeader::logo_valid(&rom));
+
+    rom[0x0104..0x0134].fill(0);
+    assert!(!CartridgeHeader::logo_valid(&rom));
+}
+
+#[test]
+fn test_logo_valid_short_rom() {
+    assert!(!CartridgeHeader::logo_valid(&[0; 0x120]));
+}
+
+#[test]
+fn test_undersized_rom() {
+    let rom = vec![0; 0x6000];
+    assert_eq!(
+        memory::build_mbc(&rom).err(),
+        Some(CartridgeError::SizeMismatch {
+            declared: 0x8000,
+            actual: 0x6000
+        })
+    );
+}
+
+#[test]
+fn test_oversized_rom_is_truncated() {
+    let mut rom = vec![0; 0x8000 + 0x100];
+    rom[0x7FFF] = 0x42;
+    let mbc = memory::build_mbc(&rom).unwrap();
+    assert_eq!(mbc.read_memory(0x7FFF), 0x42);
+}
+
+#[test]
+fn test_unsupported_rom_size() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x148] = 0x52;
+    assert_eq!(
+        memory::build_mbc(&rom).err(),
+        Some(CartridgeError::UnsupportedRomSize(0x52))
+    );
+}
+
+#[test]
+fn test_rom_only_cartridge_too_large() {
+    let mut rom = vec![0; 0x10000];
+    rom[0x148] = 0x01;
+    assert_eq!(
+        memory::build_mbc(&rom).err(),
+        Some(CartridgeError::UnsupportedRomSize(0x01))
+    );
+}
+
+#[test]
+fn test_unknown_ram_size() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = 0x03;
+    rom[0x149] = 0x07;
+    assert_eq!(
+        memory::build_mbc(&rom).err(),
+        Some(CartridgeError::UnknownRamSize(0x07))
+    );
+}
+
+#[test]
+fn test_unsupported_cartridge_type() {
+    let mut rom = vec![0; 0x8000];
+    // Pocket Camera
+    rom[0x147] = 0xFC;
+    assert_eq!(
+        memory::build_mbc(&rom).err(),
+        Some(CartridgeError::UnsupportedCartridgeType(0xFC))
+    );
+}
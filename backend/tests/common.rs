@@ -1,51 +1,28 @@
-use std::sync::{Arc, Mutex, RwLock};
+#![allow(dead_code)]
 
 use gbemu::{
-    display::Display,
-    interrupt::{InterruptController, InterruptControllerPtr},
-    memory::{self, MMU},
+    memory::{self, MMUBuilder, MMU},
     serial::{SerialPtr, StdoutSerialWrite},
-    CPU, PPU,
+    Emulator,
 };
 
-type MMUPtr = Arc<RwLock<MMU>>;
-type DisplayPtr = Arc<Mutex<Display>>;
-
-pub struct EmuComponents {
-    pub interrupt_controller: InterruptControllerPtr,
-    pub memory: MMUPtr,
-    pub cpu: CPU<MMUPtr>,
-    pub ppu: PPU<MMUPtr>,
-    pub display: DisplayPtr,
-}
-
-pub fn setup_rom(rom_path: &str, serial: Option<SerialPtr>) -> EmuComponents {
+pub fn setup_rom(rom_path: &str, serial: Option<SerialPtr>) -> Emulator {
     let rom = std::fs::read(rom_path).unwrap();
-
-    let interrupt_controller = Arc::new(Mutex::new(InterruptController::new()));
     let serial = serial.unwrap_or_else(|| Box::new(StdoutSerialWrite));
 
-    let mbc = memory::build_mbc(&rom);
-    let mut mmu = memory::MMU::new(mbc, interrupt_controller.clone(), serial);
-    mmu.unmount_bootstrap_rom();
-
-    let memory = Arc::new(RwLock::new(mmu));
-    let display = Arc::new(Mutex::new(Display::default()));
+    Emulator::new(&rom, None, serial).unwrap()
+}
 
-    let mut cpu = CPU::new(memory.clone(), interrupt_controller.clone());
-    cpu.pc = 0x100;
+pub fn build_rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..(0x100 + program.len())].copy_from_slice(program);
+    rom
+}
 
-    let ppu = PPU::new(
-        memory.clone(),
-        interrupt_controller.clone(),
-        display.clone(),
-    );
+pub fn setup_program(program: &[u8]) -> Emulator {
+    Emulator::new(&build_rom(program), None, Box::new(StdoutSerialWrite)).unwrap()
+}
 
-    EmuComponents {
-        interrupt_controller,
-        memory,
-        cpu,
-        ppu,
-        display,
-    }
+pub fn setup_mmu(rom: &[u8]) -> MMU {
+    MMUBuilder::new(memory::build_mbc(rom).unwrap()).build()
 }
@@ -0,0 +1,107 @@
+use gbemu::{Emulator, Memory};
+
+mod common;
+
+/// Runs instructions until the one at `addr` completes, returns its M-cycles.
+fn cycles_of_instruction_at(emu: &mut Emulator, addr: u16) -> u8 {
+    for _ in 0..100 {
+        let start_pc = emu.cpu.pc;
+        let cycles = emu.step_instruction();
+        if start_pc == addr {
+            assert_eq!(cycles, emu.cpu.last_instruction_cycles());
+            return cycles;
+        }
+    }
+    panic!("instruction at {:#06x} never ran", addr);
+}
+
+fn cycles_of(program: &[u8], addr: u16) -> u8 {
+    let mut emu = common::setup_program(program);
+    cycles_of_instruction_at(&mut emu, addr)
+}
+
+#[test]
+fn test_push_pop_cycles() {
+    // PUSH BC; POP BC
+    let program = [0xC5, 0xC1, 0x18, 0xFE];
+    assert_eq!(cycles_of(&program, 0x100), 4);
+    assert_eq!(cycles_of(&program, 0x101), 3);
+}
+
+#[test]
+fn test_call_ret_cycles() {
+    // CALL $0105; JR -2; RET
+    let program = [0xCD, 0x05, 0x01, 0x18, 0xFE, 0xC9];
+    assert_eq!(cycles_of(&program, 0x100), 6);
+    assert_eq!(cycles_of(&program, 0x105), 4);
+}
+
+#[test]
+fn test_conditional_call_ret_cycles() {
+    // XOR A; CALL NZ, $0109; CALL Z, $0109; JR -2; RET NZ; RET Z
+    let program = [
+        0xAF, 0xC4, 0x09, 0x01, 0xCC, 0x09, 0x01, 0x18, 0xFE, 0xC0, 0xC8,
+    ];
+    assert_eq!(cycles_of(&program, 0x101), 3);
+    assert_eq!(cycles_of(&program, 0x104), 6);
+    assert_eq!(cycles_of(&program, 0x109), 2);
+    assert_eq!(cycles_of(&program, 0x10A), 5);
+}
+
+#[test]
+fn test_rst_cycles() {
+    // RST $08
+    let mut emu = common::setup_program(&[0xCF]);
+    assert_eq!(cycles_of_instruction_at(&mut emu, 0x100), 4);
+    assert_eq!(emu.cpu.pc, 0x0008);
+}
+
+#[test]
+fn test_interrupt_dispatch_cycles() {
+    // EI; NOP; JR -2
+    let mut emu = common::setup_program(&[0xFB, 0x00, 0x18, 0xFE]);
+    // timer interrupt enabled and requested
+    emu.memory.write_memory(0xFFFF, 0x04);
+    emu.memory.write_memory(0xFF0F, 0x04);
+
+    for _ in 0..10 {
+        emu.step();
+        if emu.cpu.pc == 0x50 && emu.cpu.is_pipeline_empty() {
+            assert_eq!(emu.cpu.last_instruction_cycles(), 5);
+            return;
+        }
+    }
+    panic!("interrupt never dispatched");
+}
+
+#[test]
+fn test_write_sp_at_address() {
+    // LD SP, $1234; LD ($C000), SP; JR -2
+    let program = [0x31, 0x34, 0x12, 0x08, 0x00, 0xC0, 0x18, 0xFE];
+    let mut emu = common::setup_program(&program);
+    assert_eq!(cycles_of_instruction_at(&mut emu, 0x103), 5);
+    assert_eq!(emu.memory.read_memory(0xC000), 0x34);
+    assert_eq!(emu.memory.read_memory(0xC001), 0x12);
+}
+
+#[test]
+fn test_write_sp_at_last_address() {
+    // LD SP, $1234; LD ($FFFF), SP; JR -2
+    let program = [0x31, 0x34, 0x12, 0x08, 0xFF, 0xFF, 0x18, 0xFE];
+    let mut emu = common::setup_program(&program);
+    cycles_of_instruction_at(&mut emu, 0x103);
+    // the high byte goes to the ROM at 0x0000 and is dropped
+    assert_eq!(emu.memory.read_memory(0xFFFF), 0x34);
+}
+
+#[test]
+fn test_step_instruction_halted() {
+    // HALT
+    let mut emu = common::setup_program(&[0x76]);
+    assert_eq!(emu.step_instruction(), 1);
+    assert_eq!(emu.cpu.pc, 0x101);
+    for _ in 0..3 {
+        assert_eq!(emu.step_instruction(), 1);
+        assert_eq!(emu.cpu.pc, 0x101);
+    }
+}
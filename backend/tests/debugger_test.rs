@@ -0,0 +1,89 @@
+use gbemu::{
+    cpu::{debugger::Debugger, Instruction},
+    Memory,
+};
+
+mod common;
+
+fn run_until_breakpoint(emu: &mut gbemu::Emulator) {
+    for _ in 0..100 {
+        emu.step();
+        if emu.cpu.is_at_breakpoint() {
+            return;
+        }
+    }
+    panic!("breakpoint never hit");
+}
+
+#[test]
+fn test_break_on_opcode() {
+    // NOP; NOP; LD A, $12; HALT
+    let mut emu = common::setup_program(&[0x00, 0x00, 0x3E, 0x12, 0x76]);
+    let mut debugger = Debugger::new();
+    debugger.break_on_opcode(0x76);
+    emu.cpu.set_debugger(Some(debugger));
+
+    run_until_breakpoint(&mut emu);
+    assert_eq!(emu.cpu.debugger().unwrap().stopped_at(), Some(0x104));
+    assert_eq!(emu.cpu.pc, 0x104);
+
+    // stays stopped until resumed
+    emu.step();
+    assert_eq!(emu.cpu.pc, 0x104);
+
+    emu.cpu.debugger_mut().unwrap().resume();
+    emu.step();
+    assert!(!emu.cpu.is_at_breakpoint());
+    assert_eq!(emu.cpu.pc, 0x105);
+}
+
+#[test]
+fn test_break_on_instruction() {
+    // LD A, $05; LD ($2000), A; LD ($C000), A
+    let mut emu =
+        common::setup_program(&[0x3E, 0x05, 0xEA, 0x00, 0x20, 0xEA, 0x00, 0xC0, 0x18, 0xFE]);
+    let mut debugger = Debugger::new();
+    debugger.break_on_instruction(|instruction| {
+        matches!(
+            instruction,
+            Instruction::WriteReg8ValueAtAddress {
+                addr: 0x2000..=0x3FFF,
+                ..
+            }
+        )
+    });
+    emu.cpu.set_debugger(Some(debugger));
+
+    run_until_breakpoint(&mut emu);
+    assert_eq!(emu.cpu.pc, 0x102);
+
+    emu.cpu.debugger_mut().unwrap().resume();
+    for _ in 0..20 {
+        emu.step();
+    }
+    assert!(!emu.cpu.is_at_breakpoint());
+    assert_eq!(emu.memory.read_memory(0xC000), 0x05);
+}
+
+#[test]
+fn test_machine_frozen_at_breakpoint() {
+    // NOP; NOP; HALT
+    let mut emu = common::setup_program(&[0x00, 0x00, 0x76]);
+    let mut debugger = Debugger::new();
+    debugger.break_on_opcode(0x76);
+    emu.cpu.set_debugger(Some(debugger));
+
+    run_until_breakpoint(&mut emu);
+    let scan_line = emu.memory.read_memory(0xFF44);
+    let div = emu.memory.read_memory(0xFF04);
+    let total_cycles = emu.total_cycles();
+
+    // longer than a scan line and a DIV increment
+    for _ in 0..500 {
+        emu.step();
+    }
+    assert_eq!(emu.step_instruction(), 0);
+    assert_eq!(emu.memory.read_memory(0xFF44), scan_line);
+    assert_eq!(emu.memory.read_memory(0xFF04), div);
+    assert_eq!(emu.total_cycles(), total_cycles);
+}
@@ -0,0 +1,77 @@
+use gbemu::{
+    cpu::{
+        disassemble::{disassemble_range, disassemble_range_banked},
+        Instruction, Register16,
+    },
+    Memory,
+};
+
+mod common;
+
+fn build_mbc1_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x20000];
+    rom[0x147] = 0x01; // MBC1
+    rom[0x148] = 0x02; // 8 banks
+    rom[0x150..0x153].copy_from_slice(&[0xCD, 0x00, 0x40]); // CALL $4000
+    rom[5 * 0x4000] = 0xC9; // RET
+    rom
+}
+
+#[test]
+fn test_disassemble_range() {
+    let mmu = common::setup_mmu(&common::build_rom(&[
+        0x00, 0x3E, 0x12, 0xD3, 0xC3, 0x50, 0x01,
+    ]));
+
+    let lines: Vec<String> = disassemble_range(&mmu, 0x100..0x107)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "0100  NOP",
+            "0101  LD A, $12",
+            "0103  DB $d3",
+            "0104  JP $0150"
+        ]
+    );
+}
+
+#[test]
+fn test_disassemble_range_banked() {
+    let mut mmu = common::setup_mmu(&build_mbc1_rom());
+    mmu.write_memory(0x2000, 0x05);
+
+    let call = &disassemble_range_banked(&mmu, 0x150..0x153)[0];
+    assert_eq!(call.bank, Some(0));
+    assert_eq!(call.target, Some((5, 0x4000)));
+    assert_eq!(call.to_string(), "00:0150  CALL $4000  ; -> 05:4000");
+
+    let ret = &disassemble_range_banked(&mmu, 0x4000..0x4001)[0];
+    assert_eq!(ret.to_string(), "05:4000  RET");
+}
+
+#[test]
+fn test_disassemble_with_pc() {
+    let jr = Instruction::JumpRelative {
+        condition: None,
+        offset: -2,
+    };
+    assert_eq!(jr.to_string(), "JR -2");
+    assert_eq!(jr.disassemble_with_pc(0x150), "JR $0150");
+
+    let add = Instruction::AddOffsetToReg16 {
+        reg: Register16::SP,
+        offset: 5,
+    };
+    assert_eq!(add.disassemble_with_pc(0x150), "ADD SP, +5");
+
+    // JR NZ, +3; LD HL, SP-3
+    let mmu = common::setup_mmu(&common::build_rom(&[0x20, 0x03, 0xF8, 0xFD]));
+    let lines: Vec<String> = disassemble_range(&mmu, 0x100..0x104)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(lines, ["0100  JR NZ, $0105", "0102  LD HL, SP-3"]);
+}
@@ -0,0 +1,180 @@
+use gbemu::{
+    display::{Display, FramebufferSizeError, ScreenEffect},
+    interrupt::Keys,
+    ppu::PIXEL_COUNT,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+mod common;
+
+const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+fn fb_pixel(fb: &[u8], width: usize, x: usize, y: usize) -> &[u8] {
+    let offset = (y * width + x) * 4;
+    &fb[offset..(offset + 4)]
+}
+
+#[test]
+fn test_lcd_grid_darkens_pixel_edges() {
+    let mut display = Display::default();
+    display.push_frame(&[0; PIXEL_COUNT]);
+
+    let scale = ScreenEffect::LcdGrid.scale();
+    let width = SCREEN_WIDTH as usize * scale;
+    let mut fb = vec![0; width * SCREEN_HEIGHT as usize * scale * 4];
+    display.draw_into_fb_with_effect(&mut fb, ScreenEffect::LcdGrid);
+
+    for (px, py) in [(0, 0), (10, 20), (159, 143)] {
+        for dy in 0..scale {
+            for dx in 0..scale {
+                let pixel = fb_pixel(&fb, width, px * scale + dx, py * scale + dy);
+                if dx == scale - 1 || dy == scale - 1 {
+                    assert_eq!(pixel, [191, 191, 191, 255]);
+                } else {
+                    assert_eq!(pixel, WHITE);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ghosting_blends_previous_frame() {
+    let mut display = Display::default();
+    display.push_frame(&[3; PIXEL_COUNT]);
+    display.push_frame(&[0; PIXEL_COUNT]);
+
+    let mut fb = vec![0; PIXEL_COUNT * 4];
+    display.draw_into_fb_with_effect(&mut fb, ScreenEffect::Ghosting);
+    assert_eq!(&fb[..4], [127, 127, 127, 255]);
+
+    display.push_frame(&[0; PIXEL_COUNT]);
+    display.draw_into_fb_with_effect(&mut fb, ScreenEffect::Ghosting);
+    assert_eq!(&fb[..4], WHITE);
+}
+
+#[test]
+fn test_frame_blend_averages_frames() {
+    let mut display = Display::default();
+    display.set_frame_blend(2);
+
+    let mut fb = vec![0; PIXEL_COUNT * 4];
+    display.push_frame(&[2; PIXEL_COUNT]);
+    for _ in 0..3 {
+        display.push_frame(&[0; PIXEL_COUNT]);
+        display.draw_into_fb(&mut fb);
+        assert_eq!(&fb[..4], [170, 170, 170, 255]);
+
+        display.push_frame(&[2; PIXEL_COUNT]);
+        display.draw_into_fb(&mut fb);
+        assert_eq!(&fb[..4], [170, 170, 170, 255]);
+    }
+
+    display.set_frame_blend(1);
+    display.draw_into_fb(&mut fb);
+    assert_eq!(&fb[..4], [85, 85, 85, 255]);
+}
+
+#[test]
+fn test_first_frame_after_fast_boot() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    emu.skip_frames(2);
+    assert!(emu.total_frames() > 0);
+
+    let mut fb = vec![0; PIXEL_COUNT * 4];
+    emu.display.lock().unwrap().draw_into_fb(&mut fb);
+    assert!(fb.chunks_exact(4).all(|pixel| pixel == WHITE));
+}
+
+#[test]
+fn test_load_frame_from_indices() {
+    // 4 vertical bands, one per color
+    let mut indices = [0; PIXEL_COUNT];
+    for (i, index) in indices.iter_mut().enumerate() {
+        *index = ((i % SCREEN_WIDTH as usize) / 40) as u8;
+    }
+    let mut display = Display::default();
+    display.push_frame(&[3; PIXEL_COUNT]);
+    display.set_frame_blend(4);
+    display.load_frame_from_indices(&indices);
+
+    let width = SCREEN_WIDTH as usize;
+    let mut fb = vec![0; PIXEL_COUNT * 4];
+    let mut ghosting_fb = vec![0; PIXEL_COUNT * 4];
+    let scale = ScreenEffect::LcdGrid.scale();
+    let mut grid_fb = vec![0; PIXEL_COUNT * scale * scale * 4];
+    display.draw_into_fb(&mut fb);
+    display.draw_into_fb_with_effect(&mut ghosting_fb, ScreenEffect::Ghosting);
+    display.draw_into_fb_with_effect(&mut grid_fb, ScreenEffect::LcdGrid);
+
+    for (x, expected) in [
+        (0, WHITE),
+        (40, [170, 170, 170, 255]),
+        (80, [85, 85, 85, 255]),
+        (159, [0, 0, 0, 255]),
+    ] {
+        for y in [0, 100] {
+            assert_eq!(fb_pixel(&fb, width, x, y), expected);
+            assert_eq!(fb_pixel(&ghosting_fb, width, x, y), expected);
+            assert_eq!(
+                fb_pixel(&grid_fb, width * scale, x * scale, y * scale),
+                expected
+            );
+        }
+    }
+}
+
+fn lit_overlay_pixels(keys_state: [bool; 8]) -> Vec<usize> {
+    let mut fb = vec![0; PIXEL_COUNT * 4];
+    Display::draw_input_overlay(&mut fb, keys_state).unwrap();
+    fb.chunks_exact(4)
+        .enumerate()
+        .filter(|(_, pixel)| *pixel == [255, 0, 0, 255])
+        .map(|(index, _)| index)
+        .collect()
+}
+
+#[test]
+fn test_input_overlay_lights_pressed_buttons() {
+    assert!(lit_overlay_pixels([false; 8]).is_empty());
+
+    let mut keys_state = [false; 8];
+    keys_state[Keys::A as usize] = true;
+    let a_pixels = lit_overlay_pixels(keys_state);
+    assert!(!a_pixels.is_empty());
+    // the overlay sits in the bottom left corner
+    for index in &a_pixels {
+        let (x, y) = (index % SCREEN_WIDTH as usize, index / SCREEN_WIDTH as usize);
+        assert!(x < 40 && y > SCREEN_HEIGHT as usize - 16);
+    }
+
+    let mut keys_state = [false; 8];
+    keys_state[Keys::Up as usize] = true;
+    let up_pixels = lit_overlay_pixels(keys_state);
+    assert!(!up_pixels.is_empty());
+    assert!(up_pixels.iter().all(|index| !a_pixels.contains(index)));
+}
+
+#[test]
+fn test_input_overlay_scales_with_lcd_grid() {
+    let scale = ScreenEffect::LcdGrid.scale();
+    let mut fb = vec![0; PIXEL_COUNT * scale * scale * 4];
+    let mut keys_state = [false; 8];
+    keys_state[Keys::Start as usize] = true;
+    Display::draw_input_overlay(&mut fb, keys_state).unwrap();
+
+    let lit = fb
+        .chunks_exact(4)
+        .filter(|pixel| *pixel == [255, 0, 0, 255])
+        .count();
+    assert_eq!(lit, lit_overlay_pixels(keys_state).len() * scale * scale);
+}
+
+#[test]
+fn test_input_overlay_rejects_unscaled_framebuffer() {
+    let mut fb = vec![0; PIXEL_COUNT * 4 + 4];
+    assert_eq!(
+        Display::draw_input_overlay(&mut fb, [false; 8]),
+        Err(FramebufferSizeError(PIXEL_COUNT * 4 + 4))
+    );
+}
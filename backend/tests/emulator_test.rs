@@ -0,0 +1,561 @@
+use gbemu::{
+    cpu::{debugger::Debugger, Register8, Registers},
+    emulator::T_CYCLES_PER_FRAME,
+    memory::CartridgeError,
+    ppu::RendererKind,
+    serial::StdoutSerialWrite,
+    Emulator, Memory, Model,
+};
+
+mod common;
+
+const LY_ADDR: u16 = 0xFF44;
+
+#[test]
+fn test_step_cycles_scanline() {
+    let mut emu = common::setup_program(&[]);
+
+    let start_ly = emu.memory.read_memory(LY_ADDR);
+    emu.step_cycles(456);
+    assert_eq!(emu.memory.read_memory(LY_ADDR), start_ly + 1);
+}
+
+#[test]
+fn test_step_cycles_keeps_partial_m_cycles() {
+    let mut emu = common::setup_program(&[]);
+
+    for _ in 0..(456 / 2) {
+        emu.step_cycles(2);
+    }
+    assert_eq!(emu.memory.read_memory(LY_ADDR), 1);
+}
+
+#[test]
+fn test_load_rom_resets_machine() {
+    // LD A, $42; LD ($C000), A; JR -2
+    let mut emu = common::setup_program(&[0x3E, 0x42, 0xEA, 0x00, 0xC0, 0x18, 0xFE]);
+    for _ in 0..100 {
+        emu.step();
+    }
+    assert_eq!(emu.memory.read_memory(0xC000), 0x42);
+    assert_ne!(emu.cpu.pc, 0x100);
+
+    // NOP; JR -2
+    emu.load_rom(&common::build_rom(&[0x00, 0x18, 0xFE]))
+        .unwrap();
+    assert_eq!(emu.cpu.pc, 0x100);
+    assert_eq!(emu.memory.read_memory(0x100), 0x00);
+    assert_eq!(emu.memory.read_memory(0xC000), 0x00);
+    assert_eq!(emu.memory.read_memory(LY_ADDR), 0);
+}
+
+#[test]
+fn test_load_rom_rejects_bad_cartridge() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+
+    assert_eq!(
+        emu.load_rom(&[0; 0x20]),
+        Err(CartridgeError::TooShort(0x20))
+    );
+    assert_eq!(emu.memory.read_memory(0x100), 0x18);
+}
+
+#[test]
+fn test_load_rom_keeps_options() {
+    // HALT
+    let mut emu = common::setup_program(&[0x76]);
+    let mut debugger = Debugger::new();
+    debugger.break_on_opcode(0x76);
+    emu.cpu.set_debugger(Some(debugger));
+    emu.cpu.set_opcode_coverage(true);
+    emu.ppu.set_renderer(RendererKind::Line);
+    for _ in 0..10 {
+        emu.step();
+    }
+    assert!(emu.cpu.is_at_breakpoint());
+
+    // NOP; HALT
+    emu.load_rom(&common::build_rom(&[0x00, 0x76])).unwrap();
+    assert!(!emu.cpu.is_at_breakpoint());
+    assert_eq!(emu.ppu.renderer(), RendererKind::Line);
+    for _ in 0..10 {
+        emu.step();
+    }
+    assert_eq!(emu.cpu.debugger().unwrap().stopped_at(), Some(0x101));
+    assert!(emu.cpu.opcode_coverage()[0x00]);
+}
+
+#[test]
+fn test_step_frame() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+
+    emu.step_cycles(456 * 3);
+    assert_eq!(emu.memory.read_memory(LY_ADDR), 3);
+    emu.step_frame();
+    assert_eq!(emu.memory.read_memory(LY_ADDR), 3);
+}
+
+fn run_inc_hl_in_oam(oam_bug: bool) -> Vec<u8> {
+    // LD HL, $FE40; INC HL; JR -2
+    run_during_oam_scan(&[0x21, 0x40, 0xFE, 0x23, 0x18, 0xFE], oam_bug)
+}
+
+fn run_during_oam_scan(program: &[u8], oam_bug: bool) -> Vec<u8> {
+    let mut emu = common::setup_program(program);
+    emu.memory.write().unwrap().set_oam_bug(oam_bug);
+    for addr in 0xFE00..0xFEA0 {
+        emu.memory.write_memory(addr, addr as u8);
+    }
+
+    // the first line starts in mode 2
+    for _ in 0..8 {
+        emu.step();
+    }
+    // the CPU can't read OAM during mode 2, read it as the PPU does
+    (0xFE00..0xFEA0)
+        .map(|addr| emu.memory.read_oam(addr))
+        .collect()
+}
+
+// each OAM byte holds the low byte of its address
+fn clean_oam() -> Vec<u8> {
+    (0xFE00..0xFEA0u16).map(|addr| addr as u8).collect()
+}
+
+// the PPU scans row 3 when HL is incremented or written, the corrupted
+// first word ((a ^ c) & (b ^ c)) ^ c and the copied 3 last words give row 2
+fn corrupted_oam() -> Vec<u8> {
+    let mut oam = clean_oam();
+    oam.copy_within(0x10..0x18, 0x18);
+    oam
+}
+
+#[test]
+fn test_skip_frames() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+
+    emu.step_cycles(456 * 10);
+    let start = emu.total_frames();
+    emu.skip_frames(60);
+    assert_eq!(emu.total_frames(), start + 60);
+}
+
+#[test]
+fn test_total_frames_and_cycles() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+
+    for _ in 0..10 {
+        emu.step_frame();
+    }
+    assert_eq!(emu.total_frames(), 10);
+    assert_eq!(emu.total_cycles(), 10 * T_CYCLES_PER_FRAME);
+
+    // leftover T-cycles are only counted once run
+    emu.step_cycles(6);
+    assert_eq!(emu.total_cycles(), 10 * T_CYCLES_PER_FRAME + 4);
+}
+
+#[test]
+fn test_oam_bug_inc_during_oam_scan() {
+    assert_eq!(run_inc_hl_in_oam(false), clean_oam());
+    assert_eq!(run_inc_hl_in_oam(true), corrupted_oam());
+}
+
+#[test]
+fn test_oam_bug_write_with_increment() {
+    // LD HL, $FE40; LD (HL+), A; JR -2
+    let write_inc = run_during_oam_scan(&[0x21, 0x40, 0xFE, 0x22, 0x18, 0xFE], true);
+    // LD HL, $FE40; LD (HL), A; JR -2
+    let write = run_during_oam_scan(&[0x21, 0x40, 0xFE, 0x77, 0x18, 0xFE], true);
+
+    // the increment happens with the write, they corrupt the OAM once
+    assert_eq!(write, corrupted_oam());
+    assert_eq!(write_inc, write);
+}
+
+#[test]
+fn test_post_boot_io_registers() {
+    // LDH A, ($47); LD ($C000), A; JR -2
+    let mut emu = common::setup_program(&[0xF0, 0x47, 0xEA, 0x00, 0xC0, 0x18, 0xFE]);
+    for _ in 0..10 {
+        emu.step();
+    }
+
+    assert_eq!(emu.memory.read_memory(0xC000), 0xFC);
+    assert_eq!(emu.memory.read_memory(0xFF40), 0x91);
+    assert_eq!(emu.memory.read_memory(0xFF00), 0xCF);
+    assert_eq!(emu.memory.read_memory(0xFF0F), 0xE1);
+    assert_eq!(emu.memory.read_memory(0xFF04), 0xAB);
+}
+
+#[test]
+#[cfg(feature = "cgb")]
+fn test_post_boot_cgb_registers() {
+    let mut rom = common::build_rom(&[0x18, 0xFE]);
+    rom[0x143] = 0x80;
+    let emu = Emulator::with_model(&rom, None, Box::new(StdoutSerialWrite), Model::Cgb).unwrap();
+
+    assert_eq!(emu.cpu.load_reg8(Register8::A), 0x11);
+    assert_eq!(emu.memory.read_memory(0xFF47), 0xFC);
+    assert_eq!(emu.memory.read_memory(0xFF4F), 0xFE);
+}
+
+#[test]
+fn test_model_initial_a_register() {
+    let rom = common::build_rom(&[0x18, 0xFE]);
+    let expected = [
+        (Model::Dmg, 0x01),
+        (Model::Mgb, 0xFF),
+        (Model::Sgb, 0x01),
+        (Model::Cgb, 0x11),
+        (Model::Agb, 0x11),
+    ];
+
+    for (model, a) in expected {
+        let emu = Emulator::with_model(&rom, None, Box::new(StdoutSerialWrite), model).unwrap();
+        assert_eq!(emu.model(), model);
+        assert_eq!(emu.cpu.load_reg8(Register8::A), a, "{:?}", model);
+    }
+}
+
+#[test]
+fn test_model_without_request() {
+    let dmg_rom = common::build_rom(&[0x18, 0xFE]);
+    let mut cgb_rom = dmg_rom.clone();
+    cgb_rom[0x143] = 0x80;
+
+    // CGB cartridges run on a CGB, the others on a DMG
+    let mut emu = Emulator::new(&cgb_rom, None, Box::new(StdoutSerialWrite)).unwrap();
+    #[cfg(feature = "cgb")]
+    {
+        assert_eq!(emu.model(), Model::Cgb);
+        assert_eq!(emu.cpu.load_reg8(Register8::A), 0x11);
+        assert_eq!(emu.memory.read_memory(0xFF4F), 0xFE);
+    }
+    emu.load_rom(&dmg_rom).unwrap();
+    assert_eq!(emu.model(), Model::Dmg);
+    assert_eq!(emu.cpu.load_reg8(Register8::A), 0x01);
+
+    // a requested model is kept whatever the cartridge
+    let mut emu =
+        Emulator::with_model(&dmg_rom, None, Box::new(StdoutSerialWrite), Model::Mgb).unwrap();
+    emu.load_rom(&cgb_rom).unwrap();
+    assert_eq!(emu.model(), Model::Mgb);
+    assert_eq!(emu.cpu.load_reg8(Register8::A), 0xFF);
+    assert_eq!(emu.memory.read_memory(0xFF4F), 0xFF);
+}
+
+#[test]
+#[cfg(feature = "cgb")]
+fn test_speed_switch() {
+    // LD A, $01; LDH ($4D), A; STOP; LDH A, ($4D); LD ($C000), A; JR -2
+    let program = [
+        0x3E, 0x01, 0xE0, 0x4D, 0x10, 0x00, 0xF0, 0x4D, 0xEA, 0x00, 0xC0, 0x18, 0xFE,
+    ];
+    let mut rom = common::build_rom(&program);
+    rom[0x143] = 0x80;
+    let mut emu = Emulator::new(&rom, None, Box::new(StdoutSerialWrite)).unwrap();
+    assert_eq!(emu.memory.read_memory(0xFF4D), 0x7E);
+
+    // the armed switch makes STOP toggle the speed instead of stopping
+    for _ in 0..20 {
+        emu.step();
+    }
+    assert_eq!(emu.memory.read_memory(0xC000), 0xFE);
+
+    // a DMG has no KEY1, STOP stops the CPU
+    let mut emu = common::setup_program(&program);
+    assert_eq!(emu.memory.read_memory(0xFF4D), 0xFF);
+    for _ in 0..20 {
+        emu.step();
+    }
+    assert_eq!(emu.memory.read_memory(0xC000), 0x00);
+}
+
+fn run_rmw_hl(opcode: u8, value: u8) -> Emulator {
+    // LD HL, $C000; SCF; <opcode>
+    let mut emu = common::setup_program(&[0x21, 0x00, 0xC0, 0x37, opcode]);
+    emu.memory.write_memory(0xC000, value);
+
+    for _ in 0..4 {
+        emu.step();
+    }
+    // the write happens on the last of the 3 M-cycles
+    for _ in 0..2 {
+        emu.step();
+        assert_eq!(emu.memory.read_memory(0xC000), value);
+    }
+    emu.step();
+    assert!(emu.cpu.is_pipeline_empty());
+    emu
+}
+
+#[test]
+fn test_inc_indirect_preserves_carry() {
+    let emu = run_rmw_hl(0x34, 0x0F);
+
+    assert_eq!(emu.memory.read_memory(0xC000), 0x10);
+    // H and C set, Z and N clear
+    assert_eq!(emu.cpu.load_reg8(Register8::Flags), 0x30);
+}
+
+#[test]
+fn test_dec_indirect_preserves_carry() {
+    let emu = run_rmw_hl(0x35, 0x10);
+
+    assert_eq!(emu.memory.read_memory(0xC000), 0x0F);
+    // N, H and C set, Z clear
+    assert_eq!(emu.cpu.load_reg8(Register8::Flags), 0x70);
+}
+
+#[test]
+fn test_opcode_coverage() {
+    // LD A, $05; SWAP A; JR -2
+    let mut emu = common::setup_program(&[0x3E, 0x05, 0xCB, 0x37, 0x18, 0xFE]);
+    emu.cpu.set_opcode_coverage(true);
+
+    for _ in 0..20 {
+        emu.step();
+    }
+
+    let coverage = emu.cpu.opcode_coverage();
+    let executed: Vec<usize> = (0..coverage.len()).filter(|&i| coverage[i]).collect();
+    assert_eq!(executed, vec![0x18, 0x3E, 0x137]);
+}
+
+#[test]
+fn test_execute_from_ram() {
+    // CALL $FF80; CALL $C000; JR -2
+    let mut emu = common::setup_program(&[0xCD, 0x80, 0xFF, 0xCD, 0x00, 0xC0, 0x18, 0xFE]);
+    // INC A; RET
+    for (addr, byte) in [
+        (0xFF80, 0x3C),
+        (0xFF81, 0xC9),
+        (0xC000, 0x3C),
+        (0xC001, 0xC9),
+    ] {
+        emu.memory.write_memory(addr, byte);
+    }
+    let a = emu.cpu.load_reg8(Register8::A);
+
+    for _ in 0..50 {
+        emu.step();
+    }
+    assert_eq!(emu.cpu.load_reg8(Register8::A), a.wrapping_add(2));
+    // back in the JR loop
+    assert!((0x106..=0x108).contains(&emu.cpu.pc));
+}
+
+#[test]
+fn test_bootstrap_rom_unmount() {
+    // NOPs up to the same exit sequence as the real bootstrap ROM:
+    // LD A, $01; LDH ($50), A
+    let mut bootstrap = vec![0x00; 0x100];
+    bootstrap[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+    let rom = common::build_rom(&[0x18, 0xFE]);
+    let mut emu = Emulator::new(&rom, Some(&bootstrap), Box::new(StdoutSerialWrite)).unwrap();
+    assert!(emu.is_bootstrap_rom_mounted());
+    assert_eq!(emu.cpu.pc, 0x0000);
+
+    let mut steps = 0;
+    while emu.is_bootstrap_rom_mounted() {
+        emu.step();
+        steps += 1;
+        assert!(steps < 0x200, "bootstrap ROM never unmounted");
+    }
+    assert_eq!(emu.cpu.pc, 0x0100);
+    assert_eq!(emu.memory.read_memory(0x100), 0x18);
+}
+
+fn setup_ram_cartridge(cartridge_type: u8) -> Emulator {
+    let mut rom = common::build_rom(&[0x18, 0xFE]);
+    rom[0x147] = cartridge_type;
+    rom[0x149] = 0x02; // 8KB of RAM
+    let mut emu = Emulator::new(&rom, None, Box::new(StdoutSerialWrite)).unwrap();
+    emu.memory.write_memory(0x0000, 0x0A);
+    emu.memory.write_memory(0xA000, 0x42);
+    emu
+}
+
+#[test]
+fn test_reset_clears_ram_without_battery() {
+    let mut emu = setup_ram_cartridge(0x02); // MBC1+RAM
+    assert_eq!(emu.memory.read().unwrap().dump_cartridge_ram(), None);
+
+    emu.reset();
+    assert_eq!(emu.cpu.pc, 0x100);
+    emu.memory.write_memory(0x0000, 0x0A);
+    assert_eq!(emu.memory.read_memory(0xA000), 0x00);
+}
+
+#[test]
+fn test_reset_keeps_battery_ram() {
+    let mut emu = setup_ram_cartridge(0x03); // MBC1+RAM+BATTERY
+
+    emu.reset();
+    // RAM is disabled again after the power cycle
+    emu.memory.write_memory(0x0000, 0x0A);
+    assert_eq!(emu.memory.read_memory(0xA000), 0x42);
+    assert_eq!(
+        emu.memory.read().unwrap().dump_cartridge_ram().unwrap()[0],
+        0x42
+    );
+}
+
+#[test]
+fn test_memory_search() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    emu.memory.write_memory(0xC123, 0x63);
+    emu.memory.write_memory(0xD456, 0x63);
+    emu.memory.write_memory(0xFF90, 0x63);
+
+    let found = emu.search_memory(|_, value| value == 0x63);
+    assert_eq!(found, [0xC123, 0xD456, 0xFF90]);
+}
+
+#[test]
+fn test_fill_memory() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    emu.memory.write_memory(0xC0FF, 0x11);
+    emu.memory.write_memory(0xC200, 0x11);
+
+    emu.fill_memory(0xC100..=0xC1FF, 0x63);
+    assert_eq!(emu.search_memory(|_, value| value == 0x63).len(), 0x100);
+    assert_eq!(emu.memory.read_memory(0xC100), 0x63);
+    assert_eq!(emu.memory.read_memory(0xC1FF), 0x63);
+    assert_eq!(emu.memory.read_memory(0xC0FF), 0x11);
+    assert_eq!(emu.memory.read_memory(0xC200), 0x11);
+}
+
+#[test]
+fn test_changed_addresses() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    emu.memory.write_memory(0xC010, 10);
+    emu.memory.write_memory(0xC020, 10);
+
+    let snapshot = emu.snapshot_ram();
+    assert_eq!(snapshot.get(0xC010), Some(10));
+    emu.memory.write_memory(0xC010, 7);
+    emu.memory.write_memory(0xC020, 12);
+
+    assert_eq!(
+        emu.changed_addresses(&snapshot, |old, new| new < old),
+        [0xC010]
+    );
+    assert_eq!(
+        emu.changed_addresses(&snapshot, |old, new| old != new),
+        [0xC010, 0xC020]
+    );
+}
+
+#[test]
+fn test_flush_ram_on_shutdown() {
+    let save_path = std::env::temp_dir().join(format!("gbemu-shutdown-{}.sav", std::process::id()));
+    let _ = std::fs::remove_file(&save_path);
+
+    let mut emu = setup_ram_cartridge(0x03); // MBC1+RAM+BATTERY
+    emu.set_save_path(&save_path).unwrap();
+    emu.step_frame();
+    emu.flush_ram().unwrap();
+    drop(emu);
+
+    let saved = std::fs::read(&save_path).unwrap();
+    assert_eq!(saved.len(), 0x2000);
+    assert_eq!(saved[0], 0x42);
+
+    // the next run starts from the save
+    let mut rom = common::build_rom(&[0x18, 0xFE]);
+    rom[0x147] = 0x03;
+    rom[0x149] = 0x02;
+    let mut emu = Emulator::new(&rom, None, Box::new(StdoutSerialWrite)).unwrap();
+    emu.set_save_path(&save_path).unwrap();
+    emu.memory.write_memory(0x0000, 0x0A);
+    assert_eq!(emu.memory.read_memory(0xA000), 0x42);
+
+    std::fs::remove_file(&save_path).unwrap();
+}
+
+#[test]
+fn test_load_rom_keeps_save_path() {
+    let save_path = std::env::temp_dir().join(format!("gbemu-reload-{}.sav", std::process::id()));
+    let _ = std::fs::remove_file(&save_path);
+
+    let mut emu = setup_ram_cartridge(0x03); // MBC1+RAM+BATTERY
+    emu.set_save_path(&save_path).unwrap();
+
+    // a rebuilt ROM starts from the RAM of the previous one
+    let mut rom = common::build_rom(&[0x00, 0x18, 0xFE]);
+    rom[0x147] = 0x03;
+    rom[0x149] = 0x02;
+    emu.load_rom(&rom).unwrap();
+    emu.memory.write_memory(0x0000, 0x0A);
+    assert_eq!(emu.memory.read_memory(0xA000), 0x42);
+
+    // and its RAM is still saved
+    emu.memory.write_memory(0xA000, 0x24);
+    emu.flush_ram().unwrap();
+    assert_eq!(std::fs::read(&save_path).unwrap()[0], 0x24);
+
+    std::fs::remove_file(&save_path).unwrap();
+}
+
+#[test]
+fn test_illegal_opcode_locks_cpu() {
+    // EI; NOP; illegal 0xD3; INC A
+    let mut emu = common::setup_program(&[0xFB, 0x00, 0xD3, 0x3C]);
+    emu.cpu.store_reg8(Register8::A, 0);
+    for _ in 0..10 {
+        emu.step();
+    }
+    assert!(emu.cpu.is_locked());
+    assert_eq!(emu.cpu.pc, 0x103);
+
+    // not even an interrupt gets it out
+    emu.memory.write_memory(0xFFFF, 0x01);
+    emu.memory.write_memory(0xFF0F, 0x01);
+    for _ in 0..100 {
+        emu.step();
+    }
+    assert!(emu.cpu.is_locked());
+    assert_eq!(emu.cpu.pc, 0x103);
+    assert_eq!(emu.cpu.load_reg8(Register8::A), 0);
+}
+
+#[test]
+fn test_cpu_registers() {
+    // ADD A, B; JR -2
+    let mut emu = common::setup_program(&[0x80, 0x18, 0xFE]);
+    let registers = Registers {
+        a: 0x3A,
+        f: 0x00,
+        b: 0xC6,
+        c: 0x01,
+        d: 0x02,
+        e: 0x03,
+        h: 0x04,
+        l: 0x05,
+        sp: 0xDFF0,
+        pc: 0x100,
+    };
+    emu.cpu.set_registers(registers);
+    assert_eq!(emu.cpu.registers(), registers);
+
+    emu.step_instruction();
+    // 0x3A + 0xC6 overflows to 0 with both carries
+    assert_eq!(
+        emu.cpu.registers(),
+        Registers {
+            a: 0x00,
+            f: 0xB0,
+            pc: 0x101,
+            ..registers
+        }
+    );
+
+    // the unused low nibble of F does not exist
+    emu.cpu.set_registers(Registers {
+        f: 0xFF,
+        ..registers
+    });
+    assert_eq!(emu.cpu.registers().f, 0xF0);
+}
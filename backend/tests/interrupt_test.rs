@@ -0,0 +1,270 @@
+use gbemu::{
+    cpu::{Register16, Register8},
+    interrupt::{IntKind, InterruptController, Keys, TimerState},
+    serial::StdoutSerialWrite,
+    Emulator, Memory,
+};
+
+mod common;
+
+fn setup_interrupt_rom() -> Emulator {
+    // LD A, $05; LDH ($FF), A; EI; JR -2
+    let mut rom = common::build_rom(&[0x3E, 0x05, 0xE0, 0xFF, 0xFB, 0x18, 0xFE]);
+    // RETI in the VBlank and timer handlers
+    rom[0x40] = 0xD9;
+    rom[0x50] = 0xD9;
+    Emulator::new(&rom, None, Box::new(StdoutSerialWrite)).unwrap()
+}
+
+#[test]
+fn test_interrupt_log() {
+    let mut emu = setup_interrupt_rom();
+    {
+        let mut int_controller = emu.interrupt_controller.lock().unwrap();
+        int_controller.set_interrupt_log(true);
+        int_controller.trigger_timer_int();
+        int_controller.trigger_vblank_int();
+    }
+
+    for _ in 0..50 {
+        emu.step();
+    }
+
+    let int_controller = emu.interrupt_controller.lock().unwrap();
+    let log: Vec<_> = int_controller.interrupt_log().collect();
+    assert_eq!(log.len(), 2);
+    assert_eq!(log[0].kind, IntKind::VBLANK);
+    assert_eq!(log[1].kind, IntKind::TIMER);
+    assert!(log[0].cycle < log[1].cycle);
+}
+
+#[test]
+fn test_interrupt_log_disabled() {
+    let mut emu = setup_interrupt_rom();
+    emu.interrupt_controller
+        .lock()
+        .unwrap()
+        .trigger_vblank_int();
+
+    for _ in 0..50 {
+        emu.step();
+    }
+
+    let int_controller = emu.interrupt_controller.lock().unwrap();
+    assert!(!int_controller.interrupt_flag.contains(IntKind::VBLANK));
+    assert_eq!(int_controller.interrupt_log().count(), 0);
+}
+
+#[test]
+fn test_halt_ignores_disabled_interrupt() {
+    // HALT; INC A; JR -2
+    let mut emu = common::setup_program(&[0x76, 0x3C, 0x18, 0xFE]);
+    for _ in 0..4 {
+        emu.step();
+    }
+    let a = emu.cpu.load_reg8(Register8::A);
+
+    emu.interrupt_controller.lock().unwrap().trigger_timer_int();
+    for _ in 0..10 {
+        emu.step();
+    }
+    assert_eq!(emu.cpu.load_reg8(Register8::A), a);
+
+    // enabling it wakes the CPU, without servicing it as IME is cleared
+    emu.memory.write_memory(0xFFFF, IntKind::TIMER.bits());
+    for _ in 0..2 {
+        emu.step();
+    }
+    assert_eq!(emu.cpu.load_reg8(Register8::A), a.wrapping_add(1));
+}
+
+#[test]
+fn test_request_interrupt() {
+    // EI; JR -2
+    let mut emu = common::setup_program(&[0xFB, 0x18, 0xFE]);
+    {
+        let mut int_controller = emu.interrupt_controller.lock().unwrap();
+        int_controller.request_interrupt(IntKind::SERIAL);
+        assert!(int_controller.pending_interrupts().is_empty());
+        int_controller.interrupt_enable = IntKind::SERIAL;
+        assert_eq!(int_controller.pending_interrupts(), IntKind::SERIAL);
+    }
+
+    for _ in 0..20 {
+        emu.step();
+        if emu.cpu.pc == 0x58 {
+            break;
+        }
+    }
+    assert_eq!(emu.cpu.pc, 0x58);
+    assert!(emu
+        .interrupt_controller
+        .lock()
+        .unwrap()
+        .pending_interrupts()
+        .is_empty());
+}
+
+// TIMA at 0xFF, incremented every 4 M-cycles
+fn setup_overflowing_timer() -> InterruptController {
+    let mut int = InterruptController::new();
+    int.timer_control = 0b101;
+    int.timer_modulo = 0x42;
+    int.timer_counter = 0xFF;
+    for _ in 0..4 {
+        int.timer_step(4);
+    }
+    int
+}
+
+#[test]
+fn test_tima_reload_delay() {
+    let mut int = setup_overflowing_timer();
+    assert_eq!(int.timer_state(), TimerState::Overflowing);
+    assert_eq!(int.timer_counter, 0x00);
+    assert!(!int.interrupt_flag.contains(IntKind::TIMER));
+
+    int.timer_step(4);
+    assert_eq!(int.timer_state(), TimerState::Reloading);
+    assert_eq!(int.timer_counter, 0x42);
+    assert!(int.interrupt_flag.contains(IntKind::TIMER));
+
+    int.timer_step(4);
+    assert_eq!(int.timer_state(), TimerState::Running);
+}
+
+#[test]
+fn test_tima_write_cancels_reload() {
+    let mut int = setup_overflowing_timer();
+    int.write_timer_counter(0x10);
+
+    int.timer_step(4);
+    assert_eq!(int.timer_counter, 0x10);
+    assert!(!int.interrupt_flag.contains(IntKind::TIMER));
+}
+
+#[test]
+fn test_tma_write_during_reload() {
+    let mut int = setup_overflowing_timer();
+    int.timer_step(4);
+
+    // TIMA writes are ignored while it is reloaded, TMA writes go through
+    int.write_timer_counter(0x10);
+    assert_eq!(int.timer_counter, 0x42);
+    int.write_timer_modulo(0x99);
+    assert_eq!(int.timer_counter, 0x99);
+    assert_eq!(int.timer_modulo, 0x99);
+}
+
+#[test]
+fn test_tma_write_before_reload() {
+    let mut int = setup_overflowing_timer();
+    int.write_timer_modulo(0x99);
+
+    int.timer_step(4);
+    assert_eq!(int.timer_counter, 0x99);
+}
+
+fn joypad_requested(int_controller: &mut InterruptController) -> bool {
+    let requested = int_controller.interrupt_flag.contains(IntKind::JOYPAD);
+    int_controller.interrupt_flag.remove(IntKind::JOYPAD);
+    requested
+}
+
+#[test]
+fn test_joypad_interrupt() {
+    let mut int_controller = InterruptController::new();
+    // directions selected
+    int_controller.write_joypad_reg(0x20);
+
+    int_controller.change_key_state(Keys::Down, true);
+    assert!(joypad_requested(&mut int_controller));
+    // released and held keys don't request it again
+    int_controller.change_key_state(Keys::Down, true);
+    int_controller.change_key_state(Keys::Down, false);
+    assert!(!joypad_requested(&mut int_controller));
+
+    // buttons are not selected
+    int_controller.change_key_state(Keys::Start, true);
+    assert!(!joypad_requested(&mut int_controller));
+    // selecting them pulls the Start line low
+    int_controller.write_joypad_reg(0x10);
+    assert!(joypad_requested(&mut int_controller));
+}
+
+#[test]
+fn test_joypad_interrupt_shared_line() {
+    let mut int_controller = InterruptController::new();
+    // both groups selected, Down and Start share a line
+    int_controller.write_joypad_reg(0x00);
+    int_controller.change_key_state(Keys::Start, true);
+    assert!(joypad_requested(&mut int_controller));
+
+    int_controller.change_key_state(Keys::Down, true);
+    assert!(!joypad_requested(&mut int_controller));
+    int_controller.change_key_state(Keys::A, true);
+    assert!(joypad_requested(&mut int_controller));
+}
+
+#[test]
+fn test_halt_bug() {
+    // DI; HALT; INC A; LD B, A; JR -2
+    let mut emu = common::setup_program(&[0xF3, 0x76, 0x3C, 0x47, 0x18, 0xFE]);
+    emu.cpu.store_reg8(Register8::A, 0);
+    emu.memory.write_memory(0xFFFF, IntKind::TIMER.bits());
+    emu.interrupt_controller.lock().unwrap().trigger_timer_int();
+
+    for _ in 0..20 {
+        emu.step();
+    }
+    // HALT does not stop the CPU and INC A runs twice
+    assert_eq!(emu.cpu.load_reg8(Register8::A), 2);
+    assert_eq!(emu.cpu.load_reg8(Register8::B), 2);
+    assert!(emu
+        .interrupt_controller
+        .lock()
+        .unwrap()
+        .interrupt_flag
+        .contains(IntKind::TIMER));
+}
+
+#[test]
+fn test_ei_delay() {
+    // DI; EI; NOP; INC A; JR -2
+    let mut emu = common::setup_program(&[0xF3, 0xFB, 0x00, 0x3C, 0x18, 0xFE]);
+    emu.cpu.store_reg8(Register8::A, 0);
+    emu.memory.write_memory(0xFFFF, IntKind::TIMER.bits());
+    emu.interrupt_controller.lock().unwrap().trigger_timer_int();
+
+    // DI then EI
+    emu.step();
+    emu.step();
+    assert_eq!(emu.cpu.pc, 0x102);
+    assert!(!emu.interrupt_controller.lock().unwrap().master_enable);
+
+    for _ in 0..10 {
+        emu.step();
+        if emu.cpu.pc == 0x50 {
+            break;
+        }
+    }
+    assert_eq!(emu.cpu.pc, 0x50);
+    // serviced right after the NOP, before INC A
+    assert_eq!(emu.cpu.load_reg8(Register8::A), 0);
+    let sp = emu.cpu.load_reg16(Register16::SP);
+    assert_eq!(emu.memory.read_memory(sp), 0x03);
+    assert_eq!(emu.memory.read_memory(sp + 1), 0x01);
+}
+
+#[test]
+fn test_ei_di_blocks_interrupt() {
+    // EI; DI; INC A; JR -2
+    let mut emu = common::setup_program(&[0xFB, 0xF3, 0x3C, 0x18, 0xFE]);
+    emu.memory.write_memory(0xFFFF, IntKind::TIMER.bits());
+    emu.interrupt_controller.lock().unwrap().trigger_timer_int();
+
+    for _ in 0..20 {
+        emu.step();
+        assert!(emu.cpu.pc >= 0x100);
+    }
+}
@@ -0,0 +1,723 @@
+use gbemu::{
+    memory::{
+        self,
+        rtc::{RtcRegisters, RtcSave},
+        MMUBuilder, MemoryRegion, OamBugAccess,
+    },
+    Memory, Model,
+};
+
+mod common;
+
+#[test]
+#[cfg(feature = "cgb")]
+fn test_vram_banks() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    mmu.set_cgb_mode(true);
+
+    mmu.write_memory(0x8000, 0x12);
+    mmu.write_memory(0xFF4F, 0x01);
+    assert_eq!(mmu.read_memory(0xFF4F), 0xFF);
+    mmu.write_memory(0x8000, 0x34);
+
+    assert_eq!(mmu.read_memory(0x8000), 0x34);
+    assert_eq!(mmu.read_vram(0x8000, 0), 0x12);
+    assert_eq!(mmu.read_vram(0x8000, 1), 0x34);
+}
+
+#[test]
+fn test_vram_bank_switch_ignored_on_dmg() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+
+    mmu.write_memory(0x8000, 0x12);
+    mmu.write_memory(0xFF4F, 0x01);
+    assert_eq!(mmu.read_memory(0xFF4F), 0xFF);
+    assert_eq!(mmu.read_memory(0x8000), 0x12);
+    assert_eq!(mmu.read_vram(0x8000, 1), 0xFF);
+}
+
+#[test]
+#[cfg(feature = "cgb")]
+fn test_wram_bank_out_of_range() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    mmu.set_cgb_mode(true);
+
+    mmu.write_memory(0xFF70, 0x02);
+    assert_eq!(mmu.read_memory(0xFF70), 0xFA);
+    mmu.write_memory(0xFF70, 0x0B);
+    assert_eq!(mmu.read_memory(0xFF70), 0xFB);
+}
+
+#[test]
+fn test_wram_bank_switch_ignored_on_dmg() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+
+    mmu.write_memory(0xFF70, 0x02);
+    assert_eq!(mmu.read_memory(0xFF70), 0xFF);
+}
+
+#[test]
+#[cfg(feature = "cgb")]
+fn test_cgb_undocumented_registers() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    mmu.set_cgb_mode(true);
+
+    mmu.write_memory(0xFF72, 0x5A);
+    assert_eq!(mmu.read_memory(0xFF72), 0x5A);
+
+    mmu.write_memory(0xFF75, 0xFF);
+    assert_eq!(mmu.read_memory(0xFF75), 0xFF);
+    mmu.write_memory(0xFF75, 0x00);
+    assert_eq!(mmu.read_memory(0xFF75), 0b10001111);
+    mmu.write_memory(0xFF75, 0b00100000);
+    assert_eq!(mmu.read_memory(0xFF75), 0b10101111);
+
+    mmu.write_memory(0xFF76, 0x12);
+    assert_eq!(mmu.read_memory(0xFF76), 0x00);
+}
+
+#[test]
+fn test_cgb_undocumented_registers_absent_on_dmg() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+
+    mmu.write_memory(0xFF72, 0x5A);
+    assert_eq!(mmu.read_memory(0xFF72), 0xFF);
+    assert_eq!(mmu.read_memory(0xFF75), 0xFF);
+}
+
+#[test]
+fn test_echo_ram() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+
+    mmu.write_memory(0xC123, 0x12);
+    assert_eq!(mmu.read_memory(0xE123), 0x12);
+    mmu.write_memory(0xE123, 0x34);
+    assert_eq!(mmu.read_memory(0xC123), 0x34);
+
+    for addr in 0xE000..=0xFDFF {
+        let value = (addr ^ (addr >> 8)) as u8;
+        mmu.write_memory(addr, value);
+        assert_eq!(mmu.read_memory(addr - 0x2000), value);
+    }
+    for addr in 0xC000..=0xDDFF {
+        let value = !(addr as u8);
+        mmu.write_memory(addr, value);
+        assert_eq!(mmu.read_memory(addr + 0x2000), value);
+    }
+}
+
+fn setup_oam_bug_mmu() -> gbemu::memory::MMU {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    mmu.set_oam_bug(true);
+
+    // row 1: 0x1234, 0x5678, 0x00FF, 0x9ABC
+    for (i, value) in [0x34, 0x12, 0x78, 0x56, 0xFF, 0x00, 0xBC, 0x9A]
+        .into_iter()
+        .enumerate()
+    {
+        mmu.write_memory(0xFE08 + i as u16, value);
+    }
+    // row 2: 0x0F0F, ...
+    for i in 0..8 {
+        mmu.write_memory(0xFE10 + i, 0x0F);
+    }
+    mmu
+}
+
+fn read_oam_row(mmu: &gbemu::memory::MMU, row: u16) -> Vec<u8> {
+    (0..8)
+        .map(|i| mmu.read_memory(0xFE00 + row * 8 + i))
+        .collect()
+}
+
+#[test]
+fn test_oam_bug_write_corruption() {
+    let mut mmu = setup_oam_bug_mmu();
+    mmu.set_oam_scan_row(Some(2));
+    mmu.trigger_oam_bug(0xFE20, OamBugAccess::Write);
+
+    assert_eq!(
+        read_oam_row(&mmu, 2),
+        [0x3F, 0x02, 0x78, 0x56, 0xFF, 0x00, 0xBC, 0x9A]
+    );
+}
+
+#[test]
+fn test_oam_bug_read_corruption() {
+    let mut mmu = setup_oam_bug_mmu();
+    mmu.set_oam_scan_row(Some(2));
+    mmu.trigger_oam_bug(0xFE20, OamBugAccess::Read);
+
+    assert_eq!(
+        read_oam_row(&mmu, 2),
+        [0x3F, 0x12, 0x78, 0x56, 0xFF, 0x00, 0xBC, 0x9A]
+    );
+}
+
+#[test]
+fn test_oam_bug_ignored() {
+    let mut mmu = setup_oam_bug_mmu();
+    let row_2 = read_oam_row(&mmu, 2);
+
+    // outside of mode 2
+    mmu.set_oam_scan_row(None);
+    mmu.trigger_oam_bug(0xFE20, OamBugAccess::Write);
+    assert_eq!(read_oam_row(&mmu, 2), row_2);
+
+    // outside of OAM
+    mmu.set_oam_scan_row(Some(2));
+    mmu.trigger_oam_bug(0xC000, OamBugAccess::Write);
+    assert_eq!(read_oam_row(&mmu, 2), row_2);
+
+    // disabled
+    mmu.set_oam_bug(false);
+    mmu.trigger_oam_bug(0xFE20, OamBugAccess::Write);
+    assert_eq!(read_oam_row(&mmu, 2), row_2);
+}
+
+#[test]
+fn test_dma_restart() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    for offset in 0..0xA0 {
+        mmu.write_memory(0xC000 + offset, 0x11);
+        mmu.write_memory(0xD000 + offset, 0x22);
+    }
+
+    mmu.write_memory(0xFF46, 0xC0);
+    for _ in 0..80 {
+        mmu.tick();
+    }
+    mmu.write_memory(0xFF46, 0xD0);
+    assert_eq!(mmu.read_memory(0xFF46), 0xD0);
+
+    // the first DMA would have completed here
+    for _ in 0..80 {
+        mmu.tick();
+    }
+    assert_eq!(mmu.read_memory(0xFE00), 0x00);
+
+    for _ in 0..80 {
+        mmu.tick();
+    }
+    assert!((0xFE00..0xFEA0).all(|addr| mmu.read_memory(addr) == 0x22));
+    assert_eq!(mmu.read_memory(0xFF46), 0xD0);
+}
+
+#[test]
+fn test_joypad_select_bits() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+
+    mmu.write_memory(0xFF00, 0x30);
+    assert_eq!(mmu.read_memory(0xFF00), 0xFF);
+
+    mmu.write_memory(0xFF00, 0x20);
+    assert_eq!(mmu.read_memory(0xFF00), 0xEF);
+
+    mmu.write_memory(0xFF00, 0x10);
+    assert_eq!(mmu.read_memory(0xFF00), 0xDF);
+}
+
+#[test]
+fn test_dump_wram() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    for (i, addr) in (0xC000..=0xDFFF).enumerate() {
+        mmu.write_memory(addr, i as u8);
+    }
+
+    let wram = mmu.dump_region(MemoryRegion::Wram);
+    assert_eq!(wram.len(), 0x2000);
+    assert!(wram.iter().enumerate().all(|(i, &byte)| byte == i as u8));
+
+    assert_eq!(mmu.dump_region(MemoryRegion::Oam).len(), 0xA0);
+    assert_eq!(mmu.dump_region(MemoryRegion::Hram).len(), 0x7F);
+}
+
+#[test]
+fn test_read_word() {
+    let mut rom = common::build_rom(&[]);
+    rom[0x7FFF] = 0x34;
+    let mut mmu = common::setup_mmu(&rom);
+    mmu.write_memory(0x8000, 0x12);
+    mmu.write_memory(0xC010, 0xCD);
+    mmu.write_memory(0xC011, 0xAB);
+    mmu.write_memory(0xFF80, 0x78);
+    mmu.write_memory(0xFF81, 0x56);
+
+    // straddles the ROM and VRAM
+    assert_eq!(mmu.read_word(0x7FFF), 0x1234);
+    assert_eq!(
+        mmu.read_word(0x7FFF),
+        u16::from_le_bytes([mmu.read_memory(0x7FFF), mmu.read_memory(0x8000)])
+    );
+    assert_eq!(mmu.read_word(0xC010), 0xABCD);
+    assert_eq!(mmu.read_word(0xFF80), 0x5678);
+}
+
+#[test]
+fn test_interrupt_flag_upper_bits() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+
+    mmu.write_memory(0xFF0F, 0x00);
+    assert_eq!(mmu.read_memory(0xFF0F), 0xE0);
+    mmu.write_memory(0xFF0F, 0x05);
+    assert_eq!(mmu.read_memory(0xFF0F), 0xE5);
+}
+
+#[test]
+fn test_current_banks() {
+    let mut rom = vec![0; 0x20000];
+    rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x148] = 0x02; // 8 banks
+    rom[0x149] = 0x03; // 4 RAM banks
+    let mut mmu = common::setup_mmu(&rom);
+    assert_eq!(mmu.current_rom_bank(), 1);
+    assert_eq!(mmu.current_ram_bank(), 0);
+
+    mmu.write_memory(0x2000, 0x05);
+    mmu.write_memory(0x4000, 0x02);
+    assert_eq!(mmu.current_rom_bank(), 5);
+    // the RAM bank only follows in the advanced banking mode
+    assert_eq!(mmu.current_ram_bank(), 0);
+    mmu.write_memory(0x6000, 0x01);
+    assert_eq!(mmu.current_ram_bank(), 2);
+}
+
+#[test]
+fn test_oam_blocked_during_oam_scan() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    mmu.write_memory(0xFE00, 0x12);
+    mmu.write_memory(0x8000, 0x34);
+    mmu.write_memory(0xFF40, 0x80);
+    mmu.set_oam_scan_row(Some(0));
+
+    assert_eq!(mmu.read_memory(0xFE00), 0xFF);
+    assert_eq!(mmu.read_word(0xFE00), 0xFFFF);
+    mmu.write_memory(0xFE00, 0x56);
+    assert_eq!(mmu.read_memory(0x8000), 0x34);
+
+    mmu.set_oam_scan_row(None);
+    assert_eq!(mmu.read_memory(0xFE00), 0x12);
+}
+
+#[test]
+fn test_oam_not_blocked_with_lcd_off() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    mmu.set_oam_scan_row(Some(0));
+
+    mmu.write_memory(0xFE00, 0x12);
+    assert_eq!(mmu.read_memory(0xFE00), 0x12);
+}
+
+#[test]
+fn test_dma_during_oam_scan() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    for offset in 0..0xA0 {
+        mmu.write_memory(0xC000 + offset, 0x11);
+    }
+    mmu.write_memory(0xFF40, 0x80);
+    mmu.set_oam_scan_row(Some(0));
+
+    mmu.write_memory(0xFF46, 0xC0);
+    for _ in 0..0xA0 {
+        mmu.tick();
+    }
+    assert!(mmu
+        .dump_region(MemoryRegion::Oam)
+        .iter()
+        .all(|&byte| byte == 0x11));
+}
+
+#[test]
+fn test_unusable_region_per_model() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    mmu.write_memory(0xFEA0, 0x12);
+    assert_eq!(mmu.read_memory(0xFEA0), 0x00);
+    assert_eq!(mmu.read_memory(0xFEC5), 0x00);
+
+    mmu.set_model(Model::Cgb);
+    assert_eq!(mmu.read_memory(0xFEA0), 0x00);
+    assert_eq!(mmu.read_memory(0xFEC5), 0xCC);
+    assert_eq!(mmu.read_memory(0xFEFF), 0xFF);
+
+    mmu.set_model(Model::Agb);
+    assert_eq!(mmu.read_memory(0xFEA0), 0xAA);
+}
+
+#[test]
+fn test_unusable_region_during_oam_scan() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    mmu.write_memory(0xFF40, 0x80);
+    mmu.set_oam_scan_row(Some(0));
+
+    assert_eq!(mmu.read_memory(0xFEA0), 0xFF);
+}
+
+#[test]
+#[cfg(feature = "cgb")]
+fn test_builder_preloads_memory() {
+    let mbc = memory::build_mbc(&common::build_rom(&[])).unwrap();
+    let mmu = MMUBuilder::new(mbc)
+        .model(Model::Cgb)
+        .cgb_mode(true)
+        .vram(0, &[0x12, 0x34])
+        .vram(1, &[0x56])
+        .wram(&[0x78])
+        .build();
+
+    assert_eq!(mmu.model(), Model::Cgb);
+    assert_eq!(mmu.read_memory(0x8000), 0x12);
+    assert_eq!(mmu.read_memory(0x8001), 0x34);
+    assert_eq!(mmu.read_vram(0x8000, 1), 0x56);
+    assert_eq!(mmu.read_memory(0xC000), 0x78);
+    // the bootstrap ROM is unmounted by default
+    assert!(!mmu.is_bootstrap_rom_mounted());
+}
+
+#[test]
+fn test_builder_mounts_bootstrap_rom() {
+    let mbc = memory::build_mbc(&common::build_rom(&[])).unwrap();
+    let mmu = MMUBuilder::new(mbc)
+        .bootstrap_rom(&[0x31, 0xFE, 0xFF])
+        .build();
+
+    assert!(mmu.is_bootstrap_rom_mounted());
+    assert_eq!(mmu.read_memory(0x0000), 0x31);
+}
+
+fn build_1mb_mbc1_rom(multicart: bool) -> Vec<u8> {
+    let header = std::fs::read("./test_roms/acid2/dmg-acid2.gb").unwrap();
+    let mut rom = vec![0; 64 * 0x4000];
+    for bank in 0..64 {
+        rom[bank * 0x4000 + 0x1000] = bank as u8;
+    }
+    rom[0x104..0x134].copy_from_slice(&header[0x104..0x134]);
+    if multicart {
+        let game = 0x10 * 0x4000;
+        rom[(game + 0x104)..(game + 0x134)].copy_from_slice(&header[0x104..0x134]);
+    }
+    rom[0x147] = 0x01; // MBC1
+    rom[0x148] = 0x05; // 64 banks
+    rom
+}
+
+#[test]
+fn test_mbc1_upper_rom_bank_bits() {
+    let mut mmu = common::setup_mmu(&build_1mb_mbc1_rom(false));
+    mmu.write_memory(0x2000, 0x02);
+    mmu.write_memory(0x4000, 0x01);
+    assert_eq!(mmu.read_memory(0x5000), 0x22);
+
+    mmu.write_memory(0x2000, 0x00);
+    assert_eq!(mmu.read_memory(0x5000), 0x21);
+    assert_eq!(mmu.read_memory(0x1000), 0x00);
+    mmu.write_memory(0x6000, 0x01);
+    assert_eq!(mmu.read_memory(0x1000), 0x20);
+}
+
+#[test]
+fn test_mbc1_rom_bank_mask() {
+    let mut mmu = common::setup_mmu(&build_1mb_mbc1_rom(false));
+    mmu.write_memory(0x2000, 0x01);
+    assert_eq!(mmu.read_memory(0x5000), 0x01);
+    mmu.write_memory(0x2000, 0x02);
+    assert_eq!(mmu.read_memory(0x5000), 0x02);
+
+    // only the low 5 bits are stored, and only 0 there is turned into 1
+    mmu.write_memory(0x2000, 0xE3);
+    assert_eq!(mmu.read_memory(0x5000), 0x03);
+    mmu.write_memory(0x2000, 0x10);
+    assert_eq!(mmu.read_memory(0x5000), 0x10);
+    mmu.write_memory(0x2000, 0x20);
+    assert_eq!(mmu.read_memory(0x5000), 0x01);
+}
+
+#[test]
+fn test_mbc1_ram_banking_mode() {
+    let mut rom = build_1mb_mbc1_rom(false);
+    rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x149] = 0x03; // 4 RAM banks
+    let mut mmu = common::setup_mmu(&rom);
+    mmu.write_memory(0x0000, 0x0A);
+
+    // mode 0: the upper bits go to the ROM bank, RAM stays on bank 0
+    mmu.write_memory(0x4000, 0x01);
+    mmu.write_memory(0x2000, 0x03);
+    assert_eq!(mmu.read_memory(0x5000), 0x23);
+    mmu.write_memory(0xA000, 0x11);
+
+    // mode 1: they also select the RAM bank and the bank at 0x0000
+    mmu.write_memory(0x6000, 0x01);
+    assert_eq!(mmu.read_memory(0x5000), 0x23);
+    assert_eq!(mmu.read_memory(0x1000), 0x20);
+    assert_eq!(mmu.read_memory(0xA000), 0x00);
+    mmu.write_memory(0xA000, 0x22);
+
+    mmu.write_memory(0x4000, 0x00);
+    assert_eq!(mmu.read_memory(0xA000), 0x11);
+    mmu.write_memory(0x4000, 0x01);
+    assert_eq!(mmu.read_memory(0xA000), 0x22);
+    mmu.write_memory(0x6000, 0x00);
+    assert_eq!(mmu.read_memory(0xA000), 0x11);
+}
+
+#[test]
+fn test_mbc1_large_rom_small_ram() {
+    let mut rom = build_1mb_mbc1_rom(false);
+    rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x149] = 0x02; // one 8KB RAM bank
+    let mut mmu = common::setup_mmu(&rom);
+    mmu.write_memory(0x0000, 0x0A);
+    mmu.write_memory(0xA000, 0x33);
+
+    // the RAM bank bits are not wired, the last ROM bank is still reachable
+    mmu.write_memory(0x6000, 0x01);
+    mmu.write_memory(0x4000, 0x01);
+    mmu.write_memory(0x2000, 0x1F);
+    assert_eq!(mmu.read_memory(0x5000), 0x3F);
+    assert_eq!(mmu.read_memory(0xA000), 0x33);
+}
+
+#[test]
+fn test_mbc1_multicart() {
+    let mut mmu = common::setup_mmu(&build_1mb_mbc1_rom(true));
+    // the menu selects the second game and maps its first bank at 0x0000
+    mmu.write_memory(0x6000, 0x01);
+    mmu.write_memory(0x4000, 0x01);
+    assert_eq!(mmu.read_memory(0x1000), 0x10);
+    assert_eq!(mmu.read_memory(0x5000), 0x11);
+
+    mmu.write_memory(0x2000, 0x05);
+    assert_eq!(mmu.read_memory(0x5000), 0x15);
+    assert_eq!(mmu.current_rom_bank(), 0x15);
+
+    // bit 4 of the lower register is not wired
+    mmu.write_memory(0x2000, 0x10);
+    assert_eq!(mmu.read_memory(0x5000), 0x10);
+
+    mmu.write_memory(0x4000, 0x03);
+    mmu.write_memory(0x2000, 0x0F);
+    assert_eq!(mmu.read_memory(0x1000), 0x30);
+    assert_eq!(mmu.read_memory(0x5000), 0x3F);
+}
+
+fn build_mbc3_rom(cartridge_type: u8) -> Vec<u8> {
+    let mut rom = vec![0; 128 * 0x4000];
+    for bank in 0..128 {
+        rom[bank * 0x4000 + 0x1000] = bank as u8;
+    }
+    rom[0x147] = cartridge_type;
+    rom[0x148] = 0x06; // 128 banks
+    rom[0x149] = 0x03; // 4 RAM banks
+    rom
+}
+
+fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[test]
+fn test_mbc3_banking() {
+    let mut mmu = common::setup_mmu(&build_mbc3_rom(0x13));
+    mmu.write_memory(0x2000, 0x00);
+    assert_eq!(mmu.read_memory(0x5000), 0x01);
+    mmu.write_memory(0x2000, 0x45);
+    assert_eq!(mmu.read_memory(0x5000), 0x45);
+    assert_eq!(mmu.read_memory(0x1000), 0x00);
+
+    mmu.write_memory(0x0000, 0x0A);
+    for bank in 0..4 {
+        mmu.write_memory(0x4000, bank);
+        mmu.write_memory(0xA123, 0x10 + bank);
+    }
+    for bank in 0..4 {
+        mmu.write_memory(0x4000, bank);
+        assert_eq!(mmu.read_memory(0xA123), 0x10 + bank);
+    }
+    assert_eq!(mmu.current_ram_bank(), 3);
+    // no clock on this cartridge
+    assert!(mmu.rtc_state().is_none());
+}
+
+fn read_rtc(mmu: &mut memory::MMU, register: u8) -> u8 {
+    mmu.write_memory(0x4000, register);
+    mmu.read_memory(0xA000)
+}
+
+#[test]
+fn test_mbc3_rtc_latch() {
+    let mut mmu = common::setup_mmu(&build_mbc3_rom(0x10));
+    mmu.write_memory(0x0000, 0x0A);
+    // 1 day, 1 hour, 1 minute and 1 second ago, from day 0x1FF
+    let registers = RtcRegisters {
+        days_low: 0xFF,
+        days_high: 0x01,
+        ..Default::default()
+    };
+    mmu.set_rtc_state(RtcSave {
+        current: registers,
+        latched: registers,
+        timestamp: unix_time() - (24 * 3600 + 3600 + 60 + 1),
+    });
+    // latched registers do not move until the next latch
+    assert_eq!(read_rtc(&mut mmu, 0x0B), 0xFF);
+    assert_eq!(read_rtc(&mut mmu, 0x08), 0x00);
+
+    mmu.write_memory(0x6000, 0x00);
+    mmu.write_memory(0x6000, 0x01);
+    assert!((1..=3).contains(&read_rtc(&mut mmu, 0x08)));
+    assert_eq!(read_rtc(&mut mmu, 0x09), 1);
+    assert_eq!(read_rtc(&mut mmu, 0x0A), 1);
+    assert_eq!(read_rtc(&mut mmu, 0x0B), 0x00);
+    // the day counter overflowed
+    assert_eq!(read_rtc(&mut mmu, 0x0C), 0x80);
+}
+
+#[test]
+fn test_mbc3_rtc_halt_and_save() {
+    let mut mmu = common::setup_mmu(&build_mbc3_rom(0x10));
+    mmu.write_memory(0x0000, 0x0A);
+    mmu.write_memory(0x4000, 0x0C);
+    mmu.write_memory(0xA000, 0x40);
+    for (register, value) in [(0x08, 30), (0x09, 20), (0x0A, 10), (0x0B, 5)] {
+        mmu.write_memory(0x4000, register);
+        mmu.write_memory(0xA000, value);
+    }
+    mmu.write_memory(0x4000, 0x00);
+    mmu.write_memory(0xA000, 0x42);
+
+    let save = mmu.dump_cartridge_ram().unwrap();
+    assert_eq!(save.len(), 4 * 0x2000 + memory::rtc::RTC_SAVE_SIZE);
+
+    // halted, the clock stays put even for a save made long ago
+    let mut state = RtcSave::from_bytes(&save[4 * 0x2000..]).unwrap();
+    state.timestamp -= 3600;
+    let mut save = save[..4 * 0x2000].to_vec();
+    save.extend_from_slice(&state.to_bytes());
+
+    let mut mmu = common::setup_mmu(&build_mbc3_rom(0x10));
+    mmu.load_cartridge_ram(&save);
+    mmu.write_memory(0x0000, 0x0A);
+    assert_eq!(mmu.read_memory(0xA000), 0x42);
+    mmu.write_memory(0x6000, 0x00);
+    mmu.write_memory(0x6000, 0x01);
+    assert_eq!(read_rtc(&mut mmu, 0x08), 30);
+    assert_eq!(read_rtc(&mut mmu, 0x09), 20);
+    assert_eq!(read_rtc(&mut mmu, 0x0A), 10);
+    assert_eq!(read_rtc(&mut mmu, 0x0B), 5);
+    assert_eq!(read_rtc(&mut mmu, 0x0C), 0x40);
+}
+
+#[test]
+fn test_mbc5_9bit_rom_bank() {
+    // 4MB, 256 banks
+    let mut rom = vec![0; 256 * 0x4000];
+    for bank in 0..256 {
+        rom[bank * 0x4000 + 0x1000] = bank as u8;
+        rom[bank * 0x4000 + 0x1001] = (bank >> 8) as u8;
+    }
+    rom[0x147] = 0x1B; // MBC5+RAM+BATTERY
+    rom[0x148] = 0x07;
+    rom[0x149] = 0x04; // 16 RAM banks
+    let mut mmu = common::setup_mmu(&rom);
+    assert_eq!(mmu.read_memory(0x5000), 0x01);
+
+    mmu.write_memory(0x2000, 0xA5);
+    assert_eq!(mmu.read_memory(0x5000), 0xA5);
+    assert_eq!(mmu.current_rom_bank(), 0xA5);
+
+    // bank 0 is not turned into bank 1
+    mmu.write_memory(0x2000, 0x00);
+    assert_eq!(mmu.read_memory(0x5000), 0x00);
+    assert_eq!(mmu.current_rom_bank(), 0);
+
+    mmu.write_memory(0x0000, 0x0A);
+    for bank in 0..16 {
+        mmu.write_memory(0x4000, bank);
+        mmu.write_memory(0xB000, 0x80 | bank);
+    }
+    mmu.write_memory(0x4000, 0x09);
+    assert_eq!(mmu.read_memory(0xB000), 0x89);
+    assert_eq!(mmu.current_ram_bank(), 9);
+}
+
+#[test]
+fn test_mbc5_bank_0x100() {
+    // 8MB, 512 banks
+    let mut rom = vec![0; 512 * 0x4000];
+    for bank in 0..512 {
+        rom[bank * 0x4000 + 0x1000] = bank as u8;
+        rom[bank * 0x4000 + 0x1001] = (bank >> 8) as u8;
+    }
+    rom[0x147] = 0x19; // MBC5
+    rom[0x148] = 0x08;
+    let mut mmu = common::setup_mmu(&rom);
+
+    mmu.write_memory(0x2000, 0x00);
+    mmu.write_memory(0x3000, 0x01);
+    assert_eq!(mmu.current_rom_bank(), 0x100);
+    assert_eq!(mmu.read_memory(0x5000), 0x00);
+    assert_eq!(mmu.read_memory(0x5001), 0x01);
+
+    // the low byte keeps bit 8
+    mmu.write_memory(0x2000, 0x23);
+    assert_eq!(mmu.current_rom_bank(), 0x123);
+    assert_eq!(mmu.read_memory(0x5000), 0x23);
+    mmu.write_memory(0x3000, 0x00);
+    assert_eq!(mmu.current_rom_bank(), 0x23);
+    assert_eq!(mmu.read_memory(0x5001), 0x00);
+}
+
+fn build_mbc2_rom() -> Vec<u8> {
+    let mut rom = vec![0; 16 * 0x4000];
+    for bank in 0..16 {
+        rom[bank * 0x4000 + 0x1000] = bank as u8;
+    }
+    rom[0x147] = 0x06; // MBC2+BATTERY
+    rom[0x148] = 0x03; // 16 banks
+    rom
+}
+
+#[test]
+fn test_mbc2_address_bit_8() {
+    let mut mmu = common::setup_mmu(&build_mbc2_rom());
+    // bit 8 clear: RAM enable, the ROM bank does not change
+    mmu.write_memory(0x2000, 0x0A);
+    assert_eq!(mmu.read_memory(0x5000), 0x01);
+    mmu.write_memory(0xA000, 0x05);
+    assert_eq!(mmu.read_memory(0xA000), 0xF5);
+
+    // bit 8 set: ROM bank, even in the lower half
+    mmu.write_memory(0x0100, 0x07);
+    assert_eq!(mmu.read_memory(0x5000), 0x07);
+    mmu.write_memory(0x21FF, 0x00);
+    assert_eq!(mmu.read_memory(0x5000), 0x01);
+    assert_eq!(mmu.read_memory(0xA000), 0xF5);
+
+    mmu.write_memory(0x0000, 0x00);
+    assert_eq!(mmu.read_memory(0xA000), 0xFF);
+}
+
+#[test]
+fn test_mbc2_half_byte_ram() {
+    let mut mmu = common::setup_mmu(&build_mbc2_rom());
+    mmu.write_memory(0x0000, 0x0A);
+
+    // the upper nibble is not stored and reads as 1s
+    mmu.write_memory(0xA010, 0xFF);
+    assert_eq!(mmu.read_memory(0xA010), 0xFF);
+    mmu.write_memory(0xA010, 0x03);
+    assert_eq!(mmu.read_memory(0xA010), 0xF3);
+    mmu.write_memory(0xA011, 0xA0);
+    assert_eq!(mmu.read_memory(0xA011), 0xF0);
+
+    // 512 cells mirrored over the whole area
+    assert_eq!(mmu.read_memory(0xA210), 0xF3);
+    assert_eq!(mmu.read_memory(0xBE10), 0xF3);
+
+    let save = mmu.dump_cartridge_ram().unwrap();
+    assert_eq!(save.len(), 512);
+    assert_eq!(save[0x10], 0x03);
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "test-utils")]
+
+use std::sync::{Arc, Mutex};
+
+use gbemu::{
+    display::Display, interrupt::InterruptController, memory::mock::MockMemory, Memory, PPU,
+};
+
+#[test]
+fn test_mock_memory_hooks() {
+    let mut memory = MockMemory::new();
+    memory.on_read(0xFF00, || 0x0F);
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let log = written.clone();
+    memory.on_write(0xFF46, move |value| log.lock().unwrap().push(value));
+
+    assert_eq!(memory.read_memory(0xFF00), 0x0F);
+    memory.write_memory(0xFF46, 0xC0);
+    memory.write_memory(0xC000, 0x12);
+
+    assert_eq!(*written.lock().unwrap(), vec![0xC0]);
+    assert_eq!(memory.write_count(0xFF46), 1);
+    assert_eq!(memory.read_memory(0xC000), 0x12);
+    assert_eq!(memory.reads(), vec![0xFF00, 0xC000]);
+}
+
+#[test]
+fn test_fetcher_reads_scrolled_tile_map() {
+    let memory = MockMemory::new();
+    // background on, tile map at 0x9800
+    memory.set(0xFF40, 0x91);
+    memory.set(0xFF42, 8); // SCY
+    memory.set(0xFF43, 16); // SCX
+
+    let mut ppu = PPU::new(
+        memory.clone(),
+        Arc::new(Mutex::new(InterruptController::new())),
+        Arc::new(Mutex::new(Display::default())),
+    );
+    // first scan line
+    for _ in 0..114 {
+        ppu.step();
+    }
+
+    // tile row 1, tile column 2
+    let tile_map_reads: Vec<u16> = memory
+        .reads()
+        .into_iter()
+        .filter(|addr| (0x9800..0x9C00).contains(addr))
+        .collect();
+    assert_eq!(tile_map_reads.first(), Some(&0x9822));
+    assert!(tile_map_reads.contains(&0x9823));
+}
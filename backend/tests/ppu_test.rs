@@ -0,0 +1,346 @@
+use gbemu::{
+    ppu::{Layers, Mode, ObjectPriority, RendererKind, PIXEL_COUNT, POST_BOOT_SCAN_LINE},
+    Emulator, Memory, SCREEN_WIDTH,
+};
+
+mod common;
+
+#[test]
+fn test_stat_lines() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    // LYC interrupt on line 40
+    emu.memory.write_memory(0xFF45, 40);
+    emu.memory.write_memory(0xFF41, 0x40);
+
+    emu.skip_frames(2);
+
+    let lines = emu.ppu.stat_lines();
+    assert!(lines[40]);
+    assert_eq!(lines.iter().filter(|&&fired| fired).count(), 1);
+}
+
+// a light gray background with 11 black objects on the first scan line, 10
+// pixels apart
+fn setup_sprite_line() -> Emulator {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    draw_sprite_line(&mut emu);
+    emu
+}
+
+fn draw_sprite_line(emu: &mut Emulator) {
+    // OAM is only writable with the LCD off here
+    emu.memory.write_memory(0xFF40, 0x00);
+    for addr in (0x8000..0x8010).step_by(2) {
+        emu.memory.write_memory(addr, 0xFF);
+    }
+    for addr in 0x8010..0x8020 {
+        emu.memory.write_memory(addr, 0xFF);
+    }
+    for i in 0..11 {
+        let oam_addr = 0xFE00 + i * 4;
+        emu.memory.write_memory(oam_addr, 16);
+        emu.memory.write_memory(oam_addr + 1, 8 + i as u8 * 10);
+        emu.memory.write_memory(oam_addr + 2, 1);
+        emu.memory.write_memory(oam_addr + 3, 0);
+    }
+    emu.memory.write_memory(0xFF47, 0xE4);
+    emu.memory.write_memory(0xFF48, 0xE4);
+    emu.memory.write_memory(0xFF40, 0x93);
+}
+
+fn render_first_line(emu: &mut Emulator) -> Vec<[u8; 4]> {
+    emu.skip_frames(2);
+
+    let mut fb = vec![0; PIXEL_COUNT * 4];
+    emu.display.lock().unwrap().draw_into_fb(&mut fb);
+    fb.chunks_exact(4)
+        .take(SCREEN_WIDTH as usize)
+        .map(|pixel| pixel.try_into().unwrap())
+        .collect()
+}
+
+fn render_sprite_line(sprite_limit: bool) -> Vec<[u8; 4]> {
+    let mut emu = setup_sprite_line();
+    emu.ppu.set_sprite_limit(sprite_limit);
+    render_first_line(&mut emu)
+}
+
+fn render_sprite_line_without(layers: Layers) -> Vec<[u8; 4]> {
+    let mut emu = setup_sprite_line();
+    emu.ppu.set_hidden_layers(layers);
+    render_first_line(&mut emu)
+}
+
+#[test]
+fn test_sprite_limit() {
+    let line = render_sprite_line(true);
+    let (sprite, background) = (line[0], line[159]);
+    assert_ne!(sprite, background);
+    assert!((0..10).all(|i| line[i * 10] == sprite));
+    assert_eq!(line[100], background);
+}
+
+#[test]
+fn test_no_sprite_limit() {
+    let line = render_sprite_line(false);
+    assert!((0..11).all(|i| line[i * 10] == line[0]));
+}
+
+#[test]
+fn test_no_sprite_limit_after_load_rom() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    emu.ppu.set_sprite_limit(false);
+
+    // a --watch reload goes through load_rom
+    emu.load_rom(&common::build_rom(&[0x18, 0xFE])).unwrap();
+    draw_sprite_line(&mut emu);
+    let line = render_first_line(&mut emu);
+    assert!((0..11).all(|i| line[i * 10] == line[0]));
+}
+
+#[test]
+fn test_hide_sprites() {
+    let line = render_sprite_line(true);
+    let (sprite, background) = (line[0], line[159]);
+
+    let line = render_sprite_line_without(Layers::SPRITES);
+    assert!(line.iter().all(|&pixel| pixel == background));
+    assert_ne!(line[0], sprite);
+}
+
+#[test]
+fn test_hide_background() {
+    let line = render_sprite_line(true);
+    let (sprite, background) = (line[0], line[159]);
+
+    let line = render_sprite_line_without(Layers::BACKGROUND);
+    assert_eq!(line[0], sprite);
+    assert_ne!(line[159], background);
+}
+
+#[test]
+fn test_export_bg_map() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    // LCD off, map at 0x9C00, tile data from 0x8800
+    emu.memory.write_memory(0xFF40, 0x08);
+    emu.memory.write_memory(0xFF47, 0x1B);
+    for offset in 0..0x400 {
+        emu.memory.write_memory(0x9C00 + offset, offset as u8);
+    }
+    emu.memory.write_memory(0x9050, 0x3C);
+
+    let export = emu.export_bg_map();
+    assert_eq!(export.tile_map.len(), 32 * 32);
+    for (offset, &tile_id) in export.tile_map.iter().enumerate() {
+        let map_id = emu.memory.read_memory(0x9C00 + offset as u16);
+        let expected = if map_id < 128 {
+            map_id as u16 + 256
+        } else {
+            map_id as u16
+        };
+        assert_eq!(tile_id, expected);
+    }
+    assert_eq!(export.tile_map[5], 261);
+    assert_eq!(export.tiles[261][0], 0x3C);
+    assert_eq!(export.palette, 0x1B);
+
+    let json = export.to_tiled_json("tiles.png");
+    assert!(json.contains("\"data\":[257,258,259,"));
+    assert!(json.contains("\"image\":\"tiles.png\""));
+}
+
+#[test]
+#[cfg(feature = "cgb")]
+fn test_ppu_ignores_cpu_vram_bank() {
+    let mut rom = common::build_rom(&[0x18, 0xFE]);
+    rom[0x143] = 0x80;
+    let mut emu = Emulator::with_model(
+        &rom,
+        None,
+        Box::new(gbemu::serial::StdoutSerialWrite),
+        gbemu::Model::Cgb,
+    )
+    .unwrap();
+
+    emu.memory.write_memory(0xFF40, 0x00);
+    // tile 0 of bank 0 is color 3, bank 1 stays empty
+    for addr in 0x8000..0x8010 {
+        emu.memory.write_memory(addr, 0xFF);
+    }
+    emu.memory.write_memory(0xFF4F, 0x01);
+    assert_eq!(emu.memory.read_memory(0x8000), 0x00);
+    emu.memory.write_memory(0x8000, 0x42);
+    assert_eq!(emu.memory.read_memory(0x8000), 0x42);
+    assert_eq!(emu.memory.read_vram(0x8000, 0), 0xFF);
+
+    emu.memory.write_memory(0xFF47, 0xE4);
+    emu.memory.write_memory(0xFF40, 0x91);
+    let line = render_first_line(&mut emu);
+    assert!(line.iter().all(|&pixel| pixel == [0, 0, 0, 255]));
+}
+
+#[test]
+fn test_post_boot_ppu_state() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    assert_eq!(emu.ppu.scan_line(), POST_BOOT_SCAN_LINE);
+    assert_eq!(emu.ppu.mode(), Mode::OAMSearch);
+    assert_eq!(emu.memory.read_memory(0xFF44), POST_BOOT_SCAN_LINE);
+    assert_eq!(emu.memory.read_memory(0xFF41) & 0b11, Mode::OAMSearch as u8);
+
+    // one line is 114 M-cycles
+    for _ in 0..114 {
+        emu.step();
+    }
+    assert_eq!(emu.memory.read_memory(0xFF44), POST_BOOT_SCAN_LINE + 1);
+}
+
+fn displayed_pixel(emu: &Emulator) -> [u8; 4] {
+    let mut fb = vec![0; PIXEL_COUNT * 4];
+    emu.display.lock().unwrap().draw_into_fb(&mut fb);
+    fb[..4].try_into().unwrap()
+}
+
+// frame displayed after turning the LCD on with a black background
+fn first_frame_after_lcd_enable(enable_blanking: bool) -> [u8; 4] {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    emu.ppu.set_enable_blanking(enable_blanking);
+    emu.skip_frames(1);
+
+    emu.memory.write_memory(0xFF40, 0x11);
+    for addr in 0x8000..0x8010 {
+        emu.memory.write_memory(addr, 0xFF);
+    }
+    emu.step_cycles(10_000);
+    emu.memory.write_memory(0xFF40, 0x91);
+    emu.step_frame();
+    displayed_pixel(&emu)
+}
+
+#[test]
+fn test_lcd_enable_blanking_option() {
+    assert_eq!(first_frame_after_lcd_enable(true), [255, 255, 255, 255]);
+    assert_eq!(first_frame_after_lcd_enable(false), [0, 0, 0, 255]);
+}
+
+#[test]
+fn test_first_frame_after_lcd_enable_is_blank() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    emu.skip_frames(1);
+
+    // LCD off, then a black background: tile 0 in color 3
+    emu.memory.write_memory(0xFF40, 0x11);
+    for addr in 0x8000..0x8010 {
+        emu.memory.write_memory(addr, 0xFF);
+    }
+    emu.step_cycles(10_000);
+
+    emu.memory.write_memory(0xFF40, 0x91);
+    emu.step();
+    assert_eq!(emu.ppu.scan_line(), 0);
+    // the first line has no visible OAM search
+    assert_eq!(emu.memory.read_memory(0xFF41) & 0b11, Mode::HBlank as u8);
+    for _ in 0..114 {
+        emu.step();
+    }
+    assert_eq!(emu.memory.read_memory(0xFF41) & 0b11, Mode::OAMSearch as u8);
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    emu.step_frame();
+    assert_eq!(displayed_pixel(&emu), WHITE);
+    emu.step_frame();
+    assert_eq!(displayed_pixel(&emu), BLACK);
+}
+
+// object 0 in light gray at x 12-19 over object 1 in black at x 8-15
+fn render_overlapping_objects(priority: ObjectPriority) -> Vec<[u8; 4]> {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    emu.ppu.set_object_priority(priority);
+
+    emu.memory.write_memory(0xFF40, 0x00);
+    for addr in (0x8010..0x8020).step_by(2) {
+        emu.memory.write_memory(addr, 0xFF);
+    }
+    for addr in 0x8020..0x8030 {
+        emu.memory.write_memory(addr, 0xFF);
+    }
+    for (i, (x, tile)) in [(20, 1), (16, 2)].into_iter().enumerate() {
+        let oam_addr = 0xFE00 + i as u16 * 4;
+        emu.memory.write_memory(oam_addr, 16);
+        emu.memory.write_memory(oam_addr + 1, x);
+        emu.memory.write_memory(oam_addr + 2, tile);
+        emu.memory.write_memory(oam_addr + 3, 0);
+    }
+    emu.memory.write_memory(0xFF47, 0xE4);
+    emu.memory.write_memory(0xFF48, 0xE4);
+    emu.memory.write_memory(0xFF40, 0x93);
+    render_first_line(&mut emu)
+}
+
+#[test]
+fn test_object_priority() {
+    const LIGHT_GRAY: [u8; 4] = [170, 170, 170, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+    let line = render_overlapping_objects(ObjectPriority::Coordinate);
+    assert_eq!(line[10], BLACK);
+    assert_eq!(line[13], BLACK);
+    assert_eq!(line[18], LIGHT_GRAY);
+
+    let line = render_overlapping_objects(ObjectPriority::OamIndex);
+    assert_eq!(line[10], BLACK);
+    assert_eq!(line[13], LIGHT_GRAY);
+    assert_eq!(line[18], LIGHT_GRAY);
+}
+
+// scrolled background, window on the bottom right and overlapping objects,
+// including more than 10 on one line
+fn render_scene(renderer: RendererKind) -> Vec<u8> {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    emu.ppu.set_renderer(renderer);
+
+    emu.memory.write_memory(0xFF40, 0x00);
+    for tile in 0..4u16 {
+        for row in 0..8u16 {
+            let addr = 0x8000 + tile * 16 + row * 2;
+            emu.memory
+                .write_memory(addr, 0x0F << (row % 4) | tile as u8);
+            emu.memory.write_memory(addr + 1, 0xA5 >> tile);
+        }
+    }
+    for offset in 0..0x400u16 {
+        emu.memory.write_memory(0x9800 + offset, (offset % 3) as u8);
+        emu.memory
+            .write_memory(0x9C00 + offset, 3 - (offset % 4) as u8);
+    }
+    for i in 0..14u16 {
+        let oam_addr = 0xFE00 + i * 4;
+        emu.memory.write_memory(oam_addr, 30 + (i % 3) as u8 * 4);
+        emu.memory.write_memory(oam_addr + 1, 12 + i as u8 * 6);
+        emu.memory.write_memory(oam_addr + 2, (i % 4) as u8);
+        emu.memory
+            .write_memory(oam_addr + 3, ((i % 2) << 7 | (i % 3) << 4) as u8);
+    }
+    emu.memory.write_memory(0xFF42, 5);
+    emu.memory.write_memory(0xFF43, 3);
+    emu.memory.write_memory(0xFF4A, 80);
+    emu.memory.write_memory(0xFF4B, 47);
+    emu.memory.write_memory(0xFF47, 0xE4);
+    emu.memory.write_memory(0xFF48, 0xE4);
+    emu.memory.write_memory(0xFF49, 0x1B);
+    emu.memory.write_memory(0xFF40, 0xF3);
+
+    emu.skip_frames(2);
+    let mut fb = vec![0; PIXEL_COUNT * 4];
+    emu.display.lock().unwrap().draw_into_fb(&mut fb);
+    fb
+}
+
+#[test]
+fn test_line_renderer_matches_pixel_fifo() {
+    let fifo = render_scene(RendererKind::PixelFifo);
+    let line = render_scene(RendererKind::Line);
+    // the scene is not uniform
+    assert!(fifo.chunks_exact(4).any(|pixel| pixel != &fifo[..4]));
+    assert!(fifo == line, "the renderers disagree");
+}
@@ -0,0 +1,69 @@
+#![cfg(feature = "printer")]
+
+use gbemu::serial::{PrinterSerial, SerialWrite};
+
+fn packet(command: u8, compressed: bool, data: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x88, 0x33, command, compressed as u8];
+    packet.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    packet.extend_from_slice(data);
+    let checksum = packet[2..]
+        .iter()
+        .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    packet.extend_from_slice(&checksum.to_le_bytes());
+    packet.extend_from_slice(&[0x00, 0x00]);
+    packet
+}
+
+/// Sends a packet, returns the alive and status bytes of the answer.
+fn send(printer: &mut PrinterSerial, packet: &[u8]) -> (u8, u8) {
+    let answer: Vec<u8> = packet
+        .iter()
+        .map(|&byte| printer.exchange_byte(byte))
+        .collect();
+    (answer[answer.len() - 2], answer[answer.len() - 1])
+}
+
+#[test]
+fn test_printer_assembles_image() {
+    let out_dir = std::env::temp_dir().join("gbemu-printer-test");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let mut printer = PrinterSerial::new(&out_dir);
+
+    assert_eq!(send(&mut printer, &packet(0x01, false, &[])), (0x81, 0x00));
+
+    // 2 rows of black tiles
+    let status = send(&mut printer, &packet(0x04, false, &[0xFF; 640]));
+    assert_eq!(status, (0x81, 0x08));
+    // 2 rows of white tiles, run-length encoded
+    let mut compressed = Vec::new();
+    for _ in 0..5 {
+        compressed.extend_from_slice(&[0xFF, 0x00]);
+    }
+    send(&mut printer, &packet(0x04, true, &compressed));
+    send(&mut printer, &packet(0x04, false, &[]));
+
+    send(
+        &mut printer,
+        &packet(0x02, false, &[0x01, 0x13, 0xE4, 0x40]),
+    );
+    assert_eq!(send(&mut printer, &packet(0x0F, false, &[])), (0x81, 0x02));
+    assert_eq!(send(&mut printer, &packet(0x0F, false, &[])), (0x81, 0x00));
+
+    let image = printer.last_image().unwrap();
+    assert_eq!((image.width, image.height), (160, 32));
+    assert_eq!(image.pixels[0], 3);
+    assert_eq!(image.pixels[160 * 16], 0);
+
+    let png = image::open(out_dir.join("print-000.png")).unwrap();
+    assert_eq!((png.width(), png.height()), (160, 32));
+}
+
+#[test]
+fn test_printer_checksum_error() {
+    let mut printer = PrinterSerial::new(std::env::temp_dir());
+    let mut bad = packet(0x04, false, &[0xFF; 640]);
+    let len = bad.len();
+    bad[len - 4] ^= 0xFF;
+
+    assert_eq!(send(&mut printer, &bad), (0x81, 0x01));
+}
@@ -0,0 +1,51 @@
+use gbemu::memory::rtc::{join_save, split_save, RtcRegisters, RtcSave, RTC_SAVE_SIZE};
+
+fn sample_rtc() -> RtcSave {
+    RtcSave {
+        current: RtcRegisters {
+            seconds: 12,
+            minutes: 34,
+            hours: 5,
+            days_low: 0x80,
+            days_high: 0x41,
+        },
+        latched: RtcRegisters {
+            seconds: 10,
+            minutes: 34,
+            hours: 5,
+            days_low: 0x80,
+            days_high: 0x01,
+        },
+        timestamp: 1_700_000_000,
+    }
+}
+
+#[test]
+fn test_rtc_save_round_trip() {
+    let ram: Vec<u8> = (0..0x8000).map(|i| i as u8).collect();
+    let data = join_save(&ram, &sample_rtc());
+    assert_eq!(data.len(), ram.len() + RTC_SAVE_SIZE);
+    // registers are stored as little-endian u32
+    assert_eq!(&data[0x8000..0x8008], &[12, 0, 0, 0, 34, 0, 0, 0]);
+
+    let (loaded_ram, rtc) = split_save(&data, ram.len());
+    assert_eq!(loaded_ram, &ram[..]);
+    assert_eq!(rtc, Some(sample_rtc()));
+}
+
+#[test]
+fn test_rtc_save_32bit_timestamp() {
+    let mut data = vec![0; 0x2000];
+    data.extend_from_slice(&sample_rtc().to_bytes()[..44]);
+
+    let (_, rtc) = split_save(&data, 0x2000);
+    assert_eq!(rtc.unwrap().timestamp, 1_700_000_000);
+}
+
+#[test]
+fn test_raw_ram_save() {
+    let data = vec![0x42; 0x2000];
+    let (ram, rtc) = split_save(&data, 0x2000);
+    assert_eq!(ram.len(), 0x2000);
+    assert_eq!(rtc, None);
+}
@@ -0,0 +1,69 @@
+#![cfg(feature = "scripting")]
+
+use gbemu::{scripting::Script, Memory};
+
+mod common;
+
+#[test]
+fn test_script_poke() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+
+    let script = Script::new(
+        "write(0xC000, 0x42); write(0xC001, read(0xC000) + 1);",
+        emu.memory.clone(),
+    )
+    .unwrap();
+    emu.set_script(Some(script));
+
+    assert_eq!(emu.memory.read_memory(0xC000), 0x42);
+    assert_eq!(emu.memory.read_memory(0xC001), 0x43);
+}
+
+#[test]
+fn test_script_hooks() {
+    // LD A, $12; JR -2
+    let mut emu = common::setup_program(&[0x3E, 0x12, 0x18, 0xFE]);
+
+    let script = Script::new(
+        "
+        on_frame(|| write(0xC000, read(0xC000) + 1));
+        on_pc(0x102, || write(0xC001, reg_a()));
+        ",
+        emu.memory.clone(),
+    )
+    .unwrap();
+    emu.set_script(Some(script));
+
+    emu.step_frame();
+    emu.step_frame();
+    assert_eq!(emu.memory.read_memory(0xC000), 2);
+    assert_eq!(emu.memory.read_memory(0xC001), 0x12);
+}
+
+#[test]
+fn test_script_pc_hooks_while_halted() {
+    // LD DE, $1234; HALT; NOP
+    let mut emu = common::setup_program(&[0x11, 0x34, 0x12, 0x76, 0x00]);
+
+    let script = Script::new(
+        "
+        on_pc(0x103, || {
+            write(0xC000, read(0xC000) + 1);
+            write(0xC001, reg_de() >> 8);
+            write(0xC002, reg_af() & 0xFF);
+        });
+        on_pc(0x104, || write(0xC003, read(0xC003) + 1));
+        ",
+        emu.memory.clone(),
+    )
+    .unwrap();
+    emu.set_script(Some(script));
+
+    // no interrupt is enabled, the CPU stays halted with pc on the NOP
+    emu.step_frame();
+    assert_eq!(emu.memory.read_memory(0xC000), 1);
+    assert_eq!(emu.memory.read_memory(0xC001), 0x12);
+    assert_eq!(emu.memory.read_memory(0xC002), 0xB0);
+    assert_eq!(emu.cpu.pc, 0x104);
+    assert_eq!(emu.memory.read_memory(0xC003), 0);
+}
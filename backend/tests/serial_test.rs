@@ -0,0 +1,155 @@
+use std::sync::{Arc, Mutex};
+
+use gbemu::{
+    interrupt::{IntKind, InterruptController},
+    memory::{self, MMU, SERIAL_TRANSFER_CYCLES},
+    serial::{LinkPort, SerialPtr, SerialWrite, TeeSerialWrite},
+    Emulator, Memory,
+};
+
+mod common;
+
+struct SerialCapture {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerialWrite for SerialCapture {
+    fn write_byte(&mut self, byte: u8) {
+        self.bytes.lock().unwrap().push(byte);
+    }
+}
+
+#[test]
+fn test_tee_serial_write() {
+    let first = Arc::new(Mutex::new(Vec::new()));
+    let second = Arc::new(Mutex::new(Vec::new()));
+    let tee = TeeSerialWrite::new(vec![
+        Box::new(SerialCapture {
+            bytes: first.clone(),
+        }),
+        Box::new(SerialCapture {
+            bytes: second.clone(),
+        }),
+    ]);
+
+    let interrupt_controller = Arc::new(Mutex::new(InterruptController::new()));
+    let mbc = memory::build_mbc(&common::build_rom(&[])).unwrap();
+    let mut mmu = MMU::new(mbc, interrupt_controller, Box::new(tee));
+
+    mmu.write_memory(0xFF01, 0x42);
+    mmu.write_memory(0xFF02, 0x81);
+
+    assert_eq!(*first.lock().unwrap(), vec![0x42]);
+    assert_eq!(*second.lock().unwrap(), vec![0x42]);
+}
+
+#[test]
+fn test_serial_transfer_timing() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    mmu.write_memory(0xFF01, 0x42);
+    mmu.write_memory(0xFF02, 0x81);
+
+    for _ in 0..(SERIAL_TRANSFER_CYCLES - 1) {
+        mmu.tick();
+    }
+    assert_eq!(mmu.read_memory(0xFF02) & 0x80, 0x80);
+    assert_eq!(mmu.read_memory(0xFF0F) & IntKind::SERIAL.bits(), 0);
+
+    mmu.tick();
+    assert_eq!(mmu.read_memory(0xFF02) & 0x80, 0x00);
+    // nothing is connected
+    assert_eq!(mmu.read_memory(0xFF01), 0xFF);
+    assert_ne!(mmu.read_memory(0xFF0F) & IntKind::SERIAL.bits(), 0);
+}
+
+fn setup_linked(port: SerialPtr) -> Emulator {
+    // JR -2
+    let rom = common::build_rom(&[0x18, 0xFE]);
+    let emu = Emulator::new(&rom, None, port).unwrap();
+    emu.memory.write().unwrap().set_serial_transfer_cycles(1);
+    emu
+}
+
+fn trade(master: &mut Emulator, slave: &mut Emulator) {
+    let trade = [(0x01, 0xA0), (0x02, 0xB0), (0x03, 0xC0)];
+    for (master_byte, slave_byte) in trade {
+        slave.memory.write_memory(0xFF01, slave_byte);
+        slave.memory.write_memory(0xFF02, 0x80);
+        master.memory.write_memory(0xFF01, master_byte);
+        master.memory.write_memory(0xFF02, 0x81);
+
+        for _ in 0..4 {
+            master.step();
+            slave.step();
+        }
+
+        assert_eq!(master.memory.read_memory(0xFF02) & 0x80, 0x00);
+        assert_eq!(slave.memory.read_memory(0xFF02) & 0x80, 0x00);
+        assert_eq!(master.memory.read_memory(0xFF01), slave_byte);
+        assert_eq!(slave.memory.read_memory(0xFF01), master_byte);
+    }
+}
+
+#[test]
+fn test_link_cable_trade() {
+    let (master_port, slave_port) = LinkPort::pair();
+    let mut master = setup_linked(Box::new(master_port));
+    let mut slave = setup_linked(Box::new(slave_port));
+    trade(&mut master, &mut slave);
+}
+
+#[test]
+fn test_link_cable_trade_through_tee() {
+    let (master_port, slave_port) = LinkPort::pair();
+    let master_log = Arc::new(Mutex::new(Vec::new()));
+    let slave_log = Arc::new(Mutex::new(Vec::new()));
+    let tee = |port: LinkPort, log: &Arc<Mutex<Vec<u8>>>| -> SerialPtr {
+        Box::new(TeeSerialWrite::new(vec![
+            Box::new(SerialCapture { bytes: log.clone() }),
+            Box::new(port),
+        ]))
+    };
+    let mut master = setup_linked(tee(master_port, &master_log));
+    let mut slave = setup_linked(tee(slave_port, &slave_log));
+    trade(&mut master, &mut slave);
+
+    // only the side clocking the transfers writes its bytes out
+    assert_eq!(*master_log.lock().unwrap(), vec![0x01, 0x02, 0x03]);
+    assert!(slave_log.lock().unwrap().is_empty());
+}
+
+fn serial_transfer_duration(mmu: &mut MMU, control: u8) -> u32 {
+    mmu.write_memory(0xFF01, 0x42);
+    mmu.write_memory(0xFF02, control);
+    let mut cycles = 0;
+    while mmu.read_memory(0xFF02) & 0x80 != 0 {
+        mmu.tick();
+        cycles += 1;
+    }
+    cycles
+}
+
+#[cfg(feature = "cgb")]
+#[test]
+fn test_serial_fast_clock() {
+    let mbc = memory::build_mbc(&common::build_rom(&[])).unwrap();
+    let mut mmu = memory::MMUBuilder::new(mbc).cgb_mode(true).build();
+
+    assert_eq!(
+        serial_transfer_duration(&mut mmu, 0x81),
+        SERIAL_TRANSFER_CYCLES
+    );
+    assert_eq!(
+        serial_transfer_duration(&mut mmu, 0x83),
+        SERIAL_TRANSFER_CYCLES / memory::SERIAL_FAST_CLOCK_FACTOR
+    );
+}
+
+#[test]
+fn test_serial_fast_clock_ignored_on_dmg() {
+    let mut mmu = common::setup_mmu(&common::build_rom(&[]));
+    assert_eq!(
+        serial_transfer_duration(&mut mmu, 0x83),
+        SERIAL_TRANSFER_CYCLES
+    );
+}
@@ -0,0 +1,201 @@
+use gbemu::{
+    cpu::debugger::Debugger, memory::MemoryRegion, ppu::Mode, serial::StdoutSerialWrite,
+    state::StateError, Emulator,
+};
+
+mod common;
+
+const ROM_PATH: &str = "./test_roms/blargg/cpu_instrs.gb";
+
+fn setup() -> Emulator {
+    let rom = std::fs::read(ROM_PATH).unwrap();
+    Emulator::new(&rom, None, Box::new(StdoutSerialWrite)).unwrap()
+}
+
+// registers, memory and the frame in progress
+fn machine_content(emu: &Emulator) -> (String, Vec<Vec<u8>>, Vec<u8>, usize) {
+    let memory = emu.memory.read().unwrap();
+    let regions = [
+        MemoryRegion::Vram,
+        MemoryRegion::Wram,
+        MemoryRegion::Oam,
+        MemoryRegion::Hram,
+    ]
+    .into_iter()
+    .map(|region| memory.dump_region(region))
+    .collect();
+    (
+        emu.cpu.dump_registers(),
+        regions,
+        emu.ppu.frame.to_vec(),
+        memory.current_rom_bank(),
+    )
+}
+
+#[test]
+fn test_save_state_round_trip() {
+    let mut emu = setup();
+    emu.skip_frames(30);
+    let state = emu.save_state().unwrap();
+    assert!(emu.cpu.is_pipeline_empty());
+    let saved = machine_content(&emu);
+
+    emu.skip_frames(10);
+    let expected = machine_content(&emu);
+
+    let mut restored = setup();
+    restored.load_state(&state).unwrap();
+    assert_eq!(machine_content(&restored), saved);
+    assert_eq!(restored.ppu.scan_line(), state.ppu.scan_line);
+
+    // the restored machine runs the same
+    restored.skip_frames(10);
+    assert_eq!(machine_content(&restored), expected);
+    assert_eq!(restored.total_frames(), emu.total_frames());
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn test_save_state_bytes() {
+    let mut emu = setup();
+    emu.skip_frames(30);
+    let bytes = emu.save_state().unwrap().save();
+    let saved = machine_content(&emu);
+
+    let state = gbemu::state::EmuState::load(&bytes).unwrap();
+    let mut restored = setup();
+    restored.load_state(&state).unwrap();
+    assert_eq!(machine_content(&restored), saved);
+
+    assert!(gbemu::state::EmuState::load(&bytes[..bytes.len() / 2]).is_err());
+}
+
+// stops at the breakpoint on JR -2 once `stop_at` holds
+fn run_to_breakpoint(stop_at: impl Fn(&Emulator) -> bool) -> Emulator {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    let mut debugger = Debugger::new();
+    debugger.break_on_opcode(0x18);
+    emu.cpu.set_debugger(Some(debugger));
+    loop {
+        emu.step();
+        if emu.cpu.is_at_breakpoint() {
+            if stop_at(&emu) {
+                return emu;
+            }
+            emu.cpu.debugger_mut().unwrap().resume();
+        }
+    }
+}
+
+#[test]
+fn test_save_state_at_breakpoint() {
+    let mut emu = run_to_breakpoint(|emu| emu.ppu.mode() != Mode::LCDTransfer);
+    let state = emu.save_state().unwrap();
+    assert_eq!(state.cpu.registers.pc, 0x100);
+    assert!(emu.cpu.is_at_breakpoint());
+}
+
+#[test]
+fn test_save_state_at_breakpoint_during_transfer() {
+    let mut emu = run_to_breakpoint(|emu| emu.ppu.mode() == Mode::LCDTransfer);
+    assert_eq!(
+        emu.save_state().map(|_| ()),
+        Err(StateError::Breakpoint { pc: 0x100 })
+    );
+    assert!(emu.cpu.is_at_breakpoint());
+}
+
+#[test]
+fn test_load_state_rejects_bad_position() {
+    let mut emu = setup();
+    emu.skip_frames(30);
+    let mut state = emu.save_state().unwrap();
+    state.ppu.scan_line = 200;
+
+    let mut restored = setup();
+    let before = machine_content(&restored);
+    assert_eq!(
+        restored.load_state(&state),
+        Err(StateError::PpuPosition {
+            scan_line: 200,
+            dot_in_line: state.ppu.dot_in_line,
+        })
+    );
+    assert_eq!(machine_content(&restored), before);
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn test_load_state_rejects_truncated_region() {
+    let mut emu = setup();
+    emu.skip_frames(30);
+    let bytes = emu.save_state().unwrap().save();
+    let vram_size = emu
+        .memory
+        .read()
+        .unwrap()
+        .dump_region(MemoryRegion::Vram)
+        .len();
+
+    // bincode prefixes the VRAM with its length, keep only half of it
+    let vram_len = (vram_size as u64).to_le_bytes();
+    let start = bytes
+        .windows(vram_len.len())
+        .position(|window| window == vram_len)
+        .unwrap();
+    let mut corrupted = bytes[..start].to_vec();
+    corrupted.extend_from_slice(&(vram_size as u64 / 2).to_le_bytes());
+    corrupted.extend_from_slice(&bytes[start + vram_len.len() + vram_size / 2..]);
+    let state = gbemu::state::EmuState::load(&corrupted).unwrap();
+
+    let mut restored = setup();
+    let before = machine_content(&restored);
+    assert_eq!(
+        restored.load_state(&state),
+        Err(StateError::RegionSize {
+            region: "VRAM",
+            expected: vram_size,
+            actual: vram_size / 2,
+        })
+    );
+    assert_eq!(machine_content(&restored), before);
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn test_diff_state() {
+    use gbemu::{state::Difference, Memory};
+
+    let mut emu = setup();
+    emu.skip_frames(30);
+    for (offset, byte) in b"DIFFTEST".iter().enumerate() {
+        emu.memory.write_memory(0xC800 + offset as u16, *byte);
+    }
+    let bytes = emu.save_state().unwrap().save();
+    assert!(Emulator::diff_state(&bytes, &bytes).unwrap().is_empty());
+
+    let start = bytes
+        .windows(8)
+        .position(|window| window == b"DIFFTEST")
+        .unwrap();
+    let mut modified = bytes.clone();
+    modified[start] ^= 0xFF;
+
+    let diff = Emulator::diff_state(&bytes, &modified).unwrap();
+    assert_eq!(
+        diff.differences,
+        [Difference::Region {
+            name: "WRAM",
+            byte_count: 1,
+        }]
+    );
+    assert_eq!(diff.to_string(), "WRAM differs at 1 bytes");
+
+    let mut later = setup();
+    later
+        .load_state(&gbemu::state::EmuState::load(&bytes).unwrap())
+        .unwrap();
+    later.step_instruction();
+    let diff = Emulator::diff_state(&bytes, &later.save_state().unwrap().save()).unwrap();
+    assert!(diff.to_string().contains("PC differs"));
+}
@@ -0,0 +1,35 @@
+use gbemu::{serial::StdoutSerialWrite, trace, Emulator, Model};
+
+const CPU_INSTRS_ROM: &str = "test_roms/blargg/cpu_instrs.gb";
+const CPU_INSTRS_TRACE: &str = include_str!("../test_roms/traces/cpu_instrs.trace");
+
+// the traces are recorded on a DMG, see the cpu-trace example
+fn setup() -> Emulator {
+    let rom = std::fs::read(CPU_INSTRS_ROM).unwrap();
+    Emulator::with_model(&rom, None, Box::new(StdoutSerialWrite), Model::Dmg).unwrap()
+}
+
+#[test]
+fn test_replay_cpu_instrs_trace() {
+    let mut emu = setup();
+    if let Err(divergence) = trace::verify(&mut emu, CPU_INSTRS_TRACE) {
+        panic!("{}", divergence);
+    }
+}
+
+#[test]
+fn test_trace_divergence() {
+    let mut lines: Vec<&str> = CPU_INSTRS_TRACE.lines().take(10).collect();
+    let changed = lines[5].replacen("A:", "A:F", 1).replacen("SP:", "SP:1", 1);
+    lines[5] = &changed;
+
+    let mut emu = setup();
+    let divergence = trace::verify(&mut emu, &lines.join("\n")).unwrap_err();
+    assert_eq!(divergence.index, 5);
+    let fields: Vec<&str> = divergence
+        .differing_fields()
+        .into_iter()
+        .map(|(name, _, _)| name)
+        .collect();
+    assert_eq!(fields, ["A", "SP"]);
+}
@@ -0,0 +1,93 @@
+use std::io::Cursor;
+
+use gbemu::{
+    wav::{WavRecorder, APU_SAMPLE_RATE},
+    Memory,
+};
+
+mod common;
+
+const HIGH: i16 = 960;
+
+// 64 APU samples high then 64 low
+fn square_wave(periods: usize) -> Vec<(i16, i16)> {
+    (0..periods * 128)
+        .map(|index| {
+            let value = if index % 128 < 64 { HIGH } else { -HIGH };
+            (value, -value)
+        })
+        .collect()
+}
+
+fn record(samples: &[(i16, i16)], sample_rate: u32) -> (hound::WavSpec, Vec<i16>) {
+    let mut out = Cursor::new(Vec::new());
+    let mut recorder = WavRecorder::new(&mut out, sample_rate).unwrap();
+    for &sample in samples {
+        recorder.push(sample).unwrap();
+    }
+    recorder.finalize().unwrap();
+
+    out.set_position(0);
+    let mut reader = hound::WavReader::new(out).unwrap();
+    let samples = reader.samples::<i16>().map(Result::unwrap).collect();
+    (reader.spec(), samples)
+}
+
+#[test]
+fn test_wav_native_rate() {
+    let wave = square_wave(4);
+    let (spec, samples) = record(&wave, APU_SAMPLE_RATE);
+    assert_eq!(spec.channels, 2);
+    assert_eq!(spec.bits_per_sample, 16);
+    assert_eq!(spec.sample_rate, APU_SAMPLE_RATE);
+
+    let expected: Vec<i16> = wave
+        .iter()
+        .flat_map(|&(left, right)| [left, right])
+        .collect();
+    assert_eq!(samples, expected);
+}
+
+#[test]
+fn test_wav_resampled() {
+    // one written sample per 64 APU samples, one per half period
+    let (spec, samples) = record(&square_wave(4), APU_SAMPLE_RATE / 64);
+    assert_eq!(spec.sample_rate, APU_SAMPLE_RATE / 64);
+    assert_eq!(samples, [HIGH, -HIGH, -HIGH, HIGH].repeat(4));
+
+    // samples straddling both halves are averaged
+    let (_, samples) = record(&square_wave(4), APU_SAMPLE_RATE / 128);
+    assert_eq!(samples, [0; 8]);
+}
+
+#[test]
+fn test_audio_capture() {
+    let mut emu = common::setup_program(&[0x18, 0xFE]);
+    emu.step_frame();
+    assert!(emu.take_audio_samples().is_empty());
+
+    emu.set_audio_capture(true);
+    // channel 2 at full volume, 50% duty, on the left output
+    emu.memory.write_memory(0xFF26, 0x80);
+    emu.memory.write_memory(0xFF24, 0x77);
+    emu.memory.write_memory(0xFF25, 0x20);
+    emu.memory.write_memory(0xFF16, 0x80);
+    emu.memory.write_memory(0xFF17, 0xF0);
+    emu.memory.write_memory(0xFF19, 0x87);
+    emu.step_cycles(4 * 1000);
+
+    let samples = emu.take_audio_samples();
+    assert_eq!(samples.len(), 1000);
+    assert!(samples.iter().all(|&(_, right)| right == 0));
+    assert!(samples.iter().any(|&(left, _)| left > 0));
+    assert!(samples.iter().any(|&(left, _)| left < 0));
+    assert!(emu.take_audio_samples().is_empty());
+}
+
+#[test]
+fn test_wav_lowest_rate() {
+    // a whole second of full scale samples averaged into one
+    let samples = vec![(i16::MAX, i16::MIN); APU_SAMPLE_RATE as usize];
+    let (_, samples) = record(&samples, 1);
+    assert_eq!(samples, [i16::MAX, i16::MIN]);
+}
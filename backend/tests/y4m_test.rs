@@ -0,0 +1,41 @@
+use gbemu::{y4m::Y4mWriter, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const WIDTH: usize = SCREEN_WIDTH as usize;
+const HEIGHT: usize = SCREEN_HEIGHT as usize;
+
+fn rgba_frame(color: [u8; 4]) -> Vec<u8> {
+    color.repeat(WIDTH * HEIGHT)
+}
+
+#[test]
+fn test_y4m_stream() {
+    let mut out = Vec::new();
+    {
+        let mut writer = Y4mWriter::new(&mut out, WIDTH, HEIGHT).unwrap();
+        writer
+            .write_frame(&rgba_frame([255, 255, 255, 255]))
+            .unwrap();
+        writer.write_frame(&rgba_frame([0, 0, 0, 255])).unwrap();
+    }
+
+    let header_end = out.iter().position(|&byte| byte == b'\n').unwrap();
+    let header = std::str::from_utf8(&out[..header_end]).unwrap();
+    let params: Vec<&str> = header.split(' ').collect();
+    assert_eq!(params[0], "YUV4MPEG2");
+    assert!(params.contains(&"W160"));
+    assert!(params.contains(&"H144"));
+    assert!(params.contains(&"C444"));
+
+    let frame_size = b"FRAME\n".len() + WIDTH * HEIGHT * 3;
+    let frames = &out[(header_end + 1)..];
+    assert_eq!(frames.len(), frame_size * 2);
+
+    for (frame, luma) in frames.chunks_exact(frame_size).zip([235, 16]) {
+        assert!(frame.starts_with(b"FRAME\n"));
+        let planes = &frame[6..];
+        let (y, chroma) = planes.split_at(WIDTH * HEIGHT);
+        assert!(y.iter().all(|&value| value == luma));
+        // gray shades have no chroma
+        assert!(chroma.iter().all(|&value| value == 128));
+    }
+}
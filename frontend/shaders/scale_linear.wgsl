@@ -0,0 +1,29 @@
+// Same as the pixels scaling shader, but the transform is always the
+// identity since the windows keep the aspect ratio of the framebuffer.
+
+struct VertexOutput {
+    @location(0) tex_coord: vec2<f32>,
+    @builtin(position) position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One full-screen triangle: (-1, -1), (3, -1), (-1, 3)
+    let position = vec2<f32>(
+        f32((index << 1u) & 2u) * 2.0 - 1.0,
+        f32(index & 2u) * 2.0 - 1.0,
+    );
+
+    var out: VertexOutput;
+    out.tex_coord = fma(position, vec2<f32>(0.5, -0.5), vec2<f32>(0.5, 0.5));
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    return out;
+}
+
+@group(0) @binding(0) var r_tex_color: texture_2d<f32>;
+@group(0) @binding(1) var r_tex_sampler: sampler;
+
+@fragment
+fn fs_main(@location(0) tex_coord: vec2<f32>) -> @location(0) vec4<f32> {
+    return textureSample(r_tex_color, r_tex_sampler, tex_coord);
+}
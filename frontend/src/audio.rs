@@ -0,0 +1,197 @@
+//! Plays the APU output through cpal. The emulator thread fills a ring buffer
+//! that the stream callback drains, and the fill level of the buffer slightly
+//! changes the emulation speed to keep the sound in sync with the video.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BufferSize, FromSample, SampleFormat, SizedSample, StreamConfig, SupportedBufferSize,
+};
+use gbemu::wav::Downsampler;
+use log::error;
+
+/// Largest change of the emulation speed, when the buffer is empty or twice
+/// its target level
+const MAX_SPEED_ADJUSTMENT: f64 = 0.005;
+/// Samples beyond this many times the target level are dropped, e.g. after
+/// running the bootstrap ROM at uncapped speed
+const MAX_FILL_FACTOR: usize = 4;
+
+type SampleBuffer = Arc<Mutex<VecDeque<(i16, i16)>>>;
+
+/// Keeps the stream playing until dropped, the stream cannot leave the
+/// thread that opened it.
+pub struct AudioOutput {
+    _stream: cpal::Stream,
+}
+
+/// Opens the default output device with a buffer of `latency`. Returns the
+/// stream and the sink given to the emulator thread.
+pub fn open(latency: Duration) -> Result<(AudioOutput, AudioSink), String> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or("no output device")?;
+    let supported = device
+        .default_output_config()
+        .map_err(|err| err.to_string())?;
+
+    let sample_rate = supported.sample_rate().0;
+    let frames = latency_frames(sample_rate, latency);
+    let buffer_size = match *supported.buffer_size() {
+        SupportedBufferSize::Range { min, max } => BufferSize::Fixed(frames.clamp(min, max)),
+        SupportedBufferSize::Unknown => BufferSize::Default,
+    };
+    let config = StreamConfig {
+        channels: supported.channels(),
+        sample_rate: supported.sample_rate(),
+        buffer_size,
+    };
+
+    let sink = AudioSink::new(sample_rate, frames as usize);
+    let buffer = sink.buffer.clone();
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(&device, &config, buffer),
+        SampleFormat::I16 => build_stream::<i16>(&device, &config, buffer),
+        SampleFormat::U16 => build_stream::<u16>(&device, &config, buffer),
+        format => return Err(format!("unsupported sample format {}", format)),
+    }?;
+    stream.play().map_err(|err| err.to_string())?;
+
+    Ok((AudioOutput { _stream: stream }, sink))
+}
+
+fn build_stream<T: SizedSample + FromSample<i16>>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    buffer: SampleBuffer,
+) -> Result<cpal::Stream, String> {
+    let channels = config.channels as usize;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                let mut buffer = buffer.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    // silence on underrun, e.g. while paused
+                    let (left, right) = buffer.pop_front().unwrap_or_default();
+                    write_frame(frame, left, right);
+                }
+            },
+            |err| error!("Audio stream error: {}", err),
+            None,
+        )
+        .map_err(|err| err.to_string())
+}
+
+fn write_frame<T: SizedSample + FromSample<i16>>(frame: &mut [T], left: i16, right: i16) {
+    match frame {
+        [mono] => *mono = T::from_sample(((left as i32 + right as i32) / 2) as i16),
+        [l, r, rest @ ..] => {
+            *l = T::from_sample(left);
+            *r = T::from_sample(right);
+            rest.fill(T::EQUILIBRIUM);
+        }
+        [] => {}
+    }
+}
+
+fn latency_frames(sample_rate: u32, latency: Duration) -> u32 {
+    (sample_rate as u128 * latency.as_micros() / 1_000_000).max(1) as u32
+}
+
+/// Emulator thread side of the output: lowers the APU samples to the rate of
+/// the device and queues them.
+pub struct AudioSink {
+    buffer: SampleBuffer,
+    downsampler: Downsampler,
+    /// Number of queued samples aimed for, `--audio-latency` worth of them
+    target: usize,
+}
+
+impl AudioSink {
+    fn new(sample_rate: u32, target: usize) -> Self {
+        AudioSink {
+            buffer: Arc::default(),
+            downsampler: Downsampler::new(sample_rate),
+            target: target.max(1),
+        }
+    }
+
+    /// `samples` are given at `wav::APU_SAMPLE_RATE`.
+    pub fn push(&mut self, samples: &[(i16, i16)]) {
+        let mut buffer = self.buffer.lock().unwrap();
+        for &sample in samples {
+            if let Some(sample) = self.downsampler.push(sample) {
+                buffer.push_back(sample);
+            }
+        }
+        let overflow = buffer.len().saturating_sub(self.target * MAX_FILL_FACTOR);
+        buffer.drain(..overflow);
+    }
+
+    /// Factor of the emulation speed, above 1 while the buffer is below its
+    /// target level and below 1 while it is above.
+    pub fn speed(&self) -> f64 {
+        let fill = self.buffer.lock().unwrap().len() as f64 / self.target as f64;
+        1.0 + MAX_SPEED_ADJUSTMENT * (1.0 - fill).clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gbemu::wav::APU_SAMPLE_RATE;
+
+    use super::*;
+
+    #[test]
+    fn test_latency_frames() {
+        assert_eq!(latency_frames(48000, Duration::from_millis(50)), 2400);
+        assert_eq!(latency_frames(44100, Duration::from_millis(20)), 882);
+        assert_eq!(latency_frames(48000, Duration::ZERO), 1);
+    }
+
+    #[test]
+    fn test_speed_follows_fill_level() {
+        // one queued sample per 1024 APU samples
+        let mut sink = AudioSink::new(APU_SAMPLE_RATE / 1024, 4);
+        assert_eq!(sink.speed(), 1.0 + MAX_SPEED_ADJUSTMENT);
+
+        sink.push(&[(0, 0); 2 * 1024]);
+        assert_eq!(sink.speed(), 1.0 + MAX_SPEED_ADJUSTMENT / 2.0);
+        sink.push(&[(0, 0); 2 * 1024]);
+        assert_eq!(sink.speed(), 1.0);
+        sink.push(&[(0, 0); 4 * 1024]);
+        assert_eq!(sink.speed(), 1.0 - MAX_SPEED_ADJUSTMENT);
+    }
+
+    #[test]
+    fn test_push_drops_overflow() {
+        let mut sink = AudioSink::new(APU_SAMPLE_RATE, 4);
+        let samples: Vec<_> = (0..20).map(|index| (index, -index)).collect();
+        sink.push(&samples);
+
+        let buffer = sink.buffer.lock().unwrap();
+        assert_eq!(buffer.len(), 4 * MAX_FILL_FACTOR);
+        assert_eq!(buffer.front(), Some(&(4, -4)));
+    }
+
+    #[test]
+    fn test_write_frame() {
+        let mut stereo = [0i16; 2];
+        write_frame(&mut stereo, 100, -100);
+        assert_eq!(stereo, [100, -100]);
+
+        let mut mono = [0i16; 1];
+        write_frame(&mut mono, 100, 50);
+        assert_eq!(mono, [75]);
+
+        let mut surround = [1.0f32; 4];
+        write_frame(&mut surround, i16::MIN, 0);
+        assert_eq!(surround, [-1.0, 0.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    fmt::Write as _,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use gbemu::{ppu::Mode, Emulator};
+
+pub type DebugStatePtr = Arc<Mutex<Option<DebugState>>>;
+
+#[derive(Debug, Clone)]
+pub struct DebugState {
+    registers: String,
+    recent_instructions: Vec<String>,
+    ppu_mode: Mode,
+    scan_line: u8,
+}
+
+impl DebugState {
+    pub fn capture(emulator: &Emulator) -> Self {
+        DebugState {
+            registers: emulator.cpu.dump_registers(),
+            recent_instructions: emulator
+                .cpu
+                .recent_instructions()
+                .map(|(pc, instruction)| {
+                    format!("{:#06x}: {}", pc, instruction.disassemble_with_pc(*pc))
+                })
+                .collect(),
+            ppu_mode: emulator.ppu.mode(),
+            scan_line: emulator.ppu.scan_line(),
+        }
+    }
+}
+
+pub fn install_panic_hook(path: PathBuf, rom_title: String, state: DebugStatePtr) {
+    let default_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        let mut report = String::new();
+        let _ = writeln!(report, "Gameboy Emulator crash report");
+        let _ = writeln!(report, "ROM: {}", rom_title);
+        let _ = writeln!(report, "Panic: {}", info);
+
+        // the emulation thread may have panicked while holding the lock
+        match state.try_lock().as_deref() {
+            Ok(Some(state)) => {
+                let _ = writeln!(report, "CPU: {}", state.registers);
+                let _ = writeln!(
+                    report,
+                    "PPU: mode={:?} LY={}",
+                    state.ppu_mode, state.scan_line
+                );
+                let _ = writeln!(report, "Last instructions:");
+                for instruction in &state.recent_instructions {
+                    let _ = writeln!(report, "    {}", instruction);
+                }
+            }
+            _ => {
+                let _ = writeln!(report, "No emulator state available");
+            }
+        }
+
+        match std::fs::write(&path, report) {
+            Ok(()) => eprintln!("Crash report written to {}", path.display()),
+            Err(err) => eprintln!("Failed to write crash report: {}", err),
+        }
+    }));
+}
@@ -1,33 +1,378 @@
 use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        Arc, Mutex,
     },
-    time::Instant,
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
-use gbemu::{memory::Memory, CPU, PPU};
+use gbemu::{
+    boot_logo,
+    ppu::{Layers, RendererKind, PIXEL_COUNT},
+    wav::WavRecorder,
+    y4m::Y4mWriter,
+    Emulator,
+};
+use log::{error, warn};
+
+#[cfg(feature = "audio")]
+use crate::audio::AudioSink;
+use crate::{
+    crash_report::{DebugState, DebugStatePtr},
+    turbo::Turbo,
+};
 
 const FREQUENCY: u64 = 1 << 20;
 const NANOS_IN_SECOND: u64 = 1_000_000_000;
 const NANOS_IN_CYCLE: u64 = NANOS_IN_SECOND / FREQUENCY;
 
-pub fn run<M: Memory + Clone>(mut cpu: CPU<M>, mut ppu: PPU<M>, is_ended: Arc<AtomicBool>) {
+const CYCLES_PER_FRAME: u64 = 70224 / 4;
+
+const PAUSED_SLEEP: Duration = Duration::from_millis(1);
+/// Time given to the emulator thread to finish its frame and save once
+/// `is_ended` is set
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+const FRAME_DURATION: Duration = Duration::from_nanos(CYCLES_PER_FRAME * NANOS_IN_CYCLE);
+
+#[derive(Debug, Default)]
+pub struct EmuControl {
+    pub is_ended: AtomicBool,
+    pub paused: AtomicBool,
+    /// Paused while the windows are not focused, apart from `paused` so the
+    /// manual pause is kept on refocus
+    pub unfocused: AtomicBool,
+    /// Number of frames left to run while paused
+    pub frame_steps: AtomicUsize,
+    pub turbo: Option<Turbo>,
+    /// Runs one instruction per period instead of the real speed
+    pub instruction_period: Option<Duration>,
+    /// Runs the bootstrap ROM at uncapped speed
+    pub fast_boot: bool,
+    /// Header logo scrolled on screen before starting the game
+    pub boot_logo: Option<Vec<u8>>,
+    /// `Layers` bits hidden by the PPU
+    pub hidden_layers: AtomicU8,
+    /// Uses `RendererKind::Line` instead of the pixel FIFO
+    pub line_renderer: AtomicBool,
+    /// ROM to load in place of the running one, see `request_reload`
+    pub reload_pending: AtomicBool,
+    pub reload: Mutex<Option<Vec<u8>>>,
+}
+
+impl EmuControl {
+    pub fn toggle_pause(&self) {
+        self.paused.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    pub fn request_frame_step(&self) {
+        self.frame_steps.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn toggle_layer(&self, layer: Layers) {
+        self.hidden_layers
+            .fetch_xor(layer.bits(), Ordering::Relaxed);
+    }
+
+    pub fn toggle_renderer(&self) {
+        self.line_renderer.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    /// Replaces the running ROM with `rom` on the next iteration of the
+    /// emulator thread.
+    pub fn request_reload(&self, rom: Vec<u8>) {
+        *self.reload.lock().unwrap() = Some(rom);
+        self.reload_pending.store(true, Ordering::Release);
+    }
+
+    fn take_reload(&self) -> Option<Vec<u8>> {
+        if self.reload_pending.swap(false, Ordering::Acquire) {
+            self.reload.lock().unwrap().take()
+        } else {
+            None
+        }
+    }
+}
+
+/// Writes each new frame of the emulator to a video stream.
+pub struct VideoCapture {
+    writer: Y4mWriter<Box<dyn Write + Send>>,
+    last_frame: u64,
+    fb: Vec<u8>,
+}
+
+impl VideoCapture {
+    pub fn new(writer: Y4mWriter<Box<dyn Write + Send>>) -> Self {
+        VideoCapture {
+            writer,
+            last_frame: 0,
+            fb: vec![0; PIXEL_COUNT * 4],
+        }
+    }
+
+    fn capture(&mut self, emulator: &Emulator) -> io::Result<()> {
+        let frame = emulator.total_frames();
+        if frame == self.last_frame {
+            return Ok(());
+        }
+        self.last_frame = frame;
+        emulator.display.lock().unwrap().draw_into_fb(&mut self.fb);
+        self.writer.write_frame(&self.fb)
+    }
+}
+
+/// Sends the APU output of the emulator to a WAV file and to the audio
+/// device.
+#[derive(Default)]
+pub struct AudioCapture {
+    recorder: Option<WavRecorder<BufWriter<File>>>,
+    #[cfg(feature = "audio")]
+    sink: Option<AudioSink>,
+}
+
+impl AudioCapture {
+    pub fn record(&mut self, recorder: WavRecorder<BufWriter<File>>) {
+        self.recorder = Some(recorder);
+    }
+
+    #[cfg(feature = "audio")]
+    pub fn play(&mut self, sink: AudioSink) {
+        self.sink = Some(sink);
+    }
+
+    fn is_active(&self) -> bool {
+        #[cfg(feature = "audio")]
+        if self.sink.is_some() {
+            return true;
+        }
+        self.recorder.is_some()
+    }
+
+    fn capture(&mut self, emulator: &mut Emulator) {
+        let samples = emulator.take_audio_samples();
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(err) = samples.iter().try_for_each(|&sample| recorder.push(sample)) {
+                error!("Cannot write the audio, stopping the recording: {}", err);
+                self.recorder = None;
+                emulator.set_audio_capture(self.is_active());
+            }
+        }
+        #[cfg(feature = "audio")]
+        if let Some(sink) = self.sink.as_mut() {
+            sink.push(&samples);
+        }
+    }
+
+    /// Factor of the emulation speed keeping the audio buffer filled.
+    fn speed(&self) -> f64 {
+        #[cfg(feature = "audio")]
+        if let Some(sink) = &self.sink {
+            return sink.speed();
+        }
+        1.0
+    }
+
+    fn finish(mut self, emulator: &mut Emulator) -> io::Result<()> {
+        self.capture(emulator);
+        match self.recorder {
+            Some(recorder) => recorder.finalize(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Waits for the emulator thread to stop after `is_ended` was set. Returns
+/// `None` if it panicked or did not stop in time, its save may then be lost.
+pub fn join(handle: JoinHandle<Emulator>) -> Option<Emulator> {
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while !handle.is_finished() {
+        if Instant::now() >= deadline {
+            warn!("The emulator thread did not stop, the cartridge RAM may not be saved");
+            return None;
+        }
+        std::thread::sleep(PAUSED_SLEEP);
+    }
+    handle.join().ok()
+}
+
+/// Time between two instructions to run `ips` instructions per second.
+pub fn instruction_period(ips: u64) -> Duration {
+    Duration::from_nanos(NANOS_IN_SECOND / ips.max(1))
+}
+
+fn play_boot_animation(emulator: &Emulator, logo: &[u8], control: &EmuControl) {
+    for frame in 0..boot_logo::ANIMATION_FRAMES {
+        if control.is_ended.load(Ordering::Relaxed) {
+            return;
+        }
+        let pixels = boot_logo::animation_frame(logo, frame);
+        emulator
+            .display
+            .lock()
+            .unwrap()
+            .load_frame_from_indices(&pixels);
+        emulator.interrupt_controller.lock().unwrap().should_redraw = true;
+        std::thread::sleep(FRAME_DURATION);
+    }
+}
+
+pub fn run(
+    mut emulator: Emulator,
+    control: Arc<EmuControl>,
+    debug_state: Option<DebugStatePtr>,
+    mut video_capture: Option<VideoCapture>,
+    mut audio_capture: AudioCapture,
+) -> Emulator {
+    emulator.set_audio_capture(audio_capture.is_active());
+    if let Some(logo) = &control.boot_logo {
+        play_boot_animation(&emulator, logo, &control);
+    }
+
     let mut last_instant = Instant::now();
     let mut nano_counter: u64 = 0;
+    let mut cycle_counter: u64 = 0;
+
+    let mut last_frame = emulator.total_frames();
+
+    let capture_debug_state = |emulator: &Emulator| {
+        if let Some(debug_state) = &debug_state {
+            *debug_state.lock().unwrap() = Some(DebugState::capture(emulator));
+        }
+    };
+    let mut update_turbo = |emulator: &Emulator| {
+        if let Some(turbo) = &control.turbo {
+            let frame = emulator.total_frames();
+            if frame != last_frame {
+                last_frame = frame;
+                turbo.update(&mut emulator.interrupt_controller.lock().unwrap(), frame);
+            }
+        }
+    };
+
+    while !control.is_ended.load(Ordering::Relaxed) {
+        if let Some(video) = video_capture.as_mut() {
+            if let Err(err) = video.capture(&emulator) {
+                error!(
+                    "Cannot write the video frame, stopping the capture: {}",
+                    err
+                );
+                video_capture = None;
+            }
+        }
+        audio_capture.capture(&mut emulator);
+        if let Some(rom) = control.take_reload() {
+            if let Err(err) = emulator.load_rom(&rom) {
+                error!("Cannot reload the ROM: {}", err);
+            }
+        }
+        emulator.ppu.set_hidden_layers(Layers::from_bits_truncate(
+            control.hidden_layers.load(Ordering::Relaxed),
+        ));
+        emulator
+            .ppu
+            .set_renderer(if control.line_renderer.load(Ordering::Relaxed) {
+                RendererKind::Line
+            } else {
+                RendererKind::PixelFifo
+            });
+
+        if control.paused.load(Ordering::Relaxed) || control.unfocused.load(Ordering::Relaxed) {
+            let stepped = control
+                .frame_steps
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |steps| {
+                    steps.checked_sub(1)
+                })
+                .is_ok();
+            if stepped {
+                emulator.step_frame();
+                update_turbo(&emulator);
+                capture_debug_state(&emulator);
+            } else {
+                std::thread::sleep(PAUSED_SLEEP);
+            }
+
+            // don't try to catch up on the time spent paused
+            last_instant = Instant::now();
+            nano_counter = 0;
+            continue;
+        }
+
+        if control.fast_boot && emulator.is_bootstrap_rom_mounted() {
+            for _ in 0..CYCLES_PER_FRAME {
+                emulator.step();
+                update_turbo(&emulator);
+                if !emulator.is_bootstrap_rom_mounted() {
+                    break;
+                }
+            }
+            capture_debug_state(&emulator);
+
+            // resume at the real speed from the end of the bootstrap ROM
+            last_instant = Instant::now();
+            nano_counter = 0;
+            continue;
+        }
+
+        if let Some(period) = control.instruction_period {
+            loop {
+                emulator.step();
+                update_turbo(&emulator);
+                if emulator.cpu.is_pipeline_empty() {
+                    break;
+                }
+            }
+            capture_debug_state(&emulator);
+            std::thread::sleep(period);
+            continue;
+        }
 
-    while !is_ended.load(Ordering::Relaxed) {
         let now = Instant::now();
         let elapsed = now - last_instant;
         assert_eq!(elapsed.as_secs(), 0);
-        nano_counter += elapsed.subsec_nanos() as u64;
+        nano_counter += (elapsed.subsec_nanos() as f64 * audio_capture.speed()) as u64;
         last_instant = now;
 
         while nano_counter >= NANOS_IN_CYCLE {
-            cpu.step();
-            ppu.step();
+            emulator.step();
+            update_turbo(&emulator);
+
+            cycle_counter += 1;
+            if cycle_counter.is_multiple_of(CYCLES_PER_FRAME) {
+                capture_debug_state(&emulator);
+            }
 
             nano_counter -= NANOS_IN_CYCLE;
         }
     }
+
+    if let Err(err) = audio_capture.finish(&mut emulator) {
+        error!("Cannot write the audio: {}", err);
+    }
+    if let Err(err) = emulator.flush_ram() {
+        error!("Cannot save the cartridge RAM: {}", err);
+    }
+    emulator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_period() {
+        assert_eq!(instruction_period(1), Duration::from_secs(1));
+        assert_eq!(instruction_period(10), Duration::from_millis(100));
+        assert_eq!(instruction_period(3), Duration::from_nanos(333_333_333));
+        assert_eq!(instruction_period(1_000_000), Duration::from_micros(1));
+    }
+
+    #[test]
+    fn test_instruction_period_edges() {
+        // 0 is taken as 1 instruction per second
+        assert_eq!(instruction_period(0), Duration::from_secs(1));
+        assert_eq!(instruction_period(NANOS_IN_SECOND), Duration::from_nanos(1));
+        assert_eq!(instruction_period(u64::MAX), Duration::ZERO);
+    }
 }
@@ -0,0 +1,114 @@
+use std::{fmt, io, path::PathBuf, process::ExitCode};
+
+use gbemu::memory::CartridgeError;
+use winit::error::{EventLoopError, OsError};
+
+#[derive(Debug)]
+pub enum FrontendError {
+    RomNotFound(PathBuf),
+    FileRead {
+        path: PathBuf,
+        err: io::Error,
+    },
+    FileWrite {
+        path: PathBuf,
+        err: io::Error,
+    },
+    Cartridge(CartridgeError),
+    #[cfg(feature = "scripting")]
+    Script(String),
+    WindowCreation(String),
+    SurfaceCreation(pixels::Error),
+}
+
+impl FrontendError {
+    pub fn read(path: impl Into<PathBuf>, err: io::Error) -> Self {
+        FrontendError::FileRead {
+            path: path.into(),
+            err,
+        }
+    }
+
+    pub fn rom_read(path: impl Into<PathBuf>, err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::NotFound {
+            FrontendError::RomNotFound(path.into())
+        } else {
+            FrontendError::read(path, err)
+        }
+    }
+
+    pub fn write(path: impl Into<PathBuf>, err: io::Error) -> Self {
+        FrontendError::FileWrite {
+            path: path.into(),
+            err,
+        }
+    }
+
+    /// One exit code per category, for scripts running the emulator.
+    pub fn exit_code(&self) -> ExitCode {
+        let code = match self {
+            FrontendError::RomNotFound(_) | FrontendError::FileRead { .. } => 2,
+            FrontendError::FileWrite { .. } => 3,
+            FrontendError::Cartridge(_) => 4,
+            #[cfg(feature = "scripting")]
+            FrontendError::Script(_) => 5,
+            FrontendError::WindowCreation(_) | FrontendError::SurfaceCreation(_) => 6,
+        };
+        ExitCode::from(code)
+    }
+}
+
+impl fmt::Display for FrontendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrontendError::RomNotFound(path) => {
+                write!(f, "ROM file '{}' not found", path.display())
+            }
+            FrontendError::FileRead { path, err } => {
+                write!(f, "cannot read '{}': {}", path.display(), err)
+            }
+            FrontendError::FileWrite { path, err } => {
+                write!(f, "cannot write '{}': {}", path.display(), err)
+            }
+            FrontendError::Cartridge(err) => write!(f, "invalid cartridge: {}", err),
+            #[cfg(feature = "scripting")]
+            FrontendError::Script(err) => write!(f, "script error: {}", err),
+            FrontendError::WindowCreation(err) => write!(f, "cannot create a window: {}", err),
+            FrontendError::SurfaceCreation(err) => {
+                write!(
+                    f,
+                    "cannot create the rendering surface: {}\n\
+                     Check the graphics drivers, or select another backend with \
+                     WGPU_BACKEND=gl or WGPU_BACKEND=vulkan.",
+                    err
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for FrontendError {}
+
+impl From<CartridgeError> for FrontendError {
+    fn from(err: CartridgeError) -> Self {
+        FrontendError::Cartridge(err)
+    }
+}
+
+impl From<pixels::Error> for FrontendError {
+    fn from(err: pixels::Error) -> Self {
+        FrontendError::SurfaceCreation(err)
+    }
+}
+
+impl From<EventLoopError> for FrontendError {
+    fn from(err: EventLoopError) -> Self {
+        FrontendError::WindowCreation(err.to_string())
+    }
+}
+
+impl From<OsError> for FrontendError {
+    fn from(err: OsError) -> Self {
+        FrontendError::WindowCreation(err.to_string())
+    }
+}
@@ -1,27 +1,46 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc, Mutex, RwLock,
+use std::{
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{atomic::Ordering, Arc, Mutex},
 };
 
 use clap::{Arg, ArgAction, Command};
-use pixels::{Pixels, SurfaceTexture};
+use log::warn;
+use pixels::{wgpu, Pixels, PixelsBuilder, SurfaceTexture};
 use winit::{
     dpi::LogicalSize,
     event::ElementState,
     event_loop::{ControlFlow, EventLoop},
     keyboard::{KeyCode, PhysicalKey},
-    window::{Window, WindowBuilder},
+    window::{Fullscreen, Window, WindowBuilder},
 };
 
+#[cfg(feature = "audio")]
+mod audio;
+mod crash_report;
 mod emu_thread;
+mod error;
+mod linear_renderer;
+mod sharp_renderer;
+mod turbo;
+mod watcher;
+
+use emu_thread::{AudioCapture, EmuControl, VideoCapture};
+use error::FrontendError;
+use linear_renderer::LinearRenderer;
+use sharp_renderer::SharpRenderer;
+use turbo::Turbo;
 
 use gbemu::{
-    cpu::CPU,
-    display::Display,
-    interrupt::{InterruptController, Keys},
-    memory,
-    serial::StdoutSerialWrite,
-    PPU, SCREEN_HEIGHT, SCREEN_WIDTH,
+    display::{Display, ScreenEffect, STAT_LINES_HEIGHT, STAT_LINES_WIDTH},
+    interrupt::Keys,
+    memory::{CartridgeHeader, MemoryRegion},
+    ppu::Layers,
+    serial::{FileSerialWrite, SerialPtr, StderrSerialWrite, StdoutSerialWrite, TeeSerialWrite},
+    wav::WavRecorder,
+    y4m::Y4mWriter,
+    Emulator, Model, SCREEN_HEIGHT, SCREEN_WIDTH,
 };
 
 const MULTIPLIER: u32 = 4;
@@ -31,10 +50,22 @@ const WINDOW_HEIGHT: u32 = (SCREEN_HEIGHT as u32) * MULTIPLIER;
 const TILE_WINDOW_WIDTH: u32 = 20 * 8;
 const TILE_WINDOW_HEIGHT: u32 = 20 * 8;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+const AUDIO_SAMPLE_RATE: u32 = 48000;
+
+fn main() -> ExitCode {
     env_logger::init();
 
-    let matches = Command::new("Gameboy Emulator")
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            err.exit_code()
+        }
+    }
+}
+
+fn run() -> Result<(), FrontendError> {
+    let command = Command::new("Gameboy Emulator")
         .version("0.1")
         .author("Paul Cacheux <paulcacheux@gmail.com>")
         .arg(
@@ -42,7 +73,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .short('t')
                 .long("tiles")
                 .action(ArgAction::SetTrue)
-                .help("Display the tiles data in a separate window"),
+                .help("Display the tiles data in a separate window (Tab switches the VRAM bank)"),
+        )
+        .arg(
+            Arg::new("STAT_LINES")
+                .long("stat-lines")
+                .action(ArgAction::SetTrue)
+                .help("Display the lines that requested a STAT interrupt in a separate window"),
+        )
+        .arg(
+            Arg::new("INFO")
+                .long("info")
+                .action(ArgAction::SetTrue)
+                .help("Prints the cartridge header information and exits."),
         )
         .arg(
             Arg::new("BOOTSTRAP_ROM")
@@ -52,56 +95,432 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .action(ArgAction::Set)
                 .help("Sets the path to a bootstrap rom used to init the Gameboy emulator state."),
         )
+        .arg(
+            Arg::new("FAST_BOOT_ROM")
+                .long("fast-boot-rom")
+                .action(ArgAction::SetTrue)
+                .help("Runs the bootstrap rom at uncapped speed, then the game at normal speed."),
+        )
+        .arg(
+            Arg::new("FAKE_BOOT_LOGO")
+                .long("fake-boot-logo")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("BOOTSTRAP_ROM")
+                .help("Scrolls the cartridge logo down before starting the game, like the bootstrap rom."),
+        )
+        .arg(
+            Arg::new("EFFECT")
+                .long("effect")
+                .value_name("EFFECT")
+                .value_parser(["none", "lcd-grid", "ghosting"])
+                .action(ArgAction::Set)
+                .help("Sets the effect applied to the screen."),
+        )
+        .arg(
+            Arg::new("SCALE_FILTER")
+                .long("scale-filter")
+                .value_name("FILTER")
+                .value_parser(["nearest", "linear"])
+                .default_value("nearest")
+                .action(ArgAction::Set)
+                .help("Sets the filter used to scale the screen to the window."),
+        )
+        .arg(
+            Arg::new("SHARP_FULLSCREEN")
+                .long("sharp-fullscreen")
+                .action(ArgAction::SetTrue)
+                .help("Runs fullscreen, scaling the screen by an integer factor then smoothly to fill the display."),
+        )
+        .arg(
+            Arg::new("FRAME_BLEND")
+                .long("frame-blend")
+                .value_name("FRAMES")
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+                .help("Averages the last FRAMES frames to mimic the LCD response time."),
+        )
+        .arg(
+            Arg::new("IPS")
+                .long("ips")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .action(ArgAction::Set)
+                .help("Runs N instructions per second instead of the real speed, to watch the execution."),
+        )
+        .arg(
+            Arg::new("START_PAUSED")
+                .long("start-paused")
+                .action(ArgAction::SetTrue)
+                .help("Starts the emulator paused (Space toggles pause, N advances one frame)."),
+        )
+        .arg(
+            Arg::new("PAUSE_ON_UNFOCUS")
+                .long("pause-on-unfocus")
+                .value_name("BOOL")
+                .value_parser(clap::value_parser!(bool))
+                .default_value("true")
+                .action(ArgAction::Set)
+                .help("Pauses the emulator while its windows are not focused."),
+        )
+        .arg(
+            Arg::new("WATCH")
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .help("Reloads the ROM when the file changes on disk."),
+        )
+        .arg(
+            Arg::new("TURBO_BUTTONS")
+                .long("turbo-buttons")
+                .value_name("BUTTONS")
+                .value_parser(["a", "b"])
+                .value_delimiter(',')
+                .action(ArgAction::Set)
+                .help("Enables auto-fire on these buttons while they are held, e.g. a,b."),
+        )
+        .arg(
+            Arg::new("TURBO_RATE")
+                .long("turbo-rate")
+                .value_name("FRAMES")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("2")
+                .action(ArgAction::Set)
+                .help("Sets how many frames auto-fire buttons stay pressed then released."),
+        )
+        .arg(
+            Arg::new("MODEL")
+                .long("model")
+                .value_name("MODEL")
+                .value_parser(["dmg", "mgb", "sgb", "cgb", "agb"])
+                .action(ArgAction::Set)
+                .help(
+                    "Sets the Game Boy model to emulate. Without it, CGB cartridges run on a CGB \
+                     and the others on a DMG.",
+                ),
+        )
+        .arg(
+            Arg::new("NO_BG")
+                .long("no-bg")
+                .action(ArgAction::SetTrue)
+                .help("Hides the background layer (toggled with F1)."),
+        )
+        .arg(
+            Arg::new("NO_WINDOW")
+                .long("no-window")
+                .action(ArgAction::SetTrue)
+                .help("Hides the window layer (toggled with F2)."),
+        )
+        .arg(
+            Arg::new("NO_SPRITES")
+                .long("no-sprites")
+                .action(ArgAction::SetTrue)
+                .help("Hides the sprite layer (toggled with F3)."),
+        )
+        .arg(
+            Arg::new("NO_SPRITE_LIMIT")
+                .long("no-sprite-limit")
+                .action(ArgAction::SetTrue)
+                .help("Draws every sprite of a line instead of the first 10, removing flickering."),
+        )
+        .arg(
+            Arg::new("NO_ENABLE_BLANKING")
+                .long("no-enable-blanking")
+                .action(ArgAction::SetTrue)
+                .help("Displays the first frame after the LCD is turned on instead of a blank one."),
+        )
+        .arg(
+            Arg::new("LINE_RENDERER")
+                .long("line-renderer")
+                .action(ArgAction::SetTrue)
+                .help("Draws whole scan lines at once instead of pixel by pixel, faster but ignores mid-line changes (toggled with F5)."),
+        )
+        .arg(
+            Arg::new("OAM_BUG")
+                .long("oam-bug")
+                .action(ArgAction::SetTrue)
+                .help("Emulates the DMG OAM corruption bug."),
+        )
+        .arg(
+            Arg::new("SERIAL_OUT")
+                .long("serial-out")
+                .value_name("LOG_PATH")
+                .action(ArgAction::Set)
+                .help("Also writes the bytes sent over the serial port to this file."),
+        )
+        .arg(
+            Arg::new("Y4M_OUT")
+                .long("y4m-out")
+                .value_name("VIDEO_PATH")
+                .action(ArgAction::Set)
+                .help(
+                    "Writes every frame to this file, or stdout with -, as a YUV4MPEG2 stream for \
+                     ffmpeg. With -, the serial output goes to stderr instead of stdout.",
+                ),
+        )
+        .arg(
+            Arg::new("RECORD_AUDIO")
+                .long("record-audio")
+                .value_name("WAV_PATH")
+                .action(ArgAction::Set)
+                .help("Records the sound output to this WAV file."),
+        )
+        .arg(
+            Arg::new("COVERAGE")
+                .long("coverage")
+                .action(ArgAction::SetTrue)
+                .help("Prints the opcodes that were never executed on exit."),
+        )
+        .arg(
+            Arg::new("DUMP_MEMORY")
+                .long("dump-memory")
+                .value_name("DIR")
+                .action(ArgAction::Set)
+                .help("Writes VRAM, WRAM, OAM and HRAM to binary files in this directory on exit."),
+        )
+        .arg(
+            Arg::new("CRASH_REPORT")
+                .long("crash-report")
+                .value_name("REPORT_PATH")
+                .action(ArgAction::Set)
+                .help("Writes a report with the CPU and PPU state to this path if the emulator crashes."),
+        )
         .arg(
             Arg::new("ROM_PATH")
                 .required(true)
                 .index(1)
                 .action(ArgAction::Set)
                 .help("Sets the path to the ROM to play on the Gameboy emulator."),
-        )
-        .get_matches();
+        );
+    #[cfg(feature = "scripting")]
+    let command = command.arg(
+        Arg::new("SCRIPT")
+            .long("script")
+            .value_name("SCRIPT_PATH")
+            .action(ArgAction::Set)
+            .help("Runs a rhai script with hooks on each frame and on PC addresses."),
+    );
+    #[cfg(feature = "printer")]
+    let command = command.arg(
+        Arg::new("PRINTER")
+            .long("printer")
+            .value_name("DIR")
+            .action(ArgAction::Set)
+            .help("Plugs a Game Boy Printer in the serial port, printed pages are saved to this directory."),
+    );
+    #[cfg(feature = "audio")]
+    let command = command.arg(
+        Arg::new("AUDIO_LATENCY")
+            .long("audio-latency")
+            .value_name("MS")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .default_value("50")
+            .action(ArgAction::Set)
+            .help("Sets the audio buffer length, raise it if the sound crackles."),
+    );
+    let matches = command.get_matches();
+
+    let effect = match matches.get_one::<String>("EFFECT").map(String::as_str) {
+        Some("lcd-grid") => ScreenEffect::LcdGrid,
+        Some("ghosting") => ScreenEffect::Ghosting,
+        _ => ScreenEffect::None,
+    };
+
+    let model = matches
+        .get_one::<String>("MODEL")
+        .map(|model| match model.as_str() {
+            "mgb" => Model::Mgb,
+            "sgb" => Model::Sgb,
+            "cgb" => Model::Cgb,
+            "agb" => Model::Agb,
+            _ => Model::Dmg,
+        });
+
+    let linear_filter = matches
+        .get_one::<String>("SCALE_FILTER")
+        .is_some_and(|filter| filter == "linear");
+    let sharp_fullscreen = matches.get_flag("SHARP_FULLSCREEN");
 
     let bootstrap = if let Some(mut bootstrap_path) = matches.get_raw("BOOTSTRAP_ROM") {
         let path = bootstrap_path.next().unwrap();
-        Some(std::fs::read(path)?)
+        Some(std::fs::read(path).map_err(|err| FrontendError::read(path, err))?)
     } else {
         None
     };
 
     let rom_path = matches.get_raw("ROM_PATH").unwrap().next().unwrap();
-    let rom = std::fs::read(rom_path)?;
+    let rom = std::fs::read(rom_path).map_err(|err| FrontendError::rom_read(rom_path, err))?;
+    let header = CartridgeHeader::read(&rom);
 
-    let interrupt_controller = Arc::new(Mutex::new(InterruptController::new()));
+    if matches.get_flag("INFO") {
+        print_cartridge_info(&rom, header.as_ref());
+        return Ok(());
+    }
 
-    let mbc = memory::build_mbc(&rom);
-    let mut mmu = memory::MMU::new(
-        mbc,
-        interrupt_controller.clone(),
-        Box::new(StdoutSerialWrite),
-    );
-    if let Some(bootstrap) = &bootstrap {
-        mmu.write_bootstrap_rom(bootstrap);
-    } else {
-        mmu.unmount_bootstrap_rom();
+    // the serial output would corrupt a video stream written to stdout
+    let video_to_stdout = matches
+        .get_one::<String>("Y4M_OUT")
+        .is_some_and(|path| path == "-");
+    let console_serial = || -> SerialPtr {
+        if video_to_stdout {
+            Box::new(StderrSerialWrite)
+        } else {
+            Box::new(StdoutSerialWrite)
+        }
+    };
+    // the printer replaces the console output, the log file is kept
+    #[cfg(feature = "printer")]
+    let port: SerialPtr = match matches.get_one::<String>("PRINTER") {
+        Some(dir) => Box::new(gbemu::serial::PrinterSerial::new(dir)),
+        None => console_serial(),
+    };
+    #[cfg(not(feature = "printer"))]
+    let port = console_serial();
+    let serial: SerialPtr = match matches.get_one::<String>("SERIAL_OUT") {
+        Some(path) => Box::new(TeeSerialWrite::new(vec![
+            port,
+            Box::new(FileSerialWrite::create(path).map_err(|err| FrontendError::write(path, err))?),
+        ])),
+        None => port,
+    };
+    let mut emulator = match model {
+        Some(model) => Emulator::with_model(&rom, bootstrap.as_deref(), serial, model)?,
+        None => Emulator::new(&rom, bootstrap.as_deref(), serial)?,
+    };
+    // battery backed RAM is saved next to the ROM when the emulator stops
+    let save_path = Path::new(rom_path).with_extension("sav");
+    emulator
+        .set_save_path(&save_path)
+        .map_err(|err| FrontendError::read(save_path, err))?;
+    #[cfg(feature = "scripting")]
+    if let Some(script_path) = matches.get_one::<String>("SCRIPT") {
+        let source = std::fs::read_to_string(script_path)
+            .map_err(|err| FrontendError::read(script_path, err))?;
+        let script = gbemu::scripting::Script::new(&source, emulator.memory.clone())
+            .map_err(|err| FrontendError::Script(err.to_string()))?;
+        emulator.set_script(Some(script));
+    }
+    let coverage = matches.get_flag("COVERAGE");
+    let dump_dir = matches.get_one::<String>("DUMP_MEMORY").map(PathBuf::from);
+    // load_rom keeps these options, they still apply after a --watch reload
+    emulator.cpu.set_opcode_coverage(coverage);
+    if matches.get_flag("NO_SPRITE_LIMIT") {
+        emulator.ppu.set_sprite_limit(false);
+    }
+    if matches.get_flag("NO_ENABLE_BLANKING") {
+        emulator.ppu.set_enable_blanking(false);
+    }
+    let interrupt_controller = emulator.interrupt_controller.clone();
+    let memory = emulator.memory.clone();
+    if matches.get_flag("OAM_BUG") {
+        memory.write().unwrap().set_oam_bug(true);
+    }
+    let display = emulator.display.clone();
+    if let Some(&frame_blend) = matches.get_one::<usize>("FRAME_BLEND") {
+        display.lock().unwrap().set_frame_blend(frame_blend);
     }
 
-    let memory = Arc::new(RwLock::new(mmu));
-    let display = Arc::new(Mutex::new(Display::default()));
+    let debug_state = if let Some(report_path) = matches.get_one::<String>("CRASH_REPORT") {
+        let debug_state = Arc::new(Mutex::new(None));
+        let rom_title = header.map(|h| h.title).unwrap_or_default();
+        crash_report::install_panic_hook(
+            PathBuf::from(report_path),
+            rom_title,
+            debug_state.clone(),
+        );
+        Some(debug_state)
+    } else {
+        None
+    };
+
+    let turbo = matches.get_many::<String>("TURBO_BUTTONS").map(|buttons| {
+        let keys: Vec<Keys> = buttons
+            .map(|button| if button == "a" { Keys::A } else { Keys::B })
+            .collect();
+        Turbo::new(&keys, *matches.get_one::<u64>("TURBO_RATE").unwrap())
+    });
 
-    let mut cpu = CPU::new(memory.clone(), interrupt_controller.clone());
-    if bootstrap.is_none() {
-        cpu.manual_bootstrap();
+    let control = Arc::new(EmuControl {
+        turbo,
+        instruction_period: matches
+            .get_one::<u64>("IPS")
+            .map(|&ips| emu_thread::instruction_period(ips)),
+        fast_boot: matches.get_flag("FAST_BOOT_ROM"),
+        boot_logo: matches
+            .get_flag("FAKE_BOOT_LOGO")
+            .then(|| CartridgeHeader::logo(&rom).map(<[u8]>::to_vec))
+            .flatten(),
+        ..Default::default()
+    });
+    control
+        .paused
+        .store(matches.get_flag("START_PAUSED"), Ordering::Relaxed);
+    control
+        .line_renderer
+        .store(matches.get_flag("LINE_RENDERER"), Ordering::Relaxed);
+    for (flag, layer) in [
+        ("NO_BG", Layers::BACKGROUND),
+        ("NO_WINDOW", Layers::WINDOW),
+        ("NO_SPRITES", Layers::SPRITES),
+    ] {
+        if matches.get_flag(flag) {
+            control.toggle_layer(layer);
+        }
     }
-    let ppu = PPU::new(
-        memory.clone(),
-        interrupt_controller.clone(),
-        display.clone(),
-    );
+    let watcher_thread = matches
+        .get_flag("WATCH")
+        .then(|| watcher::spawn(PathBuf::from(rom_path), control.clone()));
+    let video_capture = match matches.get_one::<String>("Y4M_OUT") {
+        Some(path) => {
+            let out: Box<dyn Write + Send> = if path == "-" {
+                Box::new(std::io::stdout())
+            } else {
+                Box::new(
+                    std::fs::File::create(path).map_err(|err| FrontendError::write(path, err))?,
+                )
+            };
+            let writer = Y4mWriter::new(out, SCREEN_WIDTH as usize, SCREEN_HEIGHT as usize)
+                .map_err(|err| FrontendError::write(path, err))?;
+            Some(VideoCapture::new(writer))
+        }
+        None => None,
+    };
+
+    let mut audio_capture = AudioCapture::default();
+    if let Some(path) = matches.get_one::<String>("RECORD_AUDIO") {
+        let out = BufWriter::new(
+            std::fs::File::create(path).map_err(|err| FrontendError::write(path, err))?,
+        );
+        let recorder = WavRecorder::new(out, AUDIO_SAMPLE_RATE)
+            .map_err(|err| FrontendError::write(path, err))?;
+        audio_capture.record(recorder);
+    }
+    // the stream plays until the end of `run`, after the emulator thread stops
+    #[cfg(feature = "audio")]
+    let _audio_output = {
+        let latency = *matches.get_one::<u64>("AUDIO_LATENCY").unwrap();
+        match audio::open(std::time::Duration::from_millis(latency)) {
+            Ok((output, sink)) => {
+                audio_capture.play(sink);
+                Some(output)
+            }
+            Err(err) => {
+                warn!(
+                    "Cannot open the audio output, running without sound: {}",
+                    err
+                );
+                None
+            }
+        }
+    };
 
-    let is_ended = Arc::new(AtomicBool::new(false));
-    let is_ended_emu = is_ended.clone();
-    let _ = std::thread::spawn(move || {
-        emu_thread::run(cpu, ppu, is_ended_emu);
+    let control_emu = control.clone();
+    let emu_thread = std::thread::spawn(move || {
+        emu_thread::run(
+            emulator,
+            control_emu,
+            debug_state,
+            video_capture,
+            audio_capture,
+        )
     });
 
     let event_loop = EventLoop::new()?;
@@ -113,93 +532,126 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .with_title("GameBoy Emulator")
                 .with_inner_size(size)
                 .with_resizable(false)
-                .build(&event_loop)
-                .unwrap()
+                .with_fullscreen(sharp_fullscreen.then_some(Fullscreen::Borderless(None)))
+                .build(&event_loop)?
         };
 
         let framebuffer = {
-            let window_physical_size = window.inner_size();
-            let surface_texture = SurfaceTexture::new(
-                window_physical_size.width,
-                window_physical_size.height,
+            let scale = effect.scale() as u32;
+            create_pixels(
                 &window,
-            );
-            Pixels::new(SCREEN_WIDTH as _, SCREEN_HEIGHT as _, surface_texture)?
+                SCREEN_WIDTH as u32 * scale,
+                SCREEN_HEIGHT as u32 * scale,
+            )?
         };
 
-        WindowData {
-            window,
-            framebuffer,
+        let mut window_data = WindowData::new(window, framebuffer, linear_filter);
+        if sharp_fullscreen {
+            window_data.enable_sharp_scaling();
         }
+        window_data
     };
 
     let mut tiles_window_data = if matches.get_flag("TILES_WINDOW") {
-        let window = {
-            let size = LogicalSize::new(
-                (TILE_WINDOW_WIDTH * MULTIPLIER) as f64,
-                (TILE_WINDOW_HEIGHT * MULTIPLIER) as f64,
-            );
-            WindowBuilder::new()
-                .with_title("GameBoy Emulator Tiles")
-                .with_inner_size(size)
-                .with_resizable(false)
-                .build(&event_loop)
-                .unwrap()
-        };
-
-        let framebuffer = {
-            let window_physical_size = window.inner_size();
-            let surface_texture = SurfaceTexture::new(
-                window_physical_size.width,
-                window_physical_size.height,
-                &window,
-            );
-            Pixels::new(TILE_WINDOW_WIDTH, TILE_WINDOW_HEIGHT, surface_texture)?
-        };
+        Some(create_debug_window(
+            &event_loop,
+            "GameBoy Emulator Tiles",
+            TILE_WINDOW_WIDTH,
+            TILE_WINDOW_HEIGHT,
+            linear_filter,
+        )?)
+    } else {
+        None
+    };
 
-        Some(WindowData {
-            window,
-            framebuffer,
-        })
+    let mut stat_lines_window_data = if matches.get_flag("STAT_LINES") {
+        Some(create_debug_window(
+            &event_loop,
+            "GameBoy Emulator STAT lines",
+            STAT_LINES_WIDTH as u32,
+            STAT_LINES_HEIGHT as u32,
+            linear_filter,
+        )?)
     } else {
         None
     };
 
+    // draw a first frame even if the emulator starts paused
+    main_window_data.window.request_redraw();
+
+    let mut tiles_bank = 0;
+    let mut input_overlay = false;
+    let pause_on_unfocus = *matches.get_one::<bool>("PAUSE_ON_UNFOCUS").unwrap();
+
     event_loop.run(move |event, loop_proxy| {
         use winit::event::{Event, WindowEvent};
 
         loop_proxy.set_control_flow(ControlFlow::Poll);
 
         match event {
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::Resized(size),
+            } if window_id == main_window_data.window.id() => {
+                main_window_data.resize(size.width, size.height);
+                main_window_data.window.request_redraw();
+            }
             Event::WindowEvent {
                 window_id,
                 event: WindowEvent::RedrawRequested,
             } if window_id == main_window_data.window.id() => {
-                display
-                    .lock()
-                    .unwrap()
-                    .draw_into_fb(main_window_data.framebuffer.frame_mut());
-                let _ = main_window_data.framebuffer.render();
+                let fb = main_window_data.framebuffer.frame_mut();
+                display.lock().unwrap().draw_into_fb_with_effect(fb, effect);
+                if input_overlay {
+                    let keys_state = interrupt_controller.lock().unwrap().keys_state();
+                    if let Err(err) = Display::draw_input_overlay(fb, keys_state) {
+                        warn!("Cannot draw the input overlay: {}", err);
+                    }
+                }
+                main_window_data.render();
             }
             Event::WindowEvent {
                 window_id,
                 event: WindowEvent::RedrawRequested,
             } if Some(window_id) == tiles_window_data.as_ref().map(|d| d.window.id()) => {
                 if let Some(data) = tiles_window_data.as_mut() {
-                    Display::draw_tiles_into_fb(&memory, data.framebuffer.frame_mut());
-                    let _ = data.framebuffer.render();
+                    Display::draw_tiles_into_fb(&memory, data.framebuffer.frame_mut(), tiles_bank);
+                    data.render();
+                }
+            }
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::RedrawRequested,
+            } if Some(window_id) == stat_lines_window_data.as_ref().map(|d| d.window.id()) => {
+                if let Some(data) = stat_lines_window_data.as_mut() {
+                    display
+                        .lock()
+                        .unwrap()
+                        .draw_stat_lines_into_fb(data.framebuffer.frame_mut());
+                    data.render();
                 }
             }
             Event::AboutToWait => {
                 let mut int_cont = interrupt_controller.lock().unwrap();
                 if int_cont.should_redraw {
                     main_window_data.window.request_redraw();
-                    if let Some(data) = tiles_window_data.as_ref() {
+                    for data in [&tiles_window_data, &stat_lines_window_data]
+                        .into_iter()
+                        .flatten()
+                    {
                         data.window.request_redraw();
                     }
                     int_cont.should_redraw = false;
                 }
             }
+            // switching between our own windows unfocuses one then focuses
+            // the other
+            Event::WindowEvent {
+                event: WindowEvent::Focused(focused),
+                ..
+            } if pause_on_unfocus => {
+                control.unfocused.store(!focused, Ordering::Relaxed);
+            }
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
@@ -223,6 +675,34 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         KeyCode::Escape => {
                             loop_proxy.exit();
                         }
+                        KeyCode::Space if pressed => {
+                            control.toggle_pause();
+                        }
+                        KeyCode::KeyN if pressed => {
+                            control.request_frame_step();
+                        }
+                        KeyCode::F1 if pressed => {
+                            control.toggle_layer(Layers::BACKGROUND);
+                        }
+                        KeyCode::F2 if pressed => {
+                            control.toggle_layer(Layers::WINDOW);
+                        }
+                        KeyCode::F3 if pressed => {
+                            control.toggle_layer(Layers::SPRITES);
+                        }
+                        KeyCode::F4 if pressed => {
+                            input_overlay = !input_overlay;
+                            main_window_data.window.request_redraw();
+                        }
+                        KeyCode::F5 if pressed => {
+                            control.toggle_renderer();
+                        }
+                        KeyCode::Tab if pressed => {
+                            tiles_bank ^= 1;
+                            if let Some(data) = tiles_window_data.as_ref() {
+                                data.window.request_redraw();
+                            }
+                        }
                         KeyCode::KeyZ | KeyCode::ArrowUp => {
                             int.change_key_state(Keys::Up, pressed);
                         }
@@ -236,11 +716,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             int.change_key_state(Keys::Right, pressed);
                         }
 
-                        KeyCode::KeyO => {
-                            int.change_key_state(Keys::A, pressed);
-                        }
-                        KeyCode::KeyP => {
-                            int.change_key_state(Keys::B, pressed);
+                        KeyCode::KeyO | KeyCode::KeyP => {
+                            let key = if vkc == KeyCode::KeyO {
+                                Keys::A
+                            } else {
+                                Keys::B
+                            };
+                            match &control.turbo {
+                                Some(turbo) => turbo.change_key_state(&mut int, key, pressed),
+                                None => int.change_key_state(key, pressed),
+                            }
                         }
 
                         KeyCode::Enter => {
@@ -254,16 +739,184 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
             Event::LoopExiting => {
-                is_ended.store(true, Ordering::Relaxed);
+                control.is_ended.store(true, Ordering::Relaxed);
             }
             _ => {}
         }
     })?;
 
+    // the emulator and watcher threads stop once `is_ended` is set
+    if let Some(watcher_thread) = watcher_thread {
+        let _ = watcher_thread.join();
+    }
+    if let Some(emulator) = emu_thread::join(emu_thread) {
+        if coverage {
+            print_missing_opcodes(&emulator.cpu.opcode_coverage());
+        }
+        if let Some(dir) = dump_dir {
+            dump_memory(&emulator, &dir).map_err(|err| FrontendError::write(dir, err))?;
+        }
+    }
+
     Ok(())
 }
 
+fn dump_memory(emulator: &Emulator, dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let mmu = emulator.memory.read().unwrap();
+    for (region, name) in [
+        (MemoryRegion::Vram, "vram.bin"),
+        (MemoryRegion::Wram, "wram.bin"),
+        (MemoryRegion::Oam, "oam.bin"),
+        (MemoryRegion::Hram, "hram.bin"),
+    ] {
+        std::fs::write(dir.join(name), mmu.dump_region(region))?;
+    }
+    Ok(())
+}
+
+fn print_missing_opcodes(coverage: &[bool]) {
+    let missing: Vec<String> = coverage
+        .iter()
+        .enumerate()
+        .filter(|(_, &executed)| !executed)
+        .map(|(index, _)| match index {
+            0x00..=0xFF => format!("{:02X}", index),
+            _ => format!("CB {:02X}", index - 0x100),
+        })
+        .collect();
+    println!("{} opcodes never executed:", missing.len());
+    for line in missing.chunks(16) {
+        println!("{}", line.join(" "));
+    }
+}
+
+fn create_debug_window(
+    event_loop: &EventLoop<()>,
+    title: &str,
+    width: u32,
+    height: u32,
+    linear_filter: bool,
+) -> Result<WindowData, FrontendError> {
+    let window = {
+        let size = LogicalSize::new((width * MULTIPLIER) as f64, (height * MULTIPLIER) as f64);
+        WindowBuilder::new()
+            .with_title(title)
+            .with_inner_size(size)
+            .with_resizable(false)
+            .build(event_loop)?
+    };
+
+    let framebuffer = create_pixels(&window, width, height)?;
+
+    Ok(WindowData::new(window, framebuffer, linear_filter))
+}
+
+/// Creates the pixels surface of `window`, falling back to a software adapter
+/// when no GPU is usable.
+fn create_pixels(window: &Window, width: u32, height: u32) -> Result<Pixels, FrontendError> {
+    let surface_texture = || {
+        let size = window.inner_size();
+        SurfaceTexture::new(size.width, size.height, window)
+    };
+
+    match Pixels::new(width, height, surface_texture()) {
+        Ok(pixels) => Ok(pixels),
+        Err(err @ (pixels::Error::AdapterNotFound | pixels::Error::DeviceNotFound(_))) => {
+            warn!("{}, trying a software adapter", err);
+            PixelsBuilder::new(width, height, surface_texture())
+                .request_adapter_options(wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    force_fallback_adapter: true,
+                    compatible_surface: None,
+                })
+                .build()
+                .map_err(|_| FrontendError::SurfaceCreation(err))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn print_cartridge_info(rom: &[u8], header: Option<&CartridgeHeader>) {
+    match header {
+        Some(header) => {
+            println!("Title: {}", header.title);
+            println!("Cartridge type: {:#04x}", header.cartridge_type);
+            println!("CGB flag: {:#04x}", header.cgb_flag);
+        }
+        None => println!("No cartridge header"),
+    }
+    println!("ROM size: {} bytes", rom.len());
+    println!(
+        "Nintendo logo: {}",
+        if CartridgeHeader::logo_valid(rom) {
+            "valid"
+        } else {
+            "invalid"
+        }
+    );
+}
+
+enum Renderer {
+    Nearest,
+    Linear(LinearRenderer),
+    Sharp(Box<SharpRenderer>),
+}
+
 struct WindowData {
     window: Window,
     framebuffer: Pixels,
+    renderer: Renderer,
+}
+
+impl WindowData {
+    fn new(window: Window, framebuffer: Pixels, linear_filter: bool) -> Self {
+        let renderer = if linear_filter {
+            Renderer::Linear(LinearRenderer::new(&framebuffer))
+        } else {
+            Renderer::Nearest
+        };
+        WindowData {
+            window,
+            framebuffer,
+            renderer,
+        }
+    }
+
+    fn enable_sharp_scaling(&mut self) {
+        let size = self.window.inner_size();
+        self.renderer = Renderer::Sharp(Box::new(SharpRenderer::new(
+            &self.framebuffer,
+            size.width,
+            size.height,
+        )));
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let _ = self.framebuffer.resize_surface(width, height);
+        if let Renderer::Sharp(renderer) = &mut self.renderer {
+            renderer.resize(&self.framebuffer, width, height);
+        }
+    }
+
+    fn render(&self) {
+        let _ = match &self.renderer {
+            Renderer::Nearest => self.framebuffer.render(),
+            Renderer::Linear(renderer) => {
+                self.framebuffer.render_with(|encoder, render_target, _| {
+                    renderer.render(encoder, render_target);
+                    Ok(())
+                })
+            }
+            Renderer::Sharp(renderer) => {
+                self.framebuffer.render_with(|encoder, render_target, _| {
+                    renderer.render(encoder, render_target);
+                    Ok(())
+                })
+            }
+        };
+    }
 }
@@ -0,0 +1,122 @@
+use pixels::{wgpu, Pixels};
+
+use crate::linear_renderer::{
+    begin_render_pass, create_bind_group, create_bind_group_layout, create_pipeline, create_sampler,
+};
+
+/// Scales the pixels texture with nearest neighbor sampling to the largest
+/// integer multiple fitting in the surface, then smoothly to the final size.
+/// Sharper than linear scaling alone, without the uneven pixels of nearest
+/// neighbor at fractional scales.
+pub struct SharpRenderer {
+    bind_group_layout: wgpu::BindGroupLayout,
+    source_bind_group: wgpu::BindGroup,
+    prescale_pipeline: wgpu::RenderPipeline,
+    intermediate_view: wgpu::TextureView,
+    linear_sampler: wgpu::Sampler,
+    intermediate_bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    // x, y, width, height of the scaled screen in the surface
+    viewport: [f32; 4],
+}
+
+impl SharpRenderer {
+    pub fn new(pixels: &Pixels, width: u32, height: u32) -> Self {
+        let device = pixels.device();
+        let texture = pixels.texture();
+
+        let bind_group_layout = create_bind_group_layout(device);
+        let source_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let nearest_sampler = create_sampler(device, wgpu::FilterMode::Nearest);
+        let source_bind_group =
+            create_bind_group(device, &bind_group_layout, &source_view, &nearest_sampler);
+        let prescale_pipeline = create_pipeline(device, &bind_group_layout, texture.format());
+
+        let linear_sampler = create_sampler(device, wgpu::FilterMode::Linear);
+        let intermediate_view = create_intermediate_view(pixels, 1);
+        let intermediate_bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            &intermediate_view,
+            &linear_sampler,
+        );
+        let render_pipeline =
+            create_pipeline(device, &bind_group_layout, pixels.render_texture_format());
+
+        let mut renderer = SharpRenderer {
+            bind_group_layout,
+            source_bind_group,
+            prescale_pipeline,
+            intermediate_view,
+            linear_sampler,
+            intermediate_bind_group,
+            render_pipeline,
+            viewport: [0.0, 0.0, width as f32, height as f32],
+        };
+        renderer.resize(pixels, width, height);
+        renderer
+    }
+
+    /// Picks the intermediate resolution for a surface of `width` x `height`.
+    pub fn resize(&mut self, pixels: &Pixels, width: u32, height: u32) {
+        let texture = pixels.texture();
+        let (texture_width, texture_height) = (texture.width(), texture.height());
+
+        let integer_scale = (width / texture_width).min(height / texture_height).max(1);
+        self.intermediate_view = create_intermediate_view(pixels, integer_scale);
+        self.intermediate_bind_group = create_bind_group(
+            pixels.device(),
+            &self.bind_group_layout,
+            &self.intermediate_view,
+            &self.linear_sampler,
+        );
+
+        let scale =
+            (width as f32 / texture_width as f32).min(height as f32 / texture_height as f32);
+        let (scaled_width, scaled_height) =
+            (texture_width as f32 * scale, texture_height as f32 * scale);
+        self.viewport = [
+            (width as f32 - scaled_width) / 2.0,
+            (height as f32 - scaled_height) / 2.0,
+            scaled_width,
+            scaled_height,
+        ];
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, render_target: &wgpu::TextureView) {
+        {
+            let mut rpass = begin_render_pass(encoder, &self.intermediate_view);
+            rpass.set_pipeline(&self.prescale_pipeline);
+            rpass.set_bind_group(0, &self.source_bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+
+        let mut rpass = begin_render_pass(encoder, render_target);
+        let [x, y, width, height] = self.viewport;
+        rpass.set_viewport(x, y, width, height, 0.0, 1.0);
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_bind_group(0, &self.intermediate_bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+fn create_intermediate_view(pixels: &Pixels, scale: u32) -> wgpu::TextureView {
+    let texture = pixels.texture();
+    pixels
+        .device()
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("sharp_renderer_intermediate_texture"),
+            size: wgpu::Extent3d {
+                width: texture.width() * scale,
+                height: texture.height() * scale,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: texture.format(),
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
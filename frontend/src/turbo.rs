@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use gbemu::interrupt::{InterruptController, Keys};
+
+/// Auto-fire for some buttons: while held, they alternate between pressed and
+/// released every `rate` frames.
+#[derive(Debug)]
+pub struct Turbo {
+    rate: u64,
+    buttons: Vec<(Keys, AtomicBool)>,
+}
+
+impl Turbo {
+    pub fn new(keys: &[Keys], rate: u64) -> Self {
+        Turbo {
+            rate: rate.max(1),
+            buttons: keys
+                .iter()
+                .map(|&key| (key, AtomicBool::new(false)))
+                .collect(),
+        }
+    }
+
+    /// Forwards a physical key change to the controller, keys with auto-fire
+    /// start pressed and are then toggled by `update`.
+    pub fn change_key_state(&self, int: &mut InterruptController, key: Keys, pressed: bool) {
+        if let Some((_, held)) = self.buttons.iter().find(|(k, _)| *k == key) {
+            // ignore the key repeat events, they would restart the alternation
+            if held.swap(pressed, Ordering::Relaxed) == pressed {
+                return;
+            }
+        }
+        int.change_key_state(key, pressed);
+    }
+
+    /// Called once per frame by the emulator thread.
+    pub fn update(&self, int: &mut InterruptController, frame: u64) {
+        for (key, held) in &self.buttons {
+            if held.load(Ordering::Relaxed) {
+                int.change_key_state(*key, pressed_on_frame(frame, self.rate));
+            }
+        }
+    }
+}
+
+fn pressed_on_frame(frame: u64, rate: u64) -> bool {
+    (frame / rate).is_multiple_of(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn a_pressed(int: &InterruptController) -> bool {
+        int.keys_state()[Keys::A as usize]
+    }
+
+    #[test]
+    fn test_pressed_on_frame() {
+        let pattern: Vec<bool> = (0..8).map(|frame| pressed_on_frame(frame, 2)).collect();
+        assert_eq!(
+            pattern,
+            [true, true, false, false, true, true, false, false]
+        );
+    }
+
+    #[test]
+    fn test_alternates_while_held() {
+        let turbo = Turbo::new(&[Keys::A], 1);
+        let mut int = InterruptController::new();
+
+        turbo.change_key_state(&mut int, Keys::A, true);
+        assert!(a_pressed(&int));
+        let pattern: Vec<bool> = (0..4)
+            .map(|frame| {
+                turbo.update(&mut int, frame);
+                a_pressed(&int)
+            })
+            .collect();
+        assert_eq!(pattern, [true, false, true, false]);
+
+        // a key repeat event does not restart the alternation
+        turbo.change_key_state(&mut int, Keys::A, true);
+        assert!(!a_pressed(&int));
+
+        turbo.change_key_state(&mut int, Keys::A, false);
+        assert!(!a_pressed(&int));
+        turbo.update(&mut int, 4);
+        assert!(!a_pressed(&int));
+    }
+
+    #[test]
+    fn test_other_keys_untouched() {
+        let turbo = Turbo::new(&[Keys::A], 0);
+        let mut int = InterruptController::new();
+
+        turbo.change_key_state(&mut int, Keys::B, true);
+        turbo.update(&mut int, 1);
+        assert!(int.keys_state()[Keys::B as usize]);
+        assert!(!a_pressed(&int));
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    path::PathBuf,
+    sync::{atomic::Ordering, Arc},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{error, info};
+
+use crate::emu_thread::EmuControl;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(300);
+
+/// Waits for a burst of changes to settle, a build usually writes the ROM in
+/// several steps.
+#[derive(Debug)]
+pub struct Debouncer {
+    delay: Duration,
+    last_change: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn new(delay: Duration) -> Self {
+        Debouncer {
+            delay,
+            last_change: None,
+        }
+    }
+
+    pub fn change(&mut self, now: Instant) {
+        self.last_change = Some(now);
+    }
+
+    /// Returns true once, when no change happened for `delay` after the last
+    /// one.
+    pub fn settled(&mut self, now: Instant) -> bool {
+        match self.last_change {
+            Some(last_change) if now.duration_since(last_change) >= self.delay => {
+                self.last_change = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn modification(path: &PathBuf) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Polls the ROM file and hands its new content to the emulator thread when
+/// it changes.
+pub fn spawn(path: PathBuf, control: Arc<EmuControl>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut last_modification = modification(&path);
+        let mut debouncer = Debouncer::new(DEBOUNCE_DELAY);
+
+        while !control.is_ended.load(Ordering::Relaxed) {
+            std::thread::sleep(POLL_INTERVAL);
+
+            let now = Instant::now();
+            let current_modification = modification(&path);
+            if current_modification != last_modification {
+                last_modification = current_modification;
+                debouncer.change(now);
+            }
+
+            if debouncer.settled(now) {
+                match std::fs::read(&path) {
+                    Ok(rom) => {
+                        info!("Reloading {}", path.display());
+                        control.request_reload(rom);
+                    }
+                    Err(err) => error!("Cannot read '{}': {}", path.display(), err),
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debouncer_coalesces_changes() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(DEBOUNCE_DELAY);
+        assert!(!debouncer.settled(start));
+
+        debouncer.change(start);
+        assert!(!debouncer.settled(start + Duration::from_millis(200)));
+        // a new change within the delay postpones the reload
+        debouncer.change(start + Duration::from_millis(200));
+        assert!(!debouncer.settled(start + Duration::from_millis(400)));
+
+        assert!(debouncer.settled(start + Duration::from_millis(500)));
+        // only once per burst
+        assert!(!debouncer.settled(start + Duration::from_millis(600)));
+    }
+
+    #[test]
+    fn test_debouncer_fires_per_burst() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(DEBOUNCE_DELAY);
+
+        debouncer.change(start);
+        assert!(debouncer.settled(start + DEBOUNCE_DELAY));
+        debouncer.change(start + Duration::from_secs(1));
+        assert!(!debouncer.settled(start + Duration::from_millis(1100)));
+        assert!(debouncer.settled(start + Duration::from_secs(2)));
+    }
+}